@@ -0,0 +1,178 @@
+//!
+//! The bytecode of a method as text that does not depend on the layout of the constant pool.
+//! Operands are resolved to names and constants and branch targets become instruction labels
+//!
+
+use crate::constant;
+use cs_parser::opcode::*;
+use cs_parser::{
+    cp_info, u1, u2, AttributeCodeException, AttributeInfoInner, ClassFile, CpInfoInner, FromPool,
+};
+
+/// One line per instruction, followed by one line per exception handler
+pub fn listing(
+    class: &ClassFile,
+    code: &[u1],
+    exception_table: &[AttributeCodeException],
+) -> Vec<String> {
+    let mut starts = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        starts.push(pc);
+        match length(code, pc) {
+            Some(len) => pc += len,
+            None => break,
+        }
+    }
+    // the end of the code is a valid target for the end of an exception handler range
+    let label = |target: i64| match starts.binary_search(&(target as usize)) {
+        Ok(index) if target >= 0 => format!("L{}", index),
+        _ if target == code.len() as i64 => format!("L{}", starts.len()),
+        _ => format!("@{}", target),
+    };
+
+    let mut lines = Vec::new();
+    for &pc in &starts {
+        let Some(len) = length(code, pc) else {
+            lines.push(format!("{} <truncated>", name(code[pc])));
+            break;
+        };
+        lines.push(instruction(class, &code[pc..pc + len], pc, &label));
+    }
+
+    for handler in exception_table {
+        let catch_type = match handler.catch_type {
+            0 => "any".to_string(),
+            index => entry(class, index),
+        };
+        lines.push(format!(
+            "catch {} {}..{} -> {}",
+            catch_type,
+            label(handler.start_pc as i64),
+            label(handler.end_pc as i64),
+            label(handler.handler_pc as i64)
+        ));
+    }
+    lines
+}
+
+/// The text of the instruction, which starts at `pc` and takes up all of `code`
+fn instruction(
+    class: &ClassFile,
+    code: &[u1],
+    pc: usize,
+    label: &impl Fn(i64) -> String,
+) -> String {
+    let u2_at = |at: usize| u2::from_be_bytes([code[at], code[at + 1]]);
+    let i4_at =
+        |at: usize| i32::from_be_bytes([code[at], code[at + 1], code[at + 2], code[at + 3]]);
+    let branch = |offset: i64| label(pc as i64 + offset);
+
+    let opcode = code[0];
+    match opcode {
+        BIPUSH => format!("bipush {}", code[1] as i8),
+        SIPUSH => format!("sipush {}", u2_at(1) as i16),
+        // `ldc_w` only differs from `ldc` by the size of the constant pool
+        LDC => format!("ldc {}", entry(class, code[1] as u2)),
+        LDC_W => format!("ldc {}", entry(class, u2_at(1))),
+        LDC2_W => format!("ldc2_w {}", entry(class, u2_at(1))),
+        ILOAD..=ALOAD | ISTORE..=ASTORE | RET => format!("{} {}", name(opcode), code[1]),
+        IINC => format!("iinc {} {}", code[1], code[2] as i8),
+        IFEQ..=JSR | IFNULL | IFNONNULL => {
+            format!("{} {}", name(opcode), branch(u2_at(1) as i16 as i64))
+        }
+        GOTO_W => format!("goto {}", branch(i4_at(1) as i64)),
+        JSR_W => format!("jsr {}", branch(i4_at(1) as i64)),
+        GETSTATIC..=INVOKEINTERFACE | NEW | ANEWARRAY | CHECKCAST | INSTANCEOF => {
+            format!("{} {}", name(opcode), entry(class, u2_at(1)))
+        }
+        INVOKEDYNAMIC => format!("invokedynamic {}", entry(class, u2_at(1))),
+        MULTIANEWARRAY => format!("multianewarray {} {}", entry(class, u2_at(1)), code[3]),
+        NEWARRAY => format!(
+            "newarray {}",
+            match code[1] {
+                4 => "boolean",
+                5 => "char",
+                6 => "float",
+                7 => "double",
+                8 => "byte",
+                9 => "short",
+                10 => "int",
+                11 => "long",
+                _ => "<invalid>",
+            }
+        ),
+        // the wide variants are written like the normal ones, only the index is larger
+        WIDE if code[1] == IINC => format!("iinc {} {}", u2_at(2), u2_at(4) as i16),
+        WIDE => format!("{} {}", name(code[1]), u2_at(2)),
+        TABLESWITCH | LOOKUPSWITCH => {
+            let start = ((pc + 4) & !3) - pc;
+            let default = branch(i4_at(start) as i64);
+            let cases: Vec<_> = if opcode == TABLESWITCH {
+                let low = i4_at(start + 4);
+                (start + 12..code.len())
+                    .step_by(4)
+                    .enumerate()
+                    .map(|(i, at)| {
+                        format!("{}: {}", low as i64 + i as i64, branch(i4_at(at) as i64))
+                    })
+                    .collect()
+            } else {
+                (start + 8..code.len())
+                    .step_by(8)
+                    .map(|at| format!("{}: {}", i4_at(at), branch(i4_at(at + 4) as i64)))
+                    .collect()
+            };
+            format!("{} {} default: {}", name(opcode), cases.join(", "), default)
+        }
+        _ => name(opcode).to_string(),
+    }
+}
+
+/// The constant pool entry at `index` as text, or the index if there is no entry
+fn entry(class: &ClassFile, index: u2) -> String {
+    match class.constant_pool.get((index as usize).wrapping_sub(1)) {
+        Some(info) => match &info.inner {
+            CpInfoInner::InvokeDynamic(cp_info::InvokeDynamic {
+                bootstrap_method_attr_index,
+                ..
+            })
+            | CpInfoInner::Dynamic(cp_info::Dynamic {
+                bootstrap_method_attr_index,
+                ..
+            }) => format!(
+                "{} {}",
+                constant(&class.constant_pool, &info.inner),
+                bootstrap(class, *bootstrap_method_attr_index)
+            ),
+            inner => constant(&class.constant_pool, inner),
+        },
+        None => format!("#{}", index),
+    }
+}
+
+/// The bootstrap method and its static arguments, instead of the index into `BootstrapMethods`
+fn bootstrap(class: &ClassFile, index: u2) -> String {
+    let cp = &class.constant_pool;
+    let method = class
+        .attributes
+        .iter()
+        .find_map(|attribute| match &attribute.inner {
+            AttributeInfoInner::BootstrapMethods { bootstrap_methods } => {
+                bootstrap_methods.get(index as usize)
+            }
+            _ => None,
+        });
+    match method {
+        Some(method) => {
+            let handle = FromPool::<CpInfoInner>::from(method.bootstrap_method_ref.inner());
+            let arguments: Vec<_> = method
+                .bootstrap_arguments
+                .iter()
+                .map(|argument| constant(cp, argument.get(cp)))
+                .collect();
+            format!("{}({})", constant(cp, handle.get(cp)), arguments.join(", "))
+        }
+        None => format!("bootstrap#{}", index),
+    }
+}
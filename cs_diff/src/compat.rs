@@ -0,0 +1,340 @@
+//!
+//! Binary compatibility between two versions of a library, following chapter 13 of the JLS.
+//!
+//! Only the API is checked, the public classes with their public and protected members.
+//! Inherited members and supertypes are looked up in the classes of the library, so the supertypes
+//! of a class outside of it, like one of the JDK, are not known. A supertype outside of the library
+//! is only reported as removed if the new supertypes are all known
+//!
+
+use crate::{class_name, Member};
+use cs_parser::{u2, ClassAccessFlag, ClassFile, MethodAccessFlag};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+// the access flags that fields and methods have in common
+const PUBLIC: u2 = MethodAccessFlag::PUBLIC as u2;
+const PRIVATE: u2 = MethodAccessFlag::PRIVATE as u2;
+const PROTECTED: u2 = MethodAccessFlag::PROTECTED as u2;
+const STATIC: u2 = MethodAccessFlag::STATIC as u2;
+const FINAL: u2 = MethodAccessFlag::FINAL as u2;
+const ABSTRACT: u2 = MethodAccessFlag::ABSTRACT as u2;
+
+/// A change that breaks binaries which were compiled against the old version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Incompatibility {
+    /// The name of the class in the old version
+    pub class: String,
+    pub member: Member,
+    pub change: IncompatibleChange,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IncompatibleChange {
+    /// The class or member does not exist anymore (§13.4.8, §13.4.12)
+    Removed,
+    /// The member does not exist anymore, but new ones with the same name and these descriptors do (§13.4.14)
+    DescriptorChanged(Vec<String>),
+    /// Less code may access the class or member (§13.4.3, §13.4.7)
+    AccessNarrowed {
+        old: &'static str,
+        new: &'static str,
+    },
+    /// The class can not be extended or the method not be overridden anymore (§13.4.2, §13.4.17),
+    /// or the field can not be assigned (§13.4.9)
+    FinalAdded,
+    /// The class can not be instantiated or the method not be invoked anymore (§13.4.1, §13.4.16)
+    AbstractAdded,
+    /// The field or method changed from static to instance or the other way around (§13.4.10, §13.4.19)
+    StaticChanged { now_static: bool },
+    /// The class is an interface now or the other way around
+    KindChanged { now_interface: bool },
+    /// The class is not a subtype of this class or interface anymore (§13.4.4)
+    SupertypeRemoved(String),
+    /// An abstract method was added to an interface, its existing implementations do not have it
+    InterfaceMethodAdded,
+}
+
+/// The incompatible changes between the classes of two versions of a library
+pub fn check(old: &[ClassFile], new: &[ClassFile]) -> Vec<Incompatibility> {
+    let old = Library::new(old);
+    let new = Library::new(new);
+    let mut incompatibilities = Vec::new();
+    for (name, class) in &old.classes {
+        if class.access_flags & ClassAccessFlag::Public as u2 == 0 {
+            continue;
+        }
+        let mut report = |member, change| {
+            incompatibilities.push(Incompatibility {
+                class: name.clone(),
+                member,
+                change,
+            })
+        };
+        match new.classes.get(name) {
+            Some(new_class) => check_class(&old, class, &new, new_class, &mut report),
+            None => report(Member::Class, IncompatibleChange::Removed),
+        }
+    }
+    incompatibilities
+}
+
+fn check_class(
+    old: &Library,
+    class: &ClassFile,
+    new: &Library,
+    new_class: &ClassFile,
+    report: &mut impl FnMut(Member, IncompatibleChange),
+) {
+    let (flags, new_flags) = (class.access_flags, new_class.access_flags);
+    let set = |flag: ClassAccessFlag| new_flags & flag as u2 != 0 && flags & flag as u2 == 0;
+    if new_flags & ClassAccessFlag::Public as u2 == 0 {
+        report(
+            Member::Class,
+            IncompatibleChange::AccessNarrowed {
+                old: "public",
+                new: "package-private",
+            },
+        );
+    }
+    let interface = flags & ClassAccessFlag::Interface as u2 != 0;
+    if interface != (new_flags & ClassAccessFlag::Interface as u2 != 0) {
+        report(
+            Member::Class,
+            IncompatibleChange::KindChanged {
+                now_interface: !interface,
+            },
+        );
+        return;
+    }
+    if !interface && set(ClassAccessFlag::Final) {
+        report(Member::Class, IncompatibleChange::FinalAdded);
+    }
+    if !interface && set(ClassAccessFlag::Abstract) {
+        report(Member::Class, IncompatibleChange::AbstractAdded);
+    }
+    let (supertypes, known) = new.supertypes(new_class);
+    for supertype in old.supertypes(class).0.difference(&supertypes) {
+        // a class outside of the library may still be a subtype of it
+        if !known && !new.classes.contains_key(supertype) {
+            continue;
+        }
+        report(
+            Member::Class,
+            IncompatibleChange::SupertypeRemoved(supertype.clone()),
+        );
+    }
+
+    let class_final = flags & ClassAccessFlag::Final as u2 != 0;
+    for method in [false, true] {
+        for (name, descriptor, flags) in members(class, method) {
+            if flags & (PUBLIC | PROTECTED) == 0 {
+                continue;
+            }
+            let member = if method {
+                Member::Method {
+                    name: name.to_string(),
+                    descriptor: descriptor.to_string(),
+                }
+            } else {
+                Member::Field {
+                    name: name.to_string(),
+                    descriptor: descriptor.to_string(),
+                }
+            };
+            let Some(new_flags) = new.find(new_class, name, descriptor, method) else {
+                // the new descriptors of the name, the old class may already have overloads
+                let descriptors: Vec<_> = members(new_class, method)
+                    .filter(|(new_name, new_descriptor, _)| {
+                        *new_name == name
+                            && !members(class, method).any(|(old_name, old_descriptor, _)| {
+                                old_name == name && old_descriptor == *new_descriptor
+                            })
+                    })
+                    .map(|(_, descriptor, _)| descriptor.to_string())
+                    .collect();
+                if descriptors.is_empty() {
+                    report(member, IncompatibleChange::Removed);
+                } else {
+                    report(member, IncompatibleChange::DescriptorChanged(descriptors));
+                }
+                continue;
+            };
+
+            if rank(new_flags) < rank(flags) {
+                report(
+                    member.clone(),
+                    IncompatibleChange::AccessNarrowed {
+                        old: access(flags),
+                        new: access(new_flags),
+                    },
+                );
+            }
+            if flags & STATIC != new_flags & STATIC {
+                report(
+                    member.clone(),
+                    IncompatibleChange::StaticChanged {
+                        now_static: new_flags & STATIC != 0,
+                    },
+                );
+            }
+            // static methods and the methods of final classes could not have been overridden
+            let overridable = !method || (flags & STATIC == 0 && !class_final);
+            if overridable && new_flags & FINAL != 0 && flags & FINAL == 0 {
+                report(member.clone(), IncompatibleChange::FinalAdded);
+            }
+            if method && !interface && new_flags & ABSTRACT != 0 && flags & ABSTRACT == 0 {
+                report(member, IncompatibleChange::AbstractAdded);
+            }
+        }
+    }
+
+    if interface {
+        for (name, descriptor, flags) in members(new_class, true) {
+            if flags & ABSTRACT != 0 && old.find(class, name, descriptor, true).is_none() {
+                report(
+                    Member::Method {
+                        name: name.to_string(),
+                        descriptor: descriptor.to_string(),
+                    },
+                    IncompatibleChange::InterfaceMethodAdded,
+                );
+            }
+        }
+    }
+}
+
+/// The classes of a version of the library by name
+struct Library<'a> {
+    classes: BTreeMap<String, &'a ClassFile>,
+}
+
+impl<'a> Library<'a> {
+    fn new(classes: &'a [ClassFile]) -> Self {
+        Self {
+            classes: classes
+                .iter()
+                .map(|class| (class_name(&class.constant_pool, class.this_class), class))
+                .collect(),
+        }
+    }
+
+    /// The direct superclass and superinterfaces of the class
+    fn direct_supertypes(class: &ClassFile) -> impl Iterator<Item = String> + '_ {
+        let cp = &class.constant_pool;
+        let super_class = class
+            .super_class
+            .maybe_get(cp)
+            .map(|super_class| super_class.name_index.get(cp).to_string());
+        super_class
+            .into_iter()
+            .chain(class.interfaces.iter().map(|i| class_name(cp, *i)))
+    }
+
+    /// All superclasses and superinterfaces that can be found, and whether these are all of them,
+    /// because every supertype except `java/lang/Object` is in the library
+    fn supertypes(&self, class: &ClassFile) -> (BTreeSet<String>, bool) {
+        let mut supertypes = BTreeSet::new();
+        let mut known = true;
+        let mut pending: Vec<_> = Self::direct_supertypes(class).collect();
+        while let Some(name) = pending.pop() {
+            match self.classes.get(&name) {
+                Some(supertype) => pending.extend(Self::direct_supertypes(supertype)),
+                None => known &= name == "java/lang/Object",
+            }
+            supertypes.insert(name);
+        }
+        (supertypes, known)
+    }
+
+    /// The access flags of the field or method in the class or, unless it is a constructor,
+    /// the nearest supertype that declares it accessibly
+    fn find(&self, class: &ClassFile, name: &str, descriptor: &str, method: bool) -> Option<u2> {
+        let declared = members(class, method)
+            .find(|&(n, d, _)| n == name && d == descriptor)
+            .map(|(_, _, flags)| flags);
+        if declared.is_some() || name == "<init>" {
+            return declared;
+        }
+        Self::direct_supertypes(class)
+            .filter_map(|supertype| self.classes.get(&supertype))
+            .find_map(|supertype| self.find(supertype, name, descriptor, method))
+            .filter(|flags| flags & PRIVATE == 0)
+    }
+}
+
+/// The name, descriptor and access flags of the methods or fields of the class
+fn members(class: &ClassFile, methods: bool) -> Box<dyn Iterator<Item = (&str, &str, u2)> + '_> {
+    let cp = &class.constant_pool;
+    if methods {
+        Box::new(class.methods.iter().map(move |method| {
+            (
+                method.name_index.get(cp),
+                method.descriptor_index.get(cp),
+                method.access_flags,
+            )
+        }))
+    } else {
+        Box::new(class.fields.iter().map(move |field| {
+            (
+                field.name_index.get(cp),
+                field.descriptor_index.get(cp),
+                field.access_flags,
+            )
+        }))
+    }
+}
+
+/// How much code may access the member, from private to public
+fn rank(flags: u2) -> u8 {
+    match flags & (PUBLIC | PROTECTED | PRIVATE) {
+        PUBLIC => 3,
+        PROTECTED => 2,
+        PRIVATE => 0,
+        _ => 1,
+    }
+}
+
+fn access(flags: u2) -> &'static str {
+    match rank(flags) {
+        3 => "public",
+        2 => "protected",
+        1 => "package-private",
+        _ => "private",
+    }
+}
+
+impl Display for Incompatibility {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} ", self.class, self.member)?;
+        match &self.change {
+            IncompatibleChange::Removed => write!(f, "was removed"),
+            IncompatibleChange::DescriptorChanged(descriptors) => {
+                write!(f, "changed its descriptor to {}", descriptors.join(" or "))
+            }
+            IncompatibleChange::AccessNarrowed { old, new } => {
+                write!(f, "is {} instead of {}", new, old)
+            }
+            IncompatibleChange::FinalAdded => write!(f, "is final now"),
+            IncompatibleChange::AbstractAdded => write!(f, "is abstract now"),
+            IncompatibleChange::StaticChanged { now_static: true } => write!(f, "is static now"),
+            IncompatibleChange::StaticChanged { now_static: false } => {
+                write!(f, "is not static anymore")
+            }
+            IncompatibleChange::KindChanged {
+                now_interface: true,
+            } => {
+                write!(f, "is an interface now")
+            }
+            IncompatibleChange::KindChanged {
+                now_interface: false,
+            } => write!(f, "is not an interface anymore"),
+            IncompatibleChange::SupertypeRemoved(supertype) => {
+                write!(f, "is not a subtype of {} anymore", supertype)
+            }
+            IncompatibleChange::InterfaceMethodAdded => {
+                write!(f, "was added to the interface without a default")
+            }
+        }
+    }
+}
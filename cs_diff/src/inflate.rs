@@ -0,0 +1,229 @@
+//!
+//! A decoder for the DEFLATE format of RFC 1951, which is how the entries of jar files are compressed.
+//!
+//! The codes are decoded one bit at a time with the canonical Huffman code of their lengths,
+//! which is slower than tables, but class files are small
+//!
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order in which the lengths of the code length code are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const MAX_BITS: usize = 15;
+/// The most bytes that one byte of compressed data can expand to
+const MAX_RATIO: usize = 1032;
+
+/// Decompresses raw DEFLATE data of at most `size` bytes. Returns `None` if the data is invalid,
+/// truncated or larger than that
+pub fn inflate(data: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut bits = Bits {
+        data,
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    // the size is read from the jar, which may be crafted
+    let mut out = Vec::with_capacity(size.min(data.len().saturating_mul(MAX_RATIO)));
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => stored(&mut bits, &mut out, size)?,
+            1 => {
+                let mut lengths = [0; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths)?;
+                let distances = Huffman::new(&[5; 30])?;
+                codes(&mut bits, &mut out, size, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic(&mut bits)?;
+                codes(&mut bits, &mut out, size, &literals, &distances)?;
+            }
+            _ => return None,
+        }
+        if last {
+            return Some(out);
+        }
+    }
+}
+
+/// Reads the bits of the bytes from the least significant one
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, n: u32) -> Option<u32> {
+        while self.count < n {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Some(value)
+    }
+}
+
+/// A canonical Huffman code, the number of codes of every length and the symbols ordered by their code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// The code from the length of the code of every symbol, zero if the symbol is not used.
+    /// Incomplete codes are allowed, a distance code may only have one symbol
+    fn new(lengths: &[u8]) -> Option<Self> {
+        let mut counts = [0; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return None;
+            }
+        }
+
+        let mut offsets = [0; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Some(Self { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Option<u16> {
+        // the first code of every length follows the last code of the previous length
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= bits.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+/// A block without compression, aligned to a byte
+fn stored(bits: &mut Bits, out: &mut Vec<u8>, size: usize) -> Option<()> {
+    bits.buffer = 0;
+    bits.count = 0;
+    let length = bits.bits(16)?;
+    if bits.bits(16)? != !length & 0xFFFF {
+        return None;
+    }
+    let data = bits.data.get(bits.pos..bits.pos + length as usize)?;
+    if out.len() + data.len() > size {
+        return None;
+    }
+    out.extend_from_slice(data);
+    bits.pos += length as usize;
+    Some(())
+}
+
+/// Reads the codes of a block with dynamic Huffman codes, which are themselves compressed
+fn dynamic(bits: &mut Bits) -> Option<(Huffman, Huffman)> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_length_count = bits.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return None;
+    }
+
+    let mut code_lengths = [0; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = bits.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last()?, 3 + bits.bits(2)?),
+            17 => (0, 3 + bits.bits(3)?),
+            18 => (0, 11 + bits.bits(7)?),
+            _ => return None,
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count || lengths[256] == 0 {
+        return None;
+    }
+
+    Some((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+/// Decodes literals and copies of earlier output until the end of the block
+fn codes(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    size: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Option<()> {
+    loop {
+        if out.len() > size {
+            return None;
+        }
+        match literals.decode(bits)? {
+            literal @ 0..=255 => out.push(literal as u8),
+            256 => return (out.len() <= size).then_some(()),
+            symbol => {
+                let symbol = symbol as usize - 257;
+                let length = *LENGTH_BASE.get(symbol)? as usize
+                    + bits.bits(*LENGTH_EXTRA.get(symbol)? as u32)? as usize;
+                let symbol = distances.decode(bits)? as usize;
+                let distance = *DISTANCE_BASE.get(symbol)? as usize
+                    + bits.bits(*DISTANCE_EXTRA.get(symbol)? as u32)? as usize;
+                let start = out.len().checked_sub(distance)?;
+                // the copy may overlap with the bytes it produces
+                for i in start..start + length {
+                    out.push(out[i]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//!
+//! A reader for the class files in jar files, which are zip archives.
+//!
+//! The central directory at the end of the archive lists the entries with the offsets of their
+//! local headers, which are followed by the contents. Entries are stored or compressed with DEFLATE,
+//! zip64 archives are not supported
+//!
+
+use crate::inflate::inflate;
+use std::io;
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const CENTRAL_DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// The class files in the jar with their names, like `java/lang/Object.class`.
+/// `module-info.class` and the versioned classes in `META-INF` are skipped
+pub fn class_files(data: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let u2 = |at: usize| -> io::Result<u16> {
+        data.get(at..at + 2)
+            .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or_else(|| invalid("Truncated jar file"))
+    };
+    let u4 = |at: usize| -> io::Result<u32> {
+        data.get(at..at + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or_else(|| invalid("Truncated jar file"))
+    };
+
+    // the end of the central directory is followed by a comment of up to 64K
+    let end = (0..=data.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE))
+        .rev()
+        .take(END_OF_CENTRAL_DIRECTORY_SIZE + u16::MAX as usize)
+        .find(|&at| u4(at).is_ok_and(|signature| signature == END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(|| invalid("Not a jar file"))?;
+    let entries = u2(end + 10)?;
+    let mut at = u4(end + 16)? as usize;
+
+    let mut class_files = Vec::new();
+    for _ in 0..entries {
+        if u4(at)? != CENTRAL_DIRECTORY_ENTRY {
+            return Err(invalid("Invalid central directory entry"));
+        }
+        let method = u2(at + 10)?;
+        let crc = u4(at + 16)?;
+        let compressed_size = u4(at + 20)? as usize;
+        let size = u4(at + 24)? as usize;
+        let name_length = u2(at + 28)? as usize;
+        let extra_length = u2(at + 30)? as usize;
+        let comment_length = u2(at + 32)? as usize;
+        let header = u4(at + 42)? as usize;
+        let name = data
+            .get(at + 46..at + 46 + name_length)
+            .ok_or_else(|| invalid("Truncated jar file"))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        at += 46 + name_length + extra_length + comment_length;
+
+        if !name.ends_with(".class")
+            || name.ends_with("module-info.class")
+            || name.starts_with("META-INF/")
+        {
+            continue;
+        }
+        if u4(header)? != LOCAL_HEADER {
+            return Err(invalid("Invalid local header"));
+        }
+        // the local header has its own lengths of the name and the extra field
+        let start = header + 30 + u2(header + 26)? as usize + u2(header + 28)? as usize;
+        let compressed = data
+            .get(start..start + compressed_size)
+            .ok_or_else(|| invalid("Truncated jar file"))?;
+        let contents = match method {
+            STORED => compressed.to_vec(),
+            DEFLATED => inflate(compressed, size)
+                .ok_or_else(|| invalid(&format!("Invalid compressed data in {}", name)))?,
+            _ => return Err(invalid(&format!("Unsupported compression of {}", name))),
+        };
+        if contents.len() != size || crc32(&contents) != crc {
+            return Err(invalid(&format!("Corrupt entry {}", name)));
+        }
+        class_files.push((name, contents));
+    }
+    Ok(class_files)
+}
+
+/// The CRC-32 checksum of zip archives
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
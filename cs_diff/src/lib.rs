@@ -0,0 +1,717 @@
+//!
+//! Compares two versions of a class file.
+//!
+//! Everything is compared by the names and constants the constant pool resolves to, not by the
+//! indices into it, so a class that was only recompiled with a different constant pool has no changes.
+//! The binary compatibility of two versions of a library, which are read from jar files, is checked
+//! on top of the same class files
+//!
+
+mod code;
+mod compat;
+mod inflate;
+mod jar;
+#[cfg(test)]
+mod test;
+
+pub use compat::{check, Incompatibility, IncompatibleChange};
+pub use jar::class_files;
+
+use cs_parser::{
+    u2, Annotation, AnnotationElementValue, AnnotationElementValueValue, AttributeInfo,
+    AttributeInfoInner, ClassAccessFlag, ClassFile, CpInfo, CpInfoInner, FieldAccessFlags,
+    FromPool, MethodAccessFlag,
+};
+use std::fmt::{Display, Formatter};
+
+/// The differences between two versions of a class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassDiff {
+    /// The name of the old class
+    pub old_name: String,
+    /// The name of the new class, the same as the old one unless the class was renamed
+    pub new_name: String,
+    pub changes: Vec<Change>,
+}
+
+/// A single difference between the two versions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The member only exists in the new class
+    Added(Member),
+    /// The member only exists in the old class
+    Removed(Member),
+    /// The member exists in both classes, but an aspect of it differs
+    Changed {
+        member: Member,
+        aspect: Aspect,
+        /// The aspect in the old class as text, empty if it does not have it
+        old: String,
+        /// The aspect in the new class as text, empty if it does not have it
+        new: String,
+    },
+}
+
+/// The class itself or one of its fields or methods, which are identified by name and descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Member {
+    Class,
+    Field { name: String, descriptor: String },
+    Method { name: String, descriptor: String },
+}
+
+/// A part of a member that is compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aspect {
+    /// The class file version, only on the class
+    Version,
+    AccessFlags,
+    /// Only on the class
+    SuperClass,
+    /// Only on the class
+    Interfaces,
+    /// The generic signature
+    Signature,
+    /// The annotations and on methods the parameter annotations, both runtime visible and invisible
+    Annotations,
+    /// Only on fields
+    ConstantValue,
+    /// The checked exceptions, only on methods
+    Exceptions,
+    /// Only on the methods of annotation types
+    AnnotationDefault,
+    /// The bytecode and exception handlers, one instruction or handler per line
+    Code,
+}
+
+/// Compares the two classes
+pub fn diff(old: &ClassFile, new: &ClassFile) -> ClassDiff {
+    let old_class = Described::class(old);
+    let new_class = Described::class(new);
+    let mut changes = Vec::new();
+    compare(
+        &mut changes,
+        Member::Class,
+        &old_class.aspects,
+        &new_class.aspects,
+    );
+    compare_members(
+        &mut changes,
+        old_class.fields,
+        new_class.fields,
+        |name, descriptor| Member::Field { name, descriptor },
+    );
+    compare_members(
+        &mut changes,
+        old_class.methods,
+        new_class.methods,
+        |name, descriptor| Member::Method { name, descriptor },
+    );
+
+    ClassDiff {
+        old_name: old_class.name,
+        new_name: new_class.name,
+        changes,
+    }
+}
+
+/// A class or member with its aspects as text
+struct Described {
+    name: String,
+    descriptor: String,
+    aspects: Vec<(Aspect, String)>,
+    fields: Vec<Described>,
+    methods: Vec<Described>,
+}
+
+impl Described {
+    fn class(class: &ClassFile) -> Self {
+        let cp = &class.constant_pool;
+        let interfaces: Vec<_> = class
+            .interfaces
+            .iter()
+            .map(|i| class_name(cp, *i))
+            .collect();
+        let aspects = vec![
+            (
+                Aspect::Version,
+                format!("{}.{}", class.major_version, class.minor_version),
+            ),
+            (Aspect::AccessFlags, flags(class.access_flags, &CLASS_FLAGS)),
+            (
+                Aspect::SuperClass,
+                class
+                    .super_class
+                    .maybe_get(cp)
+                    .map(|super_class| super_class.name_index.get(cp).to_string())
+                    .unwrap_or_default(),
+            ),
+            (Aspect::Interfaces, interfaces.join(", ")),
+            (Aspect::Signature, signature(cp, &class.attributes)),
+            (Aspect::Annotations, annotations(cp, &class.attributes)),
+        ];
+
+        let fields = class
+            .fields
+            .iter()
+            .map(|field| {
+                let constant_value = field
+                    .attributes
+                    .iter()
+                    .find_map(|attribute| match &attribute.inner {
+                        AttributeInfoInner::ConstantValue {
+                            constantvalue_index,
+                        } => Some(constant(cp, constantvalue_index.get(cp))),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+                Self::member(
+                    field.name_index.get(cp),
+                    field.descriptor_index.get(cp),
+                    vec![
+                        (Aspect::AccessFlags, flags(field.access_flags, &FIELD_FLAGS)),
+                        (Aspect::Signature, signature(cp, &field.attributes)),
+                        (Aspect::Annotations, annotations(cp, &field.attributes)),
+                        (Aspect::ConstantValue, constant_value),
+                    ],
+                )
+            })
+            .collect();
+
+        let methods = class
+            .methods
+            .iter()
+            .map(|method| {
+                let mut exceptions = String::new();
+                let mut annotation_default = String::new();
+                let mut code = String::new();
+                for attribute in &method.attributes {
+                    match &attribute.inner {
+                        AttributeInfoInner::Exceptions {
+                            exception_index_table,
+                        } => {
+                            let names: Vec<_> = exception_index_table
+                                .iter()
+                                .map(|&index| class_name(cp, index.into()))
+                                .collect();
+                            exceptions = names.join(", ");
+                        }
+                        AttributeInfoInner::AnnotationDefault { default_value } => {
+                            annotation_default = element_value(cp, default_value);
+                        }
+                        AttributeInfoInner::Code {
+                            code: bytecode,
+                            exception_table,
+                            ..
+                        } => code = code::listing(class, bytecode, exception_table).join("\n"),
+                        _ => {}
+                    }
+                }
+                Self::member(
+                    method.name_index.get(cp),
+                    method.descriptor_index.get(cp),
+                    vec![
+                        (
+                            Aspect::AccessFlags,
+                            flags(method.access_flags, &METHOD_FLAGS),
+                        ),
+                        (Aspect::Signature, signature(cp, &method.attributes)),
+                        (Aspect::Exceptions, exceptions),
+                        (Aspect::Annotations, annotations(cp, &method.attributes)),
+                        (Aspect::AnnotationDefault, annotation_default),
+                        (Aspect::Code, code),
+                    ],
+                )
+            })
+            .collect();
+
+        Self {
+            name: class_name(cp, class.this_class),
+            descriptor: String::new(),
+            aspects,
+            fields,
+            methods,
+        }
+    }
+
+    fn member(name: &str, descriptor: &str, aspects: Vec<(Aspect, String)>) -> Self {
+        Self {
+            name: name.to_string(),
+            descriptor: descriptor.to_string(),
+            aspects,
+            fields: Vec::new(),
+            methods: Vec::new(),
+        }
+    }
+}
+
+/// Adds the changes between the aspects of the same member
+fn compare(
+    changes: &mut Vec<Change>,
+    member: Member,
+    old: &[(Aspect, String)],
+    new: &[(Aspect, String)],
+) {
+    for ((aspect, old), (_, new)) in old.iter().zip(new) {
+        if old != new {
+            changes.push(Change::Changed {
+                member: member.clone(),
+                aspect: *aspect,
+                old: old.clone(),
+                new: new.clone(),
+            });
+        }
+    }
+}
+
+/// Adds the removed and changed members in the order of the old class, then the added ones in the
+/// order of the new class
+fn compare_members(
+    changes: &mut Vec<Change>,
+    old: Vec<Described>,
+    mut new: Vec<Described>,
+    member: impl Fn(String, String) -> Member,
+) {
+    for old in old {
+        let same = new
+            .iter()
+            .position(|new| new.name == old.name && new.descriptor == old.descriptor);
+        match same {
+            Some(index) => {
+                let new = new.remove(index);
+                compare(
+                    changes,
+                    member(old.name, old.descriptor),
+                    &old.aspects,
+                    &new.aspects,
+                );
+            }
+            None => changes.push(Change::Removed(member(old.name, old.descriptor))),
+        }
+    }
+    for new in new {
+        changes.push(Change::Added(member(new.name, new.descriptor)));
+    }
+}
+
+const CLASS_FLAGS: [(u2, &str); 9] = [
+    (ClassAccessFlag::Public as u2, "public"),
+    (ClassAccessFlag::Final as u2, "final"),
+    (ClassAccessFlag::Super as u2, "super"),
+    (ClassAccessFlag::Interface as u2, "interface"),
+    (ClassAccessFlag::Abstract as u2, "abstract"),
+    (ClassAccessFlag::Synthetic as u2, "synthetic"),
+    (ClassAccessFlag::Annotation as u2, "annotation"),
+    (ClassAccessFlag::Enum as u2, "enum"),
+    (ClassAccessFlag::MODULE as u2, "module"),
+];
+
+const FIELD_FLAGS: [(u2, &str); 9] = [
+    (FieldAccessFlags::PUBLIC as u2, "public"),
+    (FieldAccessFlags::PRIVATE as u2, "private"),
+    (FieldAccessFlags::PROTECTED as u2, "protected"),
+    (FieldAccessFlags::STATIC as u2, "static"),
+    (FieldAccessFlags::FINAL as u2, "final"),
+    (FieldAccessFlags::VOLATILE as u2, "volatile"),
+    (FieldAccessFlags::TRANSIENT as u2, "transient"),
+    (FieldAccessFlags::SYNTHETIC as u2, "synthetic"),
+    (FieldAccessFlags::ENUM as u2, "enum"),
+];
+
+const METHOD_FLAGS: [(u2, &str); 12] = [
+    (MethodAccessFlag::PUBLIC as u2, "public"),
+    (MethodAccessFlag::PRIVATE as u2, "private"),
+    (MethodAccessFlag::PROTECTED as u2, "protected"),
+    (MethodAccessFlag::STATIC as u2, "static"),
+    (MethodAccessFlag::FINAL as u2, "final"),
+    (MethodAccessFlag::SYNCHRONIZED as u2, "synchronized"),
+    (MethodAccessFlag::BRIDGE as u2, "bridge"),
+    (MethodAccessFlag::VARARGS as u2, "varargs"),
+    (MethodAccessFlag::NATIVE as u2, "native"),
+    (MethodAccessFlag::ABSTRACT as u2, "abstract"),
+    (MethodAccessFlag::STRICT as u2, "strict"),
+    (MethodAccessFlag::SYNTHETIC as u2, "synthetic"),
+];
+
+/// The names of the set flags, separated by spaces
+fn flags(access_flags: u2, names: &[(u2, &str)]) -> String {
+    let names: Vec<_> = names
+        .iter()
+        .filter(|(flag, _)| access_flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    names.join(" ")
+}
+
+fn class_name(cp: &[CpInfo], class: FromPool<cs_parser::cp_info::Class>) -> String {
+    class.get(cp).name_index.get(cp).to_string()
+}
+
+fn signature(cp: &[CpInfo], attributes: &[AttributeInfo]) -> String {
+    attributes
+        .iter()
+        .find_map(|attribute| match &attribute.inner {
+            AttributeInfoInner::Signature { signature_index } => {
+                Some(signature_index.get(cp).to_string())
+            }
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// The annotations in the order of the class file, separated by spaces.
+/// Parameter annotations are prefixed with the index of their parameter
+fn annotations(cp: &[CpInfo], attributes: &[AttributeInfo]) -> String {
+    let mut text = Vec::new();
+    for attribute in attributes {
+        match &attribute.inner {
+            AttributeInfoInner::RuntimeVisibleAnnotations { annotations }
+            | AttributeInfoInner::RuntimeInvisibleAnnotations { annotations } => {
+                text.extend(annotations.iter().map(|a| annotation(cp, a)));
+            }
+            AttributeInfoInner::RuntimeVisibleParameterAnnotations {
+                parameter_annotations,
+            }
+            | AttributeInfoInner::RuntimeInvisibleParameterAnnotations {
+                parameter_annotations,
+            } => {
+                for (i, parameter) in parameter_annotations.iter().enumerate() {
+                    text.extend(
+                        parameter
+                            .annotations
+                            .iter()
+                            .map(|a| format!("{}:{}", i, annotation(cp, a))),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    text.join(" ")
+}
+
+/// An annotation like in the source, but with the descriptor of its type
+fn annotation(cp: &[CpInfo], annotation: &Annotation) -> String {
+    let elements: Vec<_> = annotation
+        .element_value_pairs
+        .iter()
+        .map(|pair| {
+            format!(
+                "{}={}",
+                pair.element_name_index.get(cp),
+                element_value(cp, &pair.element_name_name)
+            )
+        })
+        .collect();
+    format!(
+        "@{}({})",
+        annotation.type_index.get(cp),
+        elements.join(", ")
+    )
+}
+
+fn element_value(cp: &[CpInfo], value: &AnnotationElementValue) -> String {
+    match &value.value {
+        AnnotationElementValueValue::ConstValueIndex { index } => {
+            match (value.tag, index.get(cp)) {
+                (b'Z', CpInfoInner::Integer(int)) => (int.bytes != 0).to_string(),
+                (b'C', CpInfoInner::Integer(int)) => {
+                    format!("{:?}", char::from_u32(int.bytes).unwrap_or('\u{FFFD}'))
+                }
+                (_, constant_value) => constant(cp, constant_value),
+            }
+        }
+        AnnotationElementValueValue::EnumConstValue {
+            type_name_index,
+            const_name_index,
+        } => format!("{}.{}", type_name_index.get(cp), const_name_index.get(cp)),
+        AnnotationElementValueValue::ClassInfoIndex { index } => {
+            format!("{}.class", index.get(cp))
+        }
+        AnnotationElementValueValue::AnnotationValue { annotation: nested } => {
+            annotation(cp, nested)
+        }
+        AnnotationElementValueValue::ArrayValue { values } => {
+            let values: Vec<_> = values.iter().map(|v| element_value(cp, v)).collect();
+            format!("{{{}}}", values.join(", "))
+        }
+    }
+}
+
+/// A constant pool entry as text, with all references to other entries resolved
+fn constant(cp: &[CpInfo], constant: &CpInfoInner) -> String {
+    let name_and_type = |index: FromPool<cs_parser::cp_info::NameAndType>| {
+        let name_and_type = index.get(cp);
+        format!(
+            "{}:{}",
+            name_and_type.name_index.get(cp),
+            name_and_type.descriptor_index.get(cp)
+        )
+    };
+    match constant {
+        CpInfoInner::Class(class) => class.name_index.get(cp).to_string(),
+        CpInfoInner::Fieldref(field) => format!(
+            "{}.{}",
+            class_name(cp, field.class_index),
+            name_and_type(field.name_and_type_index)
+        ),
+        CpInfoInner::MethodRef(method) => format!(
+            "{}.{}",
+            class_name(cp, method.class_index),
+            name_and_type(method.name_and_type_index)
+        ),
+        CpInfoInner::InterfaceMethodref(method) => format!(
+            "{}.{}",
+            class_name(cp, method.class_index),
+            name_and_type(method.name_and_type_index)
+        ),
+        CpInfoInner::String(string) => format!("{:?}", string.string_index.get(cp)),
+        CpInfoInner::Integer(int) => (int.bytes as i32).to_string(),
+        CpInfoInner::Float(float) => format!("{:?}f", f32::from_bits(float.bytes)),
+        CpInfoInner::Long(long) => {
+            format!(
+                "{}L",
+                ((long.high_bytes as u64) << 32 | long.low_bytes as u64) as i64
+            )
+        }
+        CpInfoInner::Double(double) => format!(
+            "{:?}",
+            f64::from_bits((double.high_bytes as u64) << 32 | double.low_bytes as u64)
+        ),
+        CpInfoInner::NameAndType(_) => unreachable!("not referenced by instructions or attributes"),
+        CpInfoInner::Utf8(utf8) => utf8.bytes.clone(),
+        CpInfoInner::MethodHandle(handle) => {
+            let reference = FromPool::<CpInfoInner>::from(match handle.reference_index {
+                cs_parser::cp_info::MethodHandleIndex::Field(index) => index.inner(),
+                cs_parser::cp_info::MethodHandleIndex::Method(index) => index.inner(),
+                cs_parser::cp_info::MethodHandleIndex::Interface(index) => index.inner(),
+            });
+            let kind = match handle.reference_kind {
+                1 => "getField",
+                2 => "getStatic",
+                3 => "putField",
+                4 => "putStatic",
+                5 => "invokeVirtual",
+                6 => "invokeStatic",
+                7 => "invokeSpecial",
+                8 => "newInvokeSpecial",
+                9 => "invokeInterface",
+                _ => "<invalid>",
+            };
+            format!("{} {}", kind, self::constant(cp, reference.get(cp)))
+        }
+        CpInfoInner::MethodType(method_type) => method_type.descriptor_index.get(cp).to_string(),
+        CpInfoInner::Dynamic(dynamic) => name_and_type(dynamic.name_and_type_index),
+        CpInfoInner::InvokeDynamic(dynamic) => name_and_type(dynamic.name_and_type_index),
+        CpInfoInner::Module(module) => module.name_index.get(cp).to_string(),
+        CpInfoInner::Package(package) => package.name_index.get(cp).to_string(),
+    }
+}
+
+impl ClassDiff {
+    /// Whether the two versions are the same
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.old_name == self.new_name
+    }
+
+    /// The diff as a JSON object with the names of the classes and an array of the changes
+    pub fn to_json(&self) -> String {
+        let changes: Vec<_> =
+            self.changes
+                .iter()
+                .map(|change| {
+                    let (kind, member) = match change {
+                        Change::Added(member) => ("added", member),
+                        Change::Removed(member) => ("removed", member),
+                        Change::Changed { member, .. } => ("changed", member),
+                    };
+                    let mut json = format!("{{\"change\":\"{}\",", kind);
+                    match member {
+                        Member::Class => json.push_str("\"member\":\"class\""),
+                        Member::Field { name, descriptor }
+                        | Member::Method { name, descriptor } => json.push_str(&format!(
+                            "\"member\":\"{}\",\"name\":{},\"descriptor\":{}",
+                            if let Member::Field { .. } = member {
+                                "field"
+                            } else {
+                                "method"
+                            },
+                            json_string(name),
+                            json_string(descriptor)
+                        )),
+                    }
+                    if let Change::Changed {
+                        aspect, old, new, ..
+                    } = change
+                    {
+                        json.push_str(&format!(
+                            ",\"aspect\":\"{}\",\"old\":{},\"new\":{}",
+                            aspect.key(),
+                            json_string(old),
+                            json_string(new)
+                        ));
+                    }
+                    json.push('}');
+                    json
+                })
+                .collect();
+        format!(
+            "{{\"old\":{},\"new\":{},\"changes\":[{}]}}",
+            json_string(&self.old_name),
+            json_string(&self.new_name),
+            changes.join(",")
+        )
+    }
+}
+
+fn json_string(string: &str) -> String {
+    let mut json = String::with_capacity(string.len() + 2);
+    json.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl Aspect {
+    /// The name of the aspect in the JSON output
+    pub fn key(self) -> &'static str {
+        match self {
+            Aspect::Version => "version",
+            Aspect::AccessFlags => "access_flags",
+            Aspect::SuperClass => "super_class",
+            Aspect::Interfaces => "interfaces",
+            Aspect::Signature => "signature",
+            Aspect::Annotations => "annotations",
+            Aspect::ConstantValue => "constant_value",
+            Aspect::Exceptions => "exceptions",
+            Aspect::AnnotationDefault => "annotation_default",
+            Aspect::Code => "code",
+        }
+    }
+}
+
+impl Display for Aspect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.key().replace('_', " "))
+    }
+}
+
+impl Display for Member {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Member::Class => write!(f, "class"),
+            Member::Field { name, descriptor } => write!(f, "field {} {}", name, descriptor),
+            Member::Method { name, descriptor } => write!(f, "method {}{}", name, descriptor),
+        }
+    }
+}
+
+/// The report for humans. Changed code is shown as the instructions that were removed and added
+impl Display for ClassDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.old_name == self.new_name {
+            writeln!(f, "class {}", self.old_name)?;
+        } else {
+            writeln!(f, "class {} -> {}", self.old_name, self.new_name)?;
+        }
+        if self.changes.is_empty() {
+            writeln!(f, "  no changes")?;
+        }
+        for change in &self.changes {
+            match change {
+                Change::Added(member) => writeln!(f, "  + {}", member)?,
+                Change::Removed(member) => writeln!(f, "  - {}", member)?,
+                Change::Changed {
+                    member,
+                    aspect: Aspect::Code,
+                    old,
+                    new,
+                } => {
+                    writeln!(f, "  ~ {}: code", member)?;
+                    let old: Vec<_> = old.lines().collect();
+                    let new: Vec<_> = new.lines().collect();
+                    for (sign, line) in line_diff(&old, &new) {
+                        writeln!(f, "      {} {}", sign, line)?;
+                    }
+                }
+                Change::Changed {
+                    member,
+                    aspect,
+                    old,
+                    new,
+                } => writeln!(
+                    f,
+                    "  ~ {}: {} {} -> {}",
+                    member,
+                    aspect,
+                    or_none(old),
+                    or_none(new)
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn or_none(text: &str) -> &str {
+    if text.is_empty() {
+        "<none>"
+    } else {
+        text
+    }
+}
+
+/// The removed and added lines between the two texts, from their longest common subsequence.
+/// If the changed part is too large for that, all of it is reported as replaced
+fn line_diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(char, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    let mut lines = Vec::new();
+    if old.len().saturating_mul(new.len()) > 4_000_000 {
+        lines.extend(old.iter().map(|line| ('-', *line)));
+        lines.extend(new.iter().map(|line| ('+', *line)));
+        return lines;
+    }
+
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
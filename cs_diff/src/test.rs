@@ -0,0 +1,234 @@
+use crate::inflate::inflate;
+use crate::{check, class_files, code, diff, Aspect, Change, ClassDiff, Member};
+use cs_parser::{parse_class_file, AttributeInfoInner, ClassFile};
+
+fn old() -> ClassFile {
+    parse_class_file(include_bytes!("../testdata/old/Api.class")).unwrap()
+}
+
+fn new() -> ClassFile {
+    parse_class_file(include_bytes!("../testdata/new/Api.class")).unwrap()
+}
+
+#[test]
+fn same_class() {
+    let diff = diff(&old(), &old());
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "class Api\n  no changes\n");
+}
+
+#[test]
+fn versions() {
+    let diff = diff(&old(), &new());
+    assert!(!diff.is_empty());
+    // `size` and `parse` are the same, even though the constant pool indices in their code differ
+    assert_eq!(
+        diff.to_string(),
+        "\
+class Api
+  ~ class: access flags public super -> public final super
+  ~ class: interfaces <none> -> java/lang/Comparable
+  ~ class: signature <none> -> Ljava/lang/Object;Ljava/lang/Comparable<LApi;>;
+  ~ field LIMIT I: constant value 10 -> 20
+  ~ field names Ljava/util/List;: signature Ljava/util/List<Ljava/lang/String;>; -> Ljava/util/List<Ljava/lang/Integer;>;
+  + field total J
+  ~ method <init>()V: code
+      + aload_0
+      + lconst_1
+      + putfield Api.total:J
+  ~ method greet(Ljava/lang/String;)Ljava/lang/String;: code
+      - ldc \"Hello \"
+      + ldc \"Hi \"
+  ~ method reset()V: exceptions <none> -> java/lang/IllegalStateException
+  ~ method reset()V: annotations @Ljava/lang/Deprecated;() -> <none>
+  - method old()V
+  + method twice(I)I
+  + method compareTo(LApi;)I
+  + method compareTo(Ljava/lang/Object;)I
+"
+    );
+}
+
+#[test]
+fn json() {
+    let diff = ClassDiff {
+        old_name: "Api".to_string(),
+        new_name: "Api".to_string(),
+        changes: vec![
+            Change::Removed(Member::Method {
+                name: "old".to_string(),
+                descriptor: "()V".to_string(),
+            }),
+            Change::Changed {
+                member: Member::Class,
+                aspect: Aspect::AccessFlags,
+                old: "public".to_string(),
+                new: "public final".to_string(),
+            },
+            Change::Changed {
+                member: Member::Field {
+                    name: "NAME".to_string(),
+                    descriptor: "Ljava/lang/String;".to_string(),
+                },
+                aspect: Aspect::ConstantValue,
+                old: "\"a\\b\"".to_string(),
+                new: String::new(),
+            },
+        ],
+    };
+    assert_eq!(
+        diff.to_json(),
+        r#"{"old":"Api","new":"Api","changes":[{"change":"removed","member":"method","name":"old","descriptor":"()V"},{"change":"changed","member":"class","aspect":"access_flags","old":"public","new":"public final"},{"change":"changed","member":"field","name":"NAME","descriptor":"Ljava/lang/String;","aspect":"constant_value","old":"\"a\\b\"","new":""}]}"#
+    );
+}
+
+#[test]
+fn code_listing() {
+    let class = old();
+    let cp = &class.constant_pool;
+    let parse = class
+        .methods
+        .iter()
+        .find(|method| method.name_index.get(cp) == "parse")
+        .unwrap();
+    let listing = parse
+        .attributes
+        .iter()
+        .find_map(|attribute| match &attribute.inner {
+            AttributeInfoInner::Code {
+                code,
+                exception_table,
+                ..
+            } => Some(code::listing(&class, code, exception_table)),
+            _ => None,
+        })
+        .unwrap();
+    // branch targets are the indices of the instructions
+    assert_eq!(
+        listing,
+        [
+            "iconst_0",
+            "istore_2",
+            "iconst_0",
+            "istore_3",
+            "iload_3",
+            "aload_1",
+            "invokevirtual java/lang/String.length:()I",
+            "if_icmpge L28",
+            "aload_1",
+            "iload_3",
+            "invokevirtual java/lang/String.charAt:(I)C",
+            "lookupswitch 48: L12, 49: L17 default: L24",
+            "iload_2",
+            "iconst_2",
+            "imul",
+            "istore_2",
+            "goto L26",
+            "iload_2",
+            "iconst_2",
+            "imul",
+            "iconst_1",
+            "iadd",
+            "istore_2",
+            "goto L26",
+            "iconst_m1",
+            "ireturn",
+            "iinc 3 1",
+            "goto L4",
+            "iload_2",
+            "ireturn",
+            "astore_2",
+            "bipush -2",
+            "ireturn",
+            "catch java/lang/NullPointerException L0..L25 -> L30",
+            "catch java/lang/NullPointerException L26..L29 -> L30",
+        ]
+    );
+}
+
+#[test]
+fn inflate_blocks() {
+    // a block with the fixed Huffman codes and a block that is only stored
+    let fixed = [203, 72, 205, 201, 201, 87, 200, 64, 39, 1];
+    assert_eq!(inflate(&fixed, 23).unwrap(), b"hello hello hello hello");
+    assert_eq!(
+        inflate(&[1, 3, 0, 252, 255, 97, 98, 99], 3).unwrap(),
+        b"abc"
+    );
+    assert_eq!(inflate(&fixed[..5], 23), None);
+    // the size of the entry is only trusted as a limit
+    assert_eq!(inflate(&fixed, usize::MAX).unwrap().len(), 23);
+    assert_eq!(inflate(&fixed, 22), None);
+    assert_eq!(inflate(&[1, 3, 0, 252, 255, 97, 98, 99], 2), None);
+    // the length of a stored block must match its complement
+    assert_eq!(inflate(&[1, 3, 0, 253, 255, 97, 98, 99], 3), None);
+}
+
+#[test]
+fn jar() {
+    // the entries of the old jar are compressed, the ones of the new jar stored
+    let old = class_files(include_bytes!("../testdata/compat/old.jar")).unwrap();
+    let names: Vec<_> = old.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "lib/Broken.class",
+            "lib/Circle.class",
+            "lib/Failure.class",
+            "lib/Gone.class",
+            "lib/Internal.class",
+            "lib/Shape.class",
+            "lib/Util.class",
+            "lib/Visitor.class"
+        ]
+    );
+    let new = class_files(include_bytes!("../testdata/compat/new.jar")).unwrap();
+    assert_eq!(new.len(), 7);
+    for (_, class) in old.iter().chain(&new) {
+        parse_class_file(class).unwrap();
+    }
+
+    assert!(class_files(b"not a jar").is_err());
+    let mut corrupt = include_bytes!("../testdata/compat/new.jar").to_vec();
+    let at = corrupt
+        .windows(4)
+        .position(|w| w == [0xCA, 0xFE, 0xBA, 0xBE])
+        .unwrap();
+    corrupt[at + 8] ^= 1;
+    assert!(class_files(&corrupt).is_err());
+}
+
+fn library(jar: &[u8]) -> Vec<ClassFile> {
+    class_files(jar)
+        .unwrap()
+        .iter()
+        .map(|(_, class)| parse_class_file(class).unwrap())
+        .collect()
+}
+
+#[test]
+fn compatibility() {
+    let old = library(include_bytes!("../testdata/compat/old.jar"));
+    let new = library(include_bytes!("../testdata/compat/new.jar"));
+    assert_eq!(check(&old, &old), []);
+
+    // removing `Internal`, moving `Util.log` to the new superclass and extending a subclass
+    // of the JDK are compatible
+    let incompatibilities: Vec<_> = check(&old, &new).iter().map(ToString::to_string).collect();
+    assert_eq!(
+        incompatibilities,
+        [
+            "lib/Broken: class is not a subtype of java/lang/Exception anymore",
+            "lib/Circle: class is not a subtype of lib/Shape anymore",
+            "lib/Gone: class was removed",
+            "lib/Shape: field count I is not static anymore",
+            "lib/Shape: field name Ljava/lang/String; is private instead of protected",
+            "lib/Shape: method draw()V is final now",
+            "lib/Shape: method resize(I)V changed its descriptor to (J)V",
+            "lib/Shape: method sides()I was removed",
+            "lib/Util: class is final now",
+            "lib/Util: method helper()V is not static anymore",
+            "lib/Visitor: method leave(Llib/Shape;)V was added to the interface without a default",
+        ]
+    );
+}
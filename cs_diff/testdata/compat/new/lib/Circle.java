@@ -0,0 +1,8 @@
+package lib;
+
+public class Circle implements Comparable<Circle> {
+    @Override
+    public int compareTo(Circle other) {
+        return 0;
+    }
+}
@@ -0,0 +1,5 @@
+package lib;
+
+// still a subtype of `Exception`, which the library does not contain
+public class Failure extends RuntimeException {
+}
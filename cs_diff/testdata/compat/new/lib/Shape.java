@@ -0,0 +1,20 @@
+package lib;
+
+public class Shape {
+    public int count;
+    private String name;
+
+    public double area() {
+        return 0;
+    }
+
+    public final void draw() {
+    }
+
+    public void resize(long factor) {
+    }
+
+    public String describe() {
+        return name;
+    }
+}
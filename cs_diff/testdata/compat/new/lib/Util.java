@@ -0,0 +1,7 @@
+package lib;
+
+// `log` moved to the superclass, which is compatible
+public final class Util extends Base {
+    public void helper() {
+    }
+}
@@ -0,0 +1,10 @@
+package lib;
+
+public interface Visitor {
+    void visit(Shape shape);
+
+    void leave(Shape shape);
+
+    default void enter(Shape shape) {
+    }
+}
@@ -0,0 +1,8 @@
+package lib;
+
+public class Circle extends Shape implements Comparable<Circle> {
+    @Override
+    public int compareTo(Circle other) {
+        return 0;
+    }
+}
@@ -0,0 +1,5 @@
+package lib;
+
+// not part of the API, removing it is compatible
+class Internal {
+}
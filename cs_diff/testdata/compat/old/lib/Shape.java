@@ -0,0 +1,20 @@
+package lib;
+
+public class Shape {
+    public static int count;
+    protected String name;
+
+    public double area() {
+        return 0;
+    }
+
+    public void draw() {
+    }
+
+    public void resize(int factor) {
+    }
+
+    public int sides() {
+        return 0;
+    }
+}
@@ -0,0 +1,9 @@
+package lib;
+
+public class Util {
+    public static void helper() {
+    }
+
+    public void log(String message) {
+    }
+}
@@ -0,0 +1,5 @@
+package lib;
+
+public interface Visitor {
+    void visit(Shape shape);
+}
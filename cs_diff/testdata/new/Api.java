@@ -0,0 +1,47 @@
+import java.util.List;
+
+// The second version of `old/Api.java`. Its constant pool is laid out differently,
+// `size` is not reported as changed anyway
+public final class Api implements Comparable<Api> {
+    public static final int LIMIT = 20;
+    protected int count;
+    private List<Integer> names;
+    public long total = 1;
+
+    public static int twice(int value) {
+        return value * 2;
+    }
+
+    public int size() {
+        return count;
+    }
+
+    public String greet(String name) {
+        return "Hi " + name;
+    }
+
+    public void reset() throws IllegalStateException {
+        count = 0;
+    }
+
+    @Override
+    public int compareTo(Api other) {
+        return Integer.compare(count, other.count);
+    }
+
+    public int parse(String text) {
+        try {
+            int value = 0;
+            for (int i = 0; i < text.length(); i++) {
+                switch (text.charAt(i)) {
+                    case '0': value = value * 2; break;
+                    case '1': value = value * 2 + 1; break;
+                    default: return -1;
+                }
+            }
+            return value;
+        } catch (NullPointerException e) {
+            return -2;
+        }
+    }
+}
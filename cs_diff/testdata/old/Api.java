@@ -0,0 +1,40 @@
+import java.util.List;
+
+// The first version of a class, `new/Api.java` is the second one
+public class Api {
+    public static final int LIMIT = 10;
+    protected int count;
+    private List<String> names;
+
+    public int size() {
+        return count;
+    }
+
+    public String greet(String name) {
+        return "Hello " + name;
+    }
+
+    @Deprecated
+    public void reset() {
+        count = 0;
+    }
+
+    void old() {
+    }
+
+    public int parse(String text) {
+        try {
+            int value = 0;
+            for (int i = 0; i < text.length(); i++) {
+                switch (text.charAt(i)) {
+                    case '0': value = value * 2; break;
+                    case '1': value = value * 2 + 1; break;
+                    default: return -1;
+                }
+            }
+            return value;
+        } catch (NullPointerException e) {
+            return -2;
+        }
+    }
+}
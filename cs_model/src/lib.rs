@@ -4,6 +4,7 @@
 mod test;
 
 use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
 #[derive(Debug)]
@@ -50,8 +51,8 @@ pub enum FieldType {
 /// A method descriptor for the type of a method in a class
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct MethodDescriptor {
-    parameters: Vec<FieldType>,
-    return_: MethodType,
+    pub parameters: Vec<FieldType>,
+    pub return_: MethodType,
 }
 
 /// The type of a method
@@ -71,6 +72,14 @@ impl FromStr for FieldDescriptor {
 }
 
 impl FieldType {
+    /// The number of local variable or operand stack slots a value of this type takes
+    pub fn slots(&self) -> u16 {
+        match self {
+            Self::Long | Self::Double => 2,
+            _ => 1,
+        }
+    }
+
     /// Consumes as much chars as needed from the char iterator and tries to parse itself
     pub fn from_char_iter<I>(chars: &mut I) -> Result<Self, ParseErr>
     where
@@ -111,6 +120,51 @@ impl FieldType {
     }
 }
 
+/// Formats the type as a descriptor, for example `[Ljava/lang/String;`
+impl Display for FieldType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Byte => write!(f, "B"),
+            Self::Char => write!(f, "C"),
+            Self::Double => write!(f, "D"),
+            Self::Float => write!(f, "F"),
+            Self::Int => write!(f, "I"),
+            Self::Long => write!(f, "J"),
+            Self::Object(name) => write!(f, "L{};", name),
+            Self::Short => write!(f, "S"),
+            Self::Boolean => write!(f, "Z"),
+            Self::Array(component) => write!(f, "[{}", component),
+        }
+    }
+}
+
+impl Display for MethodType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Some(ty) => write!(f, "{}", ty),
+            Self::Void => write!(f, "V"),
+        }
+    }
+}
+
+/// Formats the descriptor as it appears in a class file, for example `(I[J)V`
+impl Display for MethodDescriptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(")?;
+        for parameter in &self.parameters {
+            write!(f, "{}", parameter)?;
+        }
+        write!(f, "){}", self.return_)
+    }
+}
+
+impl MethodDescriptor {
+    /// The number of local variable slots the parameters take, without `this`
+    pub fn parameter_slots(&self) -> u16 {
+        self.parameters.iter().map(FieldType::slots).sum()
+    }
+}
+
 impl FromStr for MethodDescriptor {
     type Err = ParseErr;
 
@@ -55,7 +55,7 @@ fn field_descriptor() {
 
 #[test]
 fn method_descriptor() {
-    let descriptors = vec![
+    let descriptors = [
         MethodDescriptor::from_str("()V").unwrap(),
         MethodDescriptor::from_str("(B)V").unwrap(),
         MethodDescriptor::from_str("([ZZ)Ljava/lang/Object;").unwrap(),
@@ -123,4 +123,22 @@ fn method_descriptor() {
         .iter()
         .zip(expected_descriptors.iter())
         .for_each(|(a, b)| assert_eq!(a, b));
+
+    let slots = descriptors
+        .iter()
+        .map(MethodDescriptor::parameter_slots)
+        .collect::<Vec<_>>();
+    assert_eq!(slots, [0, 1, 2, 4, 10, 0]);
+}
+
+#[test]
+fn format_descriptors() {
+    for descriptor in ["B", "[[Z", "Ljava/lang/String;", "[[[Ljava/lang/Object;"] {
+        let field = FieldDescriptor::from_str(descriptor).unwrap();
+        assert_eq!(field.0.to_string(), descriptor);
+    }
+    for descriptor in ["()V", "(I[JLjava/lang/String;)[D", "(DJ)Ljava/lang/Object;"] {
+        let method = MethodDescriptor::from_str(descriptor).unwrap();
+        assert_eq!(method.to_string(), descriptor);
+    }
 }
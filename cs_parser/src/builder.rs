@@ -0,0 +1,270 @@
+//!
+//! Building a constant pool for generated or modified classes.
+//!
+//! Equal entries are only added once, so asking for the same constant again returns the index
+//! of the existing entry. A builder that is seeded with the pool of a parsed class keeps all of
+//! its indices and only appends new entries
+//!
+
+use crate::cp_info::{self, MethodHandleIndex};
+use crate::{u1, u2, CpInfo, CpInfoInner, FromPool};
+use std::collections::HashMap;
+
+/// The largest index into a constant pool, its count is a `u2` that is one more than the entries
+const MAX_INDEX: usize = u2::MAX as usize - 1;
+
+/// A constant pool that only adds the entries which are not in it yet
+#[derive(Debug, Clone, Default)]
+pub struct ConstantPoolBuilder {
+    pool: Vec<CpInfo>,
+    /// The index of every entry, the first one if the seeded pool has duplicates
+    indices: HashMap<CpInfoInner, u2>,
+}
+
+impl ConstantPoolBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A builder that starts with the entries of an existing pool, like `ClassFile::constant_pool`
+    pub fn from_pool(pool: Vec<CpInfo>) -> Self {
+        let mut indices = HashMap::new();
+        let mut index = 0;
+        while index < pool.len() {
+            let inner = &pool[index].inner;
+            indices.entry(inner.clone()).or_insert(index as u2 + 1);
+            index += width(inner);
+        }
+        Self { pool, indices }
+    }
+
+    /// The entries so far, to resolve the returned handles with
+    pub fn pool(&self) -> &[CpInfo] {
+        &self.pool
+    }
+
+    /// The finished pool, 8 byte constants take up two entries like in a parsed `ClassFile`
+    pub fn build(self) -> Vec<CpInfo> {
+        self.pool
+    }
+
+    /// Adds any entry, its references to other entries must already be in the pool
+    ///
+    /// # Panics
+    /// Panics if the pool is full
+    pub fn entry(&mut self, inner: CpInfoInner) -> FromPool<CpInfoInner> {
+        if let Some(&index) = self.indices.get(&inner) {
+            return index.into();
+        }
+        let width = width(&inner);
+        assert!(
+            self.pool.len() + width <= MAX_INDEX,
+            "The constant pool is full"
+        );
+        let index = self.pool.len() as u2 + 1;
+        let info = CpInfo {
+            tag: tag(&inner),
+            inner: inner.clone(),
+        };
+        // the second entry of 8 byte constants can not be used, see `parse_constant_pool`
+        if width == 2 {
+            self.pool.push(info.clone());
+        }
+        self.pool.push(info);
+        self.indices.insert(inner, index);
+        index.into()
+    }
+
+    pub fn utf8(&mut self, string: &str) -> FromPool<cp_info::Utf8> {
+        self.typed(CpInfoInner::Utf8(cp_info::Utf8 {
+            bytes: string.to_string(),
+        }))
+    }
+
+    /// A class by its binary name, like `java/lang/Object`, or the descriptor of an array type
+    pub fn class(&mut self, name: &str) -> FromPool<cp_info::Class> {
+        let name_index = self.utf8(name);
+        self.typed(CpInfoInner::Class(cp_info::Class { name_index }))
+    }
+
+    pub fn string(&mut self, string: &str) -> FromPool<cp_info::String> {
+        let string_index = self.utf8(string);
+        self.typed(CpInfoInner::String(cp_info::String { string_index }))
+    }
+
+    pub fn integer(&mut self, value: i32) -> FromPool<cp_info::Integer> {
+        self.typed(CpInfoInner::Integer(cp_info::Integer {
+            bytes: value as u32,
+        }))
+    }
+
+    pub fn float(&mut self, value: f32) -> FromPool<cp_info::Float> {
+        self.typed(CpInfoInner::Float(cp_info::Float {
+            bytes: value.to_bits(),
+        }))
+    }
+
+    pub fn long(&mut self, value: i64) -> FromPool<cp_info::Long> {
+        self.typed(CpInfoInner::Long(cp_info::Long {
+            high_bytes: (value >> 32) as u32,
+            low_bytes: value as u32,
+        }))
+    }
+
+    pub fn double(&mut self, value: f64) -> FromPool<cp_info::Double> {
+        let bits = value.to_bits();
+        self.typed(CpInfoInner::Double(cp_info::Double {
+            high_bytes: (bits >> 32) as u32,
+            low_bytes: bits as u32,
+        }))
+    }
+
+    pub fn name_and_type(
+        &mut self,
+        name: &str,
+        descriptor: &str,
+    ) -> FromPool<cp_info::NameAndType> {
+        let name_index = self.utf8(name);
+        let descriptor_index = self.utf8(descriptor);
+        self.typed(CpInfoInner::NameAndType(cp_info::NameAndType {
+            name_index,
+            descriptor_index,
+        }))
+    }
+
+    pub fn field_ref(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> FromPool<cp_info::Fieldref> {
+        let class_index = self.class(class);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.typed(CpInfoInner::Fieldref(cp_info::Fieldref {
+            class_index,
+            name_and_type_index,
+        }))
+    }
+
+    pub fn method_ref(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> FromPool<cp_info::MethodRef> {
+        let class_index = self.class(class);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.typed(CpInfoInner::MethodRef(cp_info::MethodRef {
+            class_index,
+            name_and_type_index,
+        }))
+    }
+
+    pub fn interface_method_ref(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> FromPool<cp_info::InterfaceMethodref> {
+        let class_index = self.class(class);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.typed(CpInfoInner::InterfaceMethodref(
+            cp_info::InterfaceMethodref {
+                class_index,
+                name_and_type_index,
+            },
+        ))
+    }
+
+    /// A method handle of the kind (1-9) to the field or method, which must match the kind
+    pub fn method_handle(
+        &mut self,
+        reference_kind: u1,
+        reference_index: MethodHandleIndex,
+    ) -> FromPool<cp_info::MethodHandle> {
+        self.typed(CpInfoInner::MethodHandle(cp_info::MethodHandle {
+            reference_kind,
+            reference_index,
+        }))
+    }
+
+    pub fn method_type(&mut self, descriptor: &str) -> FromPool<cp_info::MethodType> {
+        let descriptor_index = self.utf8(descriptor);
+        self.typed(CpInfoInner::MethodType(cp_info::MethodType {
+            descriptor_index,
+        }))
+    }
+
+    /// A dynamically computed constant, with an index into the `BootstrapMethods` attribute
+    pub fn dynamic(
+        &mut self,
+        bootstrap_method_attr_index: u2,
+        name: &str,
+        descriptor: &str,
+    ) -> FromPool<cp_info::Dynamic> {
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.typed(CpInfoInner::Dynamic(cp_info::Dynamic {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        }))
+    }
+
+    /// A call site for `invokedynamic`, with an index into the `BootstrapMethods` attribute
+    pub fn invoke_dynamic(
+        &mut self,
+        bootstrap_method_attr_index: u2,
+        name: &str,
+        descriptor: &str,
+    ) -> FromPool<cp_info::InvokeDynamic> {
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.typed(CpInfoInner::InvokeDynamic(cp_info::InvokeDynamic {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        }))
+    }
+
+    pub fn module(&mut self, name: &str) -> FromPool<cp_info::Module> {
+        let name_index = self.utf8(name);
+        self.typed(CpInfoInner::Module(cp_info::Module { name_index }))
+    }
+
+    pub fn package(&mut self, name: &str) -> FromPool<cp_info::Package> {
+        let name_index = self.utf8(name);
+        self.typed(CpInfoInner::Package(cp_info::Package { name_index }))
+    }
+
+    /// The handle of the entry with the type of its variant
+    fn typed<T>(&mut self, inner: CpInfoInner) -> FromPool<T> {
+        self.entry(inner).inner().into()
+    }
+}
+
+/// The number of entries the constant takes up
+fn width(inner: &CpInfoInner) -> usize {
+    match inner {
+        CpInfoInner::Long(_) | CpInfoInner::Double(_) => 2,
+        _ => 1,
+    }
+}
+
+fn tag(inner: &CpInfoInner) -> u1 {
+    match inner {
+        CpInfoInner::Utf8(_) => 1,
+        CpInfoInner::Integer(_) => 3,
+        CpInfoInner::Float(_) => 4,
+        CpInfoInner::Long(_) => 5,
+        CpInfoInner::Double(_) => 6,
+        CpInfoInner::Class(_) => 7,
+        CpInfoInner::String(_) => 8,
+        CpInfoInner::Fieldref(_) => 9,
+        CpInfoInner::MethodRef(_) => 10,
+        CpInfoInner::InterfaceMethodref(_) => 11,
+        CpInfoInner::NameAndType(_) => 12,
+        CpInfoInner::MethodHandle(_) => 15,
+        CpInfoInner::MethodType(_) => 16,
+        CpInfoInner::Dynamic(_) => 17,
+        CpInfoInner::InvokeDynamic(_) => 18,
+        CpInfoInner::Module(_) => 19,
+        CpInfoInner::Package(_) => 20,
+    }
+}
@@ -1,8 +1,11 @@
+mod builder;
 mod model;
+pub mod opcode;
 #[cfg(test)]
 mod test;
 
 use crate::cp_info::ValidateCpInfo;
+pub use builder::ConstantPoolBuilder;
 pub use model::*;
 use std::fmt::{Display, Formatter};
 
@@ -103,6 +106,21 @@ fn parse_vec<T: Parse, S: Into<usize>>(len: S, data: &mut Data, cp: &[CpInfo]) -
     Ok(vec)
 }
 
+/// 8 byte constants take up two entries in the constant pool. The second entry is unusable,
+/// it is filled with a copy of the constant so that indices into the pool stay correct
+fn parse_constant_pool(len: u2, data: &mut Data, cp: &[CpInfo]) -> Result<Vec<CpInfo>> {
+    let len = len as usize;
+    let mut vec = Vec::with_capacity(len);
+    while vec.len() < len {
+        let info = CpInfo::parse(data, cp)?;
+        if matches!(info.inner, CpInfoInner::Long(_) | CpInfoInner::Double(_)) {
+            vec.push(info.clone());
+        }
+        vec.push(info);
+    }
+    Ok(vec)
+}
+
 macro_rules! parse_primitive {
     ($($value:ident),*) => {
         $(impl Parse for $value {
@@ -133,9 +151,7 @@ impl Parse for ClassFile {
         assert_eq!(magic, 0xCAFEBABE);
         let minor_version = data.u2()?;
         let major_version = data.u2()?;
-        dbg!("reached constant pool");
-        let constant_pool = parse_vec(data.u2()? - 1, data, cp)?; // the minus one is important
-        dbg!("after constant pool");
+        let constant_pool = parse_constant_pool(data.u2()? - 1, data, cp)?; // the minus one is important
         let cp = &constant_pool;
         let access_flags = data.u2()?;
         let this_class = data.cp(cp)?;
@@ -233,9 +249,7 @@ impl Parse for CpInfo {
             1 => Self {
                 tag,
                 inner: CpInfoInner::Utf8(cp_info::Utf8 {
-                    bytes: String::from_utf8(parse_vec(data.u2()?, data, cp)?).map_err(|err| {
-                        ParseErr(format!("Invalid utf8 in CpInfo::Utf8: {}", err))
-                    })?,
+                    bytes: decode_modified_utf8(&parse_vec::<u1, _>(data.u2()?, data, cp)?)?,
                 }),
             },
             15 => Self {
@@ -329,10 +343,10 @@ impl Parse for AttributeInfo {
 impl Parse for AttributeCodeException {
     fn parse(data: &mut Data, _cp: &[CpInfo]) -> Result<Self> {
         Ok(Self {
-            start_pc: data.last_u2()?,
-            end_pc: data.last_u2()?,
-            handler_pc: data.last_u2()?,
-            catch_type: data.last_u2()?,
+            start_pc: data.u2()?,
+            end_pc: data.u2()?,
+            handler_pc: data.u2()?,
+            catch_type: data.u2()?,
         })
     }
 }
@@ -363,7 +377,7 @@ impl Parse for StackMapFrame {
             252..=254 => Self::AppendFrame {
                 frame_type,
                 offset_delta: data.u2()?,
-                locals: parse_vec(data.last_u2()?, data, cp)?,
+                locals: parse_vec(frame_type - 251, data, cp)?,
             },
             255 => Self::FullFrame {
                 frame_type,
@@ -517,6 +531,39 @@ impl Parse for BootstrapMethod {
     }
 }
 
+/// Decodes the modified UTF-8 of `Utf8` constants, see JVMS §4.4.7. It differs from UTF-8 in
+/// encoding the null character with two bytes, and supplementary characters as surrogate pairs
+fn decode_modified_utf8(bytes: &[u1]) -> Result<String> {
+    if let Ok(string) = std::str::from_utf8(bytes) {
+        if !string.contains('\0') {
+            return Ok(string.to_string());
+        }
+    }
+
+    let invalid = || ParseErr("Invalid modified utf8 in CpInfo::Utf8".to_string());
+    let continuation = |byte: Option<&u1>| match byte {
+        Some(&byte) if byte & 0xC0 == 0x80 => Ok((byte & 0x3F) as u16),
+        _ => Err(invalid()),
+    };
+
+    let mut chars = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&byte) = iter.next() {
+        let char = match byte {
+            0x01..=0x7F => byte as u16,
+            0xC0..=0xDF => ((byte as u16 & 0x1F) << 6) | continuation(iter.next())?,
+            0xE0..=0xEF => {
+                let high = continuation(iter.next())?;
+                ((byte as u16 & 0x0F) << 12) | (high << 6) | continuation(iter.next())?
+            }
+            _ => return Err(invalid()),
+        };
+        chars.push(char);
+    }
+    // unpaired surrogates can not be represented in a Rust string
+    Ok(String::from_utf16_lossy(&chars))
+}
+
 fn resolve_attributes(class: &mut ClassFile) -> Result<()> {
     let pool = &class.constant_pool;
 
@@ -756,7 +803,14 @@ impl AttributeInfo {
                         bootstrap_methods: parse_vec(data.u2()?, data, cp)?,
                     },
                 },
-                name => return Err(ParseErr(format!("Invalid Attribute name: {}", name))),
+                // unknown attributes are ignored, see JVMS §4.7.1
+                _ => Self {
+                    attribute_name_index,
+                    attribute_length,
+                    inner: AttributeInfoInner::Unknown {
+                        attribute_content: data.data.to_vec(),
+                    },
+                },
             },
         );
 
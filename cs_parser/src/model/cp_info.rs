@@ -126,7 +126,7 @@ macro_rules! impl_try_from_cp {
                         return Err(ParseErr("Index must not be 0".to_string()));
                     }
 
-                    if info.len() == 0 {
+                    if info.is_empty() {
                         return Ok(());
                     }
                     // todo this here might actually be an empty constant pool depending on whether is is still parsing the constant pool
@@ -306,7 +306,7 @@ impl ValidateCpInfo for Utf8 {
         if index == 0 {
             return Err(ParseErr("Index must not be 0".to_string()));
         }
-        if info.len() == 0 {
+        if info.is_empty() {
             return Ok(());
         }
         match &info[index as usize - 1].inner {
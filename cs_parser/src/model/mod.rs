@@ -167,7 +167,7 @@ pub enum AttributeInfoInner {
         /// Must be a `Class` constant, the innermost enclosing class
         class_index: FromPool<cp_info::Class>,
         /// Must be zero or `NameAndType`
-        method_index: FromPool<cp_info::NameAndType>,
+        method_index: FromPool<Option<cp_info::NameAndType>>,
     },
     /// Can be on `ClassFile`, `FieldInfo`,or `MethodInfo`.
     /// Every generated class has to have this attribute or the `Synthetic` Accessor modifier
@@ -341,9 +341,9 @@ pub struct AttributeInnerClass {
     /// Must be a `Class`
     pub inner_class_info_index: FromPool<cp_info::Class>,
     /// Must be 0 or a `Class`
-    pub outer_class_info_index: FromPool<cp_info::Class>,
+    pub outer_class_info_index: FromPool<Option<cp_info::Class>>,
     /// Must be 0 or `Utf8`
-    pub inner_class_name_index: FromPool<cp_info::Utf8>,
+    pub inner_class_name_index: FromPool<Option<cp_info::Utf8>>,
     /// Must be a mask of `InnerClassAccessFlags`
     pub inner_class_access_flags: u2,
 }
@@ -469,6 +469,7 @@ pub struct ModuleRequires {
     /// * 0x0040 (ACC_STATIC_PHASE) - Indicates that this dependence is mandatory in the static phase, i.e., at compile time, but is optional in the dynamic phase, i.e., at run time.
     /// * 0x1000 (ACC_SYNTHETIC) - Indicates that this dependence was not explicitly or implicitly declared in the source of the module declaration.
     /// * 0x8000 (ACC_MANDATED) - Indicates that this dependence was implicitly declared in the source of the module declaration.
+    ///
     /// If the current module is not java.base, and the class file version number is 54.0 or above, then neither ACC_TRANSITIVE nor ACC_STATIC_PHASE may be set in requires_flags.
     pub requires_flags: u2,
     pub requires_version_index: FromPool<Option<cp_info::Utf8>>,
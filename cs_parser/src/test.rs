@@ -35,6 +35,144 @@ fn data_u4() {
     assert_eq!(data.last_u4().unwrap(), 0xff331100);
 }
 
+#[test]
+fn wide_constants() {
+    let class = include_bytes!("../testdata/Constants.class");
+    let parsed = parse_class_file(class).unwrap();
+    let cp = &parsed.constant_pool;
+    let values: Vec<_> = parsed
+        .fields
+        .iter()
+        .map(|field| match &field.attributes[0].inner {
+            AttributeInfoInner::ConstantValue {
+                constantvalue_index,
+            } => constantvalue_index.get(cp).clone(),
+            _ => panic!("Field without a constant value"),
+        })
+        .collect();
+    // the entries after a long or double keep their index
+    assert_eq!(
+        values,
+        [
+            CpInfoInner::Long(cp_info::Long {
+                high_bytes: 1 << 8,
+                low_bytes: 0,
+            }),
+            CpInfoInner::Double(cp_info::Double {
+                high_bytes: 0x3FE00000,
+                low_bytes: 0,
+            }),
+            CpInfoInner::String(cp_info::String {
+                string_index: 21.into(),
+            }),
+        ]
+    );
+    assert_eq!(parsed.constant_pool.len(), 25);
+}
+
+#[test]
+fn append_frame() {
+    let class = include_bytes!("../testdata/Loop.class");
+    let parsed = parse_class_file(class).unwrap();
+    let entries = match &parsed.methods[1].attributes[0].inner {
+        AttributeInfoInner::Code { attributes, .. } => attributes
+            .iter()
+            .find_map(|attribute| match &attribute.inner {
+                AttributeInfoInner::StackMapTable { entries, .. } => Some(entries),
+                _ => None,
+            })
+            .unwrap(),
+        _ => panic!("Method without code"),
+    };
+    // the loop adds `sum` and `i` to the locals, and they are removed after it
+    match &entries[0] {
+        StackMapFrame::AppendFrame {
+            frame_type: 253,
+            offset_delta: 4,
+            locals,
+        } => assert_eq!(locals.len(), 2),
+        frame => panic!("Unexpected frame {:?}", frame),
+    }
+    assert_eq!(
+        entries[1],
+        StackMapFrame::ChopFrame {
+            frame_type: 250,
+            offset_delta: 14,
+        }
+    );
+}
+
+#[test]
+fn exception_table() {
+    let class = include_bytes!("../testdata/Catch.class");
+    let parsed = parse_class_file(class).unwrap();
+    let cp = &parsed.constant_pool;
+    let exception_table = match &parsed.methods[1].attributes[0].inner {
+        AttributeInfoInner::Code {
+            exception_table, ..
+        } => exception_table,
+        _ => panic!("Method without code"),
+    };
+    assert_eq!(exception_table.len(), 1);
+    let handler = &exception_table[0];
+    assert_eq!(
+        (handler.start_pc, handler.end_pc, handler.handler_pc),
+        (0, 4, 5)
+    );
+    let catch_type = FromPool::<cp_info::Class>::from(handler.catch_type);
+    assert_eq!(
+        catch_type.get(cp).name_index.get(cp),
+        "java/lang/NumberFormatException"
+    );
+}
+
+#[test]
+fn unknown_attribute() {
+    // renaming the `SourceFile` attribute turns it into one that is not known
+    let mut class = include_bytes!("../testdata/Test.class").to_vec();
+    let at = class
+        .windows(10)
+        .position(|window| window == b"SourceFile")
+        .unwrap();
+    class[at..at + 10].copy_from_slice(b"SourceFila");
+    let parsed = parse_class_file(&class).unwrap();
+    assert_eq!(
+        parsed.attributes[0].inner,
+        AttributeInfoInner::Unknown {
+            attribute_content: vec![0x00, 0x0c],
+        }
+    );
+}
+
+#[test]
+fn anonymous_class() {
+    // the class has no name and is created in a field initializer instead of a method
+    let class = include_bytes!("../testdata/Anonymous$1.class");
+    let parsed = parse_class_file(class).unwrap();
+    let cp = &parsed.constant_pool;
+    let mut found = 0;
+    for attribute in &parsed.attributes {
+        match &attribute.inner {
+            AttributeInfoInner::EnclosingMethod {
+                class_index,
+                method_index,
+            } => {
+                assert_eq!(class_index.get(cp).name_index.get(cp), "Anonymous");
+                assert!(method_index.maybe_get(cp).is_none());
+                found += 1;
+            }
+            AttributeInfoInner::InnerClasses { classes } => {
+                assert_eq!(classes.len(), 1);
+                assert_eq!(classes[0].outer_class_info_index.maybe_get(cp), None);
+                assert_eq!(classes[0].inner_class_name_index.maybe_get(cp), None);
+                found += 1;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(found, 2);
+}
+
 #[test]
 fn parse_empty_class() {
     let class = include_bytes!("../testdata/Test.class");
@@ -143,3 +281,124 @@ fn more_complex_file() {
     let parsed = parse_class_file(class).unwrap();
     assert_eq!(parsed.magic, 0xCAFEBABE);
 }
+
+#[test]
+fn modified_utf8() {
+    assert_eq!(decode_modified_utf8(b"plain").unwrap(), "plain");
+    // the null character is encoded in two bytes
+    assert_eq!(decode_modified_utf8(&[b'a', 0xC0, 0x80]).unwrap(), "a\0");
+    assert_eq!(decode_modified_utf8("é€".as_bytes()).unwrap(), "é€");
+    // supplementary characters are encoded as the two surrogates, three bytes each
+    assert_eq!(
+        decode_modified_utf8(&[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]).unwrap(),
+        "😀"
+    );
+    assert!(decode_modified_utf8(&[0xE2, 0x82]).is_err());
+}
+
+#[test]
+fn build_constant_pool() {
+    let mut builder = ConstantPoolBuilder::new();
+    let method = builder.method_ref("java/io/PrintStream", "println", "(I)V");
+    let class = builder.class("java/io/PrintStream");
+    let name = builder.utf8("java/io/PrintStream");
+    let long = builder.long(-2);
+    let after_long = builder.double(0.5);
+    let string = builder.string("println");
+    assert_eq!(
+        builder.method_ref("java/io/PrintStream", "println", "(I)V"),
+        method
+    );
+    assert_eq!(builder.long(-2), long);
+
+    let pool = builder.build();
+    // Utf8, Class, Utf8, Utf8, NameAndType, MethodRef, then two entries for the long and the double
+    assert_eq!(
+        [
+            name.inner(),
+            class.inner(),
+            method.inner(),
+            long.inner(),
+            after_long.inner()
+        ],
+        [1, 2, 6, 7, 9]
+    );
+    assert_eq!(pool.len(), 11);
+    assert_eq!(pool[6], pool[7]);
+    assert_eq!(
+        class.get(&pool).name_index.get(&pool),
+        "java/io/PrintStream"
+    );
+    let name_and_type = method.get(&pool).name_and_type_index.get(&pool);
+    assert_eq!(name_and_type.descriptor_index.get(&pool), "(I)V");
+    assert_eq!(string.get(&pool).string_index, name_and_type.name_index);
+    assert_eq!(
+        long.get(&pool),
+        &cp_info::Long {
+            high_bytes: 0xFFFF_FFFF,
+            low_bytes: 0xFFFF_FFFE
+        }
+    );
+    assert_eq!(pool[6].tag, 5);
+}
+
+#[test]
+fn extend_constant_pool() {
+    let class = parse_class_file(include_bytes!("../testdata/Test2.class")).unwrap();
+    let original = class.constant_pool.clone();
+    let mut builder = ConstantPoolBuilder::from_pool(class.constant_pool);
+
+    // the entries of the class are reused, only the new ones are appended
+    let println = builder.method_ref("java/io/PrintStream", "println", "(I)V");
+    assert!((println.inner() as usize) <= original.len());
+    assert_eq!(builder.class("Test2"), class.this_class);
+    let long = builder.long(1);
+    let print = builder.method_ref("java/io/PrintStream", "print", "(J)V");
+    assert_eq!(long.inner() as usize, original.len() + 1);
+
+    let mut seeded = ConstantPoolBuilder::from_pool(builder.build());
+    assert_eq!(seeded.long(1), long);
+    assert_eq!(
+        seeded.method_ref("java/io/PrintStream", "print", "(J)V"),
+        print
+    );
+    let pool = seeded.build();
+    assert_eq!(pool[..original.len()], original[..]);
+    assert_eq!(
+        print
+            .get(&pool)
+            .class_index
+            .get(&pool)
+            .name_index
+            .get(&pool),
+        "java/io/PrintStream"
+    );
+}
+
+#[test]
+fn opcode_length() {
+    use opcode::*;
+
+    // bipush 1, istore_1, iinc 1 1, wide iinc, return
+    let code = [
+        BIPUSH, 1, ISTORE_1, IINC, 1, 1, WIDE, IINC, 0, 1, 0, 1, RETURN,
+    ];
+    assert_eq!(length(&code, 0), Some(2));
+    assert_eq!(length(&code, 2), Some(1));
+    assert_eq!(length(&code, 3), Some(3));
+    assert_eq!(length(&code, 6), Some(6));
+    assert_eq!(length(&code, 12), Some(1));
+    assert_eq!(length(&code, 13), None);
+    assert_eq!(length(&code[..5], 3), None);
+
+    // a tableswitch at pc 1 with two padding bytes and the cases 0 and 1
+    let mut code = vec![NOP, TABLESWITCH, 0, 0];
+    for operand in [8, 0, 1, 8, 8] {
+        code.extend_from_slice(&i32::to_be_bytes(operand));
+    }
+    assert_eq!(length(&code, 1), Some(23));
+    assert_eq!(length(&code[..code.len() - 1], 1), None);
+
+    assert_eq!(name(INVOKEVIRTUAL), "invokevirtual");
+    assert_eq!(name(0xcb), "<invalid>");
+}
@@ -0,0 +1,5 @@
+public class Anonymous {
+    Runnable task = new Runnable() {
+        public void run() {}
+    };
+}
@@ -0,0 +1,5 @@
+public class Constants {
+    static final long BIG = 1L << 40;
+    static final double HALF = 0.5;
+    static final String NAME = "constants";
+}
@@ -0,0 +1,82 @@
+//!
+//! Benchmarks of small Java programs, run with `cargo bench -p cs_vm`
+//!
+//! Every program in `benches/programs` runs on the bundled class library, interpreted from the
+//! bytes, interpreted from the decoded instructions and with the baseline compiler. The best time
+//! of several runs is reported
+//!
+
+use cs_vm::{InterpretMode, JitMode, Vm, VmOptions};
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// The programs and what they print
+const PROGRAMS: [(&str, &str); 5] = [
+    ("Fib", "196418"),
+    ("Sieve", "9592"),
+    ("Bodies", "53557"),
+    ("Shapes", "1641695067"),
+    ("Sort", "1412743"),
+];
+
+const RUNS: usize = 5;
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn run(program: &str, expected: &str, interpret: InterpretMode, jit: JitMode) -> Duration {
+    let mut vm = Vm::with_options(VmOptions {
+        interpret,
+        jit,
+        ..VmOptions::default()
+    });
+    vm.add_class_path(concat!(env!("CARGO_MANIFEST_DIR"), "/benches/programs"));
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+
+    let start = Instant::now();
+    vm.run_main(program, &[]).unwrap();
+    let elapsed = start.elapsed();
+    let printed = String::from_utf8(out.0.take()).unwrap();
+    assert_eq!(printed.trim_end(), expected, "{}", program);
+    elapsed
+}
+
+fn best(program: &str, expected: &str, interpret: InterpretMode, jit: JitMode) -> Duration {
+    (0..RUNS)
+        .map(|_| run(program, expected, interpret, jit))
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!(
+        "{:<10} {:>14} {:>14} {:>14}",
+        "program", "bytes", "decoded", "compiled"
+    );
+    for (program, expected) in PROGRAMS {
+        let bytes = best(program, expected, InterpretMode::Bytes, JitMode::Disabled);
+        let decoded = best(program, expected, InterpretMode::Decoded, JitMode::Disabled);
+        let compiled = best(program, expected, InterpretMode::Decoded, JitMode::Enabled);
+        println!(
+            "{:<10} {:>12.1}ms {:>12.1}ms {:>12.1}ms",
+            program,
+            bytes.as_secs_f64() * 1000.0,
+            decoded.as_secs_f64() * 1000.0,
+            compiled.as_secs_f64() * 1000.0
+        );
+    }
+}
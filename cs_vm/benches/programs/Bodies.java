@@ -0,0 +1,44 @@
+// Fields and double arithmetic
+public class Bodies {
+    double x;
+    double y;
+    double vx;
+    double vy;
+    final double mass;
+
+    Bodies(double x, double y, double mass) {
+        this.x = x;
+        this.y = y;
+        this.mass = mass;
+    }
+
+    public static void main(String[] args) {
+        Bodies[] bodies = new Bodies[8];
+        for (int i = 0; i < bodies.length; i++) {
+            bodies[i] = new Bodies(i * 1.5, i * -0.5 + 3, 1 + i % 3);
+        }
+        for (int step = 0; step < 3000; step++) {
+            for (Bodies a : bodies) {
+                for (Bodies b : bodies) {
+                    if (a == b) {
+                        continue;
+                    }
+                    double dx = b.x - a.x;
+                    double dy = b.y - a.y;
+                    double distance = dx * dx + dy * dy + 0.01;
+                    a.vx += dx * b.mass / distance * 0.001;
+                    a.vy += dy * b.mass / distance * 0.001;
+                }
+            }
+            for (Bodies body : bodies) {
+                body.x += body.vx;
+                body.y += body.vy;
+            }
+        }
+        long checksum = 0;
+        for (Bodies body : bodies) {
+            checksum += (long) (body.x * 1000) + (long) (body.y * 1000);
+        }
+        System.out.println(checksum);
+    }
+}
@@ -0,0 +1,48 @@
+// Virtual and interface calls, allocation
+public class Shapes {
+    interface Shape {
+        int area();
+    }
+
+    static abstract class Base implements Shape {
+        final int size;
+
+        Base(int size) {
+            this.size = size;
+        }
+
+        int scaled() {
+            return area() * 2;
+        }
+    }
+
+    static class Square extends Base {
+        Square(int size) {
+            super(size);
+        }
+
+        public int area() {
+            return size * size;
+        }
+    }
+
+    static class Triangle extends Base {
+        Triangle(int size) {
+            super(size);
+        }
+
+        public int area() {
+            return size * size / 2;
+        }
+    }
+
+    public static void main(String[] args) {
+        long total = 0;
+        for (int i = 0; i < 200000; i++) {
+            Base shape = i % 3 == 0 ? new Triangle(i % 100) : new Square(i % 100);
+            Shape interfaceShape = shape;
+            total += shape.scaled() + interfaceShape.area();
+        }
+        System.out.println(total);
+    }
+}
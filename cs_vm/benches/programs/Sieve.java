@@ -0,0 +1,19 @@
+// Loops over arrays
+public class Sieve {
+    public static void main(String[] args) {
+        int count = 0;
+        for (int round = 0; round < 20; round++) {
+            boolean[] composite = new boolean[100000];
+            count = 0;
+            for (int i = 2; i < composite.length; i++) {
+                if (!composite[i]) {
+                    count++;
+                    for (int j = i * 2; j < composite.length; j += i) {
+                        composite[j] = true;
+                    }
+                }
+            }
+        }
+        System.out.println(count);
+    }
+}
@@ -0,0 +1,52 @@
+// Quicksort on an int array, with static fields
+public class Sort {
+    static int seed = 42;
+    static int comparisons;
+
+    static int next() {
+        seed = seed * 1103515245 + 12345;
+        return (seed >>> 8) % 100000;
+    }
+
+    static void sort(int[] values, int low, int high) {
+        if (low >= high) {
+            return;
+        }
+        int pivot = values[(low + high) >>> 1];
+        int i = low;
+        int j = high;
+        while (i <= j) {
+            while (values[i] < pivot) {
+                i++;
+                comparisons++;
+            }
+            while (values[j] > pivot) {
+                j--;
+                comparisons++;
+            }
+            if (i <= j) {
+                int swap = values[i];
+                values[i] = values[j];
+                values[j] = swap;
+                i++;
+                j--;
+            }
+        }
+        sort(values, low, j);
+        sort(values, i, high);
+    }
+
+    public static void main(String[] args) {
+        int[] values = new int[100000];
+        for (int i = 0; i < values.length; i++) {
+            values[i] = next();
+        }
+        sort(values, 0, values.length - 1);
+        for (int i = 1; i < values.length; i++) {
+            if (values[i - 1] > values[i]) {
+                throw new IllegalStateException("not sorted");
+            }
+        }
+        System.out.println(comparisons);
+    }
+}
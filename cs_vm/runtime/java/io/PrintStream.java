@@ -0,0 +1,91 @@
+package java.io;
+
+/** Writes to the standard output or standard error stream of the VM */
+public class PrintStream {
+    private final int fd;
+
+    /** The file descriptor is 1 for standard output and 2 for standard error */
+    public PrintStream(int fd) {
+        this.fd = fd;
+    }
+
+    private static native void write(int fd, String s);
+
+    public void flush() {}
+
+    public void print(String s) {
+        write(fd, s == null ? "null" : s);
+    }
+
+    public void print(Object obj) {
+        print(String.valueOf(obj));
+    }
+
+    public void print(char[] s) {
+        print(new String(s));
+    }
+
+    public void print(boolean b) {
+        print(String.valueOf(b));
+    }
+
+    public void print(char c) {
+        print(String.valueOf(c));
+    }
+
+    public void print(int i) {
+        print(String.valueOf(i));
+    }
+
+    public void print(long l) {
+        print(String.valueOf(l));
+    }
+
+    public void print(float f) {
+        print(String.valueOf(f));
+    }
+
+    public void print(double d) {
+        print(String.valueOf(d));
+    }
+
+    public void println() {
+        print("\n");
+    }
+
+    public void println(String s) {
+        print(s + "\n");
+    }
+
+    public void println(Object obj) {
+        println(String.valueOf(obj));
+    }
+
+    public void println(char[] s) {
+        println(new String(s));
+    }
+
+    public void println(boolean b) {
+        println(String.valueOf(b));
+    }
+
+    public void println(char c) {
+        println(String.valueOf(c));
+    }
+
+    public void println(int i) {
+        println(String.valueOf(i));
+    }
+
+    public void println(long l) {
+        println(String.valueOf(l));
+    }
+
+    public void println(float f) {
+        println(String.valueOf(f));
+    }
+
+    public void println(double d) {
+        println(String.valueOf(d));
+    }
+}
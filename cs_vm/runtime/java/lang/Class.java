@@ -0,0 +1,22 @@
+package java.lang;
+
+/** The objects are created by the VM, which sets the name */
+public final class Class<T> {
+    private transient String name;
+
+    private Class() {}
+
+    public String getName() {
+        return name;
+    }
+
+    public native boolean isInterface();
+
+    public native boolean isArray();
+
+    public native boolean isPrimitive();
+
+    public String toString() {
+        return (isInterface() ? "interface " : isPrimitive() ? "" : "class ") + getName();
+    }
+}
@@ -0,0 +1,69 @@
+package java.lang;
+
+public final class Double extends Number implements Comparable<Double> {
+    private final double value;
+
+    public Double(double value) {
+        this.value = value;
+    }
+
+    public static Double valueOf(double d) {
+        return new Double(d);
+    }
+
+    public static native String toString(double d);
+
+    public static boolean isNaN(double v) {
+        return v != v;
+    }
+
+    public static int compare(double x, double y) {
+        if (x < y) {
+            return -1;
+        }
+        if (x > y) {
+            return 1;
+        }
+        // -0.0 is less than 0.0, NaN is greater than everything else and equal to itself
+        return Long.compare(doubleToLongBits(x), doubleToLongBits(y));
+    }
+
+    public static long doubleToLongBits(double value) {
+        return isNaN(value) ? 0x7ff8000000000000L : doubleToRawLongBits(value);
+    }
+
+    public static native long doubleToRawLongBits(double value);
+
+    public int intValue() {
+        return (int) value;
+    }
+
+    public long longValue() {
+        return (long) value;
+    }
+
+    public float floatValue() {
+        return (float) value;
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+
+    public int compareTo(Double other) {
+        return compare(value, other.value);
+    }
+
+    public boolean equals(Object obj) {
+        return obj instanceof Double && doubleToLongBits(value) == doubleToLongBits(((Double) obj).value);
+    }
+
+    public int hashCode() {
+        long bits = doubleToLongBits(value);
+        return (int) (bits ^ bits >>> 32);
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
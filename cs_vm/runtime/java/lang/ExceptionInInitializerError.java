@@ -0,0 +1,17 @@
+package java.lang;
+
+public class ExceptionInInitializerError extends LinkageError {
+    public ExceptionInInitializerError() {}
+
+    public ExceptionInInitializerError(Throwable thrown) {
+        initCause(thrown);
+    }
+
+    public ExceptionInInitializerError(String message) {
+        super(message);
+    }
+
+    public Throwable getException() {
+        return getCause();
+    }
+}
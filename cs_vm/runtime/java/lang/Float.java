@@ -0,0 +1,69 @@
+package java.lang;
+
+public final class Float extends Number implements Comparable<Float> {
+    private final float value;
+
+    public Float(float value) {
+        this.value = value;
+    }
+
+    public static Float valueOf(float f) {
+        return new Float(f);
+    }
+
+    public static native String toString(float f);
+
+    public static boolean isNaN(float v) {
+        return v != v;
+    }
+
+    public static int compare(float x, float y) {
+        if (x < y) {
+            return -1;
+        }
+        if (x > y) {
+            return 1;
+        }
+        // -0.0 is less than 0.0, NaN is greater than everything else and equal to itself
+        return Integer.compare(floatToIntBits(x), floatToIntBits(y));
+    }
+
+    public static int floatToIntBits(float value) {
+        return isNaN(value) ? 0x7fc00000 : floatToRawIntBits(value);
+    }
+
+    public static native int floatToRawIntBits(float value);
+
+    public int intValue() {
+        return (int) value;
+    }
+
+    public long longValue() {
+        return (long) value;
+    }
+
+    public float floatValue() {
+        return (float) value;
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+
+    public int compareTo(Float other) {
+        return compare(value, other.value);
+    }
+
+    public boolean equals(Object obj) {
+        return obj instanceof Float && floatToIntBits(value) == floatToIntBits(((Float) obj).value);
+    }
+
+    public int hashCode() {
+        int bits = floatToIntBits(value);
+        return bits;
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
@@ -0,0 +1,88 @@
+package java.lang;
+
+public final class Integer extends Number implements Comparable<Integer> {
+    public static final int MIN_VALUE = 0x80000000;
+    public static final int MAX_VALUE = 0x7fffffff;
+
+    /** The boxes of -128 to 127, which are always the same objects */
+    private static final Integer[] cache = new Integer[256];
+
+    static {
+        for (int i = 0; i < cache.length; i++) {
+            cache[i] = new Integer(i - 128);
+        }
+    }
+
+    private final int value;
+
+    public Integer(int value) {
+        this.value = value;
+    }
+
+    public static Integer valueOf(int i) {
+        if (i >= -128 && i <= 127) {
+            return cache[i + 128];
+        }
+        return new Integer(i);
+    }
+
+    public static Integer valueOf(String s) throws NumberFormatException {
+        return valueOf(parseInt(s));
+    }
+
+    public static int parseInt(String s) throws NumberFormatException {
+        return (int) Long.parse(s, MIN_VALUE, MAX_VALUE);
+    }
+
+    public static String toString(int i) {
+        return Long.toString(i);
+    }
+
+    public static String toHexString(int i) {
+        return Long.toUnsignedString(i & 0xffffffffL, 4);
+    }
+
+    public static String toBinaryString(int i) {
+        return Long.toUnsignedString(i & 0xffffffffL, 1);
+    }
+
+    public static int compare(int x, int y) {
+        return x < y ? -1 : x == y ? 0 : 1;
+    }
+
+    public static int hashCode(int value) {
+        return value;
+    }
+
+    public int intValue() {
+        return value;
+    }
+
+    public long longValue() {
+        return value;
+    }
+
+    public float floatValue() {
+        return value;
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+
+    public int compareTo(Integer other) {
+        return compare(value, other.value);
+    }
+
+    public boolean equals(Object obj) {
+        return obj instanceof Integer && value == ((Integer) obj).value;
+    }
+
+    public int hashCode() {
+        return value;
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
@@ -0,0 +1,132 @@
+package java.lang;
+
+public final class Long extends Number implements Comparable<Long> {
+    public static final long MIN_VALUE = 0x8000000000000000L;
+    public static final long MAX_VALUE = 0x7fffffffffffffffL;
+
+    private final long value;
+
+    public Long(long value) {
+        this.value = value;
+    }
+
+    public static Long valueOf(long l) {
+        return new Long(l);
+    }
+
+    public static Long valueOf(String s) throws NumberFormatException {
+        return valueOf(parseLong(s));
+    }
+
+    public static long parseLong(String s) throws NumberFormatException {
+        return parse(s, MIN_VALUE, MAX_VALUE);
+    }
+
+    /** Parses a decimal number in the range, used for all integer types */
+    static long parse(String s, long min, long max) throws NumberFormatException {
+        if (s == null) {
+            throw new NumberFormatException("Cannot parse null string: null");
+        }
+        int i = 0;
+        boolean negative = false;
+        if (s.length() > 0 && (s.charAt(0) == '-' || s.charAt(0) == '+')) {
+            negative = s.charAt(0) == '-';
+            i++;
+        }
+        if (i == s.length()) {
+            throw new NumberFormatException("For input string: \"" + s + "\"");
+        }
+        // accumulate negatively, the negative range is larger
+        long result = 0;
+        long limit = negative ? min : -max;
+        for (; i < s.length(); i++) {
+            int digit = s.charAt(i) - '0';
+            if (digit < 0 || digit > 9 || result < (limit + digit) / 10) {
+                throw new NumberFormatException("For input string: \"" + s + "\"");
+            }
+            result = result * 10 - digit;
+        }
+        return negative ? result : -result;
+    }
+
+    public static String toString(long l) {
+        if (l == 0) {
+            return "0";
+        }
+        char[] digits = new char[20];
+        int pos = digits.length;
+        boolean negative = l < 0;
+        // work with negative numbers, MIN_VALUE has no positive counterpart
+        if (!negative) {
+            l = -l;
+        }
+        while (l != 0) {
+            digits[--pos] = (char) ('0' - l % 10);
+            l /= 10;
+        }
+        if (negative) {
+            digits[--pos] = '-';
+        }
+        return new String(digits, pos, digits.length - pos);
+    }
+
+    public static String toHexString(long l) {
+        return toUnsignedString(l, 4);
+    }
+
+    public static String toBinaryString(long l) {
+        return toUnsignedString(l, 1);
+    }
+
+    /** Formats the number as unsigned with a radix of `1 << shift` */
+    static String toUnsignedString(long l, int shift) {
+        char[] digits = new char[64];
+        int pos = digits.length;
+        int mask = (1 << shift) - 1;
+        do {
+            digits[--pos] = "0123456789abcdef".charAt((int) l & mask);
+            l >>>= shift;
+        } while (l != 0);
+        return new String(digits, pos, digits.length - pos);
+    }
+
+    public static int compare(long x, long y) {
+        return x < y ? -1 : x == y ? 0 : 1;
+    }
+
+    public static int hashCode(long value) {
+        return (int) (value ^ value >>> 32);
+    }
+
+    public int intValue() {
+        return (int) value;
+    }
+
+    public long longValue() {
+        return value;
+    }
+
+    public float floatValue() {
+        return value;
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+
+    public int compareTo(Long other) {
+        return compare(value, other.value);
+    }
+
+    public boolean equals(Object obj) {
+        return obj instanceof Long && value == ((Long) obj).value;
+    }
+
+    public int hashCode() {
+        return hashCode(value);
+    }
+
+    public String toString() {
+        return toString(value);
+    }
+}
@@ -0,0 +1,228 @@
+package java.lang;
+
+/**
+ * The layout is the same as in the JDK: Latin-1 strings use one byte per char, all other
+ * strings use two bytes per char in little endian order
+ */
+public final class String implements CharSequence, Comparable<String> {
+    static final byte LATIN1 = 0;
+    static final byte UTF16 = 1;
+
+    private final byte[] value;
+    private final byte coder;
+    private int hash;
+
+    public String() {
+        value = new byte[0];
+        coder = LATIN1;
+    }
+
+    public String(String original) {
+        value = original.value;
+        coder = original.coder;
+        hash = original.hash;
+    }
+
+    public String(char[] value) {
+        this(value, 0, value.length);
+    }
+
+    public String(char[] value, int offset, int count) {
+        if (offset < 0 || count < 0 || offset > value.length - count) {
+            throw new StringIndexOutOfBoundsException(
+                    "offset " + offset + ", count " + count + ", length " + value.length);
+        }
+        byte coder = LATIN1;
+        for (int i = offset; i < offset + count; i++) {
+            if (value[i] > 0xFF) {
+                coder = UTF16;
+                break;
+            }
+        }
+        byte[] bytes = new byte[count << coder];
+        for (int i = 0; i < count; i++) {
+            char c = value[offset + i];
+            if (coder == LATIN1) {
+                bytes[i] = (byte) c;
+            } else {
+                bytes[2 * i] = (byte) c;
+                bytes[2 * i + 1] = (byte) (c >> 8);
+            }
+        }
+        this.value = bytes;
+        this.coder = coder;
+    }
+
+    public int length() {
+        return value.length >> coder;
+    }
+
+    public boolean isEmpty() {
+        return value.length == 0;
+    }
+
+    public char charAt(int index) {
+        if (index < 0 || index >= length()) {
+            throw new StringIndexOutOfBoundsException("index " + index + ", length " + length());
+        }
+        if (coder == LATIN1) {
+            return (char) (value[index] & 0xFF);
+        }
+        return (char) ((value[2 * index] & 0xFF) | (value[2 * index + 1] & 0xFF) << 8);
+    }
+
+    public char[] toCharArray() {
+        char[] chars = new char[length()];
+        for (int i = 0; i < chars.length; i++) {
+            chars[i] = charAt(i);
+        }
+        return chars;
+    }
+
+    public boolean equals(Object other) {
+        if (this == other) {
+            return true;
+        }
+        if (!(other instanceof String)) {
+            return false;
+        }
+        String string = (String) other;
+        // strings are always stored as Latin-1 if they can be
+        if (coder != string.coder || value.length != string.value.length) {
+            return false;
+        }
+        for (int i = 0; i < value.length; i++) {
+            if (value[i] != string.value[i]) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    public int hashCode() {
+        int h = hash;
+        if (h == 0) {
+            int length = length();
+            for (int i = 0; i < length; i++) {
+                h = 31 * h + charAt(i);
+            }
+            hash = h;
+        }
+        return h;
+    }
+
+    /** The string from the string pool with the same contents, literals are always in the pool */
+    public native String intern();
+
+    public int compareTo(String other) {
+        int length = Math.min(length(), other.length());
+        for (int i = 0; i < length; i++) {
+            char a = charAt(i);
+            char b = other.charAt(i);
+            if (a != b) {
+                return a - b;
+            }
+        }
+        return length() - other.length();
+    }
+
+    public boolean startsWith(String prefix) {
+        return regionMatches(0, prefix);
+    }
+
+    public boolean endsWith(String suffix) {
+        return regionMatches(length() - suffix.length(), suffix);
+    }
+
+    private boolean regionMatches(int offset, String other) {
+        if (offset < 0 || offset + other.length() > length()) {
+            return false;
+        }
+        for (int i = 0; i < other.length(); i++) {
+            if (charAt(offset + i) != other.charAt(i)) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    public int indexOf(int ch) {
+        for (int i = 0; i < length(); i++) {
+            if (charAt(i) == ch) {
+                return i;
+            }
+        }
+        return -1;
+    }
+
+    public int indexOf(String str) {
+        for (int i = 0; i + str.length() <= length(); i++) {
+            if (regionMatches(i, str)) {
+                return i;
+            }
+        }
+        return -1;
+    }
+
+    public boolean contains(CharSequence s) {
+        return indexOf(s.toString()) >= 0;
+    }
+
+    public String substring(int beginIndex) {
+        return substring(beginIndex, length());
+    }
+
+    public String substring(int beginIndex, int endIndex) {
+        if (beginIndex < 0 || beginIndex > endIndex || endIndex > length()) {
+            throw new StringIndexOutOfBoundsException(
+                    "begin " + beginIndex + ", end " + endIndex + ", length " + length());
+        }
+        if (beginIndex == 0 && endIndex == length()) {
+            return this;
+        }
+        return new String(toCharArray(), beginIndex, endIndex - beginIndex);
+    }
+
+    public String concat(String str) {
+        if (str.isEmpty()) {
+            return this;
+        }
+        return new StringBuilder(this).append(str).toString();
+    }
+
+    public String toString() {
+        return this;
+    }
+
+    public static String valueOf(Object obj) {
+        return obj == null ? "null" : obj.toString();
+    }
+
+    public static String valueOf(char[] data) {
+        return new String(data);
+    }
+
+    public static String valueOf(boolean b) {
+        return b ? "true" : "false";
+    }
+
+    public static String valueOf(char c) {
+        return new String(new char[] { c });
+    }
+
+    public static String valueOf(int i) {
+        return Integer.toString(i);
+    }
+
+    public static String valueOf(long l) {
+        return Long.toString(l);
+    }
+
+    public static String valueOf(float f) {
+        return Float.toString(f);
+    }
+
+    public static String valueOf(double d) {
+        return Double.toString(d);
+    }
+}
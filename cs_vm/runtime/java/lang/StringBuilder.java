@@ -0,0 +1,141 @@
+package java.lang;
+
+public final class StringBuilder implements CharSequence {
+    private char[] value;
+    private int count;
+
+    public StringBuilder() {
+        this(16);
+    }
+
+    public StringBuilder(int capacity) {
+        value = new char[capacity];
+    }
+
+    public StringBuilder(String str) {
+        this(str.length() + 16);
+        append(str);
+    }
+
+    private void ensureCapacity(int minimumCapacity) {
+        if (minimumCapacity > value.length) {
+            char[] grown = new char[Math.max(minimumCapacity, value.length * 2 + 2)];
+            System.arraycopy(value, 0, grown, 0, count);
+            value = grown;
+        }
+    }
+
+    public StringBuilder append(String str) {
+        if (str == null) {
+            str = "null";
+        }
+        int length = str.length();
+        ensureCapacity(count + length);
+        for (int i = 0; i < length; i++) {
+            value[count + i] = str.charAt(i);
+        }
+        count += length;
+        return this;
+    }
+
+    public StringBuilder append(Object obj) {
+        return append(String.valueOf(obj));
+    }
+
+    public StringBuilder append(CharSequence s) {
+        return append(String.valueOf(s));
+    }
+
+    public StringBuilder append(char[] str) {
+        ensureCapacity(count + str.length);
+        System.arraycopy(str, 0, value, count, str.length);
+        count += str.length;
+        return this;
+    }
+
+    public StringBuilder append(char c) {
+        ensureCapacity(count + 1);
+        value[count++] = c;
+        return this;
+    }
+
+    public StringBuilder append(boolean b) {
+        return append(String.valueOf(b));
+    }
+
+    public StringBuilder append(int i) {
+        return append(Integer.toString(i));
+    }
+
+    public StringBuilder append(long l) {
+        return append(Long.toString(l));
+    }
+
+    public StringBuilder append(float f) {
+        return append(Float.toString(f));
+    }
+
+    public StringBuilder append(double d) {
+        return append(Double.toString(d));
+    }
+
+    public StringBuilder insert(int offset, String str) {
+        if (offset < 0 || offset > count) {
+            throw new StringIndexOutOfBoundsException("offset " + offset + ", length " + count);
+        }
+        String tail = new String(value, offset, count - offset);
+        count = offset;
+        return append(str).append(tail);
+    }
+
+    public StringBuilder deleteCharAt(int index) {
+        checkIndex(index);
+        System.arraycopy(value, index + 1, value, index, count - index - 1);
+        count--;
+        return this;
+    }
+
+    public StringBuilder reverse() {
+        for (int i = 0; i < count / 2; i++) {
+            char c = value[i];
+            value[i] = value[count - 1 - i];
+            value[count - 1 - i] = c;
+        }
+        return this;
+    }
+
+    public int length() {
+        return count;
+    }
+
+    public void setLength(int newLength) {
+        if (newLength < 0) {
+            throw new StringIndexOutOfBoundsException(newLength);
+        }
+        ensureCapacity(newLength);
+        for (int i = count; i < newLength; i++) {
+            value[i] = '\0';
+        }
+        count = newLength;
+    }
+
+    public char charAt(int index) {
+        checkIndex(index);
+        return value[index];
+    }
+
+    public void setCharAt(int index, char c) {
+        checkIndex(index);
+        value[index] = c;
+    }
+
+    private void checkIndex(int index) {
+        if (index < 0 || index >= count) {
+            throw new StringIndexOutOfBoundsException("index " + index + ",length " + count);
+        }
+    }
+
+    public String toString() {
+        return new String(value, 0, count);
+    }
+}
@@ -0,0 +1,125 @@
+package java.lang;
+
+public class Thread implements Runnable {
+    private static int threadNumber;
+
+    private volatile String name;
+    private boolean daemon;
+    volatile boolean interrupted;
+    private Runnable target;
+    private boolean started;
+
+    public Thread() {
+        this(null, null);
+    }
+
+    public Thread(Runnable target) {
+        this(target, null);
+    }
+
+    public Thread(String name) {
+        this(null, name);
+    }
+
+    public Thread(Runnable target, String name) {
+        this.target = target;
+        this.name = name != null ? name : "Thread-" + nextThreadNumber();
+        this.daemon = currentThread().isDaemon();
+    }
+
+    private static synchronized int nextThreadNumber() {
+        return threadNumber++;
+    }
+
+    public static native Thread currentThread();
+
+    public static native void yield();
+
+    public static native void sleep(long millis) throws InterruptedException;
+
+    public static native boolean holdsLock(Object obj);
+
+    public synchronized void start() {
+        if (started) {
+            throw new IllegalThreadStateException();
+        }
+        started = true;
+        start0();
+    }
+
+    private native void start0();
+
+    public void run() {
+        if (target != null) {
+            target.run();
+        }
+    }
+
+    public void interrupt() {
+        interrupted = true;
+        interrupt0();
+    }
+
+    private native void interrupt0();
+
+    public static boolean interrupted() {
+        Thread current = currentThread();
+        boolean interrupted = current.interrupted;
+        current.interrupted = false;
+        return interrupted;
+    }
+
+    public boolean isInterrupted() {
+        return interrupted;
+    }
+
+    public final native boolean isAlive();
+
+    public final void setDaemon(boolean on) {
+        if (isAlive()) {
+            throw new IllegalThreadStateException();
+        }
+        daemon = on;
+    }
+
+    public final boolean isDaemon() {
+        return daemon;
+    }
+
+    public final String getName() {
+        return name;
+    }
+
+    public final synchronized void setName(String name) {
+        if (name == null) {
+            throw new NullPointerException("name cannot be null");
+        }
+        this.name = name;
+    }
+
+    public final void join() throws InterruptedException {
+        join(0);
+    }
+
+    public final synchronized void join(long millis) throws InterruptedException {
+        if (millis < 0) {
+            throw new IllegalArgumentException("timeout value is negative");
+        }
+        if (millis == 0) {
+            while (isAlive()) {
+                wait(0);
+            }
+            return;
+        }
+        long deadline = System.currentTimeMillis() + millis;
+        long remaining = millis;
+        while (isAlive() && remaining > 0) {
+            wait(remaining);
+            remaining = deadline - System.currentTimeMillis();
+        }
+    }
+
+    public String toString() {
+        return "Thread[" + name + "]";
+    }
+}
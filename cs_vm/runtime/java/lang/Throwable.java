@@ -0,0 +1,55 @@
+package java.lang;
+
+public class Throwable {
+    /** Filled by the VM when the throwable is created */
+    private transient Object backtrace;
+    private String detailMessage;
+    private Throwable cause;
+
+    public Throwable() {}
+
+    public Throwable(String message) {
+        detailMessage = message;
+    }
+
+    public Throwable(String message, Throwable cause) {
+        detailMessage = message;
+        this.cause = cause;
+    }
+
+    public Throwable(Throwable cause) {
+        detailMessage = cause == null ? null : cause.toString();
+        this.cause = cause;
+    }
+
+    public String getMessage() {
+        return detailMessage;
+    }
+
+    public Throwable getCause() {
+        return cause;
+    }
+
+    public String getLocalizedMessage() {
+        return getMessage();
+    }
+
+    public Throwable initCause(Throwable cause) {
+        if (this.cause != null) {
+            throw new IllegalStateException("Can't overwrite cause", this);
+        }
+        if (cause == this) {
+            throw new IllegalArgumentException("Self-causation not permitted", this);
+        }
+        this.cause = cause;
+        return this;
+    }
+
+    public native void printStackTrace();
+
+    public String toString() {
+        String message = getLocalizedMessage();
+        String name = getClass().getName();
+        return message != null ? name + ": " + message : name;
+    }
+}
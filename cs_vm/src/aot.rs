@@ -0,0 +1,641 @@
+//!
+//! Ahead-of-time compilation of programs into executables
+//!
+//! The program is analyzed in a closed world: only its class files and the bundled class library
+//! exist. Starting from `main`, rapid type analysis finds the methods that may run, a virtual call
+//! reaches the methods that the classes instantiated anywhere in the program select. What the
+//! analysis can not follow, like reflection, method handles, lambdas or natives without an
+//! implementation, is an error that lists every use.
+//!
+//! There is no linker, so the executable is a copy of the running executable with the runtime of
+//! this crate (interpreter, heap, GC and natives), followed by an `Image`: the class files the
+//! program can reach and the machine code of the methods the baseline JIT supports, compiled for
+//! the types of their parameters. `Image::embedded` finds it again when the executable starts.
+//! The other methods are interpreted
+//!
+
+use crate::class::{ClassId, ClassKind, MethodId};
+use crate::opcode::*;
+use crate::{Result, Vm, VmError};
+use cs_parser::{u1, u2, CpInfoInner};
+use std::collections::{BTreeSet, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The last bytes of an executable with an image, after the length of the image
+const MAGIC: &[u8; 8] = b"CSQIMAGE";
+
+/// Packages of the class library that inspect or invoke code by name
+const REFLECTION_PACKAGES: &[&str] = &["java/lang/reflect/", "java/lang/invoke/"];
+
+/// Methods that find classes and members by name
+const REFLECTION_METHODS: &[(&str, &str)] = &[
+    ("java/lang/Class", "forName"),
+    ("java/lang/Class", "newInstance"),
+    ("java/lang/Class", "getMethod"),
+    ("java/lang/Class", "getMethods"),
+    ("java/lang/Class", "getDeclaredMethod"),
+    ("java/lang/Class", "getDeclaredMethods"),
+    ("java/lang/Class", "getField"),
+    ("java/lang/Class", "getFields"),
+    ("java/lang/Class", "getDeclaredField"),
+    ("java/lang/Class", "getDeclaredFields"),
+    ("java/lang/Class", "getConstructor"),
+    ("java/lang/Class", "getConstructors"),
+    ("java/lang/Class", "getDeclaredConstructor"),
+    ("java/lang/Class", "getDeclaredConstructors"),
+    ("java/lang/ClassLoader", "loadClass"),
+];
+
+/// Classes whose objects the VM creates without `new`
+const VM_INSTANTIATED: &[&str] = &["java/lang/String", "java/lang/Class", "java/lang/Thread"];
+
+/// Methods the VM invokes on objects, like `run` of a started thread
+const VM_CALLS: &[(&str, &str, &str)] = &[
+    ("java/lang/Thread", "run", "()V"),
+    ("java/lang/Thread", "exit", "()V"),
+];
+
+/// The bootstrap method of string concatenation, the only `invokedynamic` the VM links itself
+const STRING_CONCAT_FACTORY: &str = "java/lang/invoke/StringConcatFactory";
+
+/// What a program can reach from its `main` method in a closed world
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reachability {
+    /// The classes that are loaded, without array and primitive classes
+    pub classes: BTreeSet<String>,
+    /// The methods that may be invoked, like `java/lang/Object.toString()Ljava/lang/String;`
+    pub methods: BTreeSet<String>,
+    /// What can not be compiled ahead of time, with the method that uses it
+    pub unsupported: BTreeSet<String>,
+}
+
+/// A program compiled ahead of time, see `Vm::run_image`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    /// The name of the class with the `main` method
+    pub main: String,
+    /// The class files of the program that it can reach
+    pub classes: Vec<Vec<u1>>,
+    pub methods: Vec<PrecompiledMethod>,
+}
+
+/// The machine code of a method, for the types of its parameters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrecompiledMethod {
+    pub class: String,
+    pub name: String,
+    pub descriptor: String,
+    pub machine_code: Vec<u1>,
+}
+
+/// Analyzes which classes and methods the program with the class files can reach from the
+/// `main` method of the class
+pub fn reachability(class_files: &[Vec<u1>], main: &str) -> Result<Reachability> {
+    let analysis = Analysis::run(class_files, main)?;
+    Ok(analysis.reachability())
+}
+
+impl Image {
+    /// Compiles the program with the class files, or returns `VmError::Unsupported` with
+    /// everything that keeps it from being compiled
+    pub fn compile(class_files: &[Vec<u1>], main: &str) -> Result<Image> {
+        let analysis = Analysis::run(class_files, main)?;
+        if !analysis.unsupported.is_empty() {
+            let unsupported = analysis.unsupported.into_iter().collect::<Vec<_>>();
+            return Err(VmError::Unsupported(format!(
+                "{} can not be compiled ahead of time:\n  {}",
+                main,
+                unsupported.join("\n  ")
+            )));
+        }
+
+        let vm = &analysis.vm;
+        let classes = vm
+            .classes
+            .iter()
+            .filter_map(|class| vm.class_files.get(&class.name).cloned())
+            .collect();
+        let methods = analysis
+            .methods
+            .iter()
+            .filter_map(|&id| {
+                let machine_code = vm.precompile(id)?;
+                let method = vm.method(id);
+                Some(PrecompiledMethod {
+                    class: vm.class(id.class).name.clone(),
+                    name: method.name.clone(),
+                    descriptor: method.descriptor.clone(),
+                    machine_code,
+                })
+            })
+            .collect();
+        Ok(Image {
+            main: main.to_string(),
+            classes,
+            methods,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u1> {
+        let mut bytes = Vec::new();
+        put(&mut bytes, self.main.as_bytes());
+        bytes.extend((self.classes.len() as u32).to_le_bytes());
+        for class in &self.classes {
+            put(&mut bytes, class);
+        }
+        bytes.extend((self.methods.len() as u32).to_le_bytes());
+        for method in &self.methods {
+            put(&mut bytes, method.class.as_bytes());
+            put(&mut bytes, method.name.as_bytes());
+            put(&mut bytes, method.descriptor.as_bytes());
+            put(&mut bytes, &method.machine_code);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u1]) -> Result<Image> {
+        let mut reader = Reader { bytes };
+        let main = reader.string()?;
+        let classes = (0..reader.u4()?)
+            .map(|_| reader.bytes().map(<[u1]>::to_vec))
+            .collect::<Result<_>>()?;
+        let methods = (0..reader.u4()?)
+            .map(|_| {
+                Ok(PrecompiledMethod {
+                    class: reader.string()?,
+                    name: reader.string()?,
+                    descriptor: reader.string()?,
+                    machine_code: reader.bytes()?.to_vec(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        if !reader.bytes.is_empty() {
+            return Err(invalid_image());
+        }
+        Ok(Image {
+            main,
+            classes,
+            methods,
+        })
+    }
+
+    /// Writes an executable that runs the image: the runtime executable, without the image it
+    /// may already have, followed by this image
+    pub fn write_executable(&self, runtime: &[u1], path: &Path) -> std::io::Result<()> {
+        let runtime = match trailer(runtime) {
+            Some(len) => {
+                let end = runtime.len() - 16;
+                let len = usize::try_from(len).ok().filter(|&len| len <= end);
+                let len = len.ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Invalid image in the runtime".to_string(),
+                    )
+                })?;
+                &runtime[..end - len]
+            }
+            None => runtime,
+        };
+        let image = self.to_bytes();
+        let mut bytes = Vec::with_capacity(runtime.len() + image.len() + 16);
+        bytes.extend_from_slice(runtime);
+        bytes.extend_from_slice(&image);
+        bytes.extend((image.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(MAGIC);
+        std::fs::write(path, bytes)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+
+    /// The image at the end of the running executable, if it was written by `write_executable`.
+    /// An executable that can not be read has no image, it is an error if the trailer is there
+    /// but the image is not valid
+    pub fn embedded() -> Result<Option<Image>> {
+        let find = || -> std::io::Result<Option<(File, u64, u64)>> {
+            let mut file = File::open(std::env::current_exe()?)?;
+            let end = file.seek(SeekFrom::End(0))?;
+            if end < 16 {
+                return Ok(None);
+            }
+            let mut last = [0; 16];
+            file.seek(SeekFrom::Start(end - 16))?;
+            file.read_exact(&mut last)?;
+            Ok(trailer(&last).map(|len| (file, end - 16, len)))
+        };
+        let Ok(Some((mut file, end, len))) = find() else {
+            return Ok(None);
+        };
+        if len > end {
+            return Err(invalid_image());
+        }
+
+        let mut image = vec![0; len as usize];
+        file.seek(SeekFrom::Start(end - len))
+            .and_then(|_| file.read_exact(&mut image))
+            .map_err(|err| VmError::ClassFormat(format!("Could not read the image: {}", err)))?;
+        Image::from_bytes(&image).map(Some)
+    }
+}
+
+impl Vm {
+    /// Runs the `main` method of a program compiled ahead of time, with its machine code
+    /// installed. The VM should be new
+    pub fn run_image(&mut self, image: &Image, args: &[String]) -> Result<()> {
+        for class in &image.classes {
+            self.add_class_file(class.clone())?;
+        }
+        for method in &image.methods {
+            let class = self.resolve_class(&method.class)?;
+            let index = self
+                .class(class)
+                .method_index(&method.name, &method.descriptor)
+                .ok_or_else(|| {
+                    VmError::NoSuchMethod(format!(
+                        "{}.{}{}",
+                        method.class, method.name, method.descriptor
+                    ))
+                })?;
+            self.install_precompiled(MethodId { class, index }, &method.machine_code);
+        }
+        self.run_main(&image.main, args)
+    }
+}
+
+/// Rapid type analysis of a program, without running any of its code
+pub(crate) struct Analysis {
+    /// The VM that loads the classes of the program
+    pub(crate) vm: Vm,
+    /// The reachable methods, in the order they were found
+    pub(crate) methods: Vec<MethodId>,
+    reached: HashSet<MethodId>,
+    /// The reachable methods whose code was not scanned yet
+    work: Vec<MethodId>,
+    initialized: HashSet<ClassId>,
+    pub(crate) instantiated: Vec<ClassId>,
+    /// The resolved methods of `invokevirtual` and `invokeinterface`
+    virtual_calls: Vec<MethodId>,
+    pub(crate) unsupported: BTreeSet<String>,
+}
+
+impl Analysis {
+    /// An analysis of the program with the class files that did not reach anything yet
+    pub(crate) fn new(class_files: &[Vec<u1>]) -> Result<Analysis> {
+        let mut vm = Vm::new();
+        for class in class_files {
+            vm.add_class_file(class.clone())?;
+        }
+        Ok(Analysis {
+            vm,
+            methods: Vec::new(),
+            reached: HashSet::new(),
+            work: Vec::new(),
+            initialized: HashSet::new(),
+            instantiated: Vec::new(),
+            virtual_calls: Vec::new(),
+            unsupported: BTreeSet::new(),
+        })
+    }
+
+    /// Analyzes the program from `main` of the class, with the objects and calls of the VM
+    fn run(class_files: &[Vec<u1>], main: &str) -> Result<Analysis> {
+        let mut analysis = Analysis::new(class_files)?;
+        let class = analysis.vm.resolve_class(main)?;
+        let vm = &analysis.vm;
+        let main_method = vm
+            .class(class)
+            .method_index("main", "([Ljava/lang/String;)V")
+            .map(|index| MethodId { class, index })
+            .filter(|&main| vm.method(main).is_static())
+            .ok_or_else(|| VmError::NoSuchMethod(format!("{}.main([Ljava/lang/String;)V", main)))?;
+
+        for name in VM_INSTANTIATED {
+            let class = analysis.vm.resolve_class(name)?;
+            analysis.instantiate(class);
+        }
+        for (class, name, descriptor) in VM_CALLS {
+            let class = analysis.vm.resolve_class(class)?;
+            if let Some(method) = analysis.vm.lookup_method(class, name, descriptor) {
+                analysis.virtual_call(method);
+            }
+        }
+        analysis.analyze(main_method);
+
+        for &method in &analysis.methods {
+            let name = analysis.vm.method_name(method);
+            if analysis.vm.method(method).is_native() && !analysis.vm.natives.contains_key(&name) {
+                (analysis.unsupported)
+                    .insert(format!("{}: native method without an implementation", name));
+            }
+        }
+        Ok(analysis)
+    }
+
+    /// Reaches the method, which is invoked from outside the program, and everything it can reach
+    pub(crate) fn analyze(&mut self, method: MethodId) {
+        self.initialize(method.class);
+        self.reach(method);
+        while let Some(method) = self.work.pop() {
+            self.scan(method);
+        }
+    }
+
+    fn reachability(&self) -> Reachability {
+        Reachability {
+            classes: (self.vm.classes.iter())
+                .filter(|class| class.kind == ClassKind::Class)
+                .map(|class| class.name.clone())
+                .collect(),
+            methods: (self.methods.iter())
+                .map(|&method| self.vm.method_name(method))
+                .collect(),
+            unsupported: self.unsupported.clone(),
+        }
+    }
+
+    fn reach(&mut self, method: MethodId) {
+        if self.reached.insert(method) {
+            self.methods.push(method);
+            self.work.push(method);
+        }
+    }
+
+    /// Reaches the static initializers of the class and its superclasses
+    fn initialize(&mut self, class: ClassId) {
+        if !self.initialized.insert(class) {
+            return;
+        }
+        let class_ref = self.vm.class(class);
+        let super_class = class_ref.super_class;
+        if let Some(index) = class_ref.method_index("<clinit>", "()V") {
+            self.reach(MethodId { class, index });
+        }
+        if let Some(super_class) = super_class {
+            self.initialize(super_class);
+        }
+    }
+
+    fn instantiate(&mut self, class: ClassId) {
+        if self.instantiated.contains(&class) {
+            return;
+        }
+        self.instantiated.push(class);
+        self.initialize(class);
+        for call in self.virtual_calls.clone() {
+            self.dispatch(class, call);
+        }
+    }
+
+    fn virtual_call(&mut self, resolved: MethodId) {
+        if self.virtual_calls.contains(&resolved) {
+            return;
+        }
+        self.virtual_calls.push(resolved);
+        for class in self.instantiated.clone() {
+            self.dispatch(class, resolved);
+        }
+    }
+
+    /// Reaches the method that receivers of the class select for the resolved method
+    fn dispatch(&mut self, class: ClassId, resolved: MethodId) {
+        if !self.vm.is_assignable(class, resolved.class) {
+            return;
+        }
+        if let Ok(selected) = self.vm.select_method(class, resolved) {
+            self.reach(selected);
+        }
+    }
+
+    fn scan(&mut self, method: MethodId) {
+        let Some(code) = self.vm.method(method).code.clone() else {
+            return;
+        };
+        for handler in &code.exception_table {
+            // the VM creates exceptions of some classes, like `java/lang/ArithmeticException`
+            if handler.catch_type != 0 {
+                if let Some(class) = self.class_ref(method, handler.catch_type) {
+                    self.instantiate(class);
+                }
+            }
+        }
+        for pc in 0..code.code.len() {
+            let Some(instruction) = code.instruction(pc) else {
+                continue;
+            };
+            let index = instruction.a as u2;
+            match instruction.opcode {
+                NEW => {
+                    if let Some(class) = self.class_ref(method, index) {
+                        self.instantiate(class);
+                    }
+                }
+                NEWARRAY | ANEWARRAY | MULTIANEWARRAY => {
+                    if instruction.opcode != NEWARRAY {
+                        self.class_ref(method, index);
+                    }
+                    // all arrays select the methods of `java/lang/Object`
+                    if let Some(class) = self.check(method, |vm| vm.resolve_class("[I")) {
+                        self.instantiate(class);
+                    }
+                }
+                CHECKCAST | INSTANCEOF => {
+                    self.class_ref(method, index);
+                }
+                GETSTATIC | PUTSTATIC | GETFIELD | PUTFIELD => self.field(
+                    method,
+                    index,
+                    matches!(instruction.opcode, GETSTATIC | PUTSTATIC),
+                ),
+                INVOKEVIRTUAL..=INVOKEINTERFACE => self.invoke(method, instruction.opcode, index),
+                INVOKEDYNAMIC => self.invokedynamic(method, index),
+                LDC | LDC_W | LDC2_W => self.constant(method, index),
+                _ => {}
+            }
+        }
+    }
+
+    /// The result, or `None` after adding the error as unsupported
+    fn check<T>(&mut self, method: MethodId, f: impl FnOnce(&mut Vm) -> Result<T>) -> Option<T> {
+        match f(&mut self.vm) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                let what = match err {
+                    VmError::ClassNotFound(name) => format!("class {} is not in the program", name),
+                    err => err.to_string(),
+                };
+                let name = self.vm.method_name(method);
+                self.unsupported.insert(format!("{}: {}", name, what));
+                None
+            }
+        }
+    }
+
+    fn unsupported(&mut self, method: MethodId, what: String) {
+        let name = self.vm.method_name(method);
+        self.unsupported.insert(format!("{}: {}", name, what));
+    }
+
+    fn class_ref(&mut self, method: MethodId, index: u2) -> Option<ClassId> {
+        self.check(method, |vm| vm.resolve_class_ref(method.class, index))
+    }
+
+    fn field(&mut self, method: MethodId, index: u2, is_static: bool) {
+        let field = self.check(method, |vm| {
+            let member = vm.class(method.class).cp_field_ref(index)?;
+            let (class, name) = (member.class.to_string(), member.name.to_string());
+            let descriptor = crate::class::parse_field_type(member.descriptor)?;
+            let class = vm.resolve_class_from(method.class, &class)?;
+            if !is_static {
+                return Ok(None);
+            }
+            vm.find_static_field(class, &name, &descriptor)
+                .map(|(class, _)| Some(class))
+                .ok_or_else(|| VmError::NoSuchField(format!("{}.{}", vm.class(class).name, name)))
+        });
+        if let Some(Some(class)) = field {
+            self.initialize(class);
+        }
+    }
+
+    fn invoke(&mut self, method: MethodId, opcode: u1, index: u2) {
+        let Some((class, name, descriptor, interface)) = self.check(method, |vm| {
+            let (member, interface) = vm.class(method.class).cp_method_ref(index)?;
+            Ok((
+                member.class.to_string(),
+                member.name.to_string(),
+                member.descriptor.to_string(),
+                interface,
+            ))
+        }) else {
+            return;
+        };
+        if REFLECTION_PACKAGES
+            .iter()
+            .any(|package| class.starts_with(package))
+            || REFLECTION_METHODS.contains(&(class.as_str(), name.as_str()))
+        {
+            self.unsupported(method, format!("reflection with {}.{}", class, name));
+            return;
+        }
+        let Some(resolved) = self.check(method, |vm| {
+            let class = vm.resolve_class_from(method.class, &class)?;
+            let resolved = vm.resolve_method(class, &name, &descriptor, interface)?;
+            match opcode {
+                INVOKESPECIAL => vm.select_special(method.class, class, resolved),
+                _ => Ok(resolved),
+            }
+        }) else {
+            return;
+        };
+        match opcode {
+            INVOKESTATIC => {
+                self.initialize(resolved.class);
+                self.reach(resolved);
+            }
+            INVOKESPECIAL => self.reach(resolved),
+            _ => self.virtual_call(resolved),
+        }
+    }
+
+    fn invokedynamic(&mut self, method: MethodId, index: u2) {
+        let Some(bootstrap) = self.check(method, |vm| {
+            let class = vm.class(method.class);
+            let (bootstrap, _, _) = class.cp_invoke_dynamic(index)?;
+            let bootstrap = vm.bootstrap_method(method.class, bootstrap)?;
+            let (_, member) = class.cp_method_handle(bootstrap.method)?;
+            Ok(format!("{}.{}", member.class, member.name))
+        }) else {
+            return;
+        };
+        if !bootstrap.starts_with(STRING_CONCAT_FACTORY) {
+            self.unsupported(
+                method,
+                format!("invokedynamic with the bootstrap method {}", bootstrap),
+            );
+            return;
+        }
+        // the arguments are converted with `String.valueOf`
+        let Some(string) = self.check(method, |vm| vm.resolve_class("java/lang/String")) else {
+            return;
+        };
+        let value_ofs = (self.vm.class(string).methods.iter().enumerate())
+            .filter(|(_, value_of)| value_of.name == "valueOf")
+            .map(|(index, _)| MethodId {
+                class: string,
+                index,
+            })
+            .collect::<Vec<_>>();
+        for value_of in value_ofs {
+            self.reach(value_of);
+        }
+    }
+
+    fn constant(&mut self, method: MethodId, index: u2) {
+        let Some(entry) = self.check(method, |vm| {
+            Ok(match vm.class(method.class).cp_entry(index)? {
+                CpInfoInner::Class(_) => "class",
+                CpInfoInner::MethodHandle(_) => "a method handle constant",
+                CpInfoInner::MethodType(_) => "a method type constant",
+                CpInfoInner::Dynamic(_) => "a dynamic constant",
+                _ => "",
+            })
+        }) else {
+            return;
+        };
+        match entry {
+            "class" => {
+                self.class_ref(method, index);
+            }
+            "" => {}
+            what => self.unsupported(method, what.to_string()),
+        }
+    }
+}
+
+/// Appends the bytes with their length
+fn put(bytes: &mut Vec<u1>, value: &[u1]) {
+    bytes.extend((value.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+/// The length of the image before the last 16 bytes, if they end with `MAGIC`
+fn trailer(bytes: &[u1]) -> Option<u64> {
+    let last = bytes.get(bytes.len().checked_sub(16)?..)?;
+    (&last[8..] == MAGIC).then(|| u64::from_le_bytes(last[..8].try_into().expect("8 bytes")))
+}
+
+fn invalid_image() -> VmError {
+    VmError::ClassFormat("Invalid image".to_string())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u1],
+}
+
+impl<'a> Reader<'a> {
+    fn u4(&mut self) -> Result<u32> {
+        let (value, rest) = self.bytes.split_first_chunk().ok_or_else(invalid_image)?;
+        self.bytes = rest;
+        Ok(u32::from_le_bytes(*value))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u1]> {
+        let len = self.u4()? as usize;
+        if len > self.bytes.len() {
+            return Err(invalid_image());
+        }
+        let (value, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid_image())
+    }
+}
@@ -0,0 +1,487 @@
+//!
+//! Linking and invoking `invokedynamic` call sites, see JVMS §5.4.3.6
+//!
+//! Like in the JVM, `MethodHandleNatives.linkCallSite` of the class library runs the bootstrap
+//! method and returns an invoker method with an appendix, which is usually the target method handle.
+//! Every instruction is linked once, later executions call the invoker directly.
+//!
+//! Without `java.lang.invoke`, like with the bundled class library, the VM does the string
+//! concatenation of javac itself, following the recipe for `StringConcatFactory`.
+//!
+//! Dynamically-computed constants are resolved by `MethodHandleNatives.linkDynamicConstant`.
+//! Like other constants, the result of the first resolution is kept, including a failure
+//!
+
+use crate::class::{parse_field_type, BootstrapMethod, ClassId, MethodId};
+use crate::heap::ObjRef;
+use crate::invoke::REF_INVOKE_STATIC;
+use crate::model::Value;
+use crate::{Result, Vm, VmError};
+use cs_model::{FieldType, MethodDescriptor};
+use cs_parser::{u2, CpInfoInner};
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// The tags in the recipe of `StringConcatFactory.makeConcatWithConstants`
+const TAG_ARGUMENT: char = '\u{1}';
+const TAG_CONSTANT: char = '\u{2}';
+
+/// A linked call site
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallSite {
+    /// Invokes the method with the arguments and the appendix as the last argument.
+    /// `arguments` is the number of arguments on the operand stack
+    Linked {
+        invoker: MethodId,
+        appendix: Option<ObjRef>,
+        arguments: usize,
+    },
+    /// A string concatenation done by the VM
+    Concat(Rc<[ConcatPart]>),
+}
+
+/// A part of a string concatenation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConcatPart {
+    /// The next argument from the operand stack, converted like `String.valueOf`
+    Argument(FieldType),
+    Constant(String),
+}
+
+/// The state of a dynamically-computed constant, see JVMS §5.4.3.6
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DynamicConstant {
+    /// The bootstrap method runs, resolving the constant again is a cycle
+    Resolving,
+    Resolved(Value),
+    /// The resolution failed, later resolutions throw the same error
+    Failed(ObjRef),
+}
+
+impl DynamicConstant {
+    /// The object that the constant keeps alive
+    pub fn reference(&self) -> Option<ObjRef> {
+        match self {
+            DynamicConstant::Resolving => None,
+            DynamicConstant::Resolved(value) => value.as_reference().ok().flatten(),
+            DynamicConstant::Failed(error) => Some(*error),
+        }
+    }
+}
+
+impl CallSite {
+    /// The object that the call site keeps alive
+    pub fn appendix(&self) -> Option<ObjRef> {
+        match self {
+            CallSite::Linked { appendix, .. } => *appendix,
+            CallSite::Concat(_) => None,
+        }
+    }
+}
+
+impl Vm {
+    /// `invokedynamic`: links the call site of the instruction when it first runs, then invokes it.
+    /// Returns whether a frame was pushed
+    pub(crate) fn op_invokedynamic(
+        &mut self,
+        current: MethodId,
+        pc: usize,
+        index: u2,
+    ) -> Result<bool> {
+        let key = (current.index, pc);
+        let call_site = match self.class(current.class).call_sites.get(&key) {
+            Some(call_site) => call_site.clone(),
+            None => {
+                let call_site = self.link_invokedynamic(current.class, index)?;
+                self.class_mut(current.class)
+                    .call_sites
+                    .insert(key, call_site.clone());
+                call_site
+            }
+        };
+        self.invoke_call_site(&call_site)
+    }
+
+    /// Pops the arguments of the call site and invokes it. Returns whether a frame was pushed
+    pub(crate) fn invoke_call_site(&mut self, call_site: &CallSite) -> Result<bool> {
+        match call_site {
+            CallSite::Linked {
+                invoker,
+                appendix,
+                arguments,
+            } => {
+                let mut args = self.pop_values(*arguments)?;
+                if let Some(appendix) = appendix {
+                    args.push(Value::Reference(Some(*appendix)));
+                }
+                self.enter_method(*invoker, &args)
+            }
+            CallSite::Concat(parts) => {
+                let arguments = parts
+                    .iter()
+                    .filter(|part| matches!(part, ConcatPart::Argument(_)))
+                    .count();
+                let args = self.pop_values(arguments)?;
+
+                let handles = self.handles.len();
+                self.handles.extend(args.iter().filter_map(|arg| match arg {
+                    Value::Reference(obj) => *obj,
+                    _ => None,
+                }));
+                let result = self.concat(parts, &args);
+                self.handles.truncate(handles);
+
+                self.push(Value::Reference(Some(result?)))?;
+                Ok(false)
+            }
+        }
+    }
+
+    fn link_invokedynamic(&mut self, class: ClassId, index: u2) -> Result<CallSite> {
+        let (bootstrap, name, descriptor) = self.class(class).cp_invoke_dynamic(index)?;
+        let (name, descriptor) = (name.to_string(), descriptor.to_string());
+        let bootstrap = self.bootstrap_method(class, bootstrap)?;
+        let method_descriptor = MethodDescriptor::from_str(&descriptor).map_err(|err| {
+            VmError::ClassFormat(format!("Invalid descriptor {}: {}", descriptor, err.0))
+        })?;
+
+        if !self.has_method_handles() {
+            return self.link_concat(class, &bootstrap, &method_descriptor);
+        }
+
+        let handles = self.handles.len();
+        let result = self.link_call_site(class, index, &bootstrap, &name, &descriptor);
+        self.handles.truncate(handles);
+        let (invoker, appendix) = result?;
+        Ok(CallSite::Linked {
+            invoker,
+            appendix,
+            arguments: method_descriptor.parameters.len(),
+        })
+    }
+
+    pub(crate) fn bootstrap_method(&self, class: ClassId, index: u2) -> Result<BootstrapMethod> {
+        self.class(class)
+            .bootstrap_methods
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| {
+                VmError::ClassFormat(format!(
+                    "Invalid bootstrap method index {} in {}",
+                    index,
+                    self.class(class).name
+                ))
+            })
+    }
+
+    /// Whether the class library has `java.lang.invoke`, which the bundled one does not
+    fn has_method_handles(&mut self) -> bool {
+        self.resolve_class("java/lang/invoke/MethodHandleNatives")
+            .is_ok()
+    }
+
+    /// Runs the bootstrap method with `MethodHandleNatives.linkCallSite`.
+    /// Returns the invoker and the appendix, the objects are added to the handles
+    fn link_call_site(
+        &mut self,
+        class: ClassId,
+        index: u2,
+        bootstrap: &BootstrapMethod,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<(MethodId, Option<ObjRef>)> {
+        let caller = self.class_object(class)?;
+        let bootstrap_method = self.resolve_constant(class, bootstrap.method)?;
+        let method_type = self.method_type(descriptor)?;
+        self.handles.push(method_type);
+        let name = self.intern_string(name)?;
+        let arguments = self.bootstrap_arguments(class, &bootstrap.arguments)?;
+        self.handles.push(arguments);
+        let object_array = self.resolve_class("[Ljava/lang/Object;")?;
+        let appendix = self.new_array(object_array, 1)?;
+        self.handles.push(appendix);
+
+        let member = self.call_method_handle_natives(
+            "linkCallSite",
+            "(Ljava/lang/Object;ILjava/lang/Object;Ljava/lang/Object;Ljava/lang/Object;\
+            Ljava/lang/Object;[Ljava/lang/Object;)Ljava/lang/invoke/MemberName;",
+            &[
+                Value::Reference(Some(caller)),
+                Value::Int(index as i32),
+                Value::Reference(Some(bootstrap_method)),
+                Value::Reference(Some(name)),
+                Value::Reference(Some(method_type)),
+                Value::Reference(Some(arguments)),
+                Value::Reference(Some(appendix)),
+            ],
+        )?;
+        let invoker = self.member_method(member)?;
+        let appendix = self.array(appendix)?.load(0).unwrap_or(Value::NULL);
+        Ok((invoker, appendix.as_reference()?))
+    }
+
+    /// Resolves a `Dynamic` constant by running its bootstrap method once, see JVMS §5.4.3.6.
+    /// A constant that needs itself to be resolved throws a `StackOverflowError`, like in the JVM
+    pub(crate) fn resolve_dynamic_constant(&mut self, class: ClassId, index: u2) -> Result<Value> {
+        match self.class(class).dynamic_constants.get(&index) {
+            Some(DynamicConstant::Resolved(value)) => return Ok(*value),
+            Some(DynamicConstant::Failed(error)) => return Err(VmError::Exception(*error)),
+            Some(DynamicConstant::Resolving) => return Err(VmError::StackOverflow),
+            None => {}
+        }
+
+        self.class_mut(class)
+            .dynamic_constants
+            .insert(index, DynamicConstant::Resolving);
+        let handles = self.handles.len();
+        let result = self.link_dynamic_constant(class, index);
+        self.handles.truncate(handles);
+
+        let state = match result {
+            Ok(value) => DynamicConstant::Resolved(value),
+            Err(err) => match self.bootstrap_method_error(err) {
+                Ok(error) => DynamicConstant::Failed(error),
+                // errors of the VM are not remembered
+                Err(err) => {
+                    self.class_mut(class).dynamic_constants.remove(&index);
+                    return Err(err);
+                }
+            },
+        };
+        self.class_mut(class).dynamic_constants.insert(index, state);
+        match state {
+            DynamicConstant::Resolved(value) => Ok(value),
+            DynamicConstant::Failed(error) => Err(VmError::Exception(error)),
+            DynamicConstant::Resolving => unreachable!("the constant was resolved"),
+        }
+    }
+
+    /// Runs the bootstrap method with `MethodHandleNatives.linkDynamicConstant`.
+    /// The objects are added to the handles
+    fn link_dynamic_constant(&mut self, class: ClassId, index: u2) -> Result<Value> {
+        let (bootstrap, name, descriptor) = self.class(class).cp_dynamic(index)?;
+        let (name, ty) = (name.to_string(), parse_field_type(descriptor)?);
+        let bootstrap = self.bootstrap_method(class, bootstrap)?;
+        if !self.has_method_handles() {
+            return Err(VmError::Unsupported(
+                "Dynamic constants without java.lang.invoke".to_string(),
+            ));
+        }
+
+        let caller = self.class_object(class)?;
+        let bootstrap_method = self.resolve_constant(class, bootstrap.method)?;
+        let ty_class = self.field_type_class(&ty)?;
+        let ty_mirror = self.class_object(ty_class)?;
+        let name = self.intern_string(&name)?;
+        let arguments = self.bootstrap_arguments(class, &bootstrap.arguments)?;
+        self.handles.push(arguments);
+
+        let value = self.call_method_handle_natives_nullable(
+            "linkDynamicConstant",
+            "(Ljava/lang/Object;ILjava/lang/Object;Ljava/lang/Object;Ljava/lang/Object;\
+            Ljava/lang/Object;)Ljava/lang/Object;",
+            &[
+                Value::Reference(Some(caller)),
+                Value::Int(index as i32),
+                Value::Reference(Some(bootstrap_method)),
+                Value::Reference(Some(name)),
+                Value::Reference(Some(ty_mirror)),
+                Value::Reference(Some(arguments)),
+            ],
+        )?;
+        self.unbox_value(&ty, value)
+    }
+
+    /// The exception of a failed resolution. Exceptions that are not errors are wrapped
+    /// in a `BootstrapMethodError`, the JDK already does this for exceptions of bootstrap methods
+    fn bootstrap_method_error(&mut self, err: VmError) -> Result<ObjRef> {
+        let exception = self.exception_from_error(err)?;
+        let error_class = self.resolve_class("java/lang/Error")?;
+        if self.is_subclass(self.heap.get(exception).class, error_class) {
+            return Ok(exception);
+        }
+        self.handles.push(exception);
+        let wrapped = self
+            .resolve_class("java/lang/BootstrapMethodError")
+            .and_then(|class| {
+                self.construct(
+                    class,
+                    "(Ljava/lang/Throwable;)V",
+                    &[Value::Reference(Some(exception))],
+                )
+            });
+        self.handles.pop();
+        wrapped
+    }
+
+    /// The static arguments of a bootstrap method as an `Object[]`, numbers are boxed
+    pub(crate) fn bootstrap_arguments(&mut self, class: ClassId, indices: &[u2]) -> Result<ObjRef> {
+        let object_array = self.resolve_class("[Ljava/lang/Object;")?;
+        let array = self.new_array(object_array, indices.len() as i32)?;
+        self.handles.push(array);
+        let result = indices.iter().enumerate().try_for_each(|(i, &index)| {
+            let ty = match self.class(class).cp_entry(index)? {
+                CpInfoInner::Integer(_) => Some(FieldType::Int),
+                CpInfoInner::Float(_) => Some(FieldType::Float),
+                CpInfoInner::Long(_) => Some(FieldType::Long),
+                CpInfoInner::Double(_) => Some(FieldType::Double),
+                _ => None,
+            };
+            let argument = match (ty, self.class(class).cp_entry(index)?) {
+                (Some(ty), _) => {
+                    let value = self.class(class).cp_numeric(index)?;
+                    self.box_value(&ty, value)?
+                }
+                (None, CpInfoInner::Dynamic(_)) => {
+                    let value = self.resolve_dynamic_constant(class, index)?;
+                    let (_, _, descriptor) = self.class(class).cp_dynamic(index)?;
+                    let ty = parse_field_type(descriptor)?;
+                    self.box_value(&ty, value)?
+                }
+                (None, _) => Some(self.resolve_constant(class, index)?),
+            };
+            self.array_mut(array)?.store(i, Value::Reference(argument));
+            Ok(())
+        });
+        self.handles.pop();
+        result.map(|()| array)
+    }
+
+    /// Links a call site of `StringConcatFactory` without calling its bootstrap method
+    fn link_concat(
+        &mut self,
+        class: ClassId,
+        bootstrap: &BootstrapMethod,
+        descriptor: &MethodDescriptor,
+    ) -> Result<CallSite> {
+        let (kind, method) = self.class(class).cp_method_handle(bootstrap.method)?;
+        if kind != REF_INVOKE_STATIC || method.class != "java/lang/invoke/StringConcatFactory" {
+            return Err(VmError::Unsupported(format!(
+                "invokedynamic with the bootstrap method {}.{} without java.lang.invoke",
+                method.class, method.name
+            )));
+        }
+
+        let parts = match method.name {
+            "makeConcat" => descriptor
+                .parameters
+                .iter()
+                .cloned()
+                .map(ConcatPart::Argument)
+                .collect(),
+            "makeConcatWithConstants" => self.concat_recipe(class, bootstrap, descriptor)?,
+            name => {
+                return Err(VmError::Unsupported(format!(
+                    "StringConcatFactory.{} without java.lang.invoke",
+                    name
+                )))
+            }
+        };
+        Ok(CallSite::Concat(parts))
+    }
+
+    /// Parses the recipe, which is the first static argument. The other static arguments
+    /// are the constants for the constant tags
+    fn concat_recipe(
+        &self,
+        class: ClassId,
+        bootstrap: &BootstrapMethod,
+        descriptor: &MethodDescriptor,
+    ) -> Result<Rc<[ConcatPart]>> {
+        let class = self.class(class);
+        let invalid = |message: &str| VmError::BootstrapMethod(message.to_string());
+
+        let (recipe, constants) = bootstrap
+            .arguments
+            .split_first()
+            .ok_or_else(|| invalid("StringConcatFactory needs a recipe"))?;
+        let mut constants = constants.iter();
+        let mut arguments = descriptor.parameters.iter();
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        for c in class.cp_string(*recipe)?.chars() {
+            match c {
+                TAG_ARGUMENT => {
+                    let argument = arguments
+                        .next()
+                        .ok_or_else(|| invalid("Mismatched number of concat arguments"))?;
+                    if !literal.is_empty() {
+                        parts.push(ConcatPart::Constant(std::mem::take(&mut literal)));
+                    }
+                    parts.push(ConcatPart::Argument(argument.clone()));
+                }
+                TAG_CONSTANT => {
+                    let constant = constants
+                        .next()
+                        .ok_or_else(|| invalid("Mismatched number of concat constants"))?;
+                    match class.cp_entry(*constant)? {
+                        CpInfoInner::String(_) => literal.push_str(class.cp_string(*constant)?),
+                        CpInfoInner::Integer(_) | CpInfoInner::Long(_) => {
+                            match class.cp_numeric(*constant)? {
+                                Value::Int(n) => literal.push_str(&n.to_string()),
+                                Value::Long(n) => literal.push_str(&n.to_string()),
+                                _ => unreachable!("the constant is an int or long"),
+                            }
+                        }
+                        kind => {
+                            return Err(VmError::Unsupported(format!(
+                                "Concat constant {:?} without java.lang.invoke",
+                                kind
+                            )))
+                        }
+                    }
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(ConcatPart::Constant(literal));
+        }
+        if arguments.next().is_some() {
+            return Err(invalid("Mismatched number of concat arguments"));
+        }
+        Ok(parts.into())
+    }
+
+    /// Concatenates the parts, the references in the arguments have to be rooted
+    fn concat(&mut self, parts: &[ConcatPart], args: &[Value]) -> Result<ObjRef> {
+        let mut chars = Vec::new();
+        let mut args = args.iter();
+        for part in parts {
+            match part {
+                ConcatPart::Constant(constant) => chars.extend(constant.encode_utf16()),
+                ConcatPart::Argument(ty) => {
+                    let arg = *args.next().expect("there is an argument for every tag");
+                    let string = self.string_of(ty, arg)?;
+                    chars.extend(self.string_chars(string)?);
+                }
+            }
+        }
+        self.new_string_from_chars(&chars)
+    }
+
+    /// Converts a value to a string with the `String.valueOf` for its type,
+    /// which calls `toString` for objects
+    fn string_of(&mut self, ty: &FieldType, value: Value) -> Result<ObjRef> {
+        let descriptor = match ty {
+            FieldType::Boolean => "(Z)Ljava/lang/String;",
+            FieldType::Char => "(C)Ljava/lang/String;",
+            FieldType::Byte | FieldType::Short | FieldType::Int => "(I)Ljava/lang/String;",
+            FieldType::Long => "(J)Ljava/lang/String;",
+            FieldType::Float => "(F)Ljava/lang/String;",
+            FieldType::Double => "(D)Ljava/lang/String;",
+            FieldType::Object(_) | FieldType::Array(_) => "(Ljava/lang/Object;)Ljava/lang/String;",
+        };
+        let string = self.resolve_class("java/lang/String")?;
+        let value_of = self
+            .lookup_method(string, "valueOf", descriptor)
+            .ok_or_else(|| {
+                VmError::NoSuchMethod(format!("java/lang/String.valueOf{}", descriptor))
+            })?;
+        self.invoke(value_of, &[value])?
+            .ok_or_else(|| VmError::Verify("String.valueOf returned nothing".to_string()))?
+            .as_reference()?
+            .ok_or(VmError::NullPointer)
+    }
+}
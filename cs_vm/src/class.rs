@@ -0,0 +1,1201 @@
+//!
+//! The runtime representation of loaded classes
+//!
+
+use crate::call_site::{CallSite, DynamicConstant};
+use crate::heap::ObjRef;
+use crate::instruction::{self, Instruction, Switch};
+use crate::model::Value;
+use crate::vtable::ItableEntry;
+use crate::{reflect, runtime};
+use crate::{Result, Vm, VmError};
+use cs_model::{FieldDescriptor, FieldType, MethodDescriptor};
+use cs_parser::cp_info::MethodHandleIndex;
+use cs_parser::{
+    u1, u2, AttributeCodeException, AttributeInfo, AttributeInfoInner, AttributeLineNumber,
+    ClassAccessFlag, ClassFile, CpInfo, CpInfoInner, FieldAccessFlags, MethodAccessFlag,
+};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// The names of the primitive types and `void`, which have classes for their `java/lang/Class` objects
+const PRIMITIVE_NAMES: &[&str] = &[
+    "boolean", "byte", "char", "short", "int", "long", "float", "double", "void",
+];
+
+/// The descriptors of `PRIMITIVE_NAMES`
+const PRIMITIVE_DESCRIPTORS: &str = "ZBCSIJFDV";
+
+/// Fields the VM adds to classes of the JDK to store its own data, like the JVM does.
+/// The class, the name and the descriptor
+const INJECTED_FIELDS: &[(&str, &str, &str)] = &[
+    // the method of a resolved `MemberName`, see `MethodId::to_long`
+    ("java/lang/invoke/ResolvedMethodName", "vmtarget", "J"),
+    (
+        "java/lang/invoke/ResolvedMethodName",
+        "vmholder",
+        "Ljava/lang/Class;",
+    ),
+    // the `Unsafe` offset of the field of a resolved `MemberName`
+    ("java/lang/invoke/MemberName", "vmindex", "J"),
+];
+
+/// The index of a loaded class in the VM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClassId(pub(crate) u32);
+
+impl ClassId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A method of a loaded class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MethodId {
+    pub class: ClassId,
+    /// The index into `Class::methods`
+    pub index: usize,
+}
+
+impl MethodId {
+    /// Packs the method into a `long`, for fields of Java objects that refer to a method
+    pub fn to_long(self) -> i64 {
+        ((self.class.0 as i64) << 32) | self.index as i64
+    }
+
+    /// Unpacks a method from `to_long`, the result is only valid if it came from `to_long`
+    pub fn from_long(value: i64) -> Self {
+        MethodId {
+            class: ClassId((value >> 32) as u32),
+            index: value as u32 as usize,
+        }
+    }
+}
+
+/// A loaded and linked class
+#[derive(Debug, Clone)]
+pub struct Class {
+    /// The binary name of the class, for example `java/lang/Object` or `[I`
+    pub name: String,
+    pub access_flags: u2,
+    /// The major version of the class file, zero for array and primitive classes
+    pub major_version: u2,
+    pub super_class: Option<ClassId>,
+    pub interfaces: Vec<ClassId>,
+    pub kind: ClassKind,
+    /// The runtime constant pool, empty for array classes
+    pub constant_pool: Vec<CpInfo>,
+    /// All instance fields, including inherited ones. The index is the slot of the field in an object
+    pub instance_fields: Vec<Field>,
+    /// Only the static fields declared by this class
+    pub static_fields: Vec<Field>,
+    /// The values of `static_fields`
+    pub static_values: Vec<Value>,
+    pub methods: Vec<Method>,
+    /// The methods selected by virtual calls, indexed by `Method::vtable_index`.
+    /// For interfaces, these are the instance methods declared by the interface
+    pub vtable: Vec<MethodId>,
+    /// The implementations of the methods of every interface the class implements
+    pub itable: Vec<ItableEntry>,
+    /// The default methods in the `vtable` that conflict with another maximally-specific
+    /// default method. Invoking them throws an `IncompatibleClassChangeError`
+    pub conflicting_defaults: Vec<MethodId>,
+    pub init_state: InitState,
+    /// The name of the source file from the `SourceFile` attribute, used in stack traces
+    pub source_file: Option<String>,
+    /// The entry of the `InnerClasses` attribute for the class itself, if it is a nested class
+    pub inner_class: Option<InnerClass>,
+    /// The `EnclosingMethod` attribute of local and anonymous classes
+    pub enclosing_method: Option<EnclosingMethod>,
+    /// The host of the nest of the class from the `NestHost` attribute, `None` for hosts themselves
+    pub nest_host: Option<String>,
+    /// The `BootstrapMethods` attribute, indexed by `InvokeDynamic` and `Dynamic` entries
+    pub bootstrap_methods: Vec<BootstrapMethod>,
+    /// The fields declared by the class, in the order of the class file
+    pub declared_fields: Vec<FieldSlot>,
+    pub metadata: Metadata,
+    /// Hidden classes are defined by `Lookup.defineHiddenClass` and can not be found by name
+    pub hidden: bool,
+    /// The `java/lang/Class` object of the class, created when it is first needed
+    pub mirror: Option<ObjRef>,
+    /// The objects of the constants that `ldc` resolved, by their constant pool index.
+    /// Resolving a constant again results in the same object
+    pub resolved_constants: HashMap<u2, ObjRef>,
+    /// The linked `invokedynamic` and signature polymorphic call sites,
+    /// by the index of the method and the pc of the instruction
+    pub call_sites: HashMap<(usize, usize), CallSite>,
+    /// The dynamically-computed constants by their constant pool index
+    pub dynamic_constants: HashMap<u2, DynamicConstant>,
+}
+
+/// How a nested class is declared, from the `InnerClasses` attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InnerClass {
+    /// The class that declares a member class, `None` for local and anonymous classes
+    pub outer_class: Option<String>,
+    /// The simple name in the source code, `None` for anonymous classes
+    pub simple_name: Option<String>,
+    /// The modifiers in the source code, which the access flags of the class file lack
+    /// for `private`, `protected` and `static` classes
+    pub access_flags: u2,
+}
+
+/// Where a field is stored, the index in `instance_fields` or `static_fields`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldSlot {
+    Instance(usize),
+    Static(usize),
+}
+
+/// The attributes of a class, field or method that only reflection reads
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// The generic signature from the `Signature` attribute
+    pub signature: Option<String>,
+    /// The `RuntimeVisibleAnnotations` attribute in the class file format, which the class
+    /// library parses with the constant pool of the class
+    pub annotations: Option<Vec<u1>>,
+    /// The `RuntimeVisibleParameterAnnotations` attribute of a method
+    pub parameter_annotations: Option<Vec<u1>>,
+    /// The `AnnotationDefault` attribute of a method of an annotation interface
+    pub annotation_default: Option<Vec<u1>>,
+    /// The names and access flags of the parameters of a method from the `MethodParameters`
+    /// attribute. Parameters without a name have `None`
+    pub parameters: Option<Vec<(Option<String>, u2)>>,
+    /// The classes in the `Exceptions` attribute of a method
+    pub exceptions: Vec<String>,
+    /// The classes in the `PermittedSubclasses` attribute of a sealed class
+    pub permitted_subclasses: Option<Vec<String>>,
+}
+
+/// The innermost class and method that contain a local or anonymous class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnclosingMethod {
+    pub class: String,
+    /// The name and descriptor of the method, `None` if the class is in an initializer
+    pub method: Option<(String, String)>,
+}
+
+/// An entry of the `BootstrapMethods` attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootstrapMethod {
+    /// The index of the `MethodHandle` entry of the bootstrap method
+    pub method: u2,
+    /// The indices of the loadable entries that are passed as static arguments
+    pub arguments: Vec<u2>,
+}
+
+/// The initialization state of a class, see JVMS §5.5
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitState {
+    Uninitialized,
+    /// The static initializer is running on the thread with this index
+    Initializing(usize),
+    Initialized,
+    /// The static initializer failed, the class can not be used
+    Erroneous,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassKind {
+    /// A class or interface loaded from a class file
+    Class,
+    /// An array class with the given component type
+    Array(FieldType),
+    /// A primitive type or `void`, which only exists for its `java/lang/Class` object
+    Primitive,
+}
+
+/// A field of a class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub descriptor: FieldType,
+    pub access_flags: u2,
+    /// The class that declared the field
+    pub class: ClassId,
+    /// The index of the `ConstantValue` attribute, only for static fields
+    pub constant_value: Option<u2>,
+    pub metadata: Metadata,
+}
+
+/// A method of a class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Method {
+    pub name: String,
+    pub descriptor: String,
+    pub method_descriptor: MethodDescriptor,
+    pub access_flags: u2,
+    /// `None` for abstract and native methods
+    pub code: Option<Rc<Code>>,
+    /// The slot in the `vtable` of the declaring class.
+    /// `None` for methods that are never selected dynamically, like static and private methods
+    pub vtable_index: Option<usize>,
+    pub metadata: Metadata,
+}
+
+impl Field {
+    pub fn is_static(&self) -> bool {
+        self.access_flags & FieldAccessFlags::STATIC as u2 != 0
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.access_flags & FieldAccessFlags::PUBLIC as u2 != 0
+    }
+
+    pub fn is_final(&self) -> bool {
+        self.access_flags & FieldAccessFlags::FINAL as u2 != 0
+    }
+}
+
+impl Method {
+    pub fn is_static(&self) -> bool {
+        self.access_flags & MethodAccessFlag::STATIC as u2 != 0
+    }
+
+    pub fn is_abstract(&self) -> bool {
+        self.access_flags & MethodAccessFlag::ABSTRACT as u2 != 0
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.access_flags & MethodAccessFlag::PUBLIC as u2 != 0
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.access_flags & MethodAccessFlag::PRIVATE as u2 != 0
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.access_flags & MethodAccessFlag::PROTECTED as u2 != 0
+    }
+
+    pub fn is_final(&self) -> bool {
+        self.access_flags & MethodAccessFlag::FINAL as u2 != 0
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.access_flags & MethodAccessFlag::NATIVE as u2 != 0
+    }
+
+    pub fn is_synchronized(&self) -> bool {
+        self.access_flags & MethodAccessFlag::SYNCHRONIZED as u2 != 0
+    }
+
+    /// The number of local variable slots the arguments take, including `this`
+    pub fn arg_slots(&self) -> u16 {
+        self.method_descriptor.parameter_slots() + if self.is_static() { 0 } else { 1 }
+    }
+}
+
+/// The `Code` attribute of a method
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code {
+    pub max_stack: u2,
+    pub max_locals: u2,
+    pub code: Vec<u1>,
+    pub exception_table: Vec<AttributeCodeException>,
+    /// From the `LineNumberTable` attribute, ordered by `start_pc`
+    pub line_numbers: Vec<AttributeLineNumber>,
+    /// `code` decoded by pc, see `instruction`
+    pub(crate) instructions: Box<[Cell<Instruction>]>,
+    pub(crate) switches: Vec<Switch>,
+}
+
+impl Code {
+    pub fn new(
+        max_stack: u2,
+        max_locals: u2,
+        code: Vec<u1>,
+        exception_table: Vec<AttributeCodeException>,
+        line_numbers: Vec<AttributeLineNumber>,
+    ) -> Self {
+        let (instructions, switches) = instruction::decode(&code);
+        Self {
+            max_stack,
+            max_locals,
+            code,
+            exception_table,
+            line_numbers,
+            instructions,
+            switches,
+        }
+    }
+
+    /// The instruction at `pc`, if one starts there
+    pub(crate) fn instruction(&self, pc: usize) -> Option<Instruction> {
+        let instruction = self.instructions.get(pc)?.get();
+        (instruction.next != 0).then_some(instruction)
+    }
+
+    /// The instruction at `pc` decoded from the bytes again, if one starts there.
+    /// It is never quickened
+    pub(crate) fn decode_instruction(&self, pc: usize) -> Option<Instruction> {
+        let decoded = self.instruction(pc)?;
+        instruction::decode_bytes(&self.code, pc, decoded.a)
+    }
+
+    /// Rewrites the instruction at `pc` to its quick variant
+    pub(crate) fn quicken(&self, pc: usize, opcode: u1, a: i32, b: i32, c: i32) {
+        let instruction = &self.instructions[pc];
+        instruction.set(instruction.get().quick(opcode, a, b, c));
+    }
+
+    /// The source line of the instruction at `pc`, if the code has line numbers
+    pub fn line_number(&self, pc: usize) -> Option<u2> {
+        self.line_numbers
+            .iter()
+            .rev()
+            .find(|line| line.start_pc as usize <= pc)
+            .map(|line| line.line_number)
+    }
+}
+
+impl Class {
+    pub fn is_array(&self) -> bool {
+        matches!(self.kind, ClassKind::Array(_))
+    }
+
+    pub fn is_primitive(&self) -> bool {
+        self.kind == ClassKind::Primitive
+    }
+
+    pub fn is_interface(&self) -> bool {
+        self.access_flags & ClassAccessFlag::Interface as u2 != 0
+    }
+
+    /// The field descriptor of the class, for example `Ljava/lang/Object;`, `[I` or `I`
+    pub fn descriptor(&self) -> String {
+        match self.kind {
+            ClassKind::Array(_) => self.name.clone(),
+            ClassKind::Primitive => {
+                let index = PRIMITIVE_NAMES
+                    .iter()
+                    .position(|name| *name == self.name)
+                    .unwrap_or(PRIMITIVE_NAMES.len() - 1);
+                PRIMITIVE_DESCRIPTORS[index..=index].to_string()
+            }
+            _ => format!("L{};", self.name),
+        }
+    }
+
+    /// Finds the slot of an instance field. Fields of subclasses shadow fields of superclasses
+    pub fn instance_field_slot(&self, name: &str, descriptor: &FieldType) -> Option<usize> {
+        self.instance_fields
+            .iter()
+            .rposition(|field| field.name == name && &field.descriptor == descriptor)
+    }
+
+    /// Finds a method declared by this class
+    pub fn method_index(&self, name: &str, descriptor: &str) -> Option<usize> {
+        self.methods
+            .iter()
+            .position(|method| method.name == name && method.descriptor == descriptor)
+    }
+
+    /// Returns the entry of the runtime constant pool. Indices from the bytecode are not validated
+    /// by the parser, so this checks them
+    pub fn cp_entry(&self, index: u2) -> Result<&CpInfoInner> {
+        (index as usize)
+            .checked_sub(1)
+            .and_then(|index| self.constant_pool.get(index))
+            .map(|info| &info.inner)
+            .ok_or_else(|| {
+                VmError::ClassFormat(format!(
+                    "Invalid constant pool index {} in {}",
+                    index, self.name
+                ))
+            })
+    }
+
+    pub fn cp_utf8(&self, index: u2) -> Result<&str> {
+        match self.cp_entry(index)? {
+            CpInfoInner::Utf8(utf8) => Ok(&utf8.bytes),
+            kind => Err(self.cp_mismatch("Utf8", kind)),
+        }
+    }
+
+    pub fn cp_class_name(&self, index: u2) -> Result<&str> {
+        match self.cp_entry(index)? {
+            CpInfoInner::Class(class) => self.cp_utf8(class.name_index.inner()),
+            kind => Err(self.cp_mismatch("Class", kind)),
+        }
+    }
+
+    /// Returns the contents of a `String` entry
+    pub fn cp_string(&self, index: u2) -> Result<&str> {
+        match self.cp_entry(index)? {
+            CpInfoInner::String(string) => self.cp_utf8(string.string_index.inner()),
+            kind => Err(self.cp_mismatch("String", kind)),
+        }
+    }
+
+    /// Returns the name and descriptor of a `NameAndType` entry
+    pub fn cp_name_and_type(&self, index: u2) -> Result<(&str, &str)> {
+        match self.cp_entry(index)? {
+            CpInfoInner::NameAndType(nat) => Ok((
+                self.cp_utf8(nat.name_index.inner())?,
+                self.cp_utf8(nat.descriptor_index.inner())?,
+            )),
+            kind => Err(self.cp_mismatch("NameAndType", kind)),
+        }
+    }
+
+    /// Returns the class name, name and descriptor of a `Fieldref`
+    pub fn cp_field_ref(&self, index: u2) -> Result<MemberRef<'_>> {
+        match self.cp_entry(index)? {
+            CpInfoInner::Fieldref(field) => {
+                let class = self.cp_class_name(field.class_index.inner())?;
+                let (name, descriptor) =
+                    self.cp_name_and_type(field.name_and_type_index.inner())?;
+                Ok(MemberRef {
+                    class,
+                    name,
+                    descriptor,
+                })
+            }
+            kind => Err(self.cp_mismatch("Fieldref", kind)),
+        }
+    }
+
+    /// Returns the class name, name and descriptor of a `MethodRef` or `InterfaceMethodref`.
+    /// The bool is true for `InterfaceMethodref`
+    pub fn cp_method_ref(&self, index: u2) -> Result<(MemberRef<'_>, bool)> {
+        let (class_index, name_and_type_index, interface) = match self.cp_entry(index)? {
+            CpInfoInner::MethodRef(method) => {
+                (method.class_index, method.name_and_type_index, false)
+            }
+            CpInfoInner::InterfaceMethodref(method) => {
+                (method.class_index, method.name_and_type_index, true)
+            }
+            kind => return Err(self.cp_mismatch("MethodRef", kind)),
+        };
+        let class = self.cp_class_name(class_index.inner())?;
+        let (name, descriptor) = self.cp_name_and_type(name_and_type_index.inner())?;
+        Ok((
+            MemberRef {
+                class,
+                name,
+                descriptor,
+            },
+            interface,
+        ))
+    }
+
+    /// Returns the index of the bootstrap method, the name and the descriptor
+    /// of an `InvokeDynamic` entry
+    pub fn cp_invoke_dynamic(&self, index: u2) -> Result<(u2, &str, &str)> {
+        match self.cp_entry(index)? {
+            CpInfoInner::InvokeDynamic(indy) => {
+                let (name, descriptor) = self.cp_name_and_type(indy.name_and_type_index.inner())?;
+                Ok((indy.bootstrap_method_attr_index, name, descriptor))
+            }
+            kind => Err(self.cp_mismatch("InvokeDynamic", kind)),
+        }
+    }
+
+    /// Returns the index of the bootstrap method, the name and the field descriptor
+    /// of a `Dynamic` entry
+    pub fn cp_dynamic(&self, index: u2) -> Result<(u2, &str, &str)> {
+        match self.cp_entry(index)? {
+            CpInfoInner::Dynamic(condy) => {
+                let (name, descriptor) =
+                    self.cp_name_and_type(condy.name_and_type_index.inner())?;
+                Ok((condy.bootstrap_method_attr_index, name, descriptor))
+            }
+            kind => Err(self.cp_mismatch("Dynamic", kind)),
+        }
+    }
+
+    /// Returns the descriptor of a `MethodType` entry
+    pub fn cp_method_type(&self, index: u2) -> Result<&str> {
+        match self.cp_entry(index)? {
+            CpInfoInner::MethodType(method_type) => {
+                self.cp_utf8(method_type.descriptor_index.inner())
+            }
+            kind => Err(self.cp_mismatch("MethodType", kind)),
+        }
+    }
+
+    /// Returns the reference kind and the referenced field or method of a `MethodHandle` entry
+    pub fn cp_method_handle(&self, index: u2) -> Result<(u1, MemberRef<'_>)> {
+        let handle = match self.cp_entry(index)? {
+            CpInfoInner::MethodHandle(handle) => handle,
+            kind => return Err(self.cp_mismatch("MethodHandle", kind)),
+        };
+        let member = match handle.reference_index {
+            MethodHandleIndex::Field(field) => self.cp_field_ref(field.inner())?,
+            MethodHandleIndex::Method(method) => self.cp_method_ref(method.inner())?.0,
+            MethodHandleIndex::Interface(method) => self.cp_method_ref(method.inner())?.0,
+        };
+        Ok((handle.reference_kind, member))
+    }
+
+    /// Returns the value of an `Integer`, `Float`, `Long` or `Double` entry
+    pub fn cp_numeric(&self, index: u2) -> Result<Value> {
+        Ok(match self.cp_entry(index)? {
+            CpInfoInner::Integer(int) => Value::Int(int.bytes as i32),
+            CpInfoInner::Float(float) => Value::Float(f32::from_bits(float.bytes)),
+            CpInfoInner::Long(long) => {
+                Value::Long(((long.high_bytes as i64) << 32) | long.low_bytes as i64)
+            }
+            CpInfoInner::Double(double) => Value::Double(f64::from_bits(
+                ((double.high_bytes as u64) << 32) | double.low_bytes as u64,
+            )),
+            kind => return Err(self.cp_mismatch("numeric constant", kind)),
+        })
+    }
+
+    fn cp_mismatch(&self, expected: &str, found: &CpInfoInner) -> VmError {
+        VmError::ClassFormat(format!(
+            "Expected constant pool entry {} in {}, found {:?}",
+            expected, self.name, found
+        ))
+    }
+}
+
+/// A symbolic reference to a field or method from the constant pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemberRef<'a> {
+    pub class: &'a str,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+}
+
+/// The value a field has before it is assigned
+pub fn default_value(ty: &FieldType) -> Value {
+    match ty {
+        FieldType::Byte
+        | FieldType::Char
+        | FieldType::Int
+        | FieldType::Short
+        | FieldType::Boolean => Value::Int(0),
+        FieldType::Long => Value::Long(0),
+        FieldType::Float => Value::Float(0.0),
+        FieldType::Double => Value::Double(0.0),
+        FieldType::Object(_) | FieldType::Array(_) => Value::NULL,
+    }
+}
+
+/// The name of the array class with the given component class name
+pub fn array_class_name(component: &str) -> String {
+    if component.starts_with('[') {
+        format!("[{}", component)
+    } else {
+        format!("[L{};", component)
+    }
+}
+
+/// The name of the component class of an array class name with a reference component,
+/// for example `java/lang/String` for `[Ljava/lang/String;` and `[I` for `[[I`
+pub fn component_class_name(array_class: &str) -> &str {
+    let component = &array_class[1..];
+    match component.strip_prefix('L') {
+        Some(name) => name.strip_suffix(';').unwrap_or(name),
+        None => component,
+    }
+}
+
+fn line_numbers(attributes: &[AttributeInfo]) -> Vec<AttributeLineNumber> {
+    let mut lines = attributes
+        .iter()
+        .filter_map(|attr| match &attr.inner {
+            AttributeInfoInner::LineNumberTable { line_number_table } => Some(line_number_table),
+            _ => None,
+        })
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>();
+    lines.sort_by_key(|line| line.start_pc);
+    lines
+}
+
+pub(crate) fn parse_field_type(descriptor: &str) -> Result<FieldType> {
+    FieldDescriptor::from_str(descriptor)
+        .map(|desc| desc.0)
+        .map_err(|err| {
+            VmError::ClassFormat(format!("Invalid descriptor {}: {}", descriptor, err.0))
+        })
+}
+
+impl Vm {
+    pub fn class(&self, id: ClassId) -> &Class {
+        &self.classes[id.index()]
+    }
+
+    pub fn class_mut(&mut self, id: ClassId) -> &mut Class {
+        &mut self.classes[id.index()]
+    }
+
+    /// Looks up a class that has already been loaded
+    pub fn loaded_class(&self, name: &str) -> Option<ClassId> {
+        self.class_names.get(name).copied()
+    }
+
+    /// Returns the class with the name, loading it from the class path if needed
+    pub fn resolve_class(&mut self, name: &str) -> Result<ClassId> {
+        if let Some(id) = self.loaded_class(name) {
+            return Ok(id);
+        }
+
+        if name.starts_with('[') {
+            return self.define_array_class(name);
+        }
+
+        // the boot classes come first, like the boot class path
+        let boot_class = match &self.boot_image {
+            Some(image) => image.class_file(name),
+            None => runtime::class_file(name),
+        };
+        let bytes = match boot_class.or(self.class_files.get(name).map(Vec::as_slice)) {
+            Some(bytes) => bytes.to_vec(),
+            None => self
+                .class_path
+                .iter()
+                .find_map(|dir| std::fs::read(dir.join(format!("{}.class", name))).ok())
+                .ok_or_else(|| VmError::ClassNotFound(name.to_string()))?,
+        };
+
+        let id = self.load_class(&bytes)?;
+        if self.class(id).name != name {
+            return Err(VmError::ClassNotFound(format!(
+                "{} (wrong name: {})",
+                name,
+                self.class(id).name
+            )));
+        }
+        Ok(id)
+    }
+
+    /// Resolves a class name from the constant pool of `current`. Hidden classes are not found
+    /// by their name, so this is the only way they can refer to themselves
+    pub(crate) fn resolve_class_from(&mut self, current: ClassId, name: &str) -> Result<ClassId> {
+        if self.class(current).name == name {
+            return Ok(current);
+        }
+        self.resolve_class(name)
+    }
+
+    /// Parses and defines a class from its class file bytes
+    pub fn load_class(&mut self, bytes: &[u1]) -> Result<ClassId> {
+        let file = cs_parser::parse_class_file(bytes)
+            .map_err(|err| VmError::ClassFormat(err.to_string()))?;
+        self.define_class(file)
+    }
+
+    /// Defines a class, loading its superclass and interfaces first
+    pub fn define_class(&mut self, file: ClassFile) -> Result<ClassId> {
+        self.define(file, false)
+    }
+
+    /// Defines a hidden class from its class file bytes, see `Lookup.defineHiddenClass`.
+    /// It is not registered by its name, so many hidden classes can have the same name
+    pub fn define_hidden_class(&mut self, bytes: &[u1]) -> Result<ClassId> {
+        let file = cs_parser::parse_class_file(bytes)
+            .map_err(|err| VmError::ClassFormat(err.to_string()))?;
+        self.define(file, true)
+    }
+
+    fn define(&mut self, file: ClassFile, hidden: bool) -> Result<ClassId> {
+        let cp = &file.constant_pool;
+        let name = file.this_class.get(cp).name_index.get(cp).to_string();
+
+        if !hidden && self.class_names.contains_key(&name) {
+            return Err(VmError::Linkage(format!(
+                "Duplicate class definition: {}",
+                name
+            )));
+        }
+        if self.loading.contains(&name) {
+            return Err(VmError::Linkage(format!("Class circularity: {}", name)));
+        }
+
+        self.loading.push(name.clone());
+        let supers = self.resolve_supers(&file);
+        self.loading.pop();
+        let (super_class, interfaces) = supers?;
+
+        let id = ClassId(self.classes.len() as u32);
+
+        let mut instance_fields = match super_class {
+            Some(super_class) => self.class(super_class).instance_fields.clone(),
+            None => Vec::new(),
+        };
+        let mut static_fields = Vec::new();
+        let mut declared_fields = Vec::new();
+
+        for field in &file.fields {
+            let constant_value = field.attributes.iter().find_map(|attr| match attr.inner {
+                AttributeInfoInner::ConstantValue {
+                    constantvalue_index,
+                } => Some(constantvalue_index.inner()),
+                _ => None,
+            });
+            let field = Field {
+                name: field.name_index.get(cp).to_string(),
+                descriptor: parse_field_type(field.descriptor_index.get(cp))?,
+                access_flags: field.access_flags,
+                class: id,
+                constant_value,
+                metadata: reflect::metadata(&field.attributes, cp),
+            };
+            if field.access_flags & cs_parser::FieldAccessFlags::STATIC as u2 != 0 {
+                declared_fields.push(FieldSlot::Static(static_fields.len()));
+                static_fields.push(field);
+            } else {
+                declared_fields.push(FieldSlot::Instance(instance_fields.len()));
+                instance_fields.push(field);
+            }
+        }
+        for &(_, field_name, descriptor) in INJECTED_FIELDS
+            .iter()
+            .filter(|(class, _, _)| *class == name)
+        {
+            instance_fields.push(Field {
+                name: field_name.to_string(),
+                descriptor: parse_field_type(descriptor)?,
+                access_flags: cs_parser::FieldAccessFlags::PRIVATE as u2,
+                class: id,
+                constant_value: None,
+                metadata: Metadata::default(),
+            });
+        }
+
+        let methods = file
+            .methods
+            .iter()
+            .map(|method| {
+                let descriptor = method.descriptor_index.get(cp);
+                Ok(Method {
+                    name: method.name_index.get(cp).to_string(),
+                    descriptor: descriptor.to_string(),
+                    method_descriptor: MethodDescriptor::from_str(descriptor).map_err(|err| {
+                        VmError::ClassFormat(format!(
+                            "Invalid descriptor {}: {}",
+                            descriptor, err.0
+                        ))
+                    })?,
+                    access_flags: method.access_flags,
+                    code: method.attributes.iter().find_map(|attr| match &attr.inner {
+                        AttributeInfoInner::Code {
+                            max_stack,
+                            max_locals,
+                            code,
+                            exception_table,
+                            attributes,
+                        } => Some(Rc::new(Code::new(
+                            *max_stack,
+                            *max_locals,
+                            code.clone(),
+                            exception_table.clone(),
+                            line_numbers(attributes),
+                        ))),
+                        _ => None,
+                    }),
+                    vtable_index: None,
+                    metadata: reflect::metadata(&method.attributes, cp),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let static_values = static_fields
+            .iter()
+            .map(|field| default_value(&field.descriptor))
+            .collect();
+
+        let source_file = file.attributes.iter().find_map(|attr| match attr.inner {
+            AttributeInfoInner::SourceFile { sourcefile_index } => {
+                Some(sourcefile_index.get(cp).to_string())
+            }
+            _ => None,
+        });
+        let inner_class = file.attributes.iter().find_map(|attr| match &attr.inner {
+            AttributeInfoInner::InnerClasses { classes } => classes
+                .iter()
+                .find(|inner| inner.inner_class_info_index.get(cp).name_index.get(cp) == name)
+                .map(|inner| InnerClass {
+                    outer_class: inner
+                        .outer_class_info_index
+                        .maybe_get(cp)
+                        .map(|class| class.name_index.get(cp).to_string()),
+                    simple_name: inner
+                        .inner_class_name_index
+                        .maybe_get(cp)
+                        .map(str::to_string),
+                    access_flags: inner.inner_class_access_flags,
+                }),
+            _ => None,
+        });
+        let enclosing_method = file.attributes.iter().find_map(|attr| match attr.inner {
+            AttributeInfoInner::EnclosingMethod {
+                class_index,
+                method_index,
+            } => Some(EnclosingMethod {
+                class: class_index.get(cp).name_index.get(cp).to_string(),
+                method: method_index.maybe_get(cp).map(|method| {
+                    (
+                        method.name_index.get(cp).to_string(),
+                        method.descriptor_index.get(cp).to_string(),
+                    )
+                }),
+            }),
+            _ => None,
+        });
+        let bootstrap_methods = file
+            .attributes
+            .iter()
+            .find_map(|attr| match &attr.inner {
+                AttributeInfoInner::BootstrapMethods { bootstrap_methods } => Some(
+                    bootstrap_methods
+                        .iter()
+                        .map(|method| BootstrapMethod {
+                            method: method.bootstrap_method_ref.inner(),
+                            arguments: method
+                                .bootstrap_arguments
+                                .iter()
+                                .map(|argument| argument.inner())
+                                .collect(),
+                        })
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        let metadata = reflect::metadata(&file.attributes, cp);
+
+        self.classes.push(Class {
+            name: name.clone(),
+            access_flags: file.access_flags,
+            major_version: file.major_version,
+            super_class,
+            interfaces,
+            kind: ClassKind::Class,
+            constant_pool: file.constant_pool,
+            instance_fields,
+            static_fields,
+            static_values,
+            methods,
+            vtable: Vec::new(),
+            itable: Vec::new(),
+            conflicting_defaults: Vec::new(),
+            init_state: InitState::Uninitialized,
+            source_file,
+            inner_class,
+            enclosing_method,
+            nest_host: None,
+            bootstrap_methods,
+            declared_fields,
+            metadata,
+            hidden,
+            mirror: None,
+            resolved_constants: HashMap::new(),
+            call_sites: HashMap::new(),
+            dynamic_constants: HashMap::new(),
+        });
+        // the parser does not know the `NestHost` attribute, its content is the index of the class
+        let nest_host = file.attributes.iter().find_map(|attr| match &attr.inner {
+            AttributeInfoInner::Unknown { attribute_content }
+                if attr.attribute_name_index.get(&self.class(id).constant_pool) == "NestHost" =>
+            {
+                Some(u16::from_be_bytes([
+                    *attribute_content.first()?,
+                    *attribute_content.get(1)?,
+                ]))
+            }
+            _ => None,
+        });
+        let nest_host = nest_host
+            .map(|index| Ok(self.class(id).cp_class_name(index)?.to_string()))
+            .transpose();
+        let result = nest_host.and_then(|nest_host| {
+            self.class_mut(id).nest_host = nest_host;
+            self.link_methods(id)
+        });
+        if let Err(err) = result {
+            self.classes.pop();
+            return Err(err);
+        }
+        if !hidden {
+            self.class_names.insert(name, id);
+        }
+        Ok(id)
+    }
+
+    fn resolve_supers(&mut self, file: &ClassFile) -> Result<(Option<ClassId>, Vec<ClassId>)> {
+        let cp = &file.constant_pool;
+
+        let super_class = match file.super_class.maybe_get(cp) {
+            Some(class) => Some(self.resolve_class(class.name_index.get(cp))?),
+            None => None,
+        };
+
+        let interfaces = file
+            .interfaces
+            .iter()
+            .map(|interface| self.resolve_class(interface.get(cp).name_index.get(cp)))
+            .collect::<Result<_>>()?;
+
+        Ok((super_class, interfaces))
+    }
+
+    fn define_array_class(&mut self, name: &str) -> Result<ClassId> {
+        let component = match parse_field_type(name)? {
+            FieldType::Array(component) => *component,
+            _ => unreachable!("array class names start with ["),
+        };
+
+        // the element class has to be loaded before the array class
+        match &component {
+            FieldType::Object(element) => {
+                self.resolve_class(element)?;
+            }
+            FieldType::Array(_) => {
+                self.resolve_class(&name[1..])?;
+            }
+            _ => {}
+        }
+
+        let object = self.resolve_class("java/lang/Object")?;
+        let id = ClassId(self.classes.len() as u32);
+        self.classes.push(Class {
+            name: name.to_string(),
+            access_flags: ClassAccessFlag::Public as u2 | ClassAccessFlag::Final as u2,
+            major_version: 0,
+            super_class: Some(object),
+            interfaces: Vec::new(),
+            kind: ClassKind::Array(component),
+            constant_pool: Vec::new(),
+            instance_fields: Vec::new(),
+            static_fields: Vec::new(),
+            static_values: Vec::new(),
+            methods: Vec::new(),
+            vtable: self.class(object).vtable.clone(),
+            itable: Vec::new(),
+            conflicting_defaults: Vec::new(),
+            init_state: InitState::Initialized,
+            source_file: None,
+            inner_class: None,
+            enclosing_method: None,
+            nest_host: None,
+            bootstrap_methods: Vec::new(),
+            declared_fields: Vec::new(),
+            metadata: Metadata::default(),
+            hidden: false,
+            mirror: None,
+            resolved_constants: HashMap::new(),
+            call_sites: HashMap::new(),
+            dynamic_constants: HashMap::new(),
+        });
+        self.class_names.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    /// The class of a primitive type or `void` by its name, for example `int`
+    pub fn primitive_class(&mut self, name: &str) -> Result<ClassId> {
+        if let Some(id) = self.loaded_class(name) {
+            return Ok(id);
+        }
+        if !PRIMITIVE_NAMES.contains(&name) {
+            return Err(VmError::ClassNotFound(name.to_string()));
+        }
+
+        let id = ClassId(self.classes.len() as u32);
+        self.classes.push(Class {
+            name: name.to_string(),
+            access_flags: ClassAccessFlag::Public as u2
+                | ClassAccessFlag::Final as u2
+                | ClassAccessFlag::Abstract as u2,
+            major_version: 0,
+            super_class: None,
+            interfaces: Vec::new(),
+            kind: ClassKind::Primitive,
+            constant_pool: Vec::new(),
+            instance_fields: Vec::new(),
+            static_fields: Vec::new(),
+            static_values: Vec::new(),
+            methods: Vec::new(),
+            vtable: Vec::new(),
+            itable: Vec::new(),
+            conflicting_defaults: Vec::new(),
+            init_state: InitState::Initialized,
+            source_file: None,
+            inner_class: None,
+            enclosing_method: None,
+            nest_host: None,
+            bootstrap_methods: Vec::new(),
+            declared_fields: Vec::new(),
+            metadata: Metadata::default(),
+            hidden: false,
+            mirror: None,
+            resolved_constants: HashMap::new(),
+            call_sites: HashMap::new(),
+            dynamic_constants: HashMap::new(),
+        });
+        self.class_names.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    pub fn method(&self, id: MethodId) -> &Method {
+        &self.class(id.class).methods[id.index]
+    }
+
+    /// The name of the method for error messages, for example `Point.getX()I`
+    pub fn method_name(&self, id: MethodId) -> String {
+        let method = self.method(id);
+        format!(
+            "{}.{}{}",
+            self.class(id.class).name,
+            method.name,
+            method.descriptor
+        )
+    }
+
+    /// Finds a method in the class or its superclasses
+    pub fn lookup_method(&self, class: ClassId, name: &str, descriptor: &str) -> Option<MethodId> {
+        let mut current = Some(class);
+        while let Some(class) = current {
+            if let Some(index) = self.class(class).method_index(name, descriptor) {
+                return Some(MethodId { class, index });
+            }
+            current = self.class(class).super_class;
+        }
+        None
+    }
+
+    /// Finds a method in the superinterfaces of the class, see JVMS §5.4.3.3. This is the single
+    /// maximally-specific method that is not abstract if there is one, or any of them otherwise
+    pub fn lookup_interface_method(
+        &self,
+        class: ClassId,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<MethodId> {
+        let candidates = self.maximally_specific_methods(class, name, descriptor);
+        match candidates
+            .iter()
+            .filter(|&&method| !self.method(method).is_abstract())
+            .collect::<Vec<_>>()[..]
+        {
+            [&method] => Some(method),
+            _ => candidates.first().copied(),
+        }
+    }
+
+    /// The instance methods with the name and descriptor declared in superinterfaces of the class,
+    /// without the ones from interfaces that have a subinterface declaring the method as well
+    pub fn maximally_specific_methods(
+        &self,
+        class: ClassId,
+        name: &str,
+        descriptor: &str,
+    ) -> Vec<MethodId> {
+        let candidates = self
+            .all_interfaces(class)
+            .into_iter()
+            .filter_map(|interface| {
+                let index = self.class(interface).method_index(name, descriptor)?;
+                Some(MethodId {
+                    class: interface,
+                    index,
+                })
+            })
+            .filter(|&method| {
+                let method = self.method(method);
+                !method.is_static() && !method.is_private()
+            })
+            .collect::<Vec<_>>();
+
+        candidates
+            .iter()
+            .copied()
+            .filter(|candidate| {
+                !candidates.iter().any(|other| {
+                    other.class != candidate.class
+                        && self.is_assignable(other.class, candidate.class)
+                })
+            })
+            .collect()
+    }
+
+    /// All interfaces the class implements, directly or through superclasses and superinterfaces
+    pub fn all_interfaces(&self, class: ClassId) -> Vec<ClassId> {
+        let mut interfaces = Vec::new();
+        let mut worklist = vec![class];
+        while let Some(class) = worklist.pop() {
+            let class = self.class(class);
+            for &interface in class.interfaces.iter().rev() {
+                if !interfaces.contains(&interface) {
+                    interfaces.push(interface);
+                    worklist.push(interface);
+                }
+            }
+            worklist.extend(class.super_class);
+        }
+        interfaces
+    }
+
+    /// Whether a value of class `from` can be assigned to `to`, see `checkcast` in JVMS §6.5
+    pub fn is_assignable(&self, from: ClassId, to: ClassId) -> bool {
+        if from == to {
+            return true;
+        }
+        let (from_class, to_class) = (self.class(from), self.class(to));
+        match (&from_class.kind, &to_class.kind) {
+            (ClassKind::Array(from_component), ClassKind::Array(to_component)) => {
+                match (from_component, to_component) {
+                    (
+                        FieldType::Object(_) | FieldType::Array(_),
+                        FieldType::Object(_) | FieldType::Array(_),
+                    ) => {
+                        match (
+                            self.loaded_class(component_class_name(&from_class.name)),
+                            self.loaded_class(component_class_name(&to_class.name)),
+                        ) {
+                            (Some(from), Some(to)) => self.is_assignable(from, to),
+                            _ => false,
+                        }
+                    }
+                    _ => false,
+                }
+            }
+            (ClassKind::Array(_), ClassKind::Class) => {
+                to_class.super_class.is_none()
+                    || to_class.name == "java/lang/Cloneable"
+                    || to_class.name == "java/io/Serializable"
+            }
+            (ClassKind::Class, ClassKind::Array(_)) => false,
+            (ClassKind::Primitive, _) | (_, ClassKind::Primitive) => false,
+            (ClassKind::Class, ClassKind::Class) => {
+                if to_class.is_interface() {
+                    self.all_interfaces(from).contains(&to)
+                } else {
+                    self.is_subclass(from, to)
+                }
+            }
+        }
+    }
+
+    /// The host of the nest of the class, see JVMS §5.4.4.
+    /// A class whose host can not be loaded is the host of its own nest
+    pub fn nest_host(&mut self, class: ClassId) -> ClassId {
+        match self.class(class).nest_host.clone() {
+            Some(host) => self.resolve_class_from(class, &host).unwrap_or(class),
+            None => class,
+        }
+    }
+
+    /// Whether `class` is `superclass` or one of its subclasses
+    pub fn is_subclass(&self, class: ClassId, superclass: ClassId) -> bool {
+        let mut current = Some(class);
+        while let Some(class) = current {
+            if class == superclass {
+                return true;
+            }
+            current = self.class(class).super_class;
+        }
+        false
+    }
+}
@@ -0,0 +1,71 @@
+//!
+//! The time seen by Java code
+//!
+//! Usually this is the time of the system. When the scheduler is seeded with
+//! `VmOptions::schedule_seed`, the time is virtual instead: it advances by a fixed amount with
+//! every instruction and jumps to the next deadline when all threads wait, so sleeping takes
+//! no real time and every run with the same seed sees the same times
+//!
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How long an instruction takes in virtual time
+const NANOS_PER_INSTRUCTION: u64 = 100;
+
+/// The virtual time when the VM starts, 2020-09-13 12:26:40 UTC
+const VIRTUAL_EPOCH: Duration = Duration::from_secs(1_600_000_000);
+
+pub(crate) enum Clock {
+    System,
+    Virtual {
+        /// The instant the virtual time starts at, deadlines are instants like with the system time
+        origin: Instant,
+        elapsed: Duration,
+    },
+}
+
+impl Clock {
+    pub(crate) fn new(virtual_time: bool) -> Self {
+        match virtual_time {
+            true => Clock::Virtual {
+                origin: Instant::now(),
+                elapsed: Duration::ZERO,
+            },
+            false => Clock::System,
+        }
+    }
+
+    pub(crate) fn now(&self) -> Instant {
+        match self {
+            Clock::System => Instant::now(),
+            Clock::Virtual { origin, elapsed } => *origin + *elapsed,
+        }
+    }
+
+    /// The time since the Unix epoch, for `System.currentTimeMillis` and friends
+    pub(crate) fn since_epoch(&self) -> Duration {
+        match self {
+            Clock::System => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            Clock::Virtual { elapsed, .. } => VIRTUAL_EPOCH + *elapsed,
+        }
+    }
+
+    /// Advances the virtual time after an instruction
+    pub(crate) fn tick(&mut self) {
+        if let Clock::Virtual { elapsed, .. } = self {
+            *elapsed += Duration::from_nanos(NANOS_PER_INSTRUCTION);
+        }
+    }
+
+    /// Waits until the deadline, when no thread can run before it
+    pub(crate) fn wait_until(&mut self, deadline: Instant) {
+        match self {
+            Clock::System => std::thread::sleep(deadline.saturating_duration_since(Instant::now())),
+            Clock::Virtual { origin, elapsed } => {
+                *elapsed = (*elapsed).max(deadline.saturating_duration_since(*origin))
+            }
+        }
+    }
+}
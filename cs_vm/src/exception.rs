@@ -0,0 +1,300 @@
+//!
+//! Throwing and catching Java exceptions
+//!
+//! Errors of the VM that Java code can handle, like a null pointer, are turned into exception
+//! objects. An exception unwinds the frames until a handler in the exception table of a method
+//! matches it
+//!
+
+use crate::class::{ClassId, MethodId};
+use crate::heap::{Array, ObjRef};
+use crate::model::Value;
+use crate::{Result, Vm, VmError};
+use cs_model::FieldType;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+/// A frame in the stack trace of a throwable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackTraceElement {
+    /// The binary name with dots, for example `java.lang.Object`
+    pub class: String,
+    pub method: String,
+    pub file: Option<String>,
+    pub line: Option<u16>,
+    pub native: bool,
+}
+
+impl Display for StackTraceElement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}(", self.class, self.method)?;
+        match (&self.file, self.line) {
+            _ if self.native => write!(f, "Native Method")?,
+            (Some(file), Some(line)) => write!(f, "{}:{}", file, line)?,
+            (Some(file), None) => write!(f, "{}", file)?,
+            (None, _) => write!(f, "Unknown Source")?,
+        }
+        write!(f, ")")
+    }
+}
+
+impl Vm {
+    /// Creates the exception object for an error. Errors that Java code can not handle
+    /// are returned as they are, and so are errors if the exception class can not be loaded
+    pub(crate) fn exception_from_error(&mut self, err: VmError) -> Result<ObjRef> {
+        if let VmError::Exception(exception) = err {
+            return Ok(exception);
+        }
+        let name = match err.exception_class() {
+            Some(name) => name,
+            None => return Err(err),
+        };
+        self.new_exception(name, err.exception_message().as_deref())
+            .map_err(|_| err)
+    }
+
+    /// Creates an exception for a native method to throw. The result is meant to be returned
+    /// as the error of the native, it is an error of the VM if creating the exception failed
+    pub fn throw_new(&mut self, class: &str, message: Option<&str>) -> VmError {
+        match self.new_exception(class, message) {
+            Ok(exception) => VmError::Exception(exception),
+            Err(err) => err,
+        }
+    }
+
+    pub(crate) fn new_exception(&mut self, class: &str, message: Option<&str>) -> Result<ObjRef> {
+        let class = self.resolve_class(class)?;
+        let exception = self.new_throwable(class)?;
+        if let (Some(message), Some(slot)) = (
+            message,
+            self.throwable_field_slot("detailMessage", "java/lang/String"),
+        ) {
+            self.handles.push(exception);
+            let message = self.new_string(message);
+            self.handles.pop();
+            self.put_field(exception, slot, Value::Reference(Some(message?)))?;
+        }
+        Ok(exception)
+    }
+
+    /// Allocates a throwable with the stack trace of the current thread, without running a constructor
+    pub fn new_throwable(&mut self, class: ClassId) -> Result<ObjRef> {
+        self.initialize(class)?;
+        let throwable = self.new_object(class)?;
+        self.fill_backtrace(throwable)?;
+        Ok(throwable)
+    }
+
+    pub(crate) fn is_throwable(&self, class: ClassId) -> bool {
+        self.loaded_class("java/lang/Throwable")
+            .is_some_and(|throwable| self.is_subclass(class, throwable))
+    }
+
+    /// Records the frames of the current thread in the `backtrace` field of the throwable,
+    /// as an `int[]` with the class, method and pc of every frame. The frames of `fillInStackTrace`
+    /// and the constructors of the throwable are left out
+    pub(crate) fn fill_backtrace(&mut self, throwable: ObjRef) -> Result<()> {
+        let slot = match self.throwable_field_slot("backtrace", "java/lang/Object") {
+            Some(slot) => slot,
+            None => return Ok(()),
+        };
+        let class = self.heap.get(throwable).class;
+        let backtrace = self
+            .thread()
+            .frames
+            .iter()
+            .rev()
+            .skip_while(|frame| {
+                let method = self.method(frame.method);
+                method.name == "fillInStackTrace"
+                    || (method.name == "<init>" && self.is_subclass(class, frame.method.class))
+            })
+            .flat_map(|frame| {
+                [
+                    frame.method.class.0 as i32,
+                    frame.method.index as i32,
+                    frame.pc as i32,
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let int_array = self.resolve_class("[I")?;
+        self.handles.push(throwable);
+        let array = self.new_array(int_array, backtrace.len() as i32);
+        self.handles.pop();
+        let array = array?;
+
+        match self.array_mut(array)? {
+            Array::Int(elements) => elements.copy_from_slice(&backtrace),
+            _ => unreachable!("[I is an int array"),
+        }
+        self.put_field(throwable, slot, Value::Reference(Some(array)))?;
+
+        // the class library of the JDK creates the `StackTraceElement`s from the backtrace
+        let throwable_class = self.loaded_class("java/lang/Throwable");
+        if let Some(depth) = throwable_class.and_then(|class| {
+            self.class(class)
+                .instance_field_slot("depth", &FieldType::Int)
+        }) {
+            self.put_field(throwable, depth, Value::Int(backtrace.len() as i32 / 3))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn throwable_field_slot(&self, name: &str, class: &str) -> Option<usize> {
+        let throwable = self.loaded_class("java/lang/Throwable")?;
+        self.class(throwable)
+            .instance_field_slot(name, &FieldType::Object(class.to_string()))
+    }
+
+    /// Unwinds the frames above `base` until a handler for the exception is found and continues there.
+    /// If there is none, all frames above `base` are removed and the exception is returned as an error
+    pub(crate) fn dispatch_exception(&mut self, exception: ObjRef, base: usize) -> Result<()> {
+        // loading the catch types must not collect the exception
+        self.handles.push(exception);
+        let handler = self.find_handler(exception, base);
+        self.handles.pop();
+
+        match handler? {
+            Some(handler_pc) => {
+                let frame = self.frame_mut();
+                frame.stack.clear();
+                frame.stack.push(Value::Reference(Some(exception)))?;
+                frame.pc = handler_pc;
+                Ok(())
+            }
+            None => {
+                self.pop_frames(base);
+                Err(VmError::Exception(exception))
+            }
+        }
+    }
+
+    /// Pops frames until the top frame has a handler for the exception at its pc,
+    /// returns the pc of the handler
+    fn find_handler(&mut self, exception: ObjRef, base: usize) -> Result<Option<usize>> {
+        let class = self.heap.get(exception).class;
+        while self.thread().frames.len() > base {
+            let frame = self.frame_mut();
+            let (code, pc, current) = (frame.code.clone(), frame.pc, frame.method.class);
+
+            for handler in &code.exception_table {
+                if !(handler.start_pc as usize..handler.end_pc as usize).contains(&pc) {
+                    continue;
+                }
+                // a catch type of zero catches everything, it is used for `finally`
+                if handler.catch_type == 0 {
+                    return Ok(Some(handler.handler_pc as usize));
+                }
+                let catch_type = self.resolve_class_ref(current, handler.catch_type)?;
+                if self.is_subclass(class, catch_type) {
+                    return Ok(Some(handler.handler_pc as usize));
+                }
+            }
+
+            self.pop_frame();
+        }
+        Ok(None)
+    }
+
+    /// The stack trace that was recorded when the throwable was created, the innermost frame first
+    pub fn stack_trace(&self, throwable: ObjRef) -> Vec<StackTraceElement> {
+        (self.backtrace(throwable).into_iter())
+            .map(|(id, pc)| self.stack_trace_element(id, pc))
+            .collect()
+    }
+
+    /// The methods and pcs of the frames in the backtrace of the throwable
+    pub(crate) fn backtrace(&self, throwable: ObjRef) -> Vec<(MethodId, usize)> {
+        let backtrace = match self.throwable_field(throwable, "backtrace", "java/lang/Object") {
+            Some(backtrace) => backtrace,
+            None => return Vec::new(),
+        };
+        let backtrace = match self.array(backtrace) {
+            Ok(Array::Int(backtrace)) => backtrace,
+            _ => return Vec::new(),
+        };
+
+        backtrace
+            .chunks_exact(3)
+            .map(|entry| {
+                let id = MethodId {
+                    class: ClassId(entry[0] as u32),
+                    index: entry[1] as usize,
+                };
+                (id, entry[2] as usize)
+            })
+            .collect()
+    }
+
+    /// The element of a stack trace for a frame of the method at the pc
+    pub(crate) fn stack_trace_element(&self, id: MethodId, pc: usize) -> StackTraceElement {
+        let (class, method) = (self.class(id.class), self.method(id));
+        StackTraceElement {
+            class: class.name.replace('/', "."),
+            method: method.name.clone(),
+            file: class.source_file.clone(),
+            line: method.code.as_ref().and_then(|code| code.line_number(pc)),
+            native: method.is_native(),
+        }
+    }
+
+    /// Formats the stack trace of the throwable and its causes like `Throwable.printStackTrace`.
+    /// The detail message is used instead of calling `toString`
+    pub fn format_stack_trace(&self, throwable: ObjRef) -> String {
+        let mut out = String::new();
+        let mut enclosing = Vec::new();
+        let mut seen = Vec::new();
+        let mut current = Some(throwable);
+
+        while let Some(throwable) = current {
+            if seen.contains(&throwable) {
+                break;
+            }
+            if !seen.is_empty() {
+                out.push_str("Caused by: ");
+            }
+            seen.push(throwable);
+
+            let class = self.class(self.heap.get(throwable).class);
+            out.push_str(&class.name.replace('/', "."));
+            let message = self.throwable_field(throwable, "detailMessage", "java/lang/String");
+            if let Some(message) = message.and_then(|message| self.string_value(message).ok()) {
+                out.push_str(": ");
+                out.push_str(&message);
+            }
+            out.push('\n');
+
+            // the frames at the bottom that are the same as in the enclosing trace are omitted
+            let trace = self.stack_trace(throwable);
+            let common = trace
+                .iter()
+                .rev()
+                .zip(enclosing.iter().rev())
+                .take_while(|(frame, enclosing)| frame == enclosing)
+                .count();
+            for element in &trace[..trace.len() - common] {
+                out.push_str(&format!("\tat {}\n", element));
+            }
+            if common > 0 {
+                out.push_str(&format!("\t... {} more\n", common));
+            }
+
+            enclosing = trace;
+            current = self.throwable_field(throwable, "cause", "java/lang/Throwable");
+        }
+        out
+    }
+
+    /// Prints an exception that was not caught to `System.err`, like the JVM does
+    pub fn print_uncaught(&mut self, exception: ObjRef) {
+        let trace = self.format_stack_trace(exception);
+        let name = self.thread_name(self.current_thread);
+        let _ = write!(self.stderr, "Exception in thread \"{}\" {}", name, trace);
+    }
+
+    fn throwable_field(&self, throwable: ObjRef, name: &str, class: &str) -> Option<ObjRef> {
+        let slot = self.throwable_field_slot(name, class)?;
+        self.get_field(throwable, slot).ok()?.as_reference().ok()?
+    }
+}
@@ -0,0 +1,93 @@
+//!
+//! A stop-the-world mark-sweep garbage collector
+//!
+//! The roots are the frames, `java/lang/Thread` objects and locked objects of all threads,
+//! the static fields, mirrors, resolved constants, dynamically-computed constants and linked
+//! call sites of all classes, the interned strings and the handles of the VM
+//!
+
+use crate::call_site::{CallSite, DynamicConstant};
+use crate::heap::{Array, ObjRef, Object, ObjectData};
+use crate::model::Value;
+use crate::{Result, Vm, VmError};
+
+impl Vm {
+    /// Puts the object on the heap, collecting garbage first if the heap is full
+    /// or the VM runs in stress mode
+    pub fn alloc(&mut self, object: Object) -> Result<ObjRef> {
+        let size = object.size();
+        if self.options.gc_stress || self.heap.used() + size > self.options.max_heap {
+            self.gc();
+        }
+        if self.heap.used() + size > self.options.max_heap {
+            return Err(VmError::OutOfMemory);
+        }
+        Ok(self.heap.alloc(object))
+    }
+
+    /// Collects all objects that are not reachable from the roots.
+    /// Returns the number of freed objects
+    pub fn gc(&mut self) -> usize {
+        let mut marked = vec![false; self.heap.capacity()];
+        let mut worklist = Vec::new();
+
+        let mut mark = |value: &Value| {
+            if let Value::Reference(Some(obj)) = value {
+                worklist.push(*obj);
+            }
+        };
+
+        for thread in &self.threads {
+            mark(&Value::Reference(thread.object));
+            mark(&Value::Reference(thread.state.monitor()));
+            for frame in &thread.frames {
+                mark(&Value::Reference(frame.monitor));
+                frame.locals.values().iter().for_each(&mut mark);
+                frame.stack.values().iter().for_each(&mut mark);
+            }
+        }
+        for class in &self.classes {
+            class.static_values.iter().for_each(&mut mark);
+        }
+        for class in &self.classes {
+            worklist.extend(class.resolved_constants.values().copied());
+            worklist.extend(class.call_sites.values().filter_map(CallSite::appendix));
+            worklist.extend(
+                class
+                    .dynamic_constants
+                    .values()
+                    .filter_map(DynamicConstant::reference),
+            );
+        }
+        worklist.extend(self.mirrors.keys().copied());
+        worklist.extend(self.unnamed_module);
+        worklist.extend(self.monitors.keys().copied());
+        worklist.extend(self.strings.values().copied());
+        worklist.extend(self.handles.iter().copied());
+
+        while let Some(obj) = worklist.pop() {
+            if std::mem::replace(&mut marked[obj.index()], true) {
+                continue;
+            }
+            trace(self.heap.get(obj), &mut worklist);
+        }
+
+        self.heap.sweep(&marked)
+    }
+}
+
+/// Pushes all references of the object to the worklist
+fn trace(object: &Object, worklist: &mut Vec<ObjRef>) {
+    match &object.data {
+        ObjectData::Fields(fields) => {
+            worklist.extend(fields.iter().filter_map(|value| match value {
+                Value::Reference(obj) => *obj,
+                _ => None,
+            }))
+        }
+        ObjectData::Array(Array::Reference(elements)) => {
+            worklist.extend(elements.iter().flatten().copied())
+        }
+        ObjectData::Array(_) => {}
+    }
+}
@@ -0,0 +1,267 @@
+//!
+//! The heap where all objects and arrays live
+//!
+//! Objects are addressed through `ObjRef`s, which are indices into the heap
+//!
+
+use crate::class::ClassId;
+use crate::model::Value;
+
+/// A non-null reference to an object on the heap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjRef(u32);
+
+impl ObjRef {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// An object or array on the heap
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    /// The class of the object, for arrays this is the array class
+    pub class: ClassId,
+    pub data: ObjectData,
+}
+
+impl Object {
+    /// The approximate number of bytes the object uses, counted against the maximum heap size
+    pub fn size(&self) -> usize {
+        const HEADER: usize = 16;
+        HEADER
+            + match &self.data {
+                ObjectData::Fields(fields) => std::mem::size_of_val::<[Value]>(fields),
+                ObjectData::Array(array) => array.size(),
+            }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectData {
+    /// The instance fields of an object, indexed by the slots in `Class::instance_fields`
+    Fields(Box<[Value]>),
+    Array(Array),
+}
+
+/// The elements of an array, stored in their real size
+#[derive(Debug, Clone, PartialEq)]
+pub enum Array {
+    Boolean(Box<[i8]>),
+    Byte(Box<[i8]>),
+    Char(Box<[u16]>),
+    Short(Box<[i16]>),
+    Int(Box<[i32]>),
+    Long(Box<[i64]>),
+    Float(Box<[f32]>),
+    Double(Box<[f64]>),
+    Reference(Box<[Option<ObjRef>]>),
+}
+
+/// The `atype` operand of the `newarray` instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrimitiveArrayType {
+    Boolean = 4,
+    Char = 5,
+    Float = 6,
+    Double = 7,
+    Byte = 8,
+    Short = 9,
+    Int = 10,
+    Long = 11,
+}
+
+impl PrimitiveArrayType {
+    pub fn from_atype(atype: u8) -> Option<Self> {
+        Some(match atype {
+            4 => Self::Boolean,
+            5 => Self::Char,
+            6 => Self::Float,
+            7 => Self::Double,
+            8 => Self::Byte,
+            9 => Self::Short,
+            10 => Self::Int,
+            11 => Self::Long,
+            _ => return None,
+        })
+    }
+
+    /// The field descriptor char of the element type
+    pub fn descriptor(&self) -> char {
+        match self {
+            Self::Boolean => 'Z',
+            Self::Char => 'C',
+            Self::Float => 'F',
+            Self::Double => 'D',
+            Self::Byte => 'B',
+            Self::Short => 'S',
+            Self::Int => 'I',
+            Self::Long => 'J',
+        }
+    }
+}
+
+impl Array {
+    /// A new array with all elements set to their default value
+    pub fn new(ty: PrimitiveArrayType, len: usize) -> Self {
+        match ty {
+            PrimitiveArrayType::Boolean => Self::Boolean(vec![0; len].into()),
+            PrimitiveArrayType::Char => Self::Char(vec![0; len].into()),
+            PrimitiveArrayType::Float => Self::Float(vec![0.0; len].into()),
+            PrimitiveArrayType::Double => Self::Double(vec![0.0; len].into()),
+            PrimitiveArrayType::Byte => Self::Byte(vec![0; len].into()),
+            PrimitiveArrayType::Short => Self::Short(vec![0; len].into()),
+            PrimitiveArrayType::Int => Self::Int(vec![0; len].into()),
+            PrimitiveArrayType::Long => Self::Long(vec![0; len].into()),
+        }
+    }
+
+    /// A new array of references, all `null`
+    pub fn new_reference(len: usize) -> Self {
+        Self::Reference(vec![None; len].into())
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Boolean(arr) | Self::Byte(arr) => arr.len(),
+            Self::Char(arr) => arr.len(),
+            Self::Short(arr) => arr.len(),
+            Self::Int(arr) => arr.len(),
+            Self::Long(arr) => arr.len(),
+            Self::Float(arr) => arr.len(),
+            Self::Double(arr) => arr.len(),
+            Self::Reference(arr) => arr.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The size of the elements in bytes
+    fn size(&self) -> usize {
+        match self {
+            Self::Boolean(arr) | Self::Byte(arr) => std::mem::size_of_val::<[i8]>(arr),
+            Self::Char(arr) => std::mem::size_of_val::<[u16]>(arr),
+            Self::Short(arr) => std::mem::size_of_val::<[i16]>(arr),
+            Self::Int(arr) => std::mem::size_of_val::<[i32]>(arr),
+            Self::Long(arr) => std::mem::size_of_val::<[i64]>(arr),
+            Self::Float(arr) => std::mem::size_of_val::<[f32]>(arr),
+            Self::Double(arr) => std::mem::size_of_val::<[f64]>(arr),
+            Self::Reference(arr) => std::mem::size_of_val::<[Option<ObjRef>]>(arr),
+        }
+    }
+
+    /// Loads an element as a stack value. Returns `None` if the index is out of bounds
+    pub fn load(&self, index: usize) -> Option<Value> {
+        Some(match self {
+            Self::Boolean(arr) | Self::Byte(arr) => Value::Int(*arr.get(index)? as i32),
+            Self::Char(arr) => Value::Int(*arr.get(index)? as i32),
+            Self::Short(arr) => Value::Int(*arr.get(index)? as i32),
+            Self::Int(arr) => Value::Int(*arr.get(index)?),
+            Self::Long(arr) => Value::Long(*arr.get(index)?),
+            Self::Float(arr) => Value::Float(*arr.get(index)?),
+            Self::Double(arr) => Value::Double(*arr.get(index)?),
+            Self::Reference(arr) => Value::Reference(*arr.get(index)?),
+        })
+    }
+
+    /// Stores a stack value into the array, narrowing ints to the element type.
+    /// Returns `None` if the index is out of bounds or the value has the wrong type
+    pub fn store(&mut self, index: usize, value: Value) -> Option<()> {
+        match (self, value) {
+            (Self::Boolean(arr), Value::Int(n)) => *arr.get_mut(index)? = (n & 1) as i8,
+            (Self::Byte(arr), Value::Int(n)) => *arr.get_mut(index)? = n as i8,
+            (Self::Char(arr), Value::Int(n)) => *arr.get_mut(index)? = n as u16,
+            (Self::Short(arr), Value::Int(n)) => *arr.get_mut(index)? = n as i16,
+            (Self::Int(arr), Value::Int(n)) => *arr.get_mut(index)? = n,
+            (Self::Long(arr), Value::Long(n)) => *arr.get_mut(index)? = n,
+            (Self::Float(arr), Value::Float(n)) => *arr.get_mut(index)? = n,
+            (Self::Double(arr), Value::Double(n)) => *arr.get_mut(index)? = n,
+            (Self::Reference(arr), Value::Reference(r)) => *arr.get_mut(index)? = r,
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+/// All objects of the VM
+///
+/// Objects are never moved, an `ObjRef` stays valid until the object is collected.
+/// References that are only held in Rust variables are not seen by the garbage collector,
+/// so they have to be rooted in a frame or `Vm::handles` before allocating again
+#[derive(Debug, Default)]
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    /// Indices of free slots in `objects`
+    free: Vec<u32>,
+    /// The sum of the sizes of all live objects
+    used: usize,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts the object on the heap. This never collects, use `Vm::alloc` to respect the heap limit
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        self.used += object.size();
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index as usize] = Some(object);
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                ObjRef(self.objects.len() as u32 - 1)
+            }
+        }
+    }
+
+    pub fn get(&self, obj: ObjRef) -> &Object {
+        self.objects[obj.index()]
+            .as_ref()
+            .expect("dangling object reference")
+    }
+
+    pub fn get_mut(&mut self, obj: ObjRef) -> &mut Object {
+        self.objects[obj.index()]
+            .as_mut()
+            .expect("dangling object reference")
+    }
+
+    /// The number of live objects
+    pub fn len(&self) -> usize {
+        self.objects.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of bytes used by live objects
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// The number of slots, including free ones. All `ObjRef` indices are below this
+    pub(crate) fn capacity(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Frees all objects that are not marked, returns the number of freed objects
+    pub(crate) fn sweep(&mut self, marked: &[bool]) -> usize {
+        let mut freed = 0;
+        for (index, slot) in self.objects.iter_mut().enumerate() {
+            if !marked[index] {
+                if let Some(object) = slot.take() {
+                    self.used -= object.size();
+                    self.free.push(index as u32);
+                    freed += 1;
+                }
+            }
+        }
+        freed
+    }
+}
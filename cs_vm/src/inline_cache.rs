@@ -0,0 +1,164 @@
+//!
+//! Inline caches of `invokevirtual` and `invokeinterface`
+//!
+//! The first execution of a call site quickens it to `invokevirtual_quick` or
+//! `invokeinterface_quick` with an inline cache of the methods it selected, so that most calls
+//! skip the vtable or itable lookup.
+//!
+//! If no loaded class overrides the method of an `invokevirtual`, the cache holds that method for
+//! every receiver without looking at its class, see `CacheState::Unique`. Defining a class that
+//! overrides it invalidates the cache. Otherwise, the cache is monomorphic for the first receiver
+//! class and polymorphic for up to `POLYMORPHIC_LIMIT` classes, after that the call site is
+//! megamorphic and selects the method on every call
+//!
+
+use crate::class::{ClassId, MethodId};
+use crate::{Result, Vm, VmError};
+
+/// The number of receiver classes a cache holds before the call site is megamorphic
+pub const POLYMORPHIC_LIMIT: usize = 4;
+
+/// The methods an inline cache knows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheState {
+    /// The method that every receiver selects, because no loaded class overrides it
+    Unique(MethodId),
+    /// The selected methods by receiver class, empty until the first call
+    Receivers(Vec<(ClassId, MethodId)>),
+    /// More receiver classes than the cache can hold
+    Megamorphic,
+}
+
+/// The inline cache of an `invokevirtual` or `invokeinterface` call site
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineCache {
+    /// The method with the call site
+    pub caller: MethodId,
+    /// The pc of the call in the caller
+    pub pc: usize,
+    pub resolved: MethodId,
+    pub state: CacheState,
+    /// Calls that found their method in the cache
+    pub hits: u64,
+    pub misses: u64,
+    /// How often a newly defined class invalidated the cache
+    pub invalidations: u64,
+}
+
+impl InlineCache {
+    /// The share of calls that found their method in the cache
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            calls => self.hits as f64 / calls as f64,
+        }
+    }
+}
+
+impl Vm {
+    /// The inline caches of all call sites that were executed
+    pub fn inline_caches(&self) -> &[InlineCache] {
+        &self.inline_caches
+    }
+
+    /// Creates the inline cache of a call site, returns its index
+    pub(crate) fn new_inline_cache(
+        &mut self,
+        caller: MethodId,
+        pc: usize,
+        resolved: MethodId,
+        virtual_call: bool,
+    ) -> usize {
+        let state = match self.unique_method(resolved) {
+            Some(method) if virtual_call => CacheState::Unique(method),
+            _ => CacheState::Receivers(Vec::new()),
+        };
+        self.inline_caches.push(InlineCache {
+            caller,
+            pc,
+            resolved,
+            state,
+            hits: 0,
+            misses: 0,
+            invalidations: 0,
+        });
+        self.inline_caches.len() - 1
+    }
+
+    /// `invokevirtual_quick` and `invokeinterface_quick`: pops the arguments and pushes a frame
+    /// for the method the cache selects. Returns whether a frame was pushed
+    pub(crate) fn op_invoke_cached(&mut self, cache: usize) -> Result<bool> {
+        let resolved = self.inline_caches[cache].resolved;
+        let args = self.pop_args(resolved)?;
+        let this = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+        let selected = self.select_cached(cache, self.heap.get(this).class)?;
+        self.enter_method(selected, &args)
+    }
+
+    fn select_cached(&mut self, cache: usize, receiver: ClassId) -> Result<MethodId> {
+        let cache_ref = &mut self.inline_caches[cache];
+        let cached = match &cache_ref.state {
+            CacheState::Unique(method) => Some(*method),
+            CacheState::Receivers(receivers) => receivers
+                .iter()
+                .find(|&&(class, _)| class == receiver)
+                .map(|&(_, method)| method),
+            CacheState::Megamorphic => None,
+        };
+        if let Some(method) = cached {
+            cache_ref.hits += 1;
+            return Ok(method);
+        }
+        cache_ref.misses += 1;
+
+        let resolved = cache_ref.resolved;
+        let selected = self.select_method(receiver, resolved)?;
+        let cache_ref = &mut self.inline_caches[cache];
+        if let CacheState::Receivers(receivers) = &mut cache_ref.state {
+            if receivers.len() < POLYMORPHIC_LIMIT {
+                receivers.push((receiver, selected));
+            } else {
+                cache_ref.state = CacheState::Megamorphic;
+            }
+        }
+        Ok(selected)
+    }
+
+    /// The method that all loaded subclasses select for the resolved method of a class, if it
+    /// is not overridden. This is class hierarchy analysis
+    fn unique_method(&self, resolved: MethodId) -> Option<MethodId> {
+        let method = self.method(resolved);
+        if self.class(resolved.class).is_interface() || method.is_abstract() {
+            return None;
+        }
+        let Some(slot) = method.vtable_index else {
+            // private methods are not selected
+            return Some(resolved);
+        };
+        (0..self.classes.len())
+            .map(|index| ClassId(index as u32))
+            .filter(|&class| self.is_subclass(class, resolved.class))
+            .all(|class| self.class(class).vtable.get(slot) == Some(&resolved))
+            .then_some(resolved)
+    }
+
+    /// Invalidates the caches that assume no class overrides a method the new class overrides
+    pub(crate) fn invalidate_inline_caches(&mut self, class: ClassId) {
+        for index in 0..self.inline_caches.len() {
+            let CacheState::Unique(method) = self.inline_caches[index].state else {
+                continue;
+            };
+            let overridden = match self.method(method).vtable_index {
+                Some(slot) if self.is_subclass(class, method.class) => {
+                    self.class(class).vtable.get(slot) != Some(&method)
+                }
+                _ => false,
+            };
+            if overridden {
+                let cache = &mut self.inline_caches[index];
+                cache.state = CacheState::Receivers(Vec::new());
+                cache.invalidations += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//!
+//! Bytecode decoded ahead of execution
+//!
+//! When a class is defined, the code of every method is decoded once into instructions of a fixed
+//! size, stored at the pc of their opcode. The operands are read, branch targets are absolute,
+//! `wide` instructions become the instruction they modify and `iload_0` and friends become
+//! `iload` with an operand, so the interpreter never looks at the bytes again.
+//!
+//! Instructions that refer to the constant pool are quickened: once the interpreter resolved one,
+//! it rewrites it to a `_quick` variant that has the resolved class, field slot, method or
+//! constant as its operands, like the quick pseudo-instructions of the first edition of the JVM
+//! specification. The quick opcodes use the numbers that specification gave them
+//!
+
+use crate::opcode::{self, *};
+use cs_parser::u1;
+use std::cell::Cell;
+
+/// How the interpreter reads the code of methods
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InterpretMode {
+    /// Run the instructions decoded when the class was defined, and quicken them
+    #[default]
+    Decoded,
+    /// Decode every instruction from the bytes when it runs, and resolve it every time.
+    /// Only to measure what decoding ahead saves
+    Bytes,
+}
+
+/// What invalid opcodes, including the quick ones, are decoded to. The invalid opcode is the
+/// operand `a`
+pub(crate) const INVALID: u1 = 0xff;
+
+/// A decoded instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: u1,
+    /// The pc of the next instruction, zero if no instruction starts at this pc
+    pub next: u32,
+    /// The operands, what they mean depends on the opcode
+    pub a: i32,
+    pub b: i32,
+    pub c: i32,
+}
+
+impl Instruction {
+    /// At the pcs where no instruction starts
+    const NONE: Instruction = Instruction {
+        opcode: 0,
+        next: 0,
+        a: 0,
+        b: 0,
+        c: 0,
+    };
+
+    /// The instruction with its opcode replaced by the quick variant with the operands
+    pub(crate) fn quick(self, opcode: u1, a: i32, b: i32, c: i32) -> Self {
+        Self {
+            opcode,
+            a,
+            b,
+            c,
+            ..self
+        }
+    }
+}
+
+/// The targets of a `tableswitch` or `lookupswitch`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Switch {
+    Table {
+        low: i32,
+        /// The targets of the keys from `low` on
+        targets: Vec<usize>,
+        default: usize,
+    },
+    Lookup {
+        /// Sorted by key
+        pairs: Vec<(i32, usize)>,
+        default: usize,
+    },
+}
+
+impl Switch {
+    pub fn target(&self, key: i32) -> usize {
+        match self {
+            Switch::Table {
+                low,
+                targets,
+                default,
+            } => usize::try_from(key as i64 - *low as i64)
+                .ok()
+                .and_then(|index| targets.get(index))
+                .unwrap_or(default),
+            Switch::Lookup { pairs, default } => pairs
+                .binary_search_by_key(&key, |&(key, _)| key)
+                .map_or(default, |index| &pairs[index].1),
+        }
+        .to_owned()
+    }
+}
+
+/// Decodes the code of a method into instructions by pc, and the switches they refer to.
+/// Decoding stops at a truncated instruction
+pub(crate) fn decode(code: &[u1]) -> (Box<[Cell<Instruction>]>, Vec<Switch>) {
+    let mut instructions = vec![Instruction::NONE; code.len()];
+    let mut switches = Vec::new();
+    let mut pc = 0;
+    while let Some(len) = opcode::length(code, pc) {
+        instructions[pc] = decode_at(code, pc, len, &mut switches);
+        pc += len;
+    }
+    (instructions.into_iter().map(Cell::new).collect(), switches)
+}
+
+/// Decodes the instruction at `pc` from the bytes. The operand of a switch is the index that
+/// `decode` gave it
+pub(crate) fn decode_bytes(code: &[u1], pc: usize, switch: i32) -> Option<Instruction> {
+    let len = opcode::length(code, pc)?;
+    let instruction = decode_at(code, pc, len, &mut Vec::new());
+    Some(match instruction.opcode {
+        TABLESWITCH | LOOKUPSWITCH => Instruction {
+            a: switch,
+            ..instruction
+        },
+        _ => instruction,
+    })
+}
+
+fn decode_at(code: &[u1], pc: usize, len: usize, switches: &mut Vec<Switch>) -> Instruction {
+    // the operands were checked to be in the code by `opcode::length`
+    let u1 = |offset: usize| code[pc + offset];
+    let u2 = |offset: usize| u16::from_be_bytes([code[pc + offset], code[pc + offset + 1]]);
+    let i4 = |offset: usize| {
+        i32::from_be_bytes([
+            code[pc + offset],
+            code[pc + offset + 1],
+            code[pc + offset + 2],
+            code[pc + offset + 3],
+        ])
+    };
+    let target = |offset: i32| (pc as i64 + offset as i64) as i32;
+    let switch_target = |offset: i32| (pc as isize).wrapping_add(offset as isize) as usize;
+
+    let opcode = code[pc];
+    let (opcode, a, b) = match opcode {
+        BIPUSH => (opcode, u1(1) as i8 as i32, 0),
+        SIPUSH => (opcode, u2(1) as i16 as i32, 0),
+        LDC | NEWARRAY | RET => (opcode, u1(1) as i32, 0),
+        ILOAD..=ALOAD | ISTORE..=ASTORE => (opcode, u1(1) as i32, 0),
+        ILOAD_0..=ALOAD_3 => {
+            let n = opcode - ILOAD_0;
+            (ILOAD + n / 4, (n % 4) as i32, 0)
+        }
+        ISTORE_0..=ASTORE_3 => {
+            let n = opcode - ISTORE_0;
+            (ISTORE + n / 4, (n % 4) as i32, 0)
+        }
+        IINC => (opcode, u1(1) as i32, u1(2) as i8 as i32),
+        IFEQ..=JSR | IFNULL | IFNONNULL => (opcode, target(u2(1) as i16 as i32), 0),
+        GOTO_W | JSR_W => (opcode, target(i4(1)), 0),
+        LDC_W
+        | LDC2_W
+        | GETSTATIC..=INVOKEINTERFACE
+        | INVOKEDYNAMIC
+        | NEW
+        | ANEWARRAY
+        | CHECKCAST
+        | INSTANCEOF => (opcode, u2(1) as i32, 0),
+        MULTIANEWARRAY => (opcode, u2(1) as i32, u1(3) as i32),
+        WIDE => match u1(1) {
+            IINC => (IINC, u2(2) as i32, u2(4) as i16 as i32),
+            modified @ (ILOAD..=ALOAD | ISTORE..=ASTORE | RET) => (modified, u2(2) as i32, 0),
+            // the interpreter reports the invalid instruction
+            modified => (WIDE, modified as i32, 0),
+        },
+        TABLESWITCH | LOOKUPSWITCH => {
+            let start = ((pc + 4) & !3) - pc;
+            let default = switch_target(i4(start));
+            let switch = if opcode == TABLESWITCH {
+                let (low, high) = (i4(start + 4), i4(start + 8));
+                let count = (high as i64 - low as i64 + 1) as usize;
+                Switch::Table {
+                    low,
+                    targets: (0..count)
+                        .map(|i| switch_target(i4(start + 12 + 4 * i)))
+                        .collect(),
+                    default,
+                }
+            } else {
+                let mut pairs = (0..i4(start + 4) as usize)
+                    .map(|i| start + 8 + 8 * i)
+                    .map(|at| (i4(at), switch_target(i4(at + 4))))
+                    .collect::<Vec<_>>();
+                pairs.sort_by_key(|&(key, _)| key);
+                Switch::Lookup { pairs, default }
+            };
+            switches.push(switch);
+            (opcode, switches.len() as i32 - 1, 0)
+        }
+        invalid @ 0xca..=0xff => (INVALID, invalid as i32, 0),
+        _ => (opcode, 0, 0),
+    };
+    Instruction {
+        opcode,
+        next: (pc + len) as u32,
+        a,
+        b,
+        c: 0,
+    }
+}
@@ -0,0 +1,1039 @@
+//!
+//! The bytecode interpreter
+//!
+//! Calls between Java methods push a new frame and continue in the same loop, so deep
+//! Java recursion does not grow the Rust stack. Only calls from Rust, like static initializers,
+//! nest the interpreter loop
+//!
+
+use crate::class::{component_class_name, ClassId, InitState, MethodId};
+use crate::heap::{Array, ObjRef};
+use crate::instruction::{Instruction, InterpretMode, INVALID};
+use crate::model::{Frame, ThreadState, Value};
+use crate::opcode::{self, *};
+use crate::{Result, Vm, VmError};
+use cs_model::FieldType;
+use cs_parser::{u1, u2, ClassAccessFlag, CpInfoInner};
+use std::cmp::Ordering;
+
+/// `Some` if the current frame returned, containing the return value
+type Completion = Option<Option<Value>>;
+
+/// The type names of the `i`, `l`, `f`, `d` and `a` variants of instructions, in opcode order
+const TYPE_NAMES: [&str; 5] = ["int", "long", "float", "double", "reference"];
+
+macro_rules! binary {
+    ($vm:ident, $as:ident, $variant:ident, |$a:ident, $b:ident| $result:expr) => {{
+        let $b = $vm.pop()?.$as()?;
+        let $a = $vm.pop()?.$as()?;
+        $vm.push(Value::$variant($result))?;
+    }};
+}
+
+macro_rules! unary {
+    ($vm:ident, $as:ident, $variant:ident, |$a:ident| $result:expr) => {{
+        let $a = $vm.pop()?.$as()?;
+        $vm.push(Value::$variant($result))?;
+    }};
+}
+
+/// Shifts take an int as the shift distance, also for long values
+macro_rules! shift {
+    ($vm:ident, $as:ident, $variant:ident, |$a:ident, $b:ident| $result:expr) => {{
+        let $b = $vm.pop()?.as_int()? as u32;
+        let $a = $vm.pop()?.$as()?;
+        $vm.push(Value::$variant($result))?;
+    }};
+}
+
+impl Vm {
+    /// Invokes the method and runs it until it returns. For instance methods, the first
+    /// argument is `this`. Returns the return value, or `None` for `void` methods
+    pub fn invoke(&mut self, method: MethodId, args: &[Value]) -> Result<Option<Value>> {
+        if self.method(method).is_static() {
+            // the static initializer must not collect the arguments
+            let handles = self.handles.len();
+            let references = args
+                .iter()
+                .filter_map(|arg| arg.as_reference().ok().flatten());
+            self.handles.extend(references);
+            let result = self.initialize(method.class);
+            self.handles.truncate(handles);
+            result?;
+        }
+        if self.method(method).is_native() {
+            return self.invoke_native(method, args);
+        }
+        let base = self.thread().frames.len();
+        let result = self.push_frame(method, args).and_then(|()| self.run(base));
+        if result.is_err() {
+            self.pop_frames(base);
+        }
+        result
+    }
+
+    /// Runs `public static void main(String[])` of the class with the arguments, then waits
+    /// for the other threads that are not daemons. An uncaught exception of `main` is printed
+    /// to `System.err` and returned as the error
+    pub fn run_main(&mut self, class: &str, args: &[String]) -> Result<()> {
+        let class = self.resolve_class(class)?;
+        let main = self
+            .class(class)
+            .method_index("main", "([Ljava/lang/String;)V")
+            .map(|index| MethodId { class, index })
+            .filter(|&main| self.method(main).is_static())
+            .ok_or_else(|| {
+                VmError::NoSuchMethod(format!(
+                    "{}.main([Ljava/lang/String;)V",
+                    self.class(class).name
+                ))
+            })?;
+
+        let string_array = self.resolve_class("[Ljava/lang/String;")?;
+        let array = self.new_array(string_array, args.len() as i32)?;
+        self.handles.push(array);
+        let strings = args.iter().enumerate().try_for_each(|(index, arg)| {
+            let string = self.new_string(arg)?;
+            self.array_mut(array)?
+                .store(index, Value::Reference(Some(string)));
+            Ok(())
+        });
+        self.handles.pop();
+        strings?;
+
+        let result = self.invoke(main, &[Value::Reference(Some(array))]);
+        if let Err(VmError::Exception(exception)) = result {
+            self.print_uncaught(exception);
+        }
+        // like the JVM, the other threads keep running after an uncaught exception in `main`
+        if let Ok(_) | Err(VmError::Exception(_)) = result {
+            self.wait_for_threads()?;
+        }
+        result.map(|_| ())
+    }
+
+    /// Initializes the class if that has not happened yet, see JVMS §5.5.
+    /// This initializes the superclass, sets the constant static fields and runs `<clinit>`
+    pub fn initialize(&mut self, class: ClassId) -> Result<()> {
+        match self.class(class).init_state {
+            InitState::Initialized => return Ok(()),
+            // a recursive request from the initializer itself
+            InitState::Initializing(thread) if thread == self.current_thread => return Ok(()),
+            InitState::Initializing(_) => {
+                self.block(ThreadState::WaitingForClass { class });
+                return Err(VmError::Blocked);
+            }
+            InitState::Erroneous => {
+                return Err(VmError::NoClassDefFound(self.class(class).name.clone()))
+            }
+            InitState::Uninitialized => {}
+        }
+
+        self.class_mut(class).init_state = InitState::Initializing(self.current_thread);
+        // other threads only run while the initializer blocks
+        self.thread_mut().initializing += 1;
+        let result = self.run_initializer(class);
+        self.thread_mut().initializing -= 1;
+        self.class_mut(class).init_state = match result {
+            Ok(()) => InitState::Initialized,
+            // another thread initializes the superclass, this class is initialized after it
+            Err(VmError::Blocked) => InitState::Uninitialized,
+            Err(_) => InitState::Erroneous,
+        };
+        result.map_err(|err| self.initializer_error(err))
+    }
+
+    /// Wraps an exception of a static initializer in an `ExceptionInInitializerError`,
+    /// unless it is an `Error` already
+    fn initializer_error(&mut self, err: VmError) -> VmError {
+        let exception = match err {
+            VmError::Exception(exception) => exception,
+            err => return err,
+        };
+        let class = self.heap.get(exception).class;
+        if let Some(error) = self.loaded_class("java/lang/Error") {
+            if self.is_subclass(class, error) {
+                return err;
+            }
+        }
+
+        self.handles.push(exception);
+        let wrapper = self.new_exception("java/lang/ExceptionInInitializerError", None);
+        self.handles.pop();
+        let wrapper = match wrapper {
+            Ok(wrapper) => wrapper,
+            Err(err) => return err,
+        };
+        if let Some(slot) = self.throwable_field_slot("cause", "java/lang/Throwable") {
+            if let Err(err) = self.put_field(wrapper, slot, Value::Reference(Some(exception))) {
+                return err;
+            }
+        }
+        VmError::Exception(wrapper)
+    }
+
+    pub(crate) fn is_initialized(&self, class: ClassId) -> bool {
+        self.class(class).init_state == InitState::Initialized
+    }
+
+    fn run_initializer(&mut self, class: ClassId) -> Result<()> {
+        let class_ref = self.class(class);
+        if let (false, Some(super_class)) = (class_ref.is_interface(), class_ref.super_class) {
+            self.initialize(super_class)?;
+        }
+
+        let class_ref = self.class(class);
+        let constants = class_ref
+            .static_fields
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, field)| Some((slot, field.constant_value?)))
+            .collect::<Vec<_>>();
+        for (slot, index) in constants {
+            let value = match self.class(class).cp_entry(index)? {
+                CpInfoInner::String(_) => {
+                    Value::Reference(Some(self.resolve_constant(class, index)?))
+                }
+                _ => self.class(class).cp_numeric(index)?,
+            };
+            self.class_mut(class).static_values[slot] = value;
+        }
+
+        if let Some(index) = self.class(class).method_index("<clinit>", "()V") {
+            self.invoke(MethodId { class, index }, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Pushes a new frame for the method with the arguments stored in its local variables.
+    /// A `synchronized` method enters the monitor of `this` or of its class first
+    fn push_frame(&mut self, id: MethodId, args: &[Value]) -> Result<()> {
+        let frame = self.new_frame(id, args)?;
+        self.thread_mut().frames.push(frame);
+        if !self.method(id).is_synchronized() {
+            return self.jit_invoked();
+        }
+        // the arguments are rooted by the frame while the mirror is allocated
+        let monitor = match self.method(id).is_static() {
+            true => self.class_object(id.class),
+            false => args[0].as_reference()?.ok_or(VmError::NullPointer),
+        };
+        let monitor = monitor.inspect_err(|_| drop(self.thread_mut().frames.pop()))?;
+        self.frame_mut().monitor = Some(monitor);
+        self.monitor_enter(monitor);
+        self.jit_invoked()
+    }
+
+    /// Creates a frame for the method with the arguments stored in its local variables
+    pub(crate) fn new_frame(&mut self, id: MethodId, args: &[Value]) -> Result<Frame> {
+        let method = self.method(id);
+        let code = match &method.code {
+            Some(code) => code.clone(),
+            None if method.is_native() => {
+                return Err(VmError::UnsatisfiedLink(self.method_name(id)))
+            }
+            None => return Err(VmError::AbstractMethod(self.method_name(id))),
+        };
+        let arg_slots = method.arg_slots();
+
+        if self.thread().frames.len() >= self.options.max_stack_depth {
+            return Err(VmError::StackOverflow);
+        }
+
+        let mut frame = Frame::new(id, code);
+        let mut slot = 0;
+        for &arg in args {
+            frame.locals.store(slot, arg)?;
+            slot += arg.category() as u16;
+        }
+        if slot != arg_slots {
+            return Err(VmError::Verify(format!(
+                "{} takes {} argument slots, got {}",
+                self.method_name(id),
+                arg_slots,
+                slot
+            )));
+        }
+        Ok(frame)
+    }
+
+    /// Runs the current thread until only `base` frames are left, while the other threads
+    /// take turns. Returns the return value of the last method that returned
+    fn run(&mut self, base: usize) -> Result<Option<Value>> {
+        let owner = self.current_thread;
+        self.threads[owner].runs += 1;
+        let result = self.run_thread(owner, base);
+        self.current_thread = owner;
+        self.threads[owner].runs -= 1;
+        result
+    }
+
+    fn run_thread(&mut self, owner: usize, base: usize) -> Result<Option<Value>> {
+        loop {
+            self.schedule(Some(owner))?;
+            if self.current_thread != owner {
+                self.step_thread()?;
+                continue;
+            }
+            match self.step_current() {
+                Ok(None) => {}
+                Ok(Some(value)) if self.thread().frames.len() == base => return Ok(value),
+                Ok(Some(value)) => self.complete_invoke(value)?,
+                // the thread waits for a class, the instruction is executed again
+                Err(VmError::Blocked) => {}
+                Err(err) => {
+                    let exception = self.exception_from_error(err)?;
+                    self.dispatch_exception(exception, base)?;
+                }
+            }
+        }
+    }
+
+    /// Continues after the invoke instruction of the caller when a method returned
+    pub(crate) fn complete_invoke(&mut self, value: Option<Value>) -> Result<()> {
+        let frame = self.frame_mut();
+        let invoke = (frame.code.instruction(frame.pc))
+            .expect("the invoke instruction was already executed");
+        frame.pc = invoke.next as usize;
+        if let Some(value) = value {
+            self.push(value)?;
+        }
+        Ok(())
+    }
+
+    /// Executes a single instruction of the current frame
+    pub(crate) fn step(&mut self) -> Result<Completion> {
+        let frame = self.frame_mut();
+        let code = frame.code.clone();
+        let method = frame.method;
+        let class = method.class;
+        let pc = frame.pc;
+
+        let instruction = match self.options.interpret {
+            InterpretMode::Decoded => code.instruction(pc),
+            InterpretMode::Bytes => code.decode_instruction(pc),
+        };
+        let instruction = instruction.ok_or_else(|| {
+            VmError::Verify(match code.code.get(pc) {
+                None => format!("Execution fell off the code at {}", pc),
+                Some(&opcode) if opcode::length(&code.code, pc).is_none() => {
+                    format!("Truncated {} instruction at {}", opcode::name(opcode), pc)
+                }
+                Some(_) => format!("Execution continued inside an instruction at {}", pc),
+            })
+        })?;
+        let Instruction {
+            opcode, a, b, c, ..
+        } = instruction;
+        // the pc of the frame stays at the current instruction until it completes,
+        // so that exceptions are dispatched from it. Branches overwrite this,
+        // an invalid target is detected when fetching the next instruction
+        let mut next = instruction.next as usize;
+
+        match opcode {
+            NOP => {}
+            ACONST_NULL => self.push(Value::NULL)?,
+            ICONST_M1..=ICONST_5 => self.push(Value::Int(opcode as i32 - ICONST_0 as i32))?,
+            LCONST_0 | LCONST_1 => self.push(Value::Long((opcode - LCONST_0) as i64))?,
+            FCONST_0..=FCONST_2 => self.push(Value::Float((opcode - FCONST_0) as f32))?,
+            DCONST_0 | DCONST_1 => self.push(Value::Double((opcode - DCONST_0) as f64))?,
+            BIPUSH | SIPUSH => self.push(Value::Int(a))?,
+            LDC | LDC_W | LDC2_W => {
+                let value = self.op_ldc(class, a as u2)?;
+                if let Some((opcode, a, b, c)) = quick_constant(opcode, value) {
+                    code.quicken(pc, opcode, a, b, c);
+                }
+            }
+            LDC_QUICK | LDC_W_QUICK => self.push(match c {
+                0 => Value::Int(a),
+                _ => Value::Float(f32::from_bits(a as u32)),
+            })?,
+            LDC2_W_QUICK => {
+                let bits = (a as u32 as u64) << 32 | b as u32 as u64;
+                self.push(match c {
+                    0 => Value::Long(bits as i64),
+                    _ => Value::Double(f64::from_bits(bits)),
+                })?
+            }
+
+            ILOAD..=ALOAD => self.op_load(opcode - ILOAD, a as u2)?,
+            IALOAD..=SALOAD => self.op_array_load(opcode - IALOAD)?,
+            ISTORE..=ASTORE => self.op_store(opcode - ISTORE, a as u2)?,
+            IASTORE..=SASTORE => self.op_array_store(opcode - IASTORE)?,
+
+            POP => self.frame_mut().stack.discard()?,
+            POP2 => self.frame_mut().stack.discard2()?,
+            DUP => self.frame_mut().stack.dup()?,
+            DUP_X1 => self.frame_mut().stack.dup_x1()?,
+            DUP_X2 => self.frame_mut().stack.dup_x2()?,
+            DUP2 => self.frame_mut().stack.dup2()?,
+            DUP2_X1 => self.frame_mut().stack.dup2_x1()?,
+            DUP2_X2 => self.frame_mut().stack.dup2_x2()?,
+            SWAP => self.frame_mut().stack.swap()?,
+
+            IADD => binary!(self, as_int, Int, |a, b| a.wrapping_add(b)),
+            LADD => binary!(self, as_long, Long, |a, b| a.wrapping_add(b)),
+            FADD => binary!(self, as_float, Float, |a, b| a + b),
+            DADD => binary!(self, as_double, Double, |a, b| a + b),
+            ISUB => binary!(self, as_int, Int, |a, b| a.wrapping_sub(b)),
+            LSUB => binary!(self, as_long, Long, |a, b| a.wrapping_sub(b)),
+            FSUB => binary!(self, as_float, Float, |a, b| a - b),
+            DSUB => binary!(self, as_double, Double, |a, b| a - b),
+            IMUL => binary!(self, as_int, Int, |a, b| a.wrapping_mul(b)),
+            LMUL => binary!(self, as_long, Long, |a, b| a.wrapping_mul(b)),
+            FMUL => binary!(self, as_float, Float, |a, b| a * b),
+            DMUL => binary!(self, as_double, Double, |a, b| a * b),
+            IDIV => binary!(self, as_int, Int, |a, b| a.wrapping_div(non_zero(b)?)),
+            LDIV => binary!(self, as_long, Long, |a, b| a.wrapping_div(non_zero(b)?)),
+            FDIV => binary!(self, as_float, Float, |a, b| a / b),
+            DDIV => binary!(self, as_double, Double, |a, b| a / b),
+            IREM => binary!(self, as_int, Int, |a, b| a.wrapping_rem(non_zero(b)?)),
+            LREM => binary!(self, as_long, Long, |a, b| a.wrapping_rem(non_zero(b)?)),
+            // `%` on floats truncates like `fmod`, which is what Java does
+            FREM => binary!(self, as_float, Float, |a, b| a % b),
+            DREM => binary!(self, as_double, Double, |a, b| a % b),
+            INEG => unary!(self, as_int, Int, |a| a.wrapping_neg()),
+            LNEG => unary!(self, as_long, Long, |a| a.wrapping_neg()),
+            FNEG => unary!(self, as_float, Float, |a| -a),
+            DNEG => unary!(self, as_double, Double, |a| -a),
+            // `wrapping_shl` and friends mask the distance like Java does
+            ISHL => shift!(self, as_int, Int, |a, b| a.wrapping_shl(b)),
+            LSHL => shift!(self, as_long, Long, |a, b| a.wrapping_shl(b)),
+            ISHR => shift!(self, as_int, Int, |a, b| a.wrapping_shr(b)),
+            LSHR => shift!(self, as_long, Long, |a, b| a.wrapping_shr(b)),
+            IUSHR => shift!(self, as_int, Int, |a, b| (a as u32).wrapping_shr(b) as i32),
+            LUSHR => shift!(self, as_long, Long, |a, b| (a as u64).wrapping_shr(b)
+                as i64),
+            IAND => binary!(self, as_int, Int, |a, b| a & b),
+            LAND => binary!(self, as_long, Long, |a, b| a & b),
+            IOR => binary!(self, as_int, Int, |a, b| a | b),
+            LOR => binary!(self, as_long, Long, |a, b| a | b),
+            IXOR => binary!(self, as_int, Int, |a, b| a ^ b),
+            LXOR => binary!(self, as_long, Long, |a, b| a ^ b),
+            IINC => {
+                let index = a as u2;
+                let value = self.frame_mut().locals.load(index)?.as_int()?;
+                self.frame_mut()
+                    .locals
+                    .store(index, Value::Int(value.wrapping_add(b)))?
+            }
+
+            // `as` saturates and turns NaN into zero, which is what Java does
+            I2L => unary!(self, as_int, Long, |a| a as i64),
+            I2F => unary!(self, as_int, Float, |a| a as f32),
+            I2D => unary!(self, as_int, Double, |a| a as f64),
+            L2I => unary!(self, as_long, Int, |a| a as i32),
+            L2F => unary!(self, as_long, Float, |a| a as f32),
+            L2D => unary!(self, as_long, Double, |a| a as f64),
+            F2I => unary!(self, as_float, Int, |a| a as i32),
+            F2L => unary!(self, as_float, Long, |a| a as i64),
+            F2D => unary!(self, as_float, Double, |a| a as f64),
+            D2I => unary!(self, as_double, Int, |a| a as i32),
+            D2L => unary!(self, as_double, Long, |a| a as i64),
+            D2F => unary!(self, as_double, Float, |a| a as f32),
+            I2B => unary!(self, as_int, Int, |a| a as i8 as i32),
+            I2C => unary!(self, as_int, Int, |a| a as u16 as i32),
+            I2S => unary!(self, as_int, Int, |a| a as i16 as i32),
+
+            LCMP => binary!(self, as_long, Int, |a, b| a.cmp(&b) as i32),
+            FCMPL => binary!(self, as_float, Int, |a, b| float_compare(
+                a.partial_cmp(&b),
+                -1
+            )),
+            FCMPG => binary!(self, as_float, Int, |a, b| float_compare(
+                a.partial_cmp(&b),
+                1
+            )),
+            DCMPL => binary!(self, as_double, Int, |a, b| float_compare(
+                a.partial_cmp(&b),
+                -1
+            )),
+            DCMPG => binary!(self, as_double, Int, |a, b| float_compare(
+                a.partial_cmp(&b),
+                1
+            )),
+
+            IFEQ..=IFLE => {
+                let value = self.pop()?.as_int()?;
+                if condition(value.cmp(&0), opcode - IFEQ) {
+                    next = a as usize;
+                }
+            }
+            IF_ICMPEQ..=IF_ICMPLE => {
+                let value2 = self.pop()?.as_int()?;
+                let value1 = self.pop()?.as_int()?;
+                if condition(value1.cmp(&value2), opcode - IF_ICMPEQ) {
+                    next = a as usize;
+                }
+            }
+            IF_ACMPEQ | IF_ACMPNE => {
+                let value2 = self.pop()?.as_reference()?;
+                let value1 = self.pop()?.as_reference()?;
+                if (value1 == value2) == (opcode == IF_ACMPEQ) {
+                    next = a as usize;
+                }
+            }
+            IFNULL | IFNONNULL => {
+                let value = self.pop()?.as_reference()?;
+                if value.is_none() == (opcode == IFNULL) {
+                    next = a as usize;
+                }
+            }
+            GOTO | GOTO_W => next = a as usize,
+            TABLESWITCH | LOOKUPSWITCH => {
+                let key = self.pop()?.as_int()?;
+                next = code.switches[a as usize].target(key);
+            }
+
+            IRETURN..=ARETURN => {
+                let value = check_type(self.pop()?, opcode - IRETURN)?;
+                self.pop_frame();
+                return Ok(Some(Some(value)));
+            }
+            RETURN => {
+                self.pop_frame();
+                return Ok(Some(None));
+            }
+
+            // accesses to static fields are quickened once the class is initialized,
+            // until then every access has to check whether it is
+            GETSTATIC => {
+                let (field_class, slot) = self.resolve_static_field(class, a as u2)?;
+                if self.is_initialized(field_class) {
+                    code.quicken(pc, GETSTATIC_QUICK, field_class.0 as i32, slot as i32, 0);
+                }
+                self.push(self.class(field_class).static_values[slot])?
+            }
+            GETSTATIC_QUICK => {
+                self.push(self.class(ClassId(a as u32)).static_values[b as usize])?
+            }
+            PUTSTATIC => {
+                let (field_class, slot) = self.resolve_static_field(class, a as u2)?;
+                self.check_final_write(method, &self.class(field_class).static_fields[slot])?;
+                if self.is_initialized(field_class) {
+                    code.quicken(pc, PUTSTATIC_QUICK, field_class.0 as i32, slot as i32, 0);
+                }
+                let value = self.pop()?;
+                self.class_mut(field_class).static_values[slot] = value;
+            }
+            PUTSTATIC_QUICK => {
+                let value = self.pop()?;
+                self.class_mut(ClassId(a as u32)).static_values[b as usize] = value;
+            }
+            GETFIELD => {
+                let slot = self.op_getfield(class, a as u2)?;
+                code.quicken(pc, GETFIELD_QUICK, slot as i32, 0, 0);
+            }
+            GETFIELD_QUICK => self.op_getfield_quick(a as usize)?,
+            PUTFIELD => {
+                let slot = self.op_putfield(method, a as u2)?;
+                code.quicken(pc, PUTFIELD_QUICK, slot as i32, 0, 0);
+            }
+            PUTFIELD_QUICK => self.op_putfield_quick(a as usize)?,
+            // the pc is advanced when the invoked method returns, natives return right away
+            INVOKEVIRTUAL | INVOKESPECIAL | INVOKESTATIC | INVOKEINTERFACE => {
+                if self.op_invoke(method, pc, opcode, a as u2)? {
+                    return Ok(None);
+                }
+            }
+            INVOKEVIRTUAL_QUICK | INVOKEINTERFACE_QUICK => {
+                if self.op_invoke_cached(a as usize)? {
+                    return Ok(None);
+                }
+            }
+            INVOKENONVIRTUAL_QUICK | INVOKESTATIC_QUICK => {
+                let target = MethodId {
+                    class: ClassId(a as u32),
+                    index: b as usize,
+                };
+                if self.op_invoke_quick(opcode, target)? {
+                    return Ok(None);
+                }
+            }
+            INVOKEDYNAMIC => {
+                if self.op_invokedynamic(method, pc, a as u2)? {
+                    return Ok(None);
+                }
+            }
+
+            NEW => {
+                let new_class = self.op_new(class, a as u2)?;
+                if self.is_initialized(new_class) {
+                    code.quicken(pc, NEW_QUICK, new_class.0 as i32, 0, 0);
+                }
+            }
+            NEW_QUICK => self.op_new_quick(ClassId(a as u32))?,
+            NEWARRAY => self.op_newarray(a as u1)?,
+            ANEWARRAY => {
+                let array_class = self.op_anewarray(class, a as u2)?;
+                code.quicken(pc, ANEWARRAY_QUICK, array_class.0 as i32, 0, 0);
+            }
+            ANEWARRAY_QUICK => self.op_anewarray_quick(ClassId(a as u32))?,
+            MULTIANEWARRAY => self.op_multianewarray(class, a as u2, b as u1)?,
+            ARRAYLENGTH => {
+                let array = self.pop()?.as_reference()?.ok_or(VmError::NullPointer)?;
+                let len = self.array(array)?.len();
+                self.push(Value::Int(len as i32))?
+            }
+            CHECKCAST => {
+                let target = self.resolve_class_ref(class, a as u2)?;
+                code.quicken(pc, CHECKCAST_QUICK, target.0 as i32, 0, 0);
+                self.op_checkcast(target)?
+            }
+            CHECKCAST_QUICK => self.op_checkcast(ClassId(a as u32))?,
+            INSTANCEOF => {
+                let target = self.resolve_class_ref(class, a as u2)?;
+                code.quicken(pc, INSTANCEOF_QUICK, target.0 as i32, 0, 0);
+                self.op_instanceof(target)?
+            }
+            INSTANCEOF_QUICK => self.op_instanceof(ClassId(a as u32))?,
+            // a blocked thread continues after `monitorenter` once it holds the monitor
+            MONITORENTER => {
+                let obj = self.pop()?.as_reference()?.ok_or(VmError::NullPointer)?;
+                self.monitor_enter(obj);
+            }
+            MONITOREXIT => {
+                let obj = self.pop()?.as_reference()?.ok_or(VmError::NullPointer)?;
+                self.monitor_exit(obj)?;
+            }
+
+            // the valid `wide` instructions were decoded to the instruction they modify
+            WIDE => {
+                return Err(VmError::Verify(format!(
+                    "{} can not be modified by wide",
+                    opcode::name(a as u1)
+                )))
+            }
+
+            ATHROW => {
+                let exception = self.pop()?.as_reference()?.ok_or(VmError::NullPointer)?;
+                return Err(VmError::Exception(exception));
+            }
+            // class files since version 51 may not contain subroutines
+            JSR | JSR_W | RET => {
+                return Err(VmError::Unsupported(opcode::name(opcode).to_string()))
+            }
+            INVALID => return Err(VmError::Verify(format!("Invalid opcode {:#x}", a))),
+            _ => return Err(VmError::Verify(format!("Invalid opcode {:#x}", opcode))),
+        }
+
+        self.frame_mut().pc = next;
+        if next <= pc {
+            self.jit_back_edge()?;
+        }
+        Ok(None)
+    }
+
+    /// `ldc`, `ldc_w` and `ldc2_w`: pushes a constant from the constant pool and returns it
+    fn op_ldc(&mut self, class: ClassId, index: u2) -> Result<Value> {
+        let value = match self.class(class).cp_entry(index)? {
+            CpInfoInner::Integer(_)
+            | CpInfoInner::Float(_)
+            | CpInfoInner::Long(_)
+            | CpInfoInner::Double(_) => self.class(class).cp_numeric(index)?,
+            CpInfoInner::Dynamic(_) => self.resolve_dynamic_constant(class, index)?,
+            _ => Value::Reference(Some(self.resolve_constant(class, index)?)),
+        };
+        self.push(value)?;
+        Ok(value)
+    }
+
+    /// Resolves a `String`, `Class`, `MethodType` or `MethodHandle` constant to its object,
+    /// see JVMS §5.4.3. Every resolution of the same constant results in the same object
+    pub(crate) fn resolve_constant(&mut self, class: ClassId, index: u2) -> Result<ObjRef> {
+        if let Some(&obj) = self.class(class).resolved_constants.get(&index) {
+            return Ok(obj);
+        }
+        let obj = match self.class(class).cp_entry(index)? {
+            CpInfoInner::String(_) => {
+                let string = self.class(class).cp_string(index)?.to_string();
+                self.intern_string(&string)?
+            }
+            CpInfoInner::Class(_) => {
+                let resolved = self.resolve_class_ref(class, index)?;
+                self.class_object(resolved)?
+            }
+            CpInfoInner::MethodType(_) => {
+                let descriptor = self.class(class).cp_method_type(index)?.to_string();
+                self.method_type(&descriptor)?
+            }
+            CpInfoInner::MethodHandle(_) => self.method_handle_constant(class, index)?,
+            kind => return Err(VmError::Unsupported(format!("ldc of constant {:?}", kind))),
+        };
+        self.class_mut(class).resolved_constants.insert(index, obj);
+        Ok(obj)
+    }
+
+    /// `xload`: pushes a local variable, `ty` is the index in `TYPE_NAMES`
+    fn op_load(&mut self, ty: u1, index: u2) -> Result<()> {
+        let value = check_type(self.frame_mut().locals.load(index)?, ty)?;
+        self.push(value)
+    }
+
+    /// `xstore`: pops a value into a local variable, `ty` is the index in `TYPE_NAMES`
+    fn op_store(&mut self, ty: u1, index: u2) -> Result<()> {
+        let value = check_type(self.pop()?, ty)?;
+        self.frame_mut().locals.store(index, value)
+    }
+
+    /// `xaload`: ..., arrayref, index -> ..., value
+    fn op_array_load(&mut self, ty: u1) -> Result<()> {
+        let index = self.pop()?.as_int()?;
+        let array = self.pop()?.as_reference()?.ok_or(VmError::NullPointer)?;
+        let array = self.array(array)?;
+        check_array_type(array, ty)?;
+        let value = usize::try_from(index)
+            .ok()
+            .and_then(|index| array.load(index))
+            .ok_or(VmError::ArrayIndexOutOfBounds {
+                index,
+                len: array.len(),
+            })?;
+        self.push(value)
+    }
+
+    /// `xastore`: ..., arrayref, index, value -> ...
+    fn op_array_store(&mut self, ty: u1) -> Result<()> {
+        let value = self.pop()?;
+        let index = self.pop()?.as_int()?;
+        let array_ref = self.pop()?.as_reference()?.ok_or(VmError::NullPointer)?;
+        let array = self.array(array_ref)?;
+        check_array_type(array, ty)?;
+        let len = array.len();
+        let index = usize::try_from(index)
+            .ok()
+            .filter(|&index| index < len)
+            .ok_or(VmError::ArrayIndexOutOfBounds { index, len })?;
+
+        if let Value::Reference(Some(obj)) = value {
+            let array_class = self.class(self.heap.get(array_ref).class);
+            let component = self
+                .loaded_class(component_class_name(&array_class.name))
+                .expect("component classes are loaded before their array class");
+            let class = self.heap.get(obj).class;
+            if !self.is_assignable(class, component) {
+                return Err(VmError::ArrayStore(self.class(class).name.clone()));
+            }
+        }
+
+        self.array_mut(array_ref)?
+            .store(index, value)
+            .ok_or_else(|| {
+                VmError::Verify(format!(
+                    "Can not store {:?} in {}",
+                    value,
+                    self.class(self.heap.get(array_ref).class).name
+                ))
+            })
+    }
+
+    /// `checkcast`: ..., objectref -> ..., objectref
+    fn op_checkcast(&mut self, target: ClassId) -> Result<()> {
+        if let Some(obj) = self.frame_mut().stack.peek()?.as_reference()? {
+            let from = self.heap.get(obj).class;
+            if !self.is_assignable(from, target) {
+                return Err(VmError::ClassCast(format!(
+                    "class {} cannot be cast to class {}",
+                    self.class(from).name,
+                    self.class(target).name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// `instanceof`: ..., objectref -> ..., result
+    fn op_instanceof(&mut self, target: ClassId) -> Result<()> {
+        let result = match self.pop()?.as_reference()? {
+            Some(obj) => self.is_assignable(self.heap.get(obj).class, target),
+            None => false,
+        };
+        self.push(Value::Int(result as i32))
+    }
+
+    pub(crate) fn resolve_class_ref(&mut self, current: ClassId, index: u2) -> Result<ClassId> {
+        let name = self.class(current).cp_class_name(index)?.to_string();
+        self.resolve_class_from(current, &name)
+    }
+
+    /// Resolves a `Fieldref` to a static field and initializes the class declaring it.
+    /// Returns the class and the index of the field in `Class::static_fields`
+    fn resolve_static_field(&mut self, current: ClassId, index: u2) -> Result<(ClassId, usize)> {
+        let field = self.class(current).cp_field_ref(index)?;
+        let (class_name, name) = (field.class.to_string(), field.name.to_string());
+        let descriptor = crate::class::parse_field_type(field.descriptor)?;
+
+        let class = self.resolve_class_from(current, &class_name)?;
+        let (field_class, slot) = self
+            .find_static_field(class, &name, &descriptor)
+            .ok_or_else(|| VmError::NoSuchField(format!("{}.{}", class_name, name)))?;
+        self.initialize(field_class)?;
+        Ok((field_class, slot))
+    }
+
+    /// Looks for the field in the class, then its superinterfaces, then its superclass.
+    /// See JVMS §5.4.3.2
+    pub(crate) fn find_static_field(
+        &self,
+        class: ClassId,
+        name: &str,
+        descriptor: &FieldType,
+    ) -> Option<(ClassId, usize)> {
+        let class_ref = self.class(class);
+        if let Some(slot) = class_ref
+            .static_fields
+            .iter()
+            .position(|field| field.name == name && &field.descriptor == descriptor)
+        {
+            return Some((class, slot));
+        }
+        class_ref
+            .interfaces
+            .iter()
+            .find_map(|&interface| self.find_static_field(interface, name, descriptor))
+            .or_else(|| {
+                class_ref
+                    .super_class
+                    .and_then(|super_class| self.find_static_field(super_class, name, descriptor))
+            })
+    }
+
+    /// `invokevirtual`, `invokespecial`, `invokestatic` and `invokeinterface`:
+    /// pops the arguments and pushes a frame for the selected method
+    /// Returns whether a frame was pushed for the invoked method
+    fn op_invoke(&mut self, caller: MethodId, pc: usize, opcode: u1, index: u2) -> Result<bool> {
+        let current = caller.class;
+        let (method, interface) = self.class(current).cp_method_ref(index)?;
+        let (class_name, name, descriptor) = (
+            method.class.to_string(),
+            method.name.to_string(),
+            method.descriptor.to_string(),
+        );
+        if (opcode == INVOKEVIRTUAL && interface) || (opcode == INVOKEINTERFACE && !interface) {
+            return Err(VmError::IncompatibleClassChange(format!(
+                "{} on {}.{}{}",
+                opcode::name(opcode),
+                class_name,
+                name,
+                descriptor
+            )));
+        }
+
+        let class = self.resolve_class_from(current, &class_name)?;
+        // `invokeExact` and the like are virtual, the `linkTo` methods static
+        if opcode == INVOKEVIRTUAL || opcode == INVOKESTATIC {
+            if let Some(method) = self.signature_polymorphic_method(class, &name) {
+                return self.invoke_polymorphic(caller, pc, method, &descriptor);
+            }
+        }
+        let resolved = self.resolve_method(class, &name, &descriptor, interface)?;
+        if self.method(resolved).is_static() != (opcode == INVOKESTATIC) {
+            return Err(VmError::IncompatibleClassChange(format!(
+                "{} on {}",
+                opcode::name(opcode),
+                self.method_name(resolved)
+            )));
+        }
+
+        let code = self.frame_mut().code.clone();
+        if opcode == INVOKEVIRTUAL || opcode == INVOKEINTERFACE {
+            let cache = self.new_inline_cache(caller, pc, resolved, opcode == INVOKEVIRTUAL);
+            let quick = match opcode {
+                INVOKEVIRTUAL => INVOKEVIRTUAL_QUICK,
+                _ => INVOKEINTERFACE_QUICK,
+            };
+            code.quicken(pc, quick, cache as i32, 0, 0);
+            return self.op_invoke_cached(cache);
+        }
+        if opcode == INVOKESTATIC {
+            self.initialize(resolved.class)?;
+            if self.is_initialized(resolved.class) {
+                let (class, index) = (resolved.class.0 as i32, resolved.index as i32);
+                code.quicken(pc, INVOKESTATIC_QUICK, class, index, 0);
+            }
+            let args = self.pop_args(resolved)?;
+            return self.enter_method(resolved, &args);
+        }
+
+        let args = self.pop_args(resolved)?;
+        args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+        let selected = self.select_special(current, class, resolved)?;
+        let (class, index) = (selected.class.0 as i32, selected.index as i32);
+        code.quicken(pc, INVOKENONVIRTUAL_QUICK, class, index, 0);
+        self.enter_method(selected, &args)
+    }
+
+    /// `invokestatic_quick` and `invokenonvirtual_quick`: invokes the method that the first
+    /// execution of `invokestatic` or `invokespecial` selected
+    fn op_invoke_quick(&mut self, opcode: u1, method: MethodId) -> Result<bool> {
+        let args = self.pop_args(method)?;
+        if opcode == INVOKENONVIRTUAL_QUICK && args[0].as_reference()?.is_none() {
+            return Err(VmError::NullPointer);
+        }
+        self.enter_method(method, &args)
+    }
+
+    /// Pushes a frame for the method, or runs it if it is native and pushes its return value.
+    /// Returns whether a frame was pushed
+    pub(crate) fn enter_method(&mut self, method: MethodId, args: &[Value]) -> Result<bool> {
+        if self.method(method).is_native() {
+            match self.invoke_native(method, args) {
+                Ok(Some(value)) => self.push(value)?,
+                Ok(None) => {}
+                // the invoke instruction is executed again and pops the arguments again
+                Err(VmError::Blocked) => {
+                    for &arg in args {
+                        self.push(arg)?;
+                    }
+                    return Err(VmError::Blocked);
+                }
+                Err(err) => return Err(err),
+            }
+            return Ok(false);
+        }
+        self.push_frame(method, args)?;
+        Ok(true)
+    }
+
+    /// Pops the arguments of the method, including `this`, in order
+    pub(crate) fn pop_args(&mut self, method: MethodId) -> Result<Vec<Value>> {
+        let method = self.method(method);
+        let count = method.method_descriptor.parameters.len() + !method.is_static() as usize;
+        self.pop_values(count)
+    }
+
+    /// Pops `count` values, in the order they were pushed
+    pub(crate) fn pop_values(&mut self, count: usize) -> Result<Vec<Value>> {
+        let mut values = (0..count).map(|_| self.pop()).collect::<Result<Vec<_>>>()?;
+        values.reverse();
+        Ok(values)
+    }
+
+    /// Resolves a symbolic method reference, see JVMS §5.4.3.3 and §5.4.3.4
+    pub(crate) fn resolve_method(
+        &self,
+        class: ClassId,
+        name: &str,
+        descriptor: &str,
+        interface: bool,
+    ) -> Result<MethodId> {
+        if self.class(class).is_interface() != interface {
+            return Err(VmError::IncompatibleClassChange(format!(
+                "{} is {}an interface",
+                self.class(class).name,
+                if interface { "not " } else { "" }
+            )));
+        }
+
+        // for interfaces, this finds methods of the interface itself and of `java/lang/Object`
+        self.lookup_method(class, name, descriptor)
+            .or_else(|| self.lookup_interface_method(class, name, descriptor))
+            .ok_or_else(|| {
+                VmError::NoSuchMethod(format!("{}.{}{}", self.class(class).name, name, descriptor))
+            })
+    }
+
+    /// Selects the method that is invoked by `invokespecial`. For `super.method()` calls,
+    /// where `class` is the class named by the method reference, the method is selected
+    /// from the superclass, see JVMS §6.5
+    pub(crate) fn select_special(
+        &self,
+        current: ClassId,
+        class: ClassId,
+        resolved: MethodId,
+    ) -> Result<MethodId> {
+        let current_ref = self.class(current);
+        let is_super_call = self.method(resolved).name != "<init>"
+            && !self.class(class).is_interface()
+            && current_ref.access_flags & ClassAccessFlag::Super as u2 != 0
+            && current != class
+            && self.is_subclass(current, class);
+
+        match (is_super_call, current_ref.super_class) {
+            (true, Some(super_class)) => self.select_method(super_class, resolved),
+            _ if self.method(resolved).is_abstract() => {
+                Err(VmError::AbstractMethod(self.method_name(resolved)))
+            }
+            _ => Ok(resolved),
+        }
+    }
+}
+
+/// The quick variant of `ldc`, `ldc_w` or `ldc2_w` and its operands if the constant is numeric.
+/// The operand `c` is 0 for integral and 1 for floating point constants
+fn quick_constant(opcode: u1, value: Value) -> Option<(u1, i32, i32, i32)> {
+    let quick = if opcode == LDC {
+        LDC_QUICK
+    } else {
+        LDC_W_QUICK
+    };
+    match value {
+        Value::Int(n) => Some((quick, n, 0, 0)),
+        Value::Float(n) => Some((quick, n.to_bits() as i32, 0, 1)),
+        Value::Long(n) => Some((LDC2_W_QUICK, (n >> 32) as i32, n as i32, 0)),
+        Value::Double(n) => {
+            let bits = n.to_bits();
+            Some((LDC2_W_QUICK, (bits >> 32) as i32, bits as i32, 1))
+        }
+        _ => None,
+    }
+}
+
+fn non_zero<T: Default + PartialEq>(divisor: T) -> Result<T> {
+    if divisor == T::default() {
+        Err(VmError::Arithmetic("/ by zero".to_string()))
+    } else {
+        Ok(divisor)
+    }
+}
+
+/// The result of `fcmp` and `dcmp`, `nan` is the result if one of the values is NaN
+fn float_compare(ordering: Option<Ordering>, nan: i32) -> i32 {
+    ordering.map_or(nan, |ordering| ordering as i32)
+}
+
+/// Evaluates the condition of `if<cond>` and `if_icmp<cond>`, which are ordered
+/// `eq`, `ne`, `lt`, `ge`, `gt`, `le`
+fn condition(ordering: Ordering, cond: u1) -> bool {
+    match cond {
+        0 => ordering == Ordering::Equal,
+        1 => ordering != Ordering::Equal,
+        2 => ordering == Ordering::Less,
+        3 => ordering != Ordering::Less,
+        4 => ordering == Ordering::Greater,
+        _ => ordering != Ordering::Greater,
+    }
+}
+
+/// Checks that the value has the type with the index in `TYPE_NAMES`
+fn check_type(value: Value, ty: u1) -> Result<Value> {
+    match (ty, value) {
+        (0, Value::Int(_))
+        | (1, Value::Long(_))
+        | (2, Value::Float(_))
+        | (3, Value::Double(_))
+        | (4, Value::Reference(_)) => Ok(value),
+        _ => Err(VmError::Verify(format!(
+            "Expected {}, found {:?}",
+            TYPE_NAMES[ty as usize], value
+        ))),
+    }
+}
+
+/// Checks the array type of `xaload` and `xastore`, which are ordered
+/// `i`, `l`, `f`, `d`, `a`, `b`, `c`, `s`. `b` is used for both byte and boolean arrays
+fn check_array_type(array: &Array, ty: u1) -> Result<()> {
+    match (ty, array) {
+        (0, Array::Int(_))
+        | (1, Array::Long(_))
+        | (2, Array::Float(_))
+        | (3, Array::Double(_))
+        | (4, Array::Reference(_))
+        | (5, Array::Byte(_) | Array::Boolean(_))
+        | (6, Array::Char(_))
+        | (7, Array::Short(_)) => Ok(()),
+        _ => Err(VmError::Verify(
+            "Array instruction does not match the element type".to_string(),
+        )),
+    }
+}
@@ -0,0 +1,697 @@
+//!
+//! Objects of `java.lang.invoke`
+//!
+//! Like the JVM, the VM lets the class library of the JDK create method types and method handles
+//! by calling methods of `MethodHandleNatives`. The bundled class library has no `java.lang.invoke`,
+//! so these constants need a boot image.
+//!
+//! A resolved `MemberName` refers to its method with the injected `vmtarget` field of its
+//! `ResolvedMethodName`. Method handles run lambda forms, whose bytecode calls the target through
+//! the signature polymorphic `invokeBasic` and `linkTo*` methods, which the interpreter handles
+//! directly. `invokeExact`, `invoke` and the methods of `VarHandle` are linked by
+//! `MethodHandleNatives.linkMethod` to an invoker, like an `invokedynamic` call site.
+//!
+//! Rust code gets the method handle of a method with `Vm::method_handle` and calls it with
+//! `Vm::invoke_method_handle`
+//!
+
+use crate::call_site::CallSite;
+use crate::class::{parse_field_type, ClassId, MethodId};
+use crate::heap::ObjRef;
+use crate::jdk::{field_slot, STATIC_FIELD_OFFSET};
+use crate::model::Value;
+use crate::runtime::string_result;
+use crate::{Result, Vm, VmError};
+use cs_model::{MethodDescriptor, MethodType};
+use cs_parser::{u2, MethodAccessFlag};
+use std::str::FromStr;
+
+/// The reference kinds of method handles, see JVMS §5.4.3.5.
+/// The kinds up to `REF_putStatic` refer to fields, the others to methods
+const REF_GET_FIELD: u8 = 1;
+const REF_GET_STATIC: u8 = 2;
+const REF_PUT_FIELD: u8 = 3;
+const REF_PUT_STATIC: u8 = 4;
+const REF_INVOKE_VIRTUAL: u8 = 5;
+pub(crate) const REF_INVOKE_STATIC: u8 = 6;
+const REF_INVOKE_SPECIAL: u8 = 7;
+const REF_NEW_INVOKE_SPECIAL: u8 = 8;
+const REF_INVOKE_INTERFACE: u8 = 9;
+
+/// The flags of a `MemberName`, see `MethodHandleNatives.Constants`
+const MN_IS_METHOD: i32 = 0x0001_0000;
+const MN_IS_CONSTRUCTOR: i32 = 0x0002_0000;
+const MN_IS_FIELD: i32 = 0x0004_0000;
+const MN_REFERENCE_KIND_SHIFT: i32 = 24;
+const MN_REFERENCE_KIND_MASK: i32 = 0xF;
+
+/// The classes that declare signature polymorphic methods, see JVMS §2.9.3
+const SIGNATURE_POLYMORPHIC_CLASSES: &[&str] = &[
+    "java/lang/invoke/MethodHandle",
+    "java/lang/invoke/VarHandle",
+];
+
+impl Vm {
+    /// The `java/lang/invoke/MethodType` of a method descriptor
+    pub fn method_type(&mut self, descriptor: &str) -> Result<ObjRef> {
+        let descriptor = MethodDescriptor::from_str(descriptor).map_err(|err| {
+            VmError::ClassFormat(format!(
+                "Invalid method descriptor {}: {}",
+                descriptor, err.0
+            ))
+        })?;
+        let return_class = match &descriptor.return_ {
+            MethodType::Some(ty) => self.field_type_class(ty)?,
+            MethodType::Void => self.primitive_class("void")?,
+        };
+        let return_class = self.class_object(return_class)?;
+
+        let class_array = self.resolve_class("[Ljava/lang/Class;")?;
+        let parameters = self.new_array(class_array, descriptor.parameters.len() as i32)?;
+        self.handles.push(parameters);
+        let result = self.method_type_of(&descriptor, return_class, parameters);
+        self.handles.pop();
+        result
+    }
+
+    fn method_type_of(
+        &mut self,
+        descriptor: &MethodDescriptor,
+        return_class: ObjRef,
+        parameters: ObjRef,
+    ) -> Result<ObjRef> {
+        for (index, parameter) in descriptor.parameters.iter().enumerate() {
+            let class = self.field_type_class(parameter)?;
+            let class = self.class_object(class)?;
+            self.array_mut(parameters)?
+                .store(index, Value::Reference(Some(class)));
+        }
+        self.call_method_handle_natives(
+            "findMethodHandleType",
+            "(Ljava/lang/Class;[Ljava/lang/Class;)Ljava/lang/invoke/MethodType;",
+            &[
+                Value::Reference(Some(return_class)),
+                Value::Reference(Some(parameters)),
+            ],
+        )
+    }
+
+    /// Resolves a `MethodHandle` constant of the class, see JVMS §5.4.3.5.
+    /// The referenced class is resolved first
+    pub(crate) fn method_handle_constant(&mut self, class: ClassId, index: u2) -> Result<ObjRef> {
+        let (kind, member) = self.class(class).cp_method_handle(index)?;
+        let (class_name, name, descriptor) = (
+            member.class.to_string(),
+            member.name.to_string(),
+            member.descriptor.to_string(),
+        );
+        let declaring = self.resolve_class_from(class, &class_name)?;
+
+        let member_type = if kind <= REF_PUT_STATIC {
+            let ty = parse_field_type(&descriptor)?;
+            let ty = self.field_type_class(&ty)?;
+            self.class_object(ty)?
+        } else {
+            self.method_type(&descriptor)?
+        };
+        self.handles.push(member_type);
+        let result = self.link_method_handle_constant(class, kind, declaring, &name, member_type);
+        self.handles.pop();
+        result
+    }
+
+    /// A direct `java/lang/invoke/MethodHandle` of the method, as if its class had a
+    /// `MethodHandle` constant of it. Constructors create a new object
+    pub fn method_handle(&mut self, method: MethodId) -> Result<ObjRef> {
+        let method_ref = self.method(method);
+        let kind = if method_ref.is_static() {
+            REF_INVOKE_STATIC
+        } else if method_ref.name == "<init>" {
+            REF_NEW_INVOKE_SPECIAL
+        } else if self.class(method.class).is_interface() {
+            REF_INVOKE_INTERFACE
+        } else {
+            REF_INVOKE_VIRTUAL
+        };
+        let (name, descriptor) = (method_ref.name.clone(), method_ref.descriptor.clone());
+
+        let method_type = self.method_type(&descriptor)?;
+        self.handles.push(method_type);
+        let result =
+            self.link_method_handle_constant(method.class, kind, method.class, &name, method_type);
+        self.handles.pop();
+        result
+    }
+
+    /// Calls the method handle like `MethodHandle.invoke`, which converts the arguments and
+    /// the result. The arguments match the type of the method handle
+    pub fn invoke_method_handle(
+        &mut self,
+        handle: ObjRef,
+        args: &[Value],
+    ) -> Result<Option<Value>> {
+        let method_type = object_field(self, handle, "type")?.ok_or(VmError::NullPointer)?;
+        let descriptor = self.type_descriptor(method_type)?;
+        let descriptor = MethodDescriptor::from_str(&descriptor).map_err(|err| {
+            VmError::ClassFormat(format!("Invalid descriptor {}: {}", descriptor, err.0))
+        })?;
+        if descriptor.parameters.len() != args.len() {
+            return Err(self.throw_new(
+                "java/lang/invoke/WrongMethodTypeException",
+                Some(&format!(
+                    "expected {} arguments, got {}",
+                    descriptor.parameters.len(),
+                    args.len()
+                )),
+            ));
+        }
+
+        let handles = self.handles.len();
+        let result = self.invoke_with_arguments(handle, &descriptor, args);
+        self.handles.truncate(handles);
+        match (&descriptor.return_, result?) {
+            (MethodType::Void, _) => Ok(None),
+            (MethodType::Some(ty), result) => self.unbox_value(ty, result).map(Some),
+        }
+    }
+
+    /// Boxes the arguments and calls `MethodHandle.invokeWithArguments`.
+    /// The objects are added to the handles
+    fn invoke_with_arguments(
+        &mut self,
+        handle: ObjRef,
+        descriptor: &MethodDescriptor,
+        args: &[Value],
+    ) -> Result<Option<ObjRef>> {
+        self.handles.push(handle);
+        let object_array = self.resolve_class("[Ljava/lang/Object;")?;
+        let array = self.new_array(object_array, args.len() as i32)?;
+        self.handles.push(array);
+        for (index, (ty, &arg)) in descriptor.parameters.iter().zip(args).enumerate() {
+            let boxed = self.box_value(ty, arg)?;
+            self.array_mut(array)?.store(index, Value::Reference(boxed));
+        }
+
+        let class = self.heap.get(handle).class;
+        let invoke = self
+            .lookup_method(
+                class,
+                "invokeWithArguments",
+                "([Ljava/lang/Object;)Ljava/lang/Object;",
+            )
+            .ok_or_else(|| {
+                VmError::NoSuchMethod(
+                    "java/lang/invoke/MethodHandle.invokeWithArguments([Ljava/lang/Object;)\
+                    Ljava/lang/Object;"
+                        .to_string(),
+                )
+            })?;
+        let result = self.invoke(
+            invoke,
+            &[
+                Value::Reference(Some(handle)),
+                Value::Reference(Some(array)),
+            ],
+        )?;
+        result
+            .map(|value| value.as_reference())
+            .transpose()
+            .map(Option::flatten)
+    }
+
+    fn link_method_handle_constant(
+        &mut self,
+        caller: ClassId,
+        kind: u8,
+        declaring: ClassId,
+        name: &str,
+        member_type: ObjRef,
+    ) -> Result<ObjRef> {
+        let caller = self.class_object(caller)?;
+        let declaring = self.class_object(declaring)?;
+        let name = self.intern_string(name)?;
+        self.call_method_handle_natives(
+            "linkMethodHandleConstant",
+            "(Ljava/lang/Class;ILjava/lang/Class;Ljava/lang/String;Ljava/lang/Object;)\
+            Ljava/lang/invoke/MethodHandle;",
+            &[
+                Value::Reference(Some(caller)),
+                Value::Int(kind as i32),
+                Value::Reference(Some(declaring)),
+                Value::Reference(Some(name)),
+                Value::Reference(Some(member_type)),
+            ],
+        )
+    }
+
+    /// Calls a static method of `MethodHandleNatives` that returns an object
+    pub(crate) fn call_method_handle_natives(
+        &mut self,
+        name: &str,
+        descriptor: &str,
+        args: &[Value],
+    ) -> Result<ObjRef> {
+        self.call_method_handle_natives_nullable(name, descriptor, args)?
+            .ok_or(VmError::NullPointer)
+    }
+
+    /// Calls a static method of `MethodHandleNatives` that may return `null`
+    pub(crate) fn call_method_handle_natives_nullable(
+        &mut self,
+        name: &str,
+        descriptor: &str,
+        args: &[Value],
+    ) -> Result<Option<ObjRef>> {
+        let natives = self.resolve_class("java/lang/invoke/MethodHandleNatives")?;
+        let method = self
+            .lookup_method(natives, name, descriptor)
+            .ok_or_else(|| {
+                VmError::NoSuchMethod(format!(
+                    "java/lang/invoke/MethodHandleNatives.{}{}",
+                    name, descriptor
+                ))
+            })?;
+        Ok(self
+            .invoke(method, args)?
+            .map(|value| value.as_reference())
+            .transpose()?
+            .flatten())
+    }
+
+    /// Whether the method with the name in the class is signature polymorphic, see JVMS §2.9.3.
+    /// These are native varargs methods of `MethodHandle` and `VarHandle` that accept any descriptor
+    pub(crate) fn signature_polymorphic_method(
+        &self,
+        class: ClassId,
+        name: &str,
+    ) -> Option<MethodId> {
+        let class_ref = self.class(class);
+        if !SIGNATURE_POLYMORPHIC_CLASSES.contains(&class_ref.name.as_str()) {
+            return None;
+        }
+        let flags = MethodAccessFlag::NATIVE as u2 | MethodAccessFlag::VARARGS as u2;
+        let index = class_ref
+            .methods
+            .iter()
+            .position(|method| method.name == name && method.access_flags & flags == flags)?;
+        Some(MethodId { class, index })
+    }
+
+    /// Invokes a signature polymorphic method with the arguments on the operand stack, which
+    /// match the descriptor of the call site. Returns whether a frame was pushed
+    pub(crate) fn invoke_polymorphic(
+        &mut self,
+        current: MethodId,
+        pc: usize,
+        method: MethodId,
+        descriptor: &str,
+    ) -> Result<bool> {
+        let descriptor = MethodDescriptor::from_str(descriptor).map_err(|err| {
+            VmError::ClassFormat(format!("Invalid descriptor {}: {}", descriptor, err.0))
+        })?;
+        let arguments = descriptor.parameters.len();
+
+        let name = self.method(method).name.clone();
+        match name.as_str() {
+            // the receiver is a method handle, its lambda form has the method to run
+            "invokeBasic" => {
+                let args = self.pop_values(arguments + 1)?;
+                let handle = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let target = self.handle_entry(handle)?;
+                self.enter_method(target, &args)
+            }
+            // the trailing argument is the `MemberName` of the method
+            name @ ("linkToStatic" | "linkToSpecial" | "linkToVirtual" | "linkToInterface") => {
+                let mut args = self.pop_values(arguments)?;
+                let member = args
+                    .pop()
+                    .ok_or_else(|| VmError::Verify(format!("{} without a MemberName", name)))?
+                    .as_reference()?
+                    .ok_or(VmError::NullPointer)?;
+                let target = self.member_method(member)?;
+                let target = match name {
+                    "linkToStatic" => target,
+                    _ => {
+                        let receiver = args
+                            .first()
+                            .ok_or_else(|| VmError::Verify(format!("{} without a receiver", name)))?
+                            .as_reference()?
+                            .ok_or(VmError::NullPointer)?;
+                        match name {
+                            "linkToSpecial" => target,
+                            _ => self.select_method(self.heap.get(receiver).class, target)?,
+                        }
+                    }
+                };
+                self.enter_method(target, &args)
+            }
+            _ => {
+                let key = (current.index, pc);
+                let call_site = match self.class(current.class).call_sites.get(&key) {
+                    Some(call_site) => call_site.clone(),
+                    None => {
+                        let handles = self.handles.len();
+                        let invoker = self.link_method(current.class, method, &descriptor);
+                        self.handles.truncate(handles);
+                        let (invoker, appendix) = invoker?;
+                        let call_site = CallSite::Linked {
+                            invoker,
+                            appendix,
+                            arguments: arguments + 1,
+                        };
+                        self.class_mut(current.class)
+                            .call_sites
+                            .insert(key, call_site.clone());
+                        call_site
+                    }
+                };
+                self.invoke_call_site(&call_site)
+            }
+        }
+    }
+
+    /// Links a call of `invokeExact`, `invoke` or a `VarHandle` method with
+    /// `MethodHandleNatives.linkMethod`. Returns the invoker and the appendix,
+    /// the objects are added to the handles
+    fn link_method(
+        &mut self,
+        caller: ClassId,
+        method: MethodId,
+        descriptor: &MethodDescriptor,
+    ) -> Result<(MethodId, Option<ObjRef>)> {
+        let caller = self.class_object(caller)?;
+        let declaring = self.class_object(method.class)?;
+        let name = self.method(method).name.clone();
+        let name = self.intern_string(&name)?;
+        let method_type = self.method_type(&descriptor.to_string())?;
+        self.handles.push(method_type);
+        let object_array = self.resolve_class("[Ljava/lang/Object;")?;
+        let appendix = self.new_array(object_array, 1)?;
+        self.handles.push(appendix);
+
+        let member = self.call_method_handle_natives(
+            "linkMethod",
+            "(Ljava/lang/Class;ILjava/lang/Class;Ljava/lang/String;Ljava/lang/Object;\
+            [Ljava/lang/Object;)Ljava/lang/invoke/MemberName;",
+            &[
+                Value::Reference(Some(caller)),
+                Value::Int(REF_INVOKE_VIRTUAL as i32),
+                Value::Reference(Some(declaring)),
+                Value::Reference(Some(name)),
+                Value::Reference(Some(method_type)),
+                Value::Reference(Some(appendix)),
+            ],
+        )?;
+        let invoker = self.member_method(member)?;
+        let appendix = self.array(appendix)?.load(0).unwrap_or(Value::NULL);
+        Ok((invoker, appendix.as_reference()?))
+    }
+
+    /// The method that the lambda form of a method handle runs
+    fn handle_entry(&self, handle: ObjRef) -> Result<MethodId> {
+        let form = object_field(self, handle, "form")?.ok_or(VmError::NullPointer)?;
+        let entry = object_field(self, form, "vmentry")?.ok_or(VmError::NullPointer)?;
+        self.member_method(entry)
+    }
+
+    /// The method of a resolved `java/lang/invoke/MemberName`
+    pub(crate) fn member_method(&self, member: ObjRef) -> Result<MethodId> {
+        let resolved = object_field(self, member, "method")?
+            .ok_or_else(|| VmError::Linkage("MemberName is not resolved".to_string()))?;
+        let slot = field_slot(self, self.heap.get(resolved).class, "vmtarget")?;
+        let method = MethodId::from_long(self.get_field(resolved, slot)?.as_long()?);
+        let valid = self
+            .classes
+            .get(method.class.index())
+            .is_some_and(|class| method.index < class.methods.len());
+        if !valid {
+            return Err(VmError::Verify(format!(
+                "Invalid vmtarget {:?} of a MemberName",
+                method
+            )));
+        }
+        Ok(method)
+    }
+
+    /// Resolves a `MemberName` that names its class, name, type and reference kind,
+    /// like `MethodHandleNatives.resolve`. This sets the flags, the declaring class and the method
+    fn resolve_member_name(&mut self, member: ObjRef) -> Result<()> {
+        let class = object_field(self, member, "clazz")?.ok_or(VmError::NullPointer)?;
+        let class = self
+            .mirror_class(class)
+            .ok_or_else(|| VmError::Verify("Class object without a class".to_string()))?;
+        let name = object_field(self, member, "name")?.ok_or(VmError::NullPointer)?;
+        let name = self.string_value(name)?;
+        let ty = object_field(self, member, "type")?.ok_or(VmError::NullPointer)?;
+        let flags = int_field(self, member, "flags")?;
+        let kind = ((flags >> MN_REFERENCE_KIND_SHIFT) & MN_REFERENCE_KIND_MASK) as u8;
+
+        if flags & (MN_IS_METHOD | MN_IS_CONSTRUCTOR) != 0 {
+            let descriptor = self.type_descriptor(ty)?;
+            let method = match self.signature_polymorphic_method(class, &name) {
+                Some(method) => method,
+                None => {
+                    let interface = self.class(class).is_interface();
+                    self.resolve_method(class, &name, &descriptor, interface)?
+                }
+            };
+            self.init_method_member(member, method, kind)
+        } else if flags & MN_IS_FIELD != 0 {
+            let descriptor = self.type_descriptor(ty)?;
+            let descriptor = parse_field_type(&descriptor)?;
+            let (field_class, field, offset) =
+                match self.class(class).instance_field_slot(&name, &descriptor) {
+                    Some(slot) => {
+                        let field = &self.class(class).instance_fields[slot];
+                        (field.class, field.clone(), slot as i64)
+                    }
+                    None => {
+                        let (field_class, slot) = self
+                            .find_static_field(class, &name, &descriptor)
+                            .ok_or_else(|| {
+                                VmError::NoSuchField(format!("{}.{}", self.class(class).name, name))
+                            })?;
+                        let field = self.class(field_class).static_fields[slot].clone();
+                        (field_class, field, STATIC_FIELD_OFFSET + slot as i64)
+                    }
+                };
+            let is_static = field.access_flags & cs_parser::FieldAccessFlags::STATIC as u2 != 0;
+            let setter = kind == REF_PUT_FIELD || kind == REF_PUT_STATIC;
+            let kind = match (is_static, setter) {
+                (false, false) => REF_GET_FIELD,
+                (true, false) => REF_GET_STATIC,
+                (false, true) => REF_PUT_FIELD,
+                (true, true) => REF_PUT_STATIC,
+            };
+            let flags =
+                field.access_flags as i32 | MN_IS_FIELD | (kind as i32) << MN_REFERENCE_KIND_SHIFT;
+            let mirror = self.class_object(field_class)?;
+            set_field(self, member, "vmindex", Value::Long(offset))?;
+            set_field(self, member, "flags", Value::Int(flags))?;
+            set_field(self, member, "clazz", Value::Reference(Some(mirror)))
+        } else {
+            Err(VmError::Linkage(format!(
+                "MemberName {}.{} is neither a method nor a field",
+                self.class(class).name,
+                name
+            )))
+        }
+    }
+
+    /// Sets the flags, the declaring class and the `ResolvedMethodName` of a member for the method.
+    /// The reference kind is changed like the JVM does, for example methods that can not be
+    /// overridden are invoked with `REF_invokeSpecial`
+    fn init_method_member(&mut self, member: ObjRef, method: MethodId, kind: u8) -> Result<()> {
+        let method_ref = self.method(method);
+        let kind = if method_ref.is_static() {
+            REF_INVOKE_STATIC
+        } else if method_ref.name == "<init>" {
+            REF_NEW_INVOKE_SPECIAL
+        } else if kind == REF_INVOKE_SPECIAL
+            || method_ref.is_private()
+            || method_ref.is_final()
+            || self.class(method.class).access_flags & cs_parser::ClassAccessFlag::Final as u2 != 0
+        {
+            REF_INVOKE_SPECIAL
+        } else if self.class(method.class).is_interface() {
+            REF_INVOKE_INTERFACE
+        } else {
+            REF_INVOKE_VIRTUAL
+        };
+        let is_constructor = method_ref.name == "<init>";
+        let flags = method_ref.access_flags as i32
+            | if is_constructor {
+                MN_IS_CONSTRUCTOR
+            } else {
+                MN_IS_METHOD
+            }
+            | (kind as i32) << MN_REFERENCE_KIND_SHIFT;
+
+        let holder = self.class_object(method.class)?;
+        let resolved_class = self.resolve_class("java/lang/invoke/ResolvedMethodName")?;
+        let resolved = self.new_object(resolved_class)?;
+        set_field(self, resolved, "vmtarget", Value::Long(method.to_long()))?;
+        set_field(self, resolved, "vmholder", Value::Reference(Some(holder)))?;
+
+        set_field(self, member, "method", Value::Reference(Some(resolved)))?;
+        set_field(self, member, "flags", Value::Int(flags))?;
+        set_field(self, member, "clazz", Value::Reference(Some(holder)))
+    }
+
+    /// The descriptor of the type of a `MemberName`, which is a `MethodType`, a `Class`
+    /// for fields, or a descriptor string
+    fn type_descriptor(&mut self, ty: ObjRef) -> Result<String> {
+        if let Some(class) = self.mirror_class(ty) {
+            return Ok(self.class(class).descriptor());
+        }
+        let ty_class = self.class(self.heap.get(ty).class).name.as_str();
+        match ty_class {
+            "java/lang/String" => self.string_value(ty),
+            "java/lang/invoke/MethodType" => {
+                let return_type = object_field(self, ty, "rtype")?.ok_or(VmError::NullPointer)?;
+                let parameters = object_field(self, ty, "ptypes")?.ok_or(VmError::NullPointer)?;
+                let parameters = (0..self.array(parameters)?.len())
+                    .map(|index| {
+                        let parameter = self.array(parameters)?.load(index);
+                        self.mirror_descriptor(parameter.unwrap_or(Value::NULL))
+                    })
+                    .collect::<Result<String>>()?;
+                let return_type = self.mirror_descriptor(Value::Reference(Some(return_type)))?;
+                Ok(format!("({}){}", parameters, return_type))
+            }
+            name => Err(VmError::Verify(format!(
+                "Invalid MemberName type {}",
+                name.replace('/', ".")
+            ))),
+        }
+    }
+
+    fn mirror_descriptor(&self, mirror: Value) -> Result<String> {
+        let mirror = mirror.as_reference()?.ok_or(VmError::NullPointer)?;
+        let class = self
+            .mirror_class(mirror)
+            .ok_or_else(|| VmError::Verify("Class object without a class".to_string()))?;
+        Ok(self.class(class).descriptor())
+    }
+
+    /// Registers the natives of `MethodHandleNatives` and `MethodHandle`
+    pub(crate) fn register_invoke_natives(&mut self) {
+        const NATIVES: &str = "java/lang/invoke/MethodHandleNatives";
+        self.register_native(
+            NATIVES,
+            "resolve",
+            "(Ljava/lang/invoke/MemberName;Ljava/lang/Class;IZ)Ljava/lang/invoke/MemberName;",
+            |vm, args| {
+                let member = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let speculative = args[3].as_int()? != 0;
+                match vm.resolve_member_name(member) {
+                    Ok(()) => Ok(Some(Value::Reference(Some(member)))),
+                    // a speculative resolution returns null instead of throwing a linkage error
+                    Err(err) if speculative && !matches!(err, VmError::Exception(_)) => {
+                        Ok(Some(Value::NULL))
+                    }
+                    Err(err) => Err(err),
+                }
+            },
+        );
+        // the `MemberName` of a `java/lang/reflect/Method` or `Constructor`
+        self.register_native(
+            NATIVES,
+            "init",
+            "(Ljava/lang/invoke/MemberName;Ljava/lang/Object;)V",
+            |vm, args| {
+                let member = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let reflected = args[1].as_reference()?.ok_or(VmError::NullPointer)?;
+                let class = vm.class(vm.heap.get(reflected).class).name.as_str();
+                if class == "java/lang/reflect/Field" {
+                    return Err(VmError::Unsupported(
+                        "MemberName of a reflected field".to_string(),
+                    ));
+                }
+                let method = vm.reflected_method(reflected)?;
+                vm.init_method_member(member, method, REF_INVOKE_VIRTUAL)?;
+                Ok(None)
+            },
+        );
+        self.register_native(
+            NATIVES,
+            "expand",
+            "(Ljava/lang/invoke/MemberName;)V",
+            |vm, args| {
+                let member = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                if object_field(vm, member, "name")?.is_some() {
+                    return Ok(None);
+                }
+                let method = vm.member_method(member)?;
+                let (name, descriptor) = {
+                    let method = vm.method(method);
+                    (method.name.clone(), method.descriptor.clone())
+                };
+                let name = vm.intern_string(&name)?;
+                set_field(vm, member, "name", Value::Reference(Some(name)))?;
+                let descriptor = string_result(vm, &descriptor)?.unwrap_or(Value::NULL);
+                set_field(vm, member, "type", descriptor)?;
+                Ok(None)
+            },
+        );
+        for name in ["objectFieldOffset", "staticFieldOffset"] {
+            self.register_native(
+                NATIVES,
+                name,
+                "(Ljava/lang/invoke/MemberName;)J",
+                |vm, args| {
+                    let member = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                    let slot = field_slot(vm, vm.heap.get(member).class, "vmindex")?;
+                    Ok(Some(vm.get_field(member, slot)?))
+                },
+            );
+        }
+        self.register_native(
+            NATIVES,
+            "staticFieldBase",
+            "(Ljava/lang/invoke/MemberName;)Ljava/lang/Object;",
+            |vm, args| {
+                let member = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                Ok(Some(Value::Reference(object_field(vm, member, "clazz")?)))
+            },
+        );
+        // the constants are only checked with assertions
+        self.register_native(NATIVES, "getNamedCon", "(I[Ljava/lang/Object;)I", |_, _| {
+            Ok(Some(Value::Int(0)))
+        });
+        for name in ["setCallSiteTargetNormal", "setCallSiteTargetVolatile"] {
+            self.register_native(
+                NATIVES,
+                name,
+                "(Ljava/lang/invoke/CallSite;Ljava/lang/invoke/MethodHandle;)V",
+                |vm, args| {
+                    let call_site = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                    set_field(vm, call_site, "target", args[1])?;
+                    Ok(None)
+                },
+            );
+        }
+        self.register_native(
+            NATIVES,
+            "clearCallSiteContext",
+            "(Ljava/lang/invoke/MethodHandleNatives$CallSiteContext;)V",
+            |_, _| Ok(None),
+        );
+    }
+}
+
+/// Reads a reference field of an object of the class library by its name
+fn object_field(vm: &Vm, obj: ObjRef, name: &str) -> Result<Option<ObjRef>> {
+    let slot = field_slot(vm, vm.heap.get(obj).class, name)?;
+    vm.get_field(obj, slot)?.as_reference()
+}
+
+fn int_field(vm: &Vm, obj: ObjRef, name: &str) -> Result<i32> {
+    let slot = field_slot(vm, vm.heap.get(obj).class, name)?;
+    vm.get_field(obj, slot)?.as_int()
+}
+
+fn set_field(vm: &mut Vm, obj: ObjRef, name: &str, value: Value) -> Result<()> {
+    let slot = field_slot(vm, vm.heap.get(obj).class, name)?;
+    vm.put_field(obj, slot, value)
+}
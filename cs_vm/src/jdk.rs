@@ -0,0 +1,1292 @@
+//!
+//! Running the class library of a JDK
+//!
+//! With a boot image from `Vm::set_boot_image`, the classes come from a real JDK.
+//! `Vm::initialize_jdk` creates the main thread and runs `System.initPhase1` like the JVM does
+//! at startup, which needs the natives here. The module system is not booted: every class is in
+//! the unnamed module of the boot loader, and the boot layer is empty.
+//!
+//! `Unsafe` uses the slot of an instance field as its offset, and the index of an element as the
+//! offset into an array, except for wider values in byte arrays, whose offset is the index of
+//! their first byte. Static fields have the `java/lang/Class` object as their base and
+//! `STATIC_FIELD_OFFSET` plus their slot as offset. Memory outside the heap is not supported
+//!
+
+use crate::class::{ClassId, EnclosingMethod, FieldSlot, InitState, MethodId};
+use crate::heap::{Array, ObjRef, ObjectData};
+use crate::model::{ThreadState, Value};
+use crate::native::NativeMethod;
+use crate::runtime::{math_natives, string_result, this};
+use crate::{Result, Vm, VmError};
+use cs_parser::{u2, ClassAccessFlag};
+use std::io::{Read, Write};
+
+/// The classes that register their natives in a static initializer, which is not needed here
+const REGISTER_NATIVES: &[&str] = &[
+    "java/lang/Class",
+    "java/lang/ClassLoader",
+    "java/lang/Object",
+    "java/lang/System",
+    "java/lang/Thread",
+    "java/lang/invoke/MethodHandleNatives",
+    "jdk/internal/misc/ScopedMemoryAccess",
+    "jdk/internal/misc/Unsafe",
+];
+
+/// The names of the `Unsafe` accessors, the descriptor of their type and their `get` and `put`
+/// natives
+const UNSAFE_TYPES: &[(&str, &str, [NativeMethod; 2])] = &[
+    ("Int", "I", unsafe_accessors::<b'I'>()),
+    ("Long", "J", unsafe_accessors::<b'J'>()),
+    (
+        "Reference",
+        "Ljava/lang/Object;",
+        unsafe_accessors::<b'L'>(),
+    ),
+    ("Boolean", "Z", unsafe_accessors::<b'Z'>()),
+    ("Byte", "B", unsafe_accessors::<b'B'>()),
+    ("Short", "S", unsafe_accessors::<b'S'>()),
+    ("Char", "C", unsafe_accessors::<b'C'>()),
+    ("Float", "F", unsafe_accessors::<b'F'>()),
+    ("Double", "D", unsafe_accessors::<b'D'>()),
+];
+
+/// Added to the slot of a static field for its `Unsafe` offset, larger than any instance field slot
+pub(crate) const STATIC_FIELD_OFFSET: i64 = 1 << 32;
+
+/// The init level of `jdk/internal/misc/VM` after `System.initPhase2`
+const MODULE_SYSTEM_INITED: i32 = 2;
+
+/// The number of platform properties returned by `SystemProps$Raw.platformProperties`
+const PLATFORM_PROPERTIES: usize = 39;
+
+/// The flags of `ClassLoader.defineClass0` from `MethodHandles.Lookup`
+const NESTMATE_CLASS: i32 = 1;
+const HIDDEN_CLASS: i32 = 2;
+
+/// `Thread.NORM_PRIORITY`
+const NORM_PRIORITY: i32 = 5;
+
+impl Vm {
+    /// Registers the natives of the JDK class library
+    pub(crate) fn register_jdk_natives(&mut self) {
+        for class in REGISTER_NATIVES {
+            self.register_native(class, "registerNatives", "()V", |_, _| Ok(None));
+        }
+        self.register_object_natives();
+        self.register_reference_natives();
+        self.register_class_natives();
+        self.register_system_natives();
+        self.register_unsafe_natives();
+        self.register_io_natives();
+        self.register_internal_natives();
+        self.register_invoke_natives();
+        self.register_reflect_natives();
+    }
+
+    /// Initializes the JDK class library from the boot image, like the JVM does before it runs `main`.
+    /// This creates the `java/lang/Thread` object of the current thread in the `main` thread group
+    pub fn initialize_jdk(&mut self) -> Result<()> {
+        let group_class = self.resolve_class("java/lang/ThreadGroup")?;
+        let system_group = self.construct(group_class, "()V", &[])?;
+        self.handles.push(system_group);
+        let main_name = self.new_string("main");
+        self.handles.pop();
+        let main_name = main_name?;
+
+        self.handles.extend([system_group, main_name]);
+        let result = self.create_main_thread(group_class, system_group, main_name);
+        self.handles.truncate(self.handles.len() - 2);
+        result?;
+
+        // `AccessibleObject` has to publish its `JavaLangReflectAccess` before
+        // the `ReflectionFactory` is created
+        let method_class = self.resolve_class("java/lang/reflect/Method")?;
+        self.initialize(method_class)?;
+
+        let system = self.resolve_class("java/lang/System")?;
+        let init = self.declared_method(system, "initPhase1", "()V")?;
+        self.invoke(init, &[])?;
+        // which defines the unnamed module of the boot loader
+        let boot_loader = self.resolve_class("jdk/internal/loader/BootLoader")?;
+        self.initialize(boot_loader)?;
+
+        // there is no module graph to boot, so the boot layer is empty. `Proxy` only works once
+        // the module system is initialized
+        let layer_class = self.resolve_class("java/lang/ModuleLayer")?;
+        let empty = self.declared_method(layer_class, "empty", "()Ljava/lang/ModuleLayer;")?;
+        let empty = self.invoke(empty, &[])?.unwrap_or(Value::NULL);
+        set_system_field(self, "bootLayer", empty)?;
+        let vm_class = self.resolve_class("jdk/internal/misc/VM")?;
+        let init_level = self.declared_method(vm_class, "initLevel", "(I)V")?;
+        self.invoke(init_level, &[Value::Int(MODULE_SYSTEM_INITED)])?;
+        Ok(())
+    }
+
+    fn create_main_thread(
+        &mut self,
+        group_class: ClassId,
+        system_group: ObjRef,
+        name: ObjRef,
+    ) -> Result<()> {
+        let main_group = self.construct(
+            group_class,
+            "(Ljava/lang/ThreadGroup;Ljava/lang/String;)V",
+            &[
+                Value::Reference(Some(system_group)),
+                Value::Reference(Some(name)),
+            ],
+        )?;
+        self.handles.push(main_group);
+
+        // the constructor asks for the current thread, so the object has to exist before it runs
+        let thread_class = self.resolve_class("java/lang/Thread")?;
+        self.initialize(thread_class)?;
+        let thread = self.new_object(thread_class)?;
+        self.thread_mut().object = Some(thread);
+        let priority = field_slot(self, thread_class, "priority")?;
+        self.put_field(thread, priority, Value::Int(NORM_PRIORITY))?;
+        self.set_thread_state(self.current_thread, ThreadState::Runnable);
+
+        let constructor =
+            self.constructor(thread_class, "(Ljava/lang/ThreadGroup;Ljava/lang/String;)V")?;
+        let result = self.invoke(
+            constructor,
+            &[
+                Value::Reference(Some(thread)),
+                Value::Reference(Some(main_group)),
+                Value::Reference(Some(name)),
+            ],
+        );
+        self.handles.pop();
+        result.map(|_| ())
+    }
+
+    /// Allocates an object and runs the constructor with the descriptor
+    pub(crate) fn construct(
+        &mut self,
+        class: ClassId,
+        descriptor: &str,
+        args: &[Value],
+    ) -> Result<ObjRef> {
+        self.initialize(class)?;
+        let constructor = self.constructor(class, descriptor)?;
+        let obj = self.new_object(class)?;
+        self.handles.push(obj);
+        let mut constructor_args = vec![Value::Reference(Some(obj))];
+        constructor_args.extend_from_slice(args);
+        let result = self.invoke(constructor, &constructor_args);
+        self.handles.pop();
+        result.map(|_| obj)
+    }
+
+    fn constructor(&self, class: ClassId, descriptor: &str) -> Result<MethodId> {
+        self.declared_method(class, "<init>", descriptor)
+    }
+
+    /// A method declared by the class, looked up without the superclasses
+    fn declared_method(&self, class: ClassId, name: &str, descriptor: &str) -> Result<MethodId> {
+        self.class(class)
+            .method_index(name, descriptor)
+            .map(|index| MethodId { class, index })
+            .ok_or_else(|| {
+                VmError::NoSuchMethod(format!("{}.{}{}", self.class(class).name, name, descriptor))
+            })
+    }
+
+    fn register_object_natives(&mut self) {
+        self.register_native(
+            "java/lang/Throwable",
+            "fillInStackTrace",
+            "(I)Ljava/lang/Throwable;",
+            |vm, args| {
+                let throwable = this(args)?;
+                vm.fill_backtrace(throwable)?;
+                Ok(Some(Value::Reference(Some(throwable))))
+            },
+        );
+        self.register_native(
+            "java/lang/StackTraceElement",
+            "initStackTraceElements",
+            "([Ljava/lang/StackTraceElement;Ljava/lang/Throwable;)V",
+            |vm, args| {
+                let elements = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let throwable = args[1].as_reference()?.ok_or(VmError::NullPointer)?;
+                let backtrace = vm.backtrace(throwable);
+                for (index, (method, pc)) in backtrace.into_iter().enumerate() {
+                    let element = match vm.array(elements)?.load(index) {
+                        Some(Value::Reference(Some(element))) => element,
+                        _ => break,
+                    };
+                    init_stack_trace_element(vm, element, method, pc)?;
+                }
+                Ok(None)
+            },
+        );
+    }
+
+    /// The garbage collector treats the referents of `java/lang/ref/Reference` like other fields,
+    /// so references are never cleared or enqueued
+    fn register_reference_natives(&mut self) {
+        for class in ["java/lang/ref/Reference", "java/lang/ref/PhantomReference"] {
+            self.register_native(class, "refersTo0", "(Ljava/lang/Object;)Z", |vm, args| {
+                let reference = this(args)?;
+                let referent = field_slot(vm, vm.heap.get(reference).class, "referent")?;
+                let referent = vm.get_field(reference, referent)?;
+                Ok(Some(Value::Int((referent == args[1]) as i32)))
+            });
+        }
+        self.register_native("java/lang/ref/Reference", "clear0", "()V", |vm, args| {
+            let reference = this(args)?;
+            let referent = field_slot(vm, vm.heap.get(reference).class, "referent")?;
+            vm.put_field(reference, referent, Value::NULL)?;
+            Ok(None)
+        });
+        self.register_native(
+            "java/lang/ref/Reference",
+            "getAndClearReferencePendingList",
+            "()Ljava/lang/ref/Reference;",
+            |_, _| Ok(Some(Value::NULL)),
+        );
+        self.register_native(
+            "java/lang/ref/Reference",
+            "hasReferencePendingList",
+            "()Z",
+            |_, _| Ok(Some(Value::Int(0))),
+        );
+        self.register_native(
+            "java/lang/ref/Reference",
+            "waitForReferencePendingList",
+            "()V",
+            // the collector does not discover references, so the list never fills up
+            |vm, _| {
+                vm.block(ThreadState::Parked { deadline: None });
+                Ok(None)
+            },
+        );
+    }
+
+    fn register_class_natives(&mut self) {
+        self.register_native(
+            "java/lang/Class",
+            "desiredAssertionStatus0",
+            "(Ljava/lang/Class;)Z",
+            |_, _| Ok(Some(Value::Int(0))),
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getPrimitiveClass",
+            "(Ljava/lang/String;)Ljava/lang/Class;",
+            |vm, args| {
+                let name = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let name = vm.string_value(name)?;
+                let class = vm.primitive_class(&name)?;
+                Ok(Some(Value::Reference(Some(vm.class_object(class)?))))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "isAssignableFrom",
+            "(Ljava/lang/Class;)Z",
+            |vm, args| {
+                let (to, from) = (class_arg(vm, args[0])?, class_arg(vm, args[1])?);
+                Ok(Some(Value::Int(vm.is_assignable(from, to) as i32)))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "isInstance",
+            "(Ljava/lang/Object;)Z",
+            |vm, args| {
+                let class = class_arg(vm, args[0])?;
+                let instance = args[1]
+                    .as_reference()?
+                    .is_some_and(|obj| vm.is_assignable(vm.heap.get(obj).class, class));
+                Ok(Some(Value::Int(instance as i32)))
+            },
+        );
+        self.register_native("java/lang/Class", "isHidden", "()Z", |vm, args| {
+            let class = class_arg(vm, args[0])?;
+            Ok(Some(Value::Int(vm.class(class).hidden as i32)))
+        });
+        self.register_native(
+            "java/lang/Class",
+            "getSuperclass",
+            "()Ljava/lang/Class;",
+            |vm, args| {
+                let class = class_arg(vm, args[0])?;
+                let super_class = match vm.class(class).super_class {
+                    Some(super_class) if !vm.class(class).is_interface() => {
+                        Some(vm.class_object(super_class)?)
+                    }
+                    _ => None,
+                };
+                Ok(Some(Value::Reference(super_class)))
+            },
+        );
+        self.register_native("java/lang/Class", "getModifiers", "()I", |vm, args| {
+            let class = vm.class(class_arg(vm, args[0])?);
+            // member classes are private, protected or static only in their `InnerClasses` entry,
+            // which has no `ACC_SUPER`
+            let access_flags = match &class.inner_class {
+                Some(inner_class) => inner_class.access_flags,
+                None => class.access_flags & !(ClassAccessFlag::Super as u2),
+            };
+            Ok(Some(Value::Int(access_flags as i32)))
+        });
+        self.register_native(
+            "java/lang/Class",
+            "getNestHost0",
+            "()Ljava/lang/Class;",
+            |vm, args| {
+                let host = vm.nest_host(class_arg(vm, args[0])?);
+                Ok(Some(Value::Reference(Some(vm.class_object(host)?))))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getDeclaringClass0",
+            "()Ljava/lang/Class;",
+            |vm, args| {
+                let class = class_arg(vm, args[0])?;
+                let outer = vm
+                    .class(class)
+                    .inner_class
+                    .as_ref()
+                    .and_then(|inner| inner.outer_class.clone());
+                let outer = match outer {
+                    Some(outer) => {
+                        let outer = vm.resolve_class(&outer)?;
+                        Some(vm.class_object(outer)?)
+                    }
+                    None => None,
+                };
+                Ok(Some(Value::Reference(outer)))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getSimpleBinaryName0",
+            "()Ljava/lang/String;",
+            |vm, args| {
+                let class = class_arg(vm, args[0])?;
+                let name = vm
+                    .class(class)
+                    .inner_class
+                    .as_ref()
+                    .and_then(|inner| inner.simple_name.clone());
+                match name {
+                    Some(name) => string_result(vm, &name),
+                    None => Ok(Some(Value::Reference(None))),
+                }
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getEnclosingMethod0",
+            "()[Ljava/lang/Object;",
+            |vm, args| {
+                let class = class_arg(vm, args[0])?;
+                match vm.class(class).enclosing_method.clone() {
+                    Some(enclosing) => enclosing_method_info(vm, &enclosing),
+                    None => Ok(Some(Value::Reference(None))),
+                }
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "initClassName",
+            "()Ljava/lang/String;",
+            |vm, args| {
+                let class = class_arg(vm, args[0])?;
+                string_result(vm, &vm.java_name(class))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "forName0",
+            "(Ljava/lang/String;ZLjava/lang/ClassLoader;Ljava/lang/Class;)Ljava/lang/Class;",
+            |vm, args| {
+                let name = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let name = vm.string_value(name)?;
+                let class = match vm.resolve_class(&name.replace('.', "/")) {
+                    Ok(class) => class,
+                    Err(VmError::ClassNotFound(_)) => {
+                        return Err(vm.throw_new("java/lang/ClassNotFoundException", Some(&name)))
+                    }
+                    Err(err) => return Err(err),
+                };
+                if args[1].as_int()? != 0 {
+                    vm.initialize(class)?;
+                }
+                Ok(Some(Value::Reference(Some(vm.class_object(class)?))))
+            },
+        );
+        self.register_native(
+            "java/lang/ClassLoader",
+            "findBootstrapClass",
+            "(Ljava/lang/String;)Ljava/lang/Class;",
+            |vm, args| {
+                let name = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let name = vm.string_value(name)?;
+                match vm.resolve_class(&name.replace('.', "/")) {
+                    Ok(class) => Ok(Some(Value::Reference(Some(vm.class_object(class)?)))),
+                    Err(VmError::ClassNotFound(_)) => Ok(Some(Value::NULL)),
+                    Err(err) => Err(err),
+                }
+            },
+        );
+        self.register_native(
+            "java/lang/ClassLoader",
+            "defineClass1",
+            "(Ljava/lang/ClassLoader;Ljava/lang/String;[BIILjava/security/ProtectionDomain;\
+            Ljava/lang/String;)Ljava/lang/Class;",
+            |vm, args| {
+                let bytes = args[2].as_reference()?.ok_or(VmError::NullPointer)?;
+                let bytes = byte_range(vm, bytes, args[3].as_int()?, args[4].as_int()?)?;
+                let class = vm.load_class(&bytes)?;
+                Ok(Some(Value::Reference(Some(vm.class_object(class)?))))
+            },
+        );
+        self.register_native(
+            "java/lang/ClassLoader",
+            "defineClass0",
+            "(Ljava/lang/ClassLoader;Ljava/lang/Class;Ljava/lang/String;[BII\
+            Ljava/security/ProtectionDomain;ZILjava/lang/Object;)Ljava/lang/Class;",
+            |vm, args| {
+                let bytes = args[3].as_reference()?.ok_or(VmError::NullPointer)?;
+                let bytes = byte_range(vm, bytes, args[4].as_int()?, args[5].as_int()?)?;
+                let flags = args[8].as_int()?;
+                let class = match flags & HIDDEN_CLASS {
+                    0 => vm.load_class(&bytes)?,
+                    _ => vm.define_hidden_class(&bytes)?,
+                };
+                if flags & NESTMATE_CLASS != 0 {
+                    let host = vm.nest_host(class_arg(vm, args[1])?);
+                    vm.class_mut(class).nest_host = Some(vm.class(host).name.clone());
+                }
+                let mirror = vm.class_object(class)?;
+                let class_data = field_slot(vm, vm.heap.get(mirror).class, "classData")?;
+                vm.put_field(mirror, class_data, args[9])?;
+                if args[7].as_int()? != 0 {
+                    vm.initialize(class)?;
+                }
+                Ok(Some(Value::Reference(Some(mirror))))
+            },
+        );
+    }
+
+    fn register_system_natives(&mut self) {
+        self.register_native(
+            "java/lang/System",
+            "setIn0",
+            "(Ljava/io/InputStream;)V",
+            |vm, args| set_system_field(vm, "in", args[0]),
+        );
+        self.register_native(
+            "java/lang/System",
+            "setOut0",
+            "(Ljava/io/PrintStream;)V",
+            |vm, args| set_system_field(vm, "out", args[0]),
+        );
+        self.register_native(
+            "java/lang/System",
+            "setErr0",
+            "(Ljava/io/PrintStream;)V",
+            |vm, args| set_system_field(vm, "err", args[0]),
+        );
+        self.register_native(
+            "java/lang/System",
+            "mapLibraryName",
+            "(Ljava/lang/String;)Ljava/lang/String;",
+            |vm, args| {
+                let name = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let name = vm.string_value(name)?;
+                string_result(vm, &format!("lib{}.so", name))
+            },
+        );
+
+        self.register_native("java/lang/Runtime", "availableProcessors", "()I", |_, _| {
+            Ok(Some(Value::Int(1)))
+        });
+        self.register_native("java/lang/Runtime", "maxMemory", "()J", |vm, _| {
+            Ok(Some(Value::Long(vm.options.max_heap as i64)))
+        });
+        self.register_native("java/lang/Runtime", "totalMemory", "()J", |vm, _| {
+            Ok(Some(Value::Long(vm.options.max_heap as i64)))
+        });
+        self.register_native("java/lang/Runtime", "freeMemory", "()J", |vm, _| {
+            Ok(Some(Value::Long(
+                (vm.options.max_heap - vm.heap.used()) as i64,
+            )))
+        });
+        self.register_native("java/lang/Runtime", "gc", "()V", |vm, _| {
+            vm.gc();
+            Ok(None)
+        });
+
+        self.register_native("java/lang/Float", "intBitsToFloat", "(I)F", |_, args| {
+            Ok(Some(Value::Float(f32::from_bits(args[0].as_int()? as u32))))
+        });
+        self.register_native("java/lang/Double", "longBitsToDouble", "(J)D", |_, args| {
+            Ok(Some(Value::Double(f64::from_bits(
+                args[0].as_long()? as u64
+            ))))
+        });
+        math_natives!(self, "java/lang/StrictMath",
+            "sin" => f64::sin,
+            "cos" => f64::cos,
+            "tan" => f64::tan,
+            "asin" => f64::asin,
+            "acos" => f64::acos,
+            "atan" => f64::atan,
+            "log" => f64::ln,
+            "log10" => f64::log10,
+            "sqrt" => f64::sqrt,
+            "sinh" => f64::sinh,
+            "cosh" => f64::cosh,
+            "tanh" => f64::tanh,
+            "expm1" => f64::exp_m1,
+            "log1p" => f64::ln_1p,
+        );
+        self.register_native("java/lang/StrictMath", "atan2", "(DD)D", |_, args| {
+            let (y, x) = (args[0].as_double()?, args[1].as_double()?);
+            Ok(Some(Value::Double(y.atan2(x))))
+        });
+        self.register_native(
+            "java/lang/StrictMath",
+            "IEEEremainder",
+            "(DD)D",
+            |_, args| {
+                let (x, y) = (args[0].as_double()?, args[1].as_double()?);
+                Ok(Some(Value::Double(ieee_remainder(x, y))))
+            },
+        );
+
+        self.register_native("java/lang/StringUTF16", "isBigEndian", "()Z", |_, _| {
+            Ok(Some(Value::Int(0)))
+        });
+    }
+
+    fn register_unsafe_natives(&mut self) {
+        const UNSAFE: &str = "jdk/internal/misc/Unsafe";
+
+        for &(name, descriptor, [get, put]) in UNSAFE_TYPES {
+            for volatile in ["", "Volatile"] {
+                self.register_native(
+                    UNSAFE,
+                    &format!("get{}{}", name, volatile),
+                    &format!("(Ljava/lang/Object;J){}", descriptor),
+                    get,
+                );
+                self.register_native(
+                    UNSAFE,
+                    &format!("put{}{}", name, volatile),
+                    &format!("(Ljava/lang/Object;J{})V", descriptor),
+                    put,
+                );
+            }
+        }
+        for (name, descriptor, _) in &UNSAFE_TYPES[..3] {
+            self.register_native(
+                UNSAFE,
+                &format!("compareAndSet{}", name),
+                &format!("(Ljava/lang/Object;J{0}{0})Z", descriptor),
+                |vm, args| {
+                    let old = unsafe_compare_and_exchange(vm, args)?;
+                    Ok(Some(Value::Int((old == args[3]) as i32)))
+                },
+            );
+            self.register_native(
+                UNSAFE,
+                &format!("compareAndExchange{}", name),
+                &format!("(Ljava/lang/Object;J{0}{0}){0}", descriptor),
+                |vm, args| unsafe_compare_and_exchange(vm, args).map(Some),
+            );
+        }
+
+        self.register_native(
+            UNSAFE,
+            "objectFieldOffset1",
+            "(Ljava/lang/Class;Ljava/lang/String;)J",
+            |vm, args| {
+                let class = class_arg(vm, args[1])?;
+                let name = args[2].as_reference()?.ok_or(VmError::NullPointer)?;
+                let name = vm.string_value(name)?;
+                match field_slot(vm, class, &name) {
+                    Ok(slot) => Ok(Some(Value::Long(slot as i64))),
+                    Err(_) => Err(vm.throw_new("java/lang/InternalError", Some(&name))),
+                }
+            },
+        );
+        self.register_native(
+            UNSAFE,
+            "objectFieldOffset0",
+            "(Ljava/lang/reflect/Field;)J",
+            |vm, args| {
+                let field = args[1].as_reference()?.ok_or(VmError::NullPointer)?;
+                match vm.reflected_field(field)? {
+                    (_, FieldSlot::Instance(slot)) => Ok(Some(Value::Long(slot as i64))),
+                    (_, FieldSlot::Static(_)) => {
+                        Err(vm
+                            .throw_new("java/lang/IllegalArgumentException", Some("static field")))
+                    }
+                }
+            },
+        );
+        self.register_native(
+            UNSAFE,
+            "staticFieldOffset0",
+            "(Ljava/lang/reflect/Field;)J",
+            |vm, args| {
+                let field = args[1].as_reference()?.ok_or(VmError::NullPointer)?;
+                match vm.reflected_field(field)? {
+                    (_, FieldSlot::Static(slot)) => {
+                        Ok(Some(Value::Long(STATIC_FIELD_OFFSET + slot as i64)))
+                    }
+                    (_, FieldSlot::Instance(_)) => Err(vm.throw_new(
+                        "java/lang/IllegalArgumentException",
+                        Some("not a static field"),
+                    )),
+                }
+            },
+        );
+        self.register_native(
+            UNSAFE,
+            "staticFieldBase0",
+            "(Ljava/lang/reflect/Field;)Ljava/lang/Object;",
+            |vm, args| {
+                let field = args[1].as_reference()?.ok_or(VmError::NullPointer)?;
+                let (class, _) = vm.reflected_field(field)?;
+                Ok(Some(Value::Reference(Some(vm.class_object(class)?))))
+            },
+        );
+        self.register_native(
+            UNSAFE,
+            "arrayBaseOffset0",
+            "(Ljava/lang/Class;)I",
+            |_, _| Ok(Some(Value::Int(0))),
+        );
+        self.register_native(
+            UNSAFE,
+            "arrayIndexScale0",
+            "(Ljava/lang/Class;)I",
+            |_, _| Ok(Some(Value::Int(1))),
+        );
+        self.register_native(UNSAFE, "addressSize0", "()I", |_, _| {
+            Ok(Some(Value::Int(8)))
+        });
+        self.register_native(UNSAFE, "pageSize", "()I", |_, _| Ok(Some(Value::Int(4096))));
+        for fence in ["loadFence", "storeFence", "fullFence"] {
+            self.register_native(UNSAFE, fence, "()V", |_, _| Ok(None));
+        }
+        self.register_native(
+            UNSAFE,
+            "ensureClassInitialized0",
+            "(Ljava/lang/Class;)V",
+            |vm, args| {
+                let class = class_arg(vm, args[1])?;
+                vm.initialize(class)?;
+                Ok(None)
+            },
+        );
+        self.register_native(
+            UNSAFE,
+            "shouldBeInitialized0",
+            "(Ljava/lang/Class;)Z",
+            |vm, args| {
+                let class = class_arg(vm, args[1])?;
+                let uninitialized = vm.class(class).init_state != InitState::Initialized;
+                Ok(Some(Value::Int(uninitialized as i32)))
+            },
+        );
+        self.register_native(
+            UNSAFE,
+            "allocateInstance",
+            "(Ljava/lang/Class;)Ljava/lang/Object;",
+            |vm, args| {
+                let class = class_arg(vm, args[1])?;
+                vm.initialize(class)?;
+                Ok(Some(Value::Reference(Some(vm.new_object(class)?))))
+            },
+        );
+        // compare and set of longs is as atomic as the others, threads only switch between
+        // instructions
+        self.register_native(
+            "java/util/concurrent/atomic/AtomicLong",
+            "VMSupportsCS8",
+            "()Z",
+            |_, _| Ok(Some(Value::Int(1))),
+        );
+    }
+
+    fn register_io_natives(&mut self) {
+        for class in [
+            "java/io/FileDescriptor",
+            "java/io/FileInputStream",
+            "java/io/FileOutputStream",
+        ] {
+            self.register_native(class, "initIDs", "()V", |_, _| Ok(None));
+        }
+        self.register_native("java/io/FileDescriptor", "getHandle", "(I)J", |_, _| {
+            Ok(Some(Value::Long(-1)))
+        });
+        self.register_native("java/io/FileDescriptor", "getAppend", "(I)Z", |_, _| {
+            Ok(Some(Value::Int(0)))
+        });
+        self.register_native("java/io/FileDescriptor", "close0", "()V", |_, _| Ok(None));
+
+        self.register_native(
+            "java/io/FileOutputStream",
+            "writeBytes",
+            "([BIIZ)V",
+            |vm, args| {
+                let fd = stream_fd(vm, this(args)?)?;
+                let bytes = args[1].as_reference()?.ok_or(VmError::NullPointer)?;
+                let bytes = byte_range(vm, bytes, args[2].as_int()?, args[3].as_int()?)?;
+                // like the JDK writing to a closed standard stream, errors are ignored
+                let _ = match fd {
+                    2 => vm.stderr.write_all(&bytes),
+                    _ => vm.stdout.write_all(&bytes),
+                };
+                Ok(None)
+            },
+        );
+        self.register_native(
+            "java/io/FileInputStream",
+            "readBytes",
+            "([BII)I",
+            |vm, args| {
+                let bytes = args[1].as_reference()?.ok_or(VmError::NullPointer)?;
+                let (offset, len) = (
+                    args[2].as_int()?.max(0) as usize,
+                    args[3].as_int()?.max(0) as usize,
+                );
+                let mut buffer = vec![0; len];
+                let read = std::io::stdin().read(&mut buffer).unwrap_or(0);
+                if read == 0 && len > 0 {
+                    return Ok(Some(Value::Int(-1)));
+                }
+                for (i, &byte) in buffer[..read].iter().enumerate() {
+                    vm.array_mut(bytes)?
+                        .store(offset + i, Value::Int(byte as i8 as i32))
+                        .ok_or(VmError::ArrayIndexOutOfBounds {
+                            index: (offset + i) as i32,
+                            len: offset + i,
+                        })?;
+                }
+                Ok(Some(Value::Int(read as i32)))
+            },
+        );
+        self.register_native("java/io/FileInputStream", "available0", "()I", |_, _| {
+            Ok(Some(Value::Int(0)))
+        });
+    }
+
+    fn register_internal_natives(&mut self) {
+        self.register_native(
+            "jdk/internal/reflect/Reflection",
+            "getCallerClass",
+            "()Ljava/lang/Class;",
+            |vm, _| {
+                // the top frame is the method that asks for its caller
+                let caller = vm
+                    .thread()
+                    .frames
+                    .iter()
+                    .rev()
+                    .map(|frame| frame.method.class)
+                    .filter(|&class| !vm.class(class).name.starts_with("jdk/internal/reflect/"))
+                    .nth(1);
+                let caller = match caller {
+                    Some(caller) => Some(vm.class_object(caller)?),
+                    None => None,
+                };
+                Ok(Some(Value::Reference(caller)))
+            },
+        );
+        self.register_native(
+            "jdk/internal/reflect/Reflection",
+            "areNestMates",
+            "(Ljava/lang/Class;Ljava/lang/Class;)Z",
+            |vm, args| {
+                let (class, other) = (class_arg(vm, args[0])?, class_arg(vm, args[1])?);
+                let same = vm.nest_host(class) == vm.nest_host(other);
+                Ok(Some(Value::Int(same as i32)))
+            },
+        );
+        self.register_native(
+            "jdk/internal/reflect/Reflection",
+            "getClassAccessFlags",
+            "(Ljava/lang/Class;)I",
+            |vm, args| {
+                let class = class_arg(vm, args[0])?;
+                Ok(Some(Value::Int(vm.class(class).access_flags as i32)))
+            },
+        );
+
+        // modules are not checked by the VM, every class is in the unnamed module of the boot
+        // loader, and the modules that the class library defines, like the modules of proxy
+        // classes, only exist in Java
+        self.register_native(
+            "jdk/internal/loader/BootLoader",
+            "setBootLoaderUnnamedModule0",
+            "(Ljava/lang/Module;)V",
+            |vm, args| {
+                let module = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                vm.unnamed_module = Some(module);
+                let mirrors = vm.mirrors.keys().copied().collect::<Vec<_>>();
+                for mirror in mirrors {
+                    vm.set_module(mirror, module)?;
+                }
+                Ok(None)
+            },
+        );
+        const MODULE: &str = "java/lang/Module";
+        for (name, descriptor) in [
+            (
+                "defineModule0",
+                "(Ljava/lang/Module;ZLjava/lang/String;Ljava/lang/String;[Ljava/lang/Object;)V",
+            ),
+            ("addReads0", "(Ljava/lang/Module;Ljava/lang/Module;)V"),
+            (
+                "addExports0",
+                "(Ljava/lang/Module;Ljava/lang/String;Ljava/lang/Module;)V",
+            ),
+            (
+                "addExportsToAll0",
+                "(Ljava/lang/Module;Ljava/lang/String;)V",
+            ),
+            (
+                "addExportsToAllUnnamed0",
+                "(Ljava/lang/Module;Ljava/lang/String;)V",
+            ),
+        ] {
+            self.register_native(MODULE, name, descriptor, |_, _| Ok(None));
+        }
+
+        // there is no security manager, so there are no access control contexts
+        const ACCESS_CONTROLLER: &str = "java/security/AccessController";
+        for name in [
+            "getStackAccessControlContext",
+            "getInheritedAccessControlContext",
+        ] {
+            self.register_native(
+                ACCESS_CONTROLLER,
+                name,
+                "()Ljava/security/AccessControlContext;",
+                |_, _| Ok(Some(Value::NULL)),
+            );
+        }
+        self.register_native(
+            ACCESS_CONTROLLER,
+            "ensureMaterializedForStackWalk",
+            "(Ljava/lang/Object;)V",
+            |_, _| Ok(None),
+        );
+
+        // class data sharing is never used
+        const CDS: &str = "jdk/internal/misc/CDS";
+        for name in [
+            "isDumpingClassList0",
+            "isDumpingArchive0",
+            "isSharingEnabled0",
+        ] {
+            self.register_native(CDS, name, "()Z", |_, _| Ok(Some(Value::Int(0))));
+        }
+        self.register_native(CDS, "getRandomSeedForDumping", "()J", |_, _| {
+            Ok(Some(Value::Long(0)))
+        });
+        self.register_native(
+            CDS,
+            "initializeFromArchive",
+            "(Ljava/lang/Class;)V",
+            |_, _| Ok(None),
+        );
+
+        const VM: &str = "jdk/internal/misc/VM";
+        self.register_native(VM, "initialize", "()V", |_, _| Ok(None));
+        self.register_native(
+            VM,
+            "latestUserDefinedLoader0",
+            "()Ljava/lang/ClassLoader;",
+            |_, _| Ok(Some(Value::NULL)),
+        );
+        self.register_native(VM, "getNanoTimeAdjustment", "(J)J", |vm, args| {
+            let offset = args[0].as_long()?;
+            let now = vm.clock.since_epoch();
+            let adjustment =
+                (now.as_secs() as i64 - offset) * 1_000_000_000 + now.subsec_nanos() as i64;
+            Ok(Some(Value::Long(adjustment)))
+        });
+
+        self.register_native(
+            "jdk/internal/util/SystemProps$Raw",
+            "vmProperties",
+            "()[Ljava/lang/String;",
+            |vm, _| {
+                let java_home = vm
+                    .boot_image
+                    .as_ref()
+                    .and_then(|image| image.java_home())
+                    .map(|home| home.display().to_string())
+                    .unwrap_or_default();
+                let properties = [
+                    ("java.home", java_home.as_str()),
+                    ("java.vm.name", "coldsquare"),
+                    ("java.vm.vendor", "coldsquare"),
+                    ("java.vm.version", env!("CARGO_PKG_VERSION")),
+                    ("java.vm.info", "interpreted mode"),
+                    (
+                        "java.vm.specification.name",
+                        "Java Virtual Machine Specification",
+                    ),
+                    ("java.vm.specification.vendor", "Oracle Corporation"),
+                    ("java.vm.specification.version", "17"),
+                    ("sun.boot.library.path", ""),
+                    ("java.library.path", ""),
+                    ("sun.java.launcher", "SUN_STANDARD"),
+                    ("sun.nio.MaxDirectMemorySize", "-1"),
+                    ("jdk.debug", "release"),
+                    // reflection calls the natives instead of generating accessor classes
+                    ("sun.reflect.inflationThreshold", "2147483647"),
+                ];
+                let properties = properties
+                    .iter()
+                    .flat_map(|&(key, value)| [Some(key), Some(value)])
+                    .collect::<Vec<_>>();
+                string_array(vm, &properties)
+            },
+        );
+        self.register_native(
+            "jdk/internal/util/SystemProps$Raw",
+            "platformProperties",
+            "()[Ljava/lang/String;",
+            |vm, _| {
+                let mut properties = [None; PLATFORM_PROPERTIES];
+                for (index, value) in [
+                    (4, "UTF-8"), // file.encoding
+                    (5, "/"),     // file.separator
+                    (18, "/tmp"), // java.io.tmpdir
+                    (19, "\n"),   // line.separator
+                    (20, std::env::consts::ARCH),
+                    (21, "Linux"),         // os.name
+                    (22, ""),              // os.version
+                    (23, ":"),             // path.separator
+                    (28, "64"),            // sun.arch.data.model
+                    (29, "little"),        // sun.cpu.endian
+                    (31, "UnicodeLittle"), // sun.io.unicode.encoding
+                    (32, "UTF-8"),         // sun.jnu.encoding
+                    (36, "/"),             // user.dir
+                    (37, "?"),             // user.home
+                    (38, "?"),             // user.name
+                ] {
+                    properties[index] = Some(value);
+                }
+                string_array(vm, &properties)
+            },
+        );
+
+        self.register_native(
+            "jdk/internal/misc/Signal",
+            "findSignal0",
+            "(Ljava/lang/String;)I",
+            |vm, args| {
+                let name = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let number = match vm.string_value(name)?.as_str() {
+                    "HUP" => 1,
+                    "INT" => 2,
+                    "TERM" => 15,
+                    _ => -1,
+                };
+                Ok(Some(Value::Int(number)))
+            },
+        );
+        // signal handlers are never called
+        self.register_native("jdk/internal/misc/Signal", "handle0", "(IJ)J", |_, _| {
+            Ok(Some(Value::Long(0)))
+        });
+    }
+}
+
+/// The class of a `java/lang/Class` argument
+fn class_arg(vm: &Vm, value: Value) -> Result<ClassId> {
+    let mirror = value.as_reference()?.ok_or(VmError::NullPointer)?;
+    vm.mirror_class(mirror)
+        .ok_or_else(|| VmError::Verify("Class object without a class".to_string()))
+}
+
+/// The slot of the instance field with the name, fields of subclasses shadow fields of superclasses
+/// Fills in a `java/lang/StackTraceElement` for the frame of the method at the pc
+fn init_stack_trace_element(
+    vm: &mut Vm,
+    element: ObjRef,
+    method: MethodId,
+    pc: usize,
+) -> Result<()> {
+    let frame = vm.stack_trace_element(method, pc);
+    let class = vm.heap.get(element).class;
+    let mirror = vm.class_object(method.class)?;
+    vm.put_field(
+        element,
+        field_slot(vm, class, "declaringClassObject")?,
+        Value::Reference(Some(mirror)),
+    )?;
+    let line = match frame.line {
+        _ if frame.native => -2,
+        Some(line) => line as i32,
+        None => -1,
+    };
+    vm.put_field(
+        element,
+        field_slot(vm, class, "lineNumber")?,
+        Value::Int(line),
+    )?;
+    for (name, value) in [
+        ("declaringClass", Some(frame.class)),
+        ("methodName", Some(frame.method)),
+        ("fileName", frame.file),
+    ] {
+        let Some(value) = value else { continue };
+        let value = vm.new_string(&value)?;
+        vm.put_field(
+            element,
+            field_slot(vm, class, name)?,
+            Value::Reference(Some(value)),
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn field_slot(vm: &Vm, class: ClassId, name: &str) -> Result<usize> {
+    vm.class(class)
+        .instance_fields
+        .iter()
+        .rposition(|field| field.name == name)
+        .ok_or_else(|| VmError::NoSuchField(format!("{}.{}", vm.class(class).name, name)))
+}
+
+/// The `Object[]` of `Class.getEnclosingMethod0` with the class, the name and the descriptor
+fn enclosing_method_info(vm: &mut Vm, enclosing: &EnclosingMethod) -> Result<Option<Value>> {
+    let class = vm.resolve_class(&enclosing.class)?;
+    let class = vm.class_object(class)?;
+    let object_array = vm.resolve_class("[Ljava/lang/Object;")?;
+    let info = vm.new_array(object_array, 3)?;
+    vm.array_mut(info)?.store(0, Value::Reference(Some(class)));
+
+    vm.handles.push(info);
+    let result = enclosing.method.iter().try_for_each(|(name, descriptor)| {
+        let name = vm.new_string(name)?;
+        vm.array_mut(info)?.store(1, Value::Reference(Some(name)));
+        let descriptor = vm.new_string(descriptor)?;
+        vm.array_mut(info)?
+            .store(2, Value::Reference(Some(descriptor)));
+        Ok(())
+    });
+    vm.handles.pop();
+    result.map(|()| Some(Value::Reference(Some(info))))
+}
+
+fn string_array(vm: &mut Vm, strings: &[Option<&str>]) -> Result<Option<Value>> {
+    let class = vm.resolve_class("[Ljava/lang/String;")?;
+    let array = vm.new_array(class, strings.len() as i32)?;
+    vm.handles.push(array);
+    let result = strings.iter().enumerate().try_for_each(|(index, string)| {
+        if let Some(string) = string {
+            let string = vm.new_string(string)?;
+            vm.array_mut(array)?
+                .store(index, Value::Reference(Some(string)));
+        }
+        Ok(())
+    });
+    vm.handles.pop();
+    result.map(|()| Some(Value::Reference(Some(array))))
+}
+
+/// `System.setIn0`, `setOut0` and `setErr0` set the final static fields of `System`
+/// Copies `len` bytes of a `byte[]` from the offset
+fn byte_range(vm: &Vm, bytes: ObjRef, offset: i32, len: i32) -> Result<Vec<u8>> {
+    let bytes = match vm.array(bytes)? {
+        Array::Byte(bytes) => bytes,
+        _ => return Err(VmError::Verify("Expected a byte array".to_string())),
+    };
+    Ok(usize::try_from(offset)
+        .ok()
+        .zip(usize::try_from(len).ok())
+        .and_then(|(offset, len)| bytes.get(offset..offset.checked_add(len)?))
+        .ok_or(VmError::ArrayIndexOutOfBounds {
+            index: offset.saturating_add(len),
+            len: bytes.len(),
+        })?
+        .iter()
+        .map(|&byte| byte as u8)
+        .collect())
+}
+
+/// Sets a static field of `java/lang/System`, like the streams that are final in Java
+fn set_system_field(vm: &mut Vm, field: &str, value: Value) -> Result<Option<Value>> {
+    let system = vm.resolve_class("java/lang/System")?;
+    let slot = vm
+        .class(system)
+        .static_fields
+        .iter()
+        .position(|static_field| static_field.name == field)
+        .ok_or_else(|| VmError::NoSuchField(format!("java/lang/System.{}", field)))?;
+    vm.class_mut(system).static_values[slot] = value;
+    Ok(None)
+}
+
+/// The file descriptor number of a `FileInputStream` or `FileOutputStream`
+fn stream_fd(vm: &Vm, stream: ObjRef) -> Result<i32> {
+    let fd = field_slot(vm, vm.heap.get(stream).class, "fd")?;
+    let fd = vm
+        .get_field(stream, fd)?
+        .as_reference()?
+        .ok_or(VmError::NullPointer)?;
+    let number = field_slot(vm, vm.heap.get(fd).class, "fd")?;
+    vm.get_field(fd, number)?.as_int()
+}
+
+/// The `get` and `put` natives of `Unsafe` for the type with the descriptor
+const fn unsafe_accessors<const TYPE: u8>() -> [NativeMethod; 2] {
+    [
+        |vm, args| {
+            let (obj, offset) = (args[1].as_reference()?, args[2].as_long()?);
+            unsafe_get(vm, obj, offset, TYPE).map(Some)
+        },
+        |vm, args| {
+            let (obj, offset) = (args[1].as_reference()?, args[2].as_long()?);
+            unsafe_put(vm, obj, offset, TYPE, args[3])?;
+            Ok(None)
+        },
+    ]
+}
+
+/// The size of a value of the type with the descriptor when it is read from a byte array
+fn unsafe_size(ty: u8) -> usize {
+    match ty {
+        b'J' | b'D' => 8,
+        b'I' | b'F' => 4,
+        b'S' | b'C' => 2,
+        _ => 1,
+    }
+}
+
+fn unsafe_get(vm: &Vm, obj: Option<ObjRef>, offset: i64, ty: u8) -> Result<Value> {
+    let obj = obj.ok_or_else(unsupported_memory)?;
+    if let Some((class, slot)) = static_field(vm, obj, offset) {
+        return Ok(vm.class(class).static_values[slot]);
+    }
+    match &vm.heap.get(obj).data {
+        ObjectData::Fields(_) => vm.get_field(obj, offset as usize),
+        // wider values in byte arrays, like in `ByteBuffer`s, are in the native byte order
+        ObjectData::Array(Array::Byte(elements)) if unsafe_size(ty) > 1 => {
+            let bytes = usize::try_from(offset)
+                .ok()
+                .and_then(|offset| elements.get(offset..offset + unsafe_size(ty)))
+                .ok_or(VmError::ArrayIndexOutOfBounds {
+                    index: offset as i32,
+                    len: elements.len(),
+                })?;
+            let mut value = [0; 8];
+            for (byte, &element) in value.iter_mut().zip(bytes) {
+                *byte = element as u8;
+            }
+            let value = u64::from_le_bytes(value);
+            Ok(match ty {
+                b'J' => Value::Long(value as i64),
+                b'D' => Value::Double(f64::from_bits(value)),
+                b'I' => Value::Int(value as i32),
+                b'F' => Value::Float(f32::from_bits(value as u32)),
+                b'S' => Value::Int(value as i16 as i32),
+                _ => Value::Int(value as u16 as i32),
+            })
+        }
+        ObjectData::Array(array) => {
+            array
+                .load(offset as usize)
+                .ok_or(VmError::ArrayIndexOutOfBounds {
+                    index: offset as i32,
+                    len: array.len(),
+                })
+        }
+    }
+}
+
+fn unsafe_put(vm: &mut Vm, obj: Option<ObjRef>, offset: i64, ty: u8, value: Value) -> Result<()> {
+    let obj = obj.ok_or_else(unsupported_memory)?;
+    if let Some((class, slot)) = static_field(vm, obj, offset) {
+        vm.class_mut(class).static_values[slot] = value;
+        return Ok(());
+    }
+    if let ObjectData::Fields(_) = vm.heap.get(obj).data {
+        return vm.put_field(obj, offset as usize, value);
+    }
+    let array = vm.array_mut(obj)?;
+    if let (Array::Byte(elements), true) = (&mut *array, unsafe_size(ty) > 1) {
+        let bits = match value {
+            Value::Long(value) => value as u64,
+            Value::Double(value) => value.to_bits(),
+            Value::Float(value) => value.to_bits() as u64,
+            value => value.as_int()? as u64,
+        };
+        let len = elements.len();
+        let bytes = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| elements.get_mut(offset..offset + unsafe_size(ty)))
+            .ok_or(VmError::ArrayIndexOutOfBounds {
+                index: offset as i32,
+                len,
+            })?;
+        for (element, byte) in bytes.iter_mut().zip(bits.to_le_bytes()) {
+            *element = byte as i8;
+        }
+        return Ok(());
+    }
+    let len = array.len();
+    array
+        .store(offset as usize, value)
+        .ok_or(VmError::ArrayIndexOutOfBounds {
+            index: offset as i32,
+            len,
+        })
+}
+
+/// The class and slot of a static field from its `Unsafe` base and offset
+fn static_field(vm: &Vm, base: ObjRef, offset: i64) -> Option<(ClassId, usize)> {
+    let class = vm.mirror_class(base)?;
+    let slot = usize::try_from(offset.checked_sub(STATIC_FIELD_OFFSET)?).ok()?;
+    (slot < vm.class(class).static_values.len()).then_some((class, slot))
+}
+
+/// `compareAndExchange` with the arguments `this, obj, offset, expected, new`, returns the old value.
+/// References are compared by identity
+fn unsafe_compare_and_exchange(vm: &mut Vm, args: &[Value]) -> Result<Value> {
+    // the class library only compares and sets whole fields and elements
+    let (obj, offset) = (args[1].as_reference()?, args[2].as_long()?);
+    let old = unsafe_get(vm, obj, offset, b'L')?;
+    if old == args[3] {
+        unsafe_put(vm, obj, offset, b'L', args[4])?;
+    }
+    Ok(old)
+}
+
+/// `x - n * y` where `n` is the integer nearest to `x / y`, preferring an even `n` on ties
+fn ieee_remainder(x: f64, y: f64) -> f64 {
+    // the remainder of `%` is exact and has the sign of `x`
+    let remainder = x % y;
+    if !remainder.is_finite() || y.is_infinite() {
+        return remainder;
+    }
+    let half = y.abs() / 2.0;
+    let quotient_odd = ((x - remainder) / y) % 2.0 != 0.0;
+    if remainder.abs() > half || (remainder.abs() == half && quotient_odd) {
+        remainder - y.abs().copysign(remainder)
+    } else {
+        remainder
+    }
+}
+
+fn unsupported_memory() -> VmError {
+    VmError::Unsupported("Unsafe access to memory outside of the heap".to_string())
+}
@@ -0,0 +1,432 @@
+//!
+//! A reader for the jimage format of `lib/modules` in a JDK
+//!
+//! The image starts with a header and a perfect hash table, which maps the names of resources,
+//! like `/java.base/java/lang/Object.class`, to their locations. A location is a list of attributes
+//! with the parts of the name and where the contents are. The `/packages/<package>` resources
+//! list the modules that contain a package
+//!
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MAGIC: u32 = 0xCAFE_DADA;
+const MAJOR_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 7 * 4;
+const HASH_MULTIPLIER: u32 = 0x0100_0193;
+
+// the kinds of location attributes
+const ATTRIBUTE_END: usize = 0;
+const ATTRIBUTE_MODULE: usize = 1;
+const ATTRIBUTE_PARENT: usize = 2;
+const ATTRIBUTE_BASE: usize = 3;
+const ATTRIBUTE_EXTENSION: usize = 4;
+const ATTRIBUTE_OFFSET: usize = 5;
+const ATTRIBUTE_COMPRESSED: usize = 6;
+const ATTRIBUTE_UNCOMPRESSED: usize = 7;
+const ATTRIBUTE_COUNT: usize = 8;
+
+/// The attributes of a location, indexed by their kind
+type Location = [u64; ATTRIBUTE_COUNT];
+
+/// A jimage file, which is read into memory completely
+pub struct JImage {
+    data: Vec<u8>,
+    /// The byte order of the header, tables and package resources is the one of the platform
+    /// that created the image, the attributes of locations are always big endian
+    big_endian: bool,
+    table_length: usize,
+    locations_size: usize,
+    strings_size: usize,
+    /// The module of every package
+    packages: HashMap<String, String>,
+    /// The JDK that contains the image, if it was opened from `<java.home>/lib/modules`
+    java_home: Option<PathBuf>,
+}
+
+impl JImage {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut image = Self::from_bytes(std::fs::read(path)?)?;
+        image.java_home = path
+            .parent()
+            .filter(|lib| lib.ends_with("lib"))
+            .and_then(Path::parent)
+            .map(Path::to_path_buf);
+        Ok(image)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let magic = data
+            .get(..4)
+            .ok_or_else(|| invalid("Truncated jimage header"))?;
+        let big_endian = match magic.try_into().unwrap() {
+            bytes if u32::from_be_bytes(bytes) == MAGIC => true,
+            bytes if u32::from_le_bytes(bytes) == MAGIC => false,
+            _ => return Err(invalid("Not a jimage file")),
+        };
+
+        let mut image = Self {
+            data,
+            big_endian,
+            table_length: 0,
+            locations_size: 0,
+            strings_size: 0,
+            packages: HashMap::new(),
+            java_home: None,
+        };
+        if image.data.len() < HEADER_SIZE {
+            return Err(invalid("Truncated jimage header"));
+        }
+        if image.u4(4) >> 16 != MAJOR_VERSION {
+            return Err(invalid("Unsupported jimage version"));
+        }
+        image.table_length = image.u4(16) as usize;
+        image.locations_size = image.u4(20) as usize;
+        image.strings_size = image.u4(24) as usize;
+        if image.index_size() > image.data.len() {
+            return Err(invalid("Truncated jimage index"));
+        }
+
+        image.packages = image
+            .locations()
+            .filter(|location| image.string(location[ATTRIBUTE_MODULE]) == Some("packages"))
+            .filter_map(|location| {
+                let package = image.string(location[ATTRIBUTE_BASE])?;
+                Some((package.replace('.', "/"), image.package_module(&location)?))
+            })
+            .collect();
+        Ok(image)
+    }
+
+    /// The contents of the resource with the full name, for example `/java.base/java/lang/Object.class`.
+    /// Compressed resources are not supported
+    pub fn resource(&self, name: &str) -> Option<&[u8]> {
+        let location = self.find(name)?;
+        self.contents(&location)
+    }
+
+    /// The module that contains the package, for example `java.base` for `java/lang`
+    pub fn module_of(&self, package: &str) -> Option<&str> {
+        self.packages.get(package).map(String::as_str)
+    }
+
+    /// The class file of the class with the binary name, from the module that contains its package
+    pub fn class_file(&self, class: &str) -> Option<&[u8]> {
+        let (package, _) = class.rsplit_once('/')?;
+        let module = self.module_of(package)?;
+        self.resource(&format!("/{}/{}.class", module, class))
+    }
+
+    /// The directory of the JDK that contains the image, the `java.home` property
+    pub fn java_home(&self) -> Option<&Path> {
+        self.java_home.as_deref()
+    }
+
+    /// The full names of all resources in the image
+    pub fn resource_names(&self) -> Vec<String> {
+        self.locations()
+            .filter_map(|location| self.location_name(&location))
+            .collect()
+    }
+
+    fn u4(&self, offset: usize) -> u32 {
+        let bytes = self.data[offset..offset + 4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn redirect(&self, index: usize) -> i32 {
+        self.u4(HEADER_SIZE + index * 4) as i32
+    }
+
+    fn location_offset(&self, index: usize) -> usize {
+        self.u4(HEADER_SIZE + (self.table_length + index) * 4) as usize
+    }
+
+    fn locations_start(&self) -> usize {
+        HEADER_SIZE + self.table_length * 8
+    }
+
+    fn strings_start(&self) -> usize {
+        self.locations_start() + self.locations_size
+    }
+
+    /// The contents of resources start after the index
+    fn index_size(&self) -> usize {
+        self.strings_start() + self.strings_size
+    }
+
+    /// A null terminated string at the offset in the strings table
+    fn string(&self, offset: u64) -> Option<&str> {
+        let strings = &self.data[self.strings_start()..self.index_size()];
+        let string = strings.get(offset as usize..)?;
+        let end = string.iter().position(|&byte| byte == 0)?;
+        std::str::from_utf8(&string[..end]).ok()
+    }
+
+    fn location(&self, offset: usize) -> Option<Location> {
+        let locations = &self.data[self.locations_start()..self.strings_start()];
+        let mut bytes = locations.get(offset..)?.iter();
+        let mut location = [0; ATTRIBUTE_COUNT];
+        loop {
+            let byte = *bytes.next()?;
+            let kind = (byte >> 3) as usize;
+            if kind == ATTRIBUTE_END {
+                return Some(location);
+            }
+            let len = (byte & 7) as usize + 1;
+            let value = bytes
+                .by_ref()
+                .take(len)
+                .fold(0, |value, &byte| value << 8 | byte as u64);
+            *location.get_mut(kind)? = value;
+        }
+    }
+
+    fn locations(&self) -> impl Iterator<Item = Location> + '_ {
+        (0..self.table_length).filter_map(|index| self.location(self.location_offset(index)))
+    }
+
+    /// The full name of a location, `/module/parent/base.extension`
+    fn location_name(&self, location: &Location) -> Option<String> {
+        let mut name = String::new();
+        let module = self.string(location[ATTRIBUTE_MODULE])?;
+        if !module.is_empty() {
+            name.push('/');
+            name.push_str(module);
+            name.push('/');
+        }
+        let parent = self.string(location[ATTRIBUTE_PARENT])?;
+        if !parent.is_empty() {
+            name.push_str(parent);
+            name.push('/');
+        }
+        name.push_str(self.string(location[ATTRIBUTE_BASE])?);
+        let extension = self.string(location[ATTRIBUTE_EXTENSION])?;
+        if !extension.is_empty() {
+            name.push('.');
+            name.push_str(extension);
+        }
+        Some(name)
+    }
+
+    /// Looks up the location of a name in the perfect hash table
+    fn find(&self, name: &str) -> Option<Location> {
+        if self.table_length == 0 {
+            return None;
+        }
+        let index = hash(name, HASH_MULTIPLIER) as usize % self.table_length;
+        let index = match self.redirect(index) {
+            0 => return None,
+            redirect if redirect < 0 => (-1 - redirect) as usize,
+            seed => hash(name, seed as u32) as usize % self.table_length,
+        };
+        if index >= self.table_length {
+            return None;
+        }
+        let location = self.location(self.location_offset(index))?;
+        (self.location_name(&location)? == name).then_some(location)
+    }
+
+    fn contents(&self, location: &Location) -> Option<&[u8]> {
+        if location[ATTRIBUTE_COMPRESSED] != 0 {
+            return None;
+        }
+        let start = self.index_size() + location[ATTRIBUTE_OFFSET] as usize;
+        self.data
+            .get(start..start + location[ATTRIBUTE_UNCOMPRESSED] as usize)
+    }
+
+    /// The contents of a `/packages` resource are pairs of a flag whether the package is empty
+    /// in a module and the name of the module
+    fn package_module(&self, location: &Location) -> Option<String> {
+        let contents = self.contents(location)?;
+        let start = contents.as_ptr() as usize - self.data.as_ptr() as usize;
+        (0..contents.len() / 8)
+            .map(|entry| (self.u4(start + entry * 8), self.u4(start + entry * 8 + 4)))
+            .find(|&(empty, _)| empty == 0)
+            .and_then(|(_, module)| self.string(module as u64))
+            .map(str::to_string)
+    }
+}
+
+/// The hash of the perfect hash table, a FNV-1 hash of the UTF-8 bytes
+fn hash(name: &str, seed: u32) -> u32 {
+    name.bytes().fold(seed, |hash, byte| {
+        hash.wrapping_mul(HASH_MULTIPLIER) ^ byte as u32
+    }) & 0x7FFF_FFFF
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds a jimage with the resources, like `jlink` does
+    pub(crate) fn build(resources: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut strings = vec![0u8];
+        let mut string_offsets = HashMap::new();
+        let mut add_string = |string: &str| -> u64 {
+            if string.is_empty() {
+                return 0;
+            }
+            *string_offsets.entry(string.to_string()).or_insert_with(|| {
+                let offset = strings.len() as u64;
+                strings.extend_from_slice(string.as_bytes());
+                strings.push(0);
+                offset
+            })
+        };
+
+        // the resources of the packages: not empty, and the offset of the module name
+        let mut entries = resources
+            .iter()
+            .map(|&(name, contents)| (name.to_string(), contents.to_vec()))
+            .collect::<Vec<_>>();
+        let mut packages = HashMap::new();
+        for (name, _) in &entries {
+            let (module, path) = name[1..].split_once('/').unwrap();
+            if let Some((package, _)) = path.rsplit_once('/') {
+                packages.insert(package.replace('/', "."), module.to_string());
+            }
+        }
+        for (package, module) in packages {
+            let mut contents = 0u32.to_le_bytes().to_vec();
+            contents.extend_from_slice(&(add_string(&module) as u32).to_le_bytes());
+            entries.push((format!("/packages/{}", package), contents));
+        }
+
+        let mut locations = Vec::new();
+        let mut location_offsets = Vec::new();
+        let mut resources_data = Vec::new();
+        for (name, contents) in &entries {
+            let (module, path) = name[1..].split_once('/').unwrap();
+            let (parent, file) = path.rsplit_once('/').unwrap_or(("", path));
+            // the names of packages contain dots, but no extension
+            let (base, extension) = match file.rsplit_once('.') {
+                Some(split) if module != "packages" => split,
+                _ => (file, ""),
+            };
+            let attributes = [
+                (ATTRIBUTE_MODULE, add_string(module)),
+                (ATTRIBUTE_PARENT, add_string(parent)),
+                (ATTRIBUTE_BASE, add_string(base)),
+                (ATTRIBUTE_EXTENSION, add_string(extension)),
+                (ATTRIBUTE_OFFSET, resources_data.len() as u64),
+                (ATTRIBUTE_UNCOMPRESSED, contents.len() as u64),
+            ];
+            location_offsets.push(locations.len() as u32);
+            for (kind, value) in attributes {
+                let bytes = value.to_be_bytes();
+                let skip = bytes.iter().take(7).take_while(|&&byte| byte == 0).count();
+                locations.push((kind << 3) as u8 | (7 - skip) as u8);
+                locations.extend_from_slice(&bytes[skip..]);
+            }
+            locations.push(ATTRIBUTE_END as u8);
+            resources_data.extend_from_slice(contents);
+        }
+
+        // buckets with collisions get a seed that spreads them, single entries use a free slot
+        let len = entries.len();
+        let mut buckets = vec![Vec::new(); len];
+        for (index, (name, _)) in entries.iter().enumerate() {
+            buckets[hash(name, HASH_MULTIPLIER) as usize % len].push(index);
+        }
+        let mut order = (0..len).collect::<Vec<_>>();
+        order.sort_by_key(|&bucket| std::cmp::Reverse(buckets[bucket].len()));
+        let mut redirect = vec![0i32; len];
+        let mut slots = vec![None; len];
+        for bucket in order {
+            match buckets[bucket][..] {
+                [] => {}
+                [entry] => {
+                    let free = slots.iter().position(Option::is_none).unwrap();
+                    slots[free] = Some(entry);
+                    redirect[bucket] = -1 - free as i32;
+                }
+                ref entries_in_bucket => {
+                    let seed = (1..)
+                        .find(|&seed| {
+                            let mut used = Vec::new();
+                            entries_in_bucket.iter().all(|&entry| {
+                                let slot = hash(&entries[entry].0, seed) as usize % len;
+                                let free = slots[slot].is_none() && !used.contains(&slot);
+                                used.push(slot);
+                                free
+                            })
+                        })
+                        .unwrap();
+                    for &entry in entries_in_bucket {
+                        slots[hash(&entries[entry].0, seed) as usize % len] = Some(entry);
+                    }
+                    redirect[bucket] = seed as i32;
+                }
+            }
+        }
+
+        let mut image = Vec::new();
+        for value in [
+            MAGIC,
+            MAJOR_VERSION << 16,
+            0,
+            len as u32,
+            len as u32,
+            locations.len() as u32,
+            strings.len() as u32,
+        ] {
+            image.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in redirect {
+            image.extend_from_slice(&value.to_le_bytes());
+        }
+        for slot in slots {
+            image.extend_from_slice(&location_offsets[slot.unwrap()].to_le_bytes());
+        }
+        image.extend_from_slice(&locations);
+        image.extend_from_slice(&strings);
+        image.extend_from_slice(&resources_data);
+        image
+    }
+
+    #[test]
+    fn read_resources() {
+        let resources = (0..50)
+            .map(|i| {
+                (
+                    format!("/java.base/java/lang/Class{}.class", i),
+                    vec![i as u8; i],
+                )
+            })
+            .chain([
+                ("/java.base/java/util/List.class".to_string(), vec![1, 2]),
+                ("/jdk.other/jdk/other/Other.class".to_string(), vec![3]),
+                ("/java.base/META-INF/services/x.y".to_string(), vec![4]),
+            ])
+            .collect::<Vec<_>>();
+        let resources = resources
+            .iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_slice()))
+            .collect::<Vec<_>>();
+        let image = JImage::from_bytes(build(&resources)).unwrap();
+
+        for (name, contents) in &resources {
+            assert_eq!(image.resource(name), Some(*contents), "{}", name);
+        }
+        assert_eq!(image.resource("/java.base/java/lang/Missing.class"), None);
+        assert_eq!(image.module_of("java/lang"), Some("java.base"));
+        assert_eq!(image.module_of("jdk/other"), Some("jdk.other"));
+        assert_eq!(image.class_file("java/util/List"), Some(&[1, 2][..]));
+        assert_eq!(image.class_file("jdk/other/Other"), Some(&[3][..]));
+        assert_eq!(image.class_file("jdk/other/Missing"), None);
+        assert!(image
+            .resource_names()
+            .contains(&"/packages/java.lang".to_string()));
+
+        assert!(JImage::from_bytes(vec![0xCA, 0xFE, 0xBA, 0xBE]).is_err());
+    }
+}
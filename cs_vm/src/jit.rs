@@ -0,0 +1,808 @@
+//!
+//! The baseline compiler
+//!
+//! The interpreter counts how often methods are invoked and how often their loops jump back.
+//! Once a method is hot, it is compiled to machine code, one template per instruction. Compiled
+//! code only handles `int` and `long` arithmetic, local variables and branches. Every other
+//! instruction exits to the interpreter, which continues in the same frame at that instruction.
+//! Compiled code is entered when the method is invoked and when a loop jumps back to its start,
+//! so a long running loop switches to compiled code in the middle of the method.
+//!
+//! Compiled code keeps the local variables and the operand stack in an array with one `i64` per
+//! slot. The types of the slots before every instruction are known from a data flow analysis, so
+//! whenever the code exits, the frame of the interpreter is rebuilt from the array. That is also
+//! how compiled code deoptimizes when a case it does not handle comes up at runtime, like a
+//! division by zero: it exits at the instruction and the interpreter throws the exception
+//!
+
+use crate::class::{Class, Code, Method, MethodId};
+use crate::model::{Frame, ThreadState, Value};
+use crate::opcode::{self, *};
+use crate::{Result, Vm};
+use cs_model::FieldType;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+use crate::x86_64::{self, MachineCode};
+
+/// When methods are compiled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JitMode {
+    /// Only interpret
+    Disabled,
+    /// Compile methods once they are hot
+    #[default]
+    Enabled,
+    /// Compile every method the first time it is invoked or jumps back, to test the compiler
+    Forced,
+}
+
+/// The number of invocations after which a method is compiled
+const INVOCATION_THRESHOLD: u32 = 1000;
+
+/// The number of backward jumps after which a method is compiled
+const BACK_EDGE_THRESHOLD: u32 = 10_000;
+
+/// How often a method is compiled again for new entry points, before the compiler gives up on it
+const MAX_RECOMPILATIONS: usize = 8;
+
+/// The type of a local variable or operand stack entry in compiled code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SlotType {
+    Int,
+    Long,
+    /// Anything compiled code can not use: a reference, a float, a double, the second half
+    /// of a long, or different types on different paths
+    Top,
+}
+
+impl SlotType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Int(_) => SlotType::Int,
+            Value::Long(_) => SlotType::Long,
+            _ => SlotType::Top,
+        }
+    }
+}
+
+/// The types of the local variables and the operand stack entries before an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct State {
+    pub(crate) locals: Vec<SlotType>,
+    pub(crate) stack: Vec<SlotType>,
+}
+
+impl State {
+    fn of(frame: &Frame) -> Self {
+        Self {
+            locals: frame.locals.values().iter().map(SlotType::of).collect(),
+            stack: frame.stack.values().iter().map(SlotType::of).collect(),
+        }
+    }
+
+    /// The state when the method is invoked, from the types of its parameters
+    fn entry(method: &Method, code: &Code) -> Self {
+        let mut locals = vec![SlotType::Top; code.max_locals as usize];
+        let mut index = !method.is_static() as usize;
+        for parameter in &method.method_descriptor.parameters {
+            let ty = match parameter {
+                FieldType::Boolean
+                | FieldType::Byte
+                | FieldType::Char
+                | FieldType::Short
+                | FieldType::Int => SlotType::Int,
+                FieldType::Long => SlotType::Long,
+                _ => SlotType::Top,
+            };
+            if let Some(local) = locals.get_mut(index) {
+                *local = ty;
+            }
+            index += parameter.slots() as usize;
+        }
+        Self {
+            locals,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Whether the frame can enter compiled code that expects this state
+    fn admits(&self, frame: &Frame) -> bool {
+        let locals = frame.locals.values().iter().zip(&self.locals);
+        frame.stack.values().is_empty()
+            && self.stack.is_empty()
+            && locals
+                .into_iter()
+                .all(|(value, &ty)| ty == SlotType::Top || SlotType::of(value) == ty)
+    }
+
+    /// Merges the state of another path to the same instruction, `None` if they can not be merged
+    fn merge(&self, other: &State) -> Option<State> {
+        if self.stack != other.stack {
+            return None;
+        }
+        let locals = self.locals.iter().zip(&other.locals);
+        Some(State {
+            locals: locals
+                .map(|(&a, &b)| if a == b { a } else { SlotType::Top })
+                .collect(),
+            stack: self.stack.clone(),
+        })
+    }
+
+    fn pop(&mut self, ty: SlotType) -> Option<()> {
+        (self.stack.pop()? == ty).then_some(())
+    }
+
+    fn store(&mut self, index: usize, ty: SlotType) -> Option<()> {
+        let len = if ty == SlotType::Long { 2 } else { 1 };
+        if index + len > self.locals.len() {
+            return None;
+        }
+        // a long that started in the slot before is overwritten
+        if index > 0 && self.locals[index - 1] == SlotType::Long {
+            self.locals[index - 1] = SlotType::Top;
+        }
+        self.locals[index] = ty;
+        if len == 2 {
+            self.locals[index + 1] = SlotType::Top;
+        }
+        Some(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Ushr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Conversion {
+    IntToLong,
+    LongToInt,
+    IntToByte,
+    IntToChar,
+    IntToShort,
+}
+
+/// The conditions of `ifeq` to `ifle` and `if_icmpeq` to `if_icmple`, in opcode order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Condition {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Gt,
+    Le,
+}
+
+impl Condition {
+    fn from_index(index: u8) -> Self {
+        [
+            Condition::Eq,
+            Condition::Ne,
+            Condition::Lt,
+            Condition::Ge,
+            Condition::Gt,
+            Condition::Le,
+        ][index as usize]
+    }
+}
+
+/// An instruction as compiled code sees it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Op {
+    /// Pushes an `int` or `long` constant
+    Const(Value),
+    Load(SlotType, usize),
+    Store(SlotType, usize),
+    /// `iinc`: adds the constant to the `int` local variable
+    Increment(usize, i32),
+    Binary(SlotType, BinaryOp),
+    Negate(SlotType),
+    Convert(Conversion),
+    /// `lcmp`
+    CompareLongs,
+    /// Compares an `int` with zero and jumps to the target if the condition holds
+    If(Condition, usize),
+    /// Compares two `int` values and jumps to the target if the condition holds
+    IfCompare(Condition, usize),
+    Goto(usize),
+    Pop,
+    Dup,
+    /// Leaves compiled code, the interpreter executes the instruction
+    Exit,
+}
+
+impl Op {
+    /// Decodes the instruction at `pc`, along with its length
+    fn decode(class: &Class, code: &Code, pc: usize) -> (Op, usize) {
+        let bytes = &code.code;
+        let Some(len) = opcode::length(bytes, pc) else {
+            return (Op::Exit, 1);
+        };
+        let u1 = |offset: usize| bytes[pc + offset];
+        let u2 = |offset: usize| u16::from_be_bytes([bytes[pc + offset], bytes[pc + offset + 1]]);
+        let target = |offset: i16| {
+            let target = pc as isize + offset as isize;
+            (0..bytes.len() as isize)
+                .contains(&target)
+                .then_some(target as usize)
+        };
+        let ty = |n: u8| [SlotType::Int, SlotType::Long][n as usize];
+        // arithmetic instructions come in groups of `i`, `l`, `f` and `d`
+        let group = |opcode: u8, first: u8| {
+            let n = opcode - first;
+            (n % 4 < 2).then(|| (ty(n % 4), (n / 4) as usize))
+        };
+
+        let op = match bytes[pc] {
+            // a jump to the next instruction
+            NOP => Op::Goto(pc + len),
+            op @ ICONST_M1..=ICONST_5 => Op::Const(Value::Int(op as i32 - ICONST_0 as i32)),
+            op @ (LCONST_0 | LCONST_1) => Op::Const(Value::Long((op - LCONST_0) as i64)),
+            BIPUSH => Op::Const(Value::Int(u1(1) as i8 as i32)),
+            SIPUSH => Op::Const(Value::Int(u2(1) as i16 as i32)),
+            op @ (LDC | LDC_W | LDC2_W) => {
+                let index = if op == LDC { u1(1) as u16 } else { u2(1) };
+                match class.cp_numeric(index) {
+                    Ok(value @ (Value::Int(_) | Value::Long(_))) => Op::Const(value),
+                    _ => Op::Exit,
+                }
+            }
+            op @ (ILOAD | LLOAD) => Op::Load(ty(op - ILOAD), u1(1) as usize),
+            op @ ILOAD_0..=LLOAD_3 => {
+                let n = op - ILOAD_0;
+                Op::Load(ty(n / 4), (n % 4) as usize)
+            }
+            op @ (ISTORE | LSTORE) => Op::Store(ty(op - ISTORE), u1(1) as usize),
+            op @ ISTORE_0..=LSTORE_3 => {
+                let n = op - ISTORE_0;
+                Op::Store(ty(n / 4), (n % 4) as usize)
+            }
+            POP => Op::Pop,
+            DUP => Op::Dup,
+            op @ IADD..=DREM => match group(op, IADD) {
+                Some((ty, n)) => {
+                    let ops = [
+                        BinaryOp::Add,
+                        BinaryOp::Sub,
+                        BinaryOp::Mul,
+                        BinaryOp::Div,
+                        BinaryOp::Rem,
+                    ];
+                    Op::Binary(ty, ops[n])
+                }
+                None => Op::Exit,
+            },
+            op @ INEG..=DNEG => group(op, INEG).map_or(Op::Exit, |(ty, _)| Op::Negate(ty)),
+            // shifts and bitwise operations only come as `i` and `l`
+            op @ ISHL..=LUSHR => {
+                let n = op - ISHL;
+                let ops = [BinaryOp::Shl, BinaryOp::Shr, BinaryOp::Ushr];
+                Op::Binary(ty(n % 2), ops[(n / 2) as usize])
+            }
+            op @ IAND..=LXOR => {
+                let n = op - IAND;
+                let ops = [BinaryOp::And, BinaryOp::Or, BinaryOp::Xor];
+                Op::Binary(ty(n % 2), ops[(n / 2) as usize])
+            }
+            IINC => Op::Increment(u1(1) as usize, u1(2) as i8 as i32),
+            I2L => Op::Convert(Conversion::IntToLong),
+            L2I => Op::Convert(Conversion::LongToInt),
+            I2B => Op::Convert(Conversion::IntToByte),
+            I2C => Op::Convert(Conversion::IntToChar),
+            I2S => Op::Convert(Conversion::IntToShort),
+            LCMP => Op::CompareLongs,
+            op @ IFEQ..=IFLE => match target(u2(1) as i16) {
+                Some(target) => Op::If(Condition::from_index(op - IFEQ), target),
+                None => Op::Exit,
+            },
+            op @ IF_ICMPEQ..=IF_ICMPLE => match target(u2(1) as i16) {
+                Some(target) => Op::IfCompare(Condition::from_index(op - IF_ICMPEQ), target),
+                None => Op::Exit,
+            },
+            GOTO => target(u2(1) as i16).map_or(Op::Exit, Op::Goto),
+            WIDE => match u1(1) {
+                op @ (ILOAD | LLOAD) => Op::Load(ty(op - ILOAD), u2(2) as usize),
+                op @ (ISTORE | LSTORE) => Op::Store(ty(op - ISTORE), u2(2) as usize),
+                IINC => Op::Increment(u2(2) as usize, u2(4) as i16 as i32),
+                _ => Op::Exit,
+            },
+            _ => Op::Exit,
+        };
+        (op, len)
+    }
+
+    /// The state after the instruction, `None` if the types do not fit and it has to exit
+    fn transfer(&self, state: &State, max_stack: usize) -> Option<State> {
+        let mut state = state.clone();
+        let push =
+            |state: &mut State, ty| (state.stack.len() < max_stack).then(|| state.stack.push(ty));
+        match *self {
+            Op::Const(value) => push(&mut state, SlotType::of(&value))?,
+            Op::Load(ty, index) => {
+                if state.locals.get(index) != Some(&ty) {
+                    return None;
+                }
+                push(&mut state, ty)?
+            }
+            Op::Store(ty, index) => {
+                state.pop(ty)?;
+                state.store(index, ty)?
+            }
+            Op::Increment(index, _) => {
+                if state.locals.get(index) != Some(&SlotType::Int) {
+                    return None;
+                }
+            }
+            Op::Binary(ty, op) => {
+                let distance = match op {
+                    BinaryOp::Shl | BinaryOp::Shr | BinaryOp::Ushr => SlotType::Int,
+                    _ => ty,
+                };
+                state.pop(distance)?;
+                state.pop(ty)?;
+                state.stack.push(ty)
+            }
+            Op::Negate(ty) => {
+                state.pop(ty)?;
+                state.stack.push(ty)
+            }
+            Op::Convert(conversion) => {
+                let (from, to) = match conversion {
+                    Conversion::IntToLong => (SlotType::Int, SlotType::Long),
+                    Conversion::LongToInt => (SlotType::Long, SlotType::Int),
+                    _ => (SlotType::Int, SlotType::Int),
+                };
+                state.pop(from)?;
+                state.stack.push(to)
+            }
+            Op::CompareLongs => {
+                state.pop(SlotType::Long)?;
+                state.pop(SlotType::Long)?;
+                state.stack.push(SlotType::Int)
+            }
+            Op::If(..) => state.pop(SlotType::Int)?,
+            Op::IfCompare(..) => {
+                state.pop(SlotType::Int)?;
+                state.pop(SlotType::Int)?
+            }
+            Op::Goto(_) => {}
+            Op::Pop => state.pop(SlotType::Int)?,
+            Op::Dup => {
+                state.pop(SlotType::Int)?;
+                state.stack.push(SlotType::Int);
+                push(&mut state, SlotType::Int)?
+            }
+            Op::Exit => return None,
+        }
+        Some(state)
+    }
+
+    /// The instructions that can run next, the branch target last
+    fn successors(&self, pc: usize, len: usize) -> Vec<usize> {
+        match *self {
+            Op::If(_, target) | Op::IfCompare(_, target) => vec![pc + len, target],
+            Op::Goto(target) => vec![target],
+            Op::Exit => Vec::new(),
+            _ => vec![pc + len],
+        }
+    }
+}
+
+/// The instructions of a method that compiled code can reach, with the states before them
+pub(crate) struct Analysis {
+    /// The reachable instructions ordered by pc
+    pub(crate) ops: Vec<(usize, Op)>,
+    /// The state before every reachable instruction, by pc
+    pub(crate) states: Vec<Option<State>>,
+    /// The instructions compiled code can be entered at: the entries it was analyzed from,
+    /// and the targets of backward jumps with an empty operand stack
+    pub(crate) entries: Vec<usize>,
+    pub(crate) max_locals: usize,
+    pub(crate) max_stack: usize,
+}
+
+impl Analysis {
+    /// Finds the types of all slots before every instruction reachable from the entries,
+    /// `None` if the method can not be compiled
+    pub(crate) fn new(class: &Class, code: &Code, entries: &[(usize, State)]) -> Option<Self> {
+        let max_stack = code.max_stack as usize;
+        let mut states: Vec<Option<State>> = vec![None; code.code.len()];
+        let mut work = Vec::new();
+        for (pc, state) in entries {
+            merge_into(&mut states, *pc, state, &mut work)?;
+        }
+        while let Some(pc) = work.pop() {
+            let state = states[pc].clone()?;
+            let (op, len) = Op::decode(class, code, pc);
+            let Some(next) = op.transfer(&state, max_stack) else {
+                continue;
+            };
+            for successor in op.successors(pc, len) {
+                merge_into(&mut states, successor, &next, &mut work)?;
+            }
+        }
+
+        let mut ops = Vec::new();
+        let mut loop_heads = Vec::new();
+        for (pc, state) in states.iter().enumerate() {
+            let Some(state) = state else { continue };
+            let (mut op, len) = Op::decode(class, code, pc);
+            if op.transfer(state, max_stack).is_none() {
+                op = Op::Exit;
+            }
+            for successor in op.successors(pc, len) {
+                if successor <= pc && states[successor].as_ref()?.stack.is_empty() {
+                    loop_heads.push(successor);
+                }
+            }
+            ops.push((pc, op));
+        }
+        let mut entry_pcs = entries.iter().map(|(pc, _)| *pc).collect::<Vec<_>>();
+        entry_pcs.extend(loop_heads);
+        entry_pcs.sort_unstable();
+        entry_pcs.dedup();
+        // entering code that exits right away is only overhead
+        entry_pcs.retain(|&pc| {
+            let at = ops.binary_search_by_key(&pc, |&(pc, _)| pc);
+            at.is_ok_and(|at| ops[at].1 != Op::Exit)
+        });
+        Some(Self {
+            ops,
+            states,
+            entries: entry_pcs,
+            max_locals: code.max_locals as usize,
+            max_stack,
+        })
+    }
+
+    /// The index of the local variable in the slot array of compiled code
+    pub(crate) fn local_slot(&self, index: usize) -> usize {
+        index
+    }
+
+    /// The index of the operand stack entry in the slot array of compiled code
+    pub(crate) fn stack_slot(&self, depth: usize) -> usize {
+        self.max_locals + depth
+    }
+
+    /// The index of the slot that counts down the backward jumps until compiled code yields
+    pub(crate) fn fuel_slot(&self) -> usize {
+        self.max_locals + self.max_stack
+    }
+
+    pub(crate) fn slots(&self) -> usize {
+        self.fuel_slot() + 1
+    }
+}
+
+/// Merges the state into the one before the instruction, and queues it if it changed
+fn merge_into(
+    states: &mut [Option<State>],
+    pc: usize,
+    state: &State,
+    work: &mut Vec<usize>,
+) -> Option<()> {
+    let slot = states.get_mut(pc)?;
+    let merged = match slot {
+        Some(old) => old.merge(state)?,
+        None => state.clone(),
+    };
+    if slot.as_ref() != Some(&merged) {
+        *slot = Some(merged);
+        work.push(pc);
+    }
+    Some(())
+}
+
+/// Why compiled code exited, in the upper half of the value it returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExitKind {
+    /// At an instruction the interpreter executes, like an invocation or a return
+    Interpret = 0,
+    /// Jumped back too often without exiting, so that the scheduler can switch threads
+    Yield = 1,
+    /// A case compiled code does not handle came up, like a division by zero
+    Deoptimize = 2,
+}
+
+impl ExitKind {
+    /// The value compiled code returns when it exits at `pc`
+    pub(crate) fn at(self, pc: usize) -> u64 {
+        (self as u64) << 32 | pc as u64
+    }
+
+    fn split(exit: u64) -> (ExitKind, usize) {
+        let kind = match exit >> 32 {
+            0 => ExitKind::Interpret,
+            1 => ExitKind::Yield,
+            _ => ExitKind::Deoptimize,
+        };
+        (kind, exit as u32 as usize)
+    }
+}
+
+/// A method compiled to machine code
+pub(crate) struct CompiledMethod {
+    /// The states the method was compiled for, to compile it again with more entries
+    seeds: Vec<(usize, State)>,
+    states: Vec<Option<State>>,
+    entries: Vec<usize>,
+    slots: usize,
+    fuel_slot: usize,
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    code: MachineCode,
+}
+
+/// How hot a method is, and its compiled code
+#[derive(Default)]
+struct Profile {
+    invocations: u32,
+    back_edges: u32,
+    compiled: Option<Rc<CompiledMethod>>,
+    compilations: usize,
+    /// The method can not be compiled
+    failed: bool,
+}
+
+/// The profiles of all methods, and what the compiler did
+#[derive(Default)]
+pub(crate) struct Jit {
+    profiles: HashMap<MethodId, Profile>,
+    /// The number of methods that were compiled, including again for new entries
+    pub(crate) compilations: usize,
+    /// The number of times compiled code was entered
+    pub(crate) entries: usize,
+    pub(crate) deoptimizations: usize,
+}
+
+impl Vm {
+    /// Counts an invocation of the method of the frame that was just pushed,
+    /// and runs its compiled code
+    pub(crate) fn jit_invoked(&mut self) -> Result<()> {
+        self.jit_count(|profile| {
+            profile.invocations += 1;
+            profile.invocations >= INVOCATION_THRESHOLD
+        })
+    }
+
+    /// Counts a backward jump of the current frame, and continues in compiled code
+    pub(crate) fn jit_back_edge(&mut self) -> Result<()> {
+        self.jit_count(|profile| {
+            profile.back_edges += 1;
+            profile.back_edges >= BACK_EDGE_THRESHOLD
+        })
+    }
+
+    fn jit_count(&mut self, count: impl FnOnce(&mut Profile) -> bool) -> Result<()> {
+        let mode = self.options.jit;
+        if mode == JitMode::Disabled || self.thread().state != ThreadState::Runnable {
+            return Ok(());
+        }
+        let frame = self.thread().frames.last().expect("a frame was pushed");
+        // compiled code is only entered with an empty operand stack
+        if !frame.stack.values().is_empty() {
+            return Ok(());
+        }
+        let (id, pc) = (frame.method, frame.pc);
+        let profile = self.jit.profiles.entry(id).or_default();
+        if profile.failed {
+            return Ok(());
+        }
+        let hot = count(profile) || mode == JitMode::Forced;
+        let compiled = profile
+            .compiled
+            .clone()
+            .filter(|compiled| compiled.entries.binary_search(&pc).is_ok());
+        let compiled = match compiled {
+            Some(compiled) => compiled,
+            None if hot => match self.compile(id) {
+                Some(compiled) => compiled,
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        self.run_compiled(&compiled)
+    }
+
+    /// Compiles the method for the state of the current frame, in addition to the states
+    /// it was compiled for before. Keeps the old code if that fails
+    fn compile(&mut self, id: MethodId) -> Option<Rc<CompiledMethod>> {
+        let frame = self.thread().frames.last().expect("a frame was pushed");
+        let seed = (frame.pc, State::of(frame));
+        let code = frame.code.clone();
+        let profile = self
+            .jit
+            .profiles
+            .get_mut(&id)
+            .expect("the method was profiled");
+        if profile.compilations == MAX_RECOMPILATIONS {
+            return None;
+        }
+        profile.compilations += 1;
+        let mut seeds = match &profile.compiled {
+            Some(compiled) => compiled.seeds.clone(),
+            None => Vec::new(),
+        };
+        seeds.push(seed);
+
+        let compiled = compile_method(self.class(id.class), &code, seeds).map(Rc::new);
+        let profile = self
+            .jit
+            .profiles
+            .get_mut(&id)
+            .expect("the method was profiled");
+        match compiled {
+            Some(compiled) => {
+                profile.compiled = Some(compiled.clone());
+                self.jit.compilations += 1;
+                Some(compiled)
+            }
+            None => {
+                profile.failed = profile.compiled.is_none();
+                None
+            }
+        }
+    }
+
+    /// Compiles the method for its entry, for the types of its parameters, and returns the
+    /// machine code. See `aot`
+    pub(crate) fn precompile(&self, id: MethodId) -> Option<Vec<u8>> {
+        let method = self.method(id);
+        let code = method.code.as_ref()?;
+        let seeds = [(0, State::entry(method, code))];
+        assemble_method(self.class(id.class), code, &seeds)
+    }
+
+    /// Installs the machine code that `precompile` returned for the method in the same build.
+    /// The states to rebuild frames with are analyzed again, they are not part of the code
+    pub(crate) fn install_precompiled(&mut self, id: MethodId, machine_code: &[u8]) -> bool {
+        let method = self.method(id);
+        let Some(code) = method.code.clone() else {
+            return false;
+        };
+        let seeds = vec![(0, State::entry(method, &code))];
+        let compiled = load_method(self.class(id.class), &code, seeds, machine_code);
+        let Some(compiled) = compiled else {
+            return false;
+        };
+        self.jit.profiles.entry(id).or_default().compiled = Some(Rc::new(compiled));
+        true
+    }
+
+    /// Runs compiled code from the pc of the current frame, if the frame fits,
+    /// and rebuilds the frame where it exits
+    fn run_compiled(&mut self, compiled: &CompiledMethod) -> Result<()> {
+        let yield_after = self.options.time_slice as i64;
+        let frame = self.frame_mut();
+        let pc = frame.pc;
+        match &compiled.states[pc] {
+            Some(state) if state.admits(frame) => {}
+            _ => return Ok(()),
+        }
+        let mut slots = vec![0; compiled.slots];
+        for (slot, value) in slots.iter_mut().zip(frame.locals.values()) {
+            *slot = match *value {
+                Value::Int(value) => value as i64,
+                Value::Long(value) => value,
+                _ => 0,
+            };
+        }
+        slots[compiled.fuel_slot] = yield_after.max(1);
+
+        let exit = compiled.run(&mut slots, pc);
+        let (kind, pc) = ExitKind::split(exit);
+        let state = compiled.states[pc]
+            .as_ref()
+            .expect("compiled code exits at analyzed instructions");
+        let max_locals = state.locals.len();
+        for (index, &ty) in state.locals.iter().enumerate() {
+            match ty {
+                SlotType::Int => frame
+                    .locals
+                    .store(index as u16, Value::Int(slots[index] as i32))?,
+                SlotType::Long => frame
+                    .locals
+                    .store(index as u16, Value::Long(slots[index]))?,
+                SlotType::Top => {}
+            }
+        }
+        for (depth, &ty) in state.stack.iter().enumerate() {
+            let slot = slots[max_locals + depth];
+            frame.stack.push(match ty {
+                SlotType::Long => Value::Long(slot),
+                _ => Value::Int(slot as i32),
+            })?;
+        }
+        frame.pc = pc;
+        self.jit.entries += 1;
+        self.jit.deoptimizations += (kind == ExitKind::Deoptimize) as usize;
+        Ok(())
+    }
+}
+
+impl CompiledMethod {
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn run(&self, slots: &mut [i64], pc: usize) -> u64 {
+        self.code.run(slots, pc)
+    }
+
+    /// Methods are never compiled for other targets
+    #[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+    fn run(&self, _: &mut [i64], pc: usize) -> u64 {
+        ExitKind::Interpret.at(pc)
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn compile_method(
+    class: &Class,
+    code: &Code,
+    seeds: Vec<(usize, State)>,
+) -> Option<CompiledMethod> {
+    let analysis = Analysis::new(class, code, &seeds)?;
+    Some(CompiledMethod {
+        code: x86_64::compile(&analysis)?,
+        entries: analysis.entries.clone(),
+        slots: analysis.slots(),
+        fuel_slot: analysis.fuel_slot(),
+        states: analysis.states,
+        seeds,
+    })
+}
+
+/// There is no code generator for other targets
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+fn compile_method(_: &Class, _: &Code, _: Vec<(usize, State)>) -> Option<CompiledMethod> {
+    None
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn assemble_method(class: &Class, code: &Code, seeds: &[(usize, State)]) -> Option<Vec<u8>> {
+    let analysis = Analysis::new(class, code, seeds)?;
+    if analysis.entries.is_empty() {
+        return None;
+    }
+    x86_64::assemble(&analysis)
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+fn assemble_method(_: &Class, _: &Code, _: &[(usize, State)]) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn load_method(
+    class: &Class,
+    code: &Code,
+    seeds: Vec<(usize, State)>,
+    machine_code: &[u8],
+) -> Option<CompiledMethod> {
+    let analysis = Analysis::new(class, code, &seeds)?;
+    Some(CompiledMethod {
+        code: MachineCode::new(machine_code)?,
+        entries: analysis.entries.clone(),
+        slots: analysis.slots(),
+        fuel_slot: analysis.fuel_slot(),
+        states: analysis.states,
+        seeds,
+    })
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+fn load_method(_: &Class, _: &Code, _: Vec<(usize, State)>, _: &[u8]) -> Option<CompiledMethod> {
+    None
+}
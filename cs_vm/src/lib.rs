@@ -1,2 +1,400 @@
-#[allow(dead_code)]
+mod aot;
+mod call_site;
+mod class;
+mod clock;
+mod exception;
+mod gc;
+mod heap;
+mod inline_cache;
+mod instruction;
+mod interpret;
+mod invoke;
+mod jdk;
+mod jimage;
+mod jit;
+mod mirror;
 mod model;
+mod native;
+mod object;
+mod opcode;
+mod reflect;
+mod runtime;
+mod string;
+mod structure;
+#[cfg(test)]
+mod test;
+mod thread;
+mod vtable;
+mod wasm;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod x86_64;
+
+pub use aot::{reachability, Image, PrecompiledMethod, Reachability};
+pub use call_site::{CallSite, ConcatPart, DynamicConstant};
+pub use class::{
+    BootstrapMethod, Class, ClassId, ClassKind, Code, EnclosingMethod, Field, FieldSlot, InitState,
+    InnerClass, MemberRef, Metadata, Method, MethodId,
+};
+pub use exception::StackTraceElement;
+pub use heap::{Array, Heap, ObjRef, Object, ObjectData, PrimitiveArrayType};
+pub use inline_cache::{CacheState, InlineCache, POLYMORPHIC_LIMIT};
+pub use instruction::InterpretMode;
+pub use jimage::JImage;
+pub use jit::JitMode;
+pub use model::{Frame, LocalVariables, OperandStack, Thread, ThreadState, Value};
+pub use native::NativeMethod;
+pub use vtable::ItableEntry;
+pub use wasm::compile_wasm;
+
+use crate::clock::Clock;
+use crate::jit::Jit;
+use crate::thread::{Monitor, Rng};
+use cs_parser::u1;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// An error while loading or executing code
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmError {
+    /// The class could not be found on the class path
+    ClassNotFound(String),
+    /// The class file could not be parsed or is malformed
+    ClassFormat(String),
+    /// The class could not be linked, for example because of a circular superclass
+    Linkage(String),
+    /// The bytecode is not valid, for example it uses a value of the wrong type
+    Verify(String),
+    NoSuchField(String),
+    NoSuchMethod(String),
+    /// An abstract method was invoked, or no implementation of an interface method was found
+    AbstractMethod(String),
+    /// A class changed in an incompatible way, for example a method became static
+    IncompatibleClassChange(String),
+    /// A final field was written outside of the initializer of its class
+    IllegalAccess(String),
+    /// A native method without an implementation was invoked
+    UnsatisfiedLink(String),
+    /// The bootstrap method of a call site or dynamic constant failed
+    BootstrapMethod(String),
+    /// The static initializer of the class failed before
+    NoClassDefFound(String),
+    /// Tried to instantiate an abstract class, an interface or an array class with `new`
+    Instantiation(String),
+    NullPointer,
+    NegativeArraySize(i32),
+    ArrayIndexOutOfBounds {
+        index: i32,
+        len: usize,
+    },
+    /// An object of the class was stored into an array of an incompatible type
+    ArrayStore(String),
+    ClassCast(String),
+    /// Integer division by zero
+    Arithmetic(String),
+    /// The call stack is deeper than `VmOptions::max_stack_depth`
+    StackOverflow,
+    /// The heap is full, even after collecting garbage
+    OutOfMemory,
+    /// A value was pushed onto a full operand stack
+    OperandStackOverflow,
+    /// A value was popped from an empty operand stack
+    OperandStackUnderflow,
+    /// The instruction or feature is not implemented by the VM yet
+    Unsupported(String),
+    /// No thread can run anymore, because all of them are blocked or wait forever,
+    /// or because threads wait for monitors held by each other. Contains the stacks of all threads
+    Deadlock(String),
+    /// The current thread has to wait for another thread to initialize a class. The interpreter
+    /// executes the instruction that needed the class again once the thread can continue
+    Blocked,
+    /// A Java exception that was not caught. The object is not rooted anymore,
+    /// so it has to be added to `Vm::handles` before allocating again
+    Exception(ObjRef),
+}
+
+impl VmError {
+    /// The class of the Java exception that is thrown for the error, `None` for errors
+    /// that Java code can not handle, like invalid class files
+    pub fn exception_class(&self) -> Option<&'static str> {
+        Some(match self {
+            VmError::ClassNotFound(_) | VmError::NoClassDefFound(_) => {
+                "java/lang/NoClassDefFoundError"
+            }
+            VmError::NoSuchField(_) => "java/lang/NoSuchFieldError",
+            VmError::NoSuchMethod(_) => "java/lang/NoSuchMethodError",
+            VmError::AbstractMethod(_) => "java/lang/AbstractMethodError",
+            VmError::IncompatibleClassChange(_) => "java/lang/IncompatibleClassChangeError",
+            VmError::IllegalAccess(_) => "java/lang/IllegalAccessError",
+            VmError::UnsatisfiedLink(_) => "java/lang/UnsatisfiedLinkError",
+            VmError::BootstrapMethod(_) => "java/lang/BootstrapMethodError",
+            VmError::Instantiation(_) => "java/lang/InstantiationError",
+            VmError::NullPointer => "java/lang/NullPointerException",
+            VmError::NegativeArraySize(_) => "java/lang/NegativeArraySizeException",
+            VmError::ArrayIndexOutOfBounds { .. } => "java/lang/ArrayIndexOutOfBoundsException",
+            VmError::ArrayStore(_) => "java/lang/ArrayStoreException",
+            VmError::ClassCast(_) => "java/lang/ClassCastException",
+            VmError::Arithmetic(_) => "java/lang/ArithmeticException",
+            VmError::StackOverflow => "java/lang/StackOverflowError",
+            VmError::OutOfMemory => "java/lang/OutOfMemoryError",
+            VmError::ClassFormat(_)
+            | VmError::Linkage(_)
+            | VmError::Verify(_)
+            | VmError::OperandStackOverflow
+            | VmError::OperandStackUnderflow
+            | VmError::Unsupported(_)
+            | VmError::Deadlock(_)
+            | VmError::Blocked
+            | VmError::Exception(_) => return None,
+        })
+    }
+
+    /// The detail message of the Java exception that is thrown for the error
+    pub fn exception_message(&self) -> Option<String> {
+        match self {
+            VmError::ClassNotFound(name)
+            | VmError::NoClassDefFound(name)
+            | VmError::NoSuchField(name)
+            | VmError::NoSuchMethod(name)
+            | VmError::AbstractMethod(name)
+            | VmError::UnsatisfiedLink(name)
+            | VmError::Instantiation(name) => Some(name.replace('/', ".")),
+            VmError::IncompatibleClassChange(msg)
+            | VmError::IllegalAccess(msg)
+            | VmError::BootstrapMethod(msg)
+            | VmError::ArrayStore(msg)
+            | VmError::ClassCast(msg)
+            | VmError::Arithmetic(msg) => Some(msg.clone()),
+            VmError::NegativeArraySize(len) => Some(len.to_string()),
+            VmError::ArrayIndexOutOfBounds { index, len } => {
+                Some(format!("Index {} out of bounds for length {}", index, len))
+            }
+            VmError::OutOfMemory => Some("Java heap space".to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmError::ClassNotFound(name) => write!(f, "Class not found: {}", name),
+            VmError::ClassFormat(msg) => write!(f, "Invalid class file: {}", msg),
+            VmError::Linkage(msg) => write!(f, "Linkage error: {}", msg),
+            VmError::Verify(msg) => write!(f, "Verify error: {}", msg),
+            VmError::NoSuchField(name) => write!(f, "No such field: {}", name),
+            VmError::NoSuchMethod(name) => write!(f, "No such method: {}", name),
+            VmError::AbstractMethod(name) => write!(f, "Abstract method: {}", name),
+            VmError::IncompatibleClassChange(msg) => {
+                write!(f, "Incompatible class change: {}", msg)
+            }
+            VmError::IllegalAccess(msg) => write!(f, "Illegal access: {}", msg),
+            VmError::UnsatisfiedLink(name) => write!(f, "Unsatisfied link: {}", name),
+            VmError::BootstrapMethod(msg) => write!(f, "Bootstrap method error: {}", msg),
+            VmError::NoClassDefFound(name) => {
+                write!(f, "Could not initialize class {}", name)
+            }
+            VmError::Instantiation(name) => write!(f, "Cannot instantiate {}", name),
+            VmError::NullPointer => write!(f, "Null pointer"),
+            VmError::NegativeArraySize(len) => write!(f, "Negative array size: {}", len),
+            VmError::ArrayIndexOutOfBounds { index, len } => {
+                write!(f, "Index {} out of bounds for length {}", index, len)
+            }
+            VmError::ArrayStore(name) => write!(f, "Array store of {}", name),
+            VmError::ClassCast(msg) => write!(f, "Class cast: {}", msg),
+            VmError::Arithmetic(msg) => write!(f, "Arithmetic exception: {}", msg),
+            VmError::StackOverflow => write!(f, "Stack overflow"),
+            VmError::OutOfMemory => write!(f, "Out of memory"),
+            VmError::OperandStackOverflow => write!(f, "Operand stack overflow"),
+            VmError::OperandStackUnderflow => write!(f, "Operand stack underflow"),
+            VmError::Unsupported(what) => write!(f, "Unsupported: {}", what),
+            VmError::Deadlock(dump) => write!(f, "Deadlock\n{}", dump),
+            VmError::Blocked => write!(f, "Blocked on the initialization of a class"),
+            VmError::Exception(obj) => write!(f, "Uncaught exception {:?}", obj),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+pub type Result<T> = std::result::Result<T, VmError>;
+
+/// Configuration of the VM
+#[derive(Debug, Clone)]
+pub struct VmOptions {
+    /// The maximum number of bytes the heap may use before an `OutOfMemory` error
+    pub max_heap: usize,
+    /// Collect garbage before every allocation, to find missing roots in tests
+    pub gc_stress: bool,
+    /// The maximum number of frames on the call stack of a thread before a `StackOverflow` error
+    pub max_stack_depth: usize,
+    /// The number of instructions a thread runs before the scheduler switches to another thread
+    pub time_slice: usize,
+    /// Makes the scheduler deterministic, to reproduce and explore interleavings of threads.
+    /// Threads are switched after a pseudo-random number of instructions up to `time_slice`,
+    /// to a pseudo-random thread, and the time is virtual. Every run with the same seed
+    /// behaves the same
+    pub schedule_seed: Option<u64>,
+    /// When methods are compiled to machine code
+    pub jit: JitMode,
+    /// How the interpreter reads the code of methods
+    pub interpret: InterpretMode,
+}
+
+impl Default for VmOptions {
+    fn default() -> Self {
+        Self {
+            max_heap: 256 * 1024 * 1024,
+            gc_stress: false,
+            max_stack_depth: 2048,
+            time_slice: 1000,
+            schedule_seed: None,
+            jit: JitMode::default(),
+            interpret: InterpretMode::default(),
+        }
+    }
+}
+
+/// The virtual machine, containing all loaded classes and the heap
+pub struct Vm {
+    options: VmOptions,
+    classes: Vec<Class>,
+    class_names: HashMap<String, ClassId>,
+    /// The classes that are currently being defined, used to detect circularity
+    loading: Vec<String>,
+    /// The directories that are searched for class files
+    class_path: Vec<PathBuf>,
+    /// Class files that were added by name, they are found before the class path
+    class_files: HashMap<String, Vec<u1>>,
+    /// The JDK image that boot classes are loaded from instead of the bundled class library
+    boot_image: Option<JImage>,
+    pub heap: Heap,
+    threads: Vec<Thread>,
+    /// The index of the thread in `threads` that is currently running
+    current_thread: usize,
+    /// The number of instructions the current thread may still run before another thread is scheduled
+    slice: usize,
+    /// The monitors of objects that are locked or waited on
+    monitors: HashMap<ObjRef, Monitor>,
+    /// Chooses the time slices and threads if the scheduler is seeded
+    rng: Option<Rng>,
+    clock: Clock,
+    /// The profiles and compiled code of methods
+    jit: Jit,
+    /// The inline caches of call sites, by the operand of their quickened instruction
+    inline_caches: Vec<InlineCache>,
+    /// The interned strings by their UTF-16 chars
+    strings: HashMap<Vec<u16>, ObjRef>,
+    /// References held by Rust code that must survive a garbage collection
+    pub handles: Vec<ObjRef>,
+    /// The implementations of native methods, by `Vm::method_name`
+    natives: HashMap<String, NativeMethod>,
+    /// The classes of all `java/lang/Class` objects
+    mirrors: HashMap<ObjRef, ClassId>,
+    /// The `java/lang/Module` that all classes are in, the unnamed module of the boot loader
+    unnamed_module: Option<ObjRef>,
+    /// Where `System.out` writes to
+    stdout: Box<dyn Write>,
+    /// Where `System.err` writes to
+    stderr: Box<dyn Write>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_options(VmOptions::default())
+    }
+
+    pub fn with_options(options: VmOptions) -> Self {
+        let seed = options.schedule_seed;
+        let mut vm = Self {
+            options,
+            classes: Vec::new(),
+            class_names: HashMap::new(),
+            loading: Vec::new(),
+            class_path: Vec::new(),
+            class_files: HashMap::new(),
+            boot_image: None,
+            heap: Heap::new(),
+            threads: vec![Thread::new()],
+            current_thread: 0,
+            slice: 0,
+            monitors: HashMap::new(),
+            rng: seed.map(Rng::new),
+            clock: Clock::new(seed.is_some()),
+            jit: Jit::default(),
+            inline_caches: Vec::new(),
+            strings: HashMap::new(),
+            handles: Vec::new(),
+            natives: HashMap::new(),
+            mirrors: HashMap::new(),
+            unnamed_module: None,
+            stdout: Box::new(std::io::stdout()),
+            stderr: Box::new(std::io::stderr()),
+        };
+        vm.register_runtime_natives();
+        vm.register_jdk_natives();
+        vm.register_thread_natives();
+        vm
+    }
+
+    /// Adds a directory to search for class files
+    pub fn add_class_path(&mut self, dir: impl Into<PathBuf>) {
+        self.class_path.push(dir.into());
+    }
+
+    /// Adds a class file to load the class from when it is resolved, like from the class path
+    pub fn add_class_file(&mut self, bytes: Vec<u1>) -> Result<()> {
+        let file = cs_parser::parse_class_file(&bytes)
+            .map_err(|err| VmError::ClassFormat(err.to_string()))?;
+        let cp = &file.constant_pool;
+        let name = file.this_class.get(cp).name_index.get(cp).to_string();
+        self.class_files.insert(name, bytes);
+        Ok(())
+    }
+
+    /// Loads the boot classes from the `lib/modules` image of a JDK instead of the bundled class library
+    pub fn set_boot_image(&mut self, image: JImage) {
+        self.boot_image = Some(image);
+    }
+
+    /// Redirects `System.out`, which writes to the standard output of the process by default
+    pub fn set_stdout(&mut self, out: impl Write + 'static) {
+        self.stdout = Box::new(out);
+    }
+
+    /// Redirects `System.err`, which writes to the standard error of the process by default
+    pub fn set_stderr(&mut self, err: impl Write + 'static) {
+        self.stderr = Box::new(err);
+    }
+
+    pub fn thread(&self) -> &Thread {
+        &self.threads[self.current_thread]
+    }
+
+    pub fn thread_mut(&mut self) -> &mut Thread {
+        &mut self.threads[self.current_thread]
+    }
+
+    /// The frame of the currently executing method
+    pub fn frame_mut(&mut self) -> &mut Frame {
+        self.thread_mut()
+            .frames
+            .last_mut()
+            .expect("the current thread has no frame")
+    }
+
+    pub(crate) fn push(&mut self, value: Value) -> Result<()> {
+        self.frame_mut().stack.push(value)
+    }
+
+    pub(crate) fn pop(&mut self) -> Result<Value> {
+        self.frame_mut().stack.pop()
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,104 @@
+//!
+//! The `java/lang/Class` objects of classes
+//!
+//! Every class has at most one mirror, which is created when Java code first asks for it.
+//! Mirrors are never collected, like the classes themselves
+//!
+
+use crate::class::{ClassId, ClassKind};
+use crate::heap::ObjRef;
+use crate::model::Value;
+use crate::{Result, Vm, VmError};
+use cs_model::FieldType;
+
+impl Vm {
+    /// The `java/lang/Class` object of the class
+    pub fn class_object(&mut self, class: ClassId) -> Result<ObjRef> {
+        if let Some(mirror) = self.class(class).mirror {
+            return Ok(mirror);
+        }
+
+        let class_class = self.resolve_class("java/lang/Class")?;
+        let name_slot = self
+            .class(class_class)
+            .instance_field_slot("name", &FieldType::Object("java/lang/String".to_string()))
+            .ok_or_else(|| VmError::NoSuchField("java/lang/Class.name".to_string()))?;
+
+        let name = self.new_string(&self.java_name(class))?;
+        self.handles.push(name);
+        let mirror = self.new_object(class_class);
+        self.handles.pop();
+        let mirror = mirror?;
+
+        self.put_field(mirror, name_slot, Value::Reference(Some(name)))?;
+        self.class_mut(class).mirror = Some(mirror);
+        self.mirrors.insert(mirror, class);
+        if let Some(module) = self.unnamed_module {
+            self.set_module(mirror, module)?;
+        }
+
+        // the class library of the JDK stores the component type of arrays in the mirror
+        let component_slot = self.class(class_class).instance_field_slot(
+            "componentType",
+            &FieldType::Object("java/lang/Class".to_string()),
+        );
+        if let (Some(slot), ClassKind::Array(component)) =
+            (component_slot, self.class(class).kind.clone())
+        {
+            let component = self.field_type_class(&component)?;
+            let component = self.class_object(component)?;
+            self.put_field(mirror, slot, Value::Reference(Some(component)))?;
+        }
+        Ok(mirror)
+    }
+
+    /// Puts the class of the mirror into the module
+    pub(crate) fn set_module(&mut self, mirror: ObjRef, module: ObjRef) -> Result<()> {
+        let class_class = self.heap.get(mirror).class;
+        let slot = self
+            .class(class_class)
+            .instance_field_slot("module", &FieldType::Object("java/lang/Module".to_string()))
+            .ok_or_else(|| VmError::NoSuchField("java/lang/Class.module".to_string()))?;
+        self.put_field(mirror, slot, Value::Reference(Some(module)))
+    }
+
+    /// The name of the class as `Class.getName` returns it, for example `java.lang.Object`.
+    /// Hidden classes have a suffix with their id, as their binary names are not unique
+    pub fn java_name(&self, class: ClassId) -> String {
+        let class_ref = self.class(class);
+        let name = class_ref.name.replace('/', ".");
+        match class_ref.hidden {
+            true => format!("{}/0x{:016x}", name, class.index()),
+            false => name,
+        }
+    }
+
+    /// The class of a type from a descriptor, primitive types have their own classes
+    pub fn field_type_class(&mut self, ty: &FieldType) -> Result<ClassId> {
+        match ty {
+            FieldType::Object(name) => self.resolve_class(name),
+            FieldType::Array(_) => self.resolve_class(&ty.to_string()),
+            primitive => self.primitive_class(primitive_name(primitive)),
+        }
+    }
+
+    /// The class of a `java/lang/Class` object
+    pub fn mirror_class(&self, mirror: ObjRef) -> Option<ClassId> {
+        self.mirrors.get(&mirror).copied()
+    }
+}
+
+/// The name of the class of a primitive type, for example `int` for `I`
+fn primitive_name(ty: &FieldType) -> &'static str {
+    match ty {
+        FieldType::Boolean => "boolean",
+        FieldType::Byte => "byte",
+        FieldType::Char => "char",
+        FieldType::Short => "short",
+        FieldType::Int => "int",
+        FieldType::Long => "long",
+        FieldType::Float => "float",
+        FieldType::Double => "double",
+        FieldType::Object(_) | FieldType::Array(_) => unreachable!("not a primitive type"),
+    }
+}
@@ -1,89 +1,535 @@
+use crate::class::{ClassId, Code, MethodId};
+use crate::heap::ObjRef;
+use crate::{Result, VmError};
+use std::rc::Rc;
+use std::time::Instant;
+
+/// A single value on the operand stack, in a local variable or in a field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    /// `boolean`, `byte`, `char`, `short` and `int` are all represented as `int`
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// A reference to an object on the heap, `None` is `null`
+    Reference(Option<ObjRef>),
+    /// An unusable slot, for example the second half of a `long` in the local variables
+    Top,
+}
+
+impl Value {
+    pub const NULL: Value = Value::Reference(None);
+
+    /// Long and double are category 2 values, everything else is category 1
+    pub fn category(&self) -> u8 {
+        match self {
+            Value::Long(_) | Value::Double(_) => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i32> {
+        match self {
+            Value::Int(n) => Ok(*n),
+            _ => Err(VmError::Verify(format!("Expected int, found {:?}", self))),
+        }
+    }
+
+    pub fn as_long(&self) -> Result<i64> {
+        match self {
+            Value::Long(n) => Ok(*n),
+            _ => Err(VmError::Verify(format!("Expected long, found {:?}", self))),
+        }
+    }
+
+    pub fn as_float(&self) -> Result<f32> {
+        match self {
+            Value::Float(n) => Ok(*n),
+            _ => Err(VmError::Verify(format!("Expected float, found {:?}", self))),
+        }
+    }
+
+    pub fn as_double(&self) -> Result<f64> {
+        match self {
+            Value::Double(n) => Ok(*n),
+            _ => Err(VmError::Verify(format!(
+                "Expected double, found {:?}",
+                self
+            ))),
+        }
+    }
+
+    pub fn as_reference(&self) -> Result<Option<ObjRef>> {
+        match self {
+            Value::Reference(r) => Ok(*r),
+            _ => Err(VmError::Verify(format!(
+                "Expected reference, found {:?}",
+                self
+            ))),
+        }
+    }
+}
+
+/// The operand stack of a frame
+///
+/// Every value is one entry, but `long` and `double` count as two slots against the
+/// maximum depth, like in the class file
 pub struct OperandStack {
-    arr: [u32; 255],
-    sp: u8,
+    values: Vec<Value>,
+    /// The number of slots in use
+    depth: u16,
+    /// The `max_stack` of the method
+    max_depth: u16,
 }
 
 impl OperandStack {
-    pub fn new() -> Self {
+    pub fn new(max_depth: u16) -> Self {
         Self {
-            arr: [0; 255],
-            sp: 0,
+            values: Vec::with_capacity(max_depth as usize),
+            depth: 0,
+            max_depth,
+        }
+    }
+
+    pub fn pop(&mut self) -> Result<Value> {
+        let value = self.values.pop().ok_or(VmError::OperandStackUnderflow)?;
+        self.depth -= value.category() as u16;
+        Ok(value)
+    }
+
+    pub fn push(&mut self, value: Value) -> Result<()> {
+        let depth = self.depth + value.category() as u16;
+        if depth > self.max_depth {
+            return Err(VmError::OperandStackOverflow);
         }
+        self.depth = depth;
+        self.values.push(value);
+        Ok(())
+    }
+
+    /// The value on top of the stack
+    pub fn peek(&self) -> Result<Value> {
+        self.values
+            .last()
+            .copied()
+            .ok_or(VmError::OperandStackUnderflow)
+    }
+
+    /// The number of slots in use
+    pub fn depth(&self) -> u16 {
+        self.depth
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.depth = 0;
+    }
+
+    /// The values currently on the stack, from bottom to top
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// `pop`: removes a category 1 value
+    pub fn discard(&mut self) -> Result<()> {
+        self.pop_slots(1).map(drop)
     }
 
-    pub fn pop(&mut self) -> u32 {
-        self.sp -= 1;
-        self.arr[self.sp as usize]
+    /// `pop2`: removes two category 1 values or one category 2 value
+    pub fn discard2(&mut self) -> Result<()> {
+        self.pop_slots(2).map(drop)
     }
 
-    pub fn push(&mut self, n: u32) {
-        self.arr[self.sp as usize] = n;
-        self.sp += 1;
+    /// `dup`: ..., value -> ..., value, value
+    pub fn dup(&mut self) -> Result<()> {
+        self.dup_slots(1, 0)
     }
 
-    pub fn swap(&mut self) {
-        self.arr
-            .swap((self.sp - 2) as usize, (self.sp - 2) as usize);
+    /// `dup_x1`: ..., value2, value1 -> ..., value1, value2, value1
+    pub fn dup_x1(&mut self) -> Result<()> {
+        self.dup_slots(1, 1)
+    }
+
+    /// `dup_x2`: duplicates the top category 1 value below the two slots under it
+    pub fn dup_x2(&mut self) -> Result<()> {
+        self.dup_slots(1, 2)
+    }
+
+    /// `dup2`: duplicates the top two slots
+    pub fn dup2(&mut self) -> Result<()> {
+        self.dup_slots(2, 0)
+    }
+
+    /// `dup2_x1`: duplicates the top two slots below the slot under them
+    pub fn dup2_x1(&mut self) -> Result<()> {
+        self.dup_slots(2, 1)
+    }
+
+    /// `dup2_x2`: duplicates the top two slots below the two slots under them
+    pub fn dup2_x2(&mut self) -> Result<()> {
+        self.dup_slots(2, 2)
+    }
+
+    /// `swap`: ..., value2, value1 -> ..., value1, value2. Both must be category 1
+    pub fn swap(&mut self) -> Result<()> {
+        match self.values.as_mut_slice() {
+            [.., below, top] if below.category() == 1 && top.category() == 1 => {
+                std::mem::swap(below, top);
+                Ok(())
+            }
+            [.., _, _] => Err(VmError::Verify("swap on a category 2 value".to_string())),
+            _ => Err(VmError::OperandStackUnderflow),
+        }
+    }
+
+    /// Pops the values that make up exactly `slots` slots, in stack order.
+    /// It is an error if a category 2 value would have to be split
+    fn pop_slots(&mut self, slots: u16) -> Result<Vec<Value>> {
+        let mut count = 0;
+        let mut taken = 0;
+        while taken < slots {
+            let value = self
+                .values
+                .iter()
+                .rev()
+                .nth(count)
+                .ok_or(VmError::OperandStackUnderflow)?;
+            taken += value.category() as u16;
+            count += 1;
+        }
+        if taken != slots {
+            return Err(VmError::Verify(format!(
+                "Stack instruction on {} slots would split a category 2 value",
+                slots
+            )));
+        }
+        self.depth -= slots;
+        Ok(self.values.split_off(self.values.len() - count))
+    }
+
+    /// Inserts a copy of the top `slots` slots below the `skip` slots under them
+    fn dup_slots(&mut self, slots: u16, skip: u16) -> Result<()> {
+        if self.depth + slots > self.max_depth {
+            return Err(VmError::OperandStackOverflow);
+        }
+        let top = self.pop_slots(slots)?;
+        let below = match self.pop_slots(skip) {
+            Ok(below) => below,
+            Err(err) => {
+                self.values.extend(top);
+                self.depth += slots;
+                return Err(err);
+            }
+        };
+        self.values.extend_from_slice(&top);
+        self.values.extend(below);
+        self.values.extend(top);
+        self.depth += 2 * slots + skip;
+        Ok(())
     }
 }
 
+/// The local variables of a frame. `long` and `double` take up two slots
 pub struct LocalVariables {
-    arr: [u32; 255],
+    arr: Box<[Value]>,
 }
 
 impl LocalVariables {
-    pub fn new() -> Self {
-        Self { arr: [0; 255] }
+    /// Local variables with `max_locals` slots
+    pub fn new(max_locals: u16) -> Self {
+        Self {
+            arr: vec![Value::Top; max_locals as usize].into(),
+        }
+    }
+
+    /// Stores the value, category 2 values also occupy the next slot. Overwriting either slot of
+    /// a category 2 value invalidates it
+    pub fn store(&mut self, address: u16, value: Value) -> Result<()> {
+        let address = address as usize;
+        if address + value.category() as usize > self.arr.len() {
+            return Err(VmError::Verify(format!(
+                "Local variable {} out of bounds",
+                address
+            )));
+        }
+        if address > 0 && self.arr[address - 1].category() == 2 {
+            self.arr[address - 1] = Value::Top;
+        }
+        self.arr[address] = value;
+        if value.category() == 2 {
+            self.arr[address + 1] = Value::Top;
+        }
+        Ok(())
     }
 
-    pub fn store(&mut self, address: u8, value: u32) {
-        self.arr[address as usize] = value;
+    pub fn load(&self, address: u16) -> Result<Value> {
+        match self.arr.get(address as usize) {
+            Some(Value::Top) | None => Err(VmError::Verify(format!(
+                "Local variable {} is not initialized",
+                address
+            ))),
+            Some(value) => Ok(*value),
+        }
     }
 
-    pub fn store2(&mut self, address: u8, value1: u32, value2: u32) {
-        self.arr[address as usize] = value1;
-        self.arr[address as usize + 1] = value2;
+    pub fn values(&self) -> &[Value] {
+        &self.arr
     }
+}
+
+/// The state of a method invocation
+pub struct Frame {
+    pub method: MethodId,
+    pub code: Rc<Code>,
+    /// The index of the next instruction in the code
+    pub pc: usize,
+    pub locals: LocalVariables,
+    pub stack: OperandStack,
+    /// The object whose monitor a `synchronized` method holds, it is released on return
+    pub monitor: Option<ObjRef>,
+}
 
-    pub fn load(&self, address: u8) -> u32 {
-        self.arr[address as usize]
+impl Frame {
+    /// A new frame with the locals and stack sized by the code
+    pub fn new(method: MethodId, code: Rc<Code>) -> Self {
+        Self {
+            method,
+            pc: 0,
+            locals: LocalVariables::new(code.max_locals),
+            stack: OperandStack::new(code.max_stack),
+            code,
+            monitor: None,
+        }
     }
-    pub fn load2(&self, address: u8) -> (u32, u32) {
-        (self.arr[address as usize], self.arr[address as usize + 1])
+}
+
+/// A thread of execution with its call stack
+#[derive(Default)]
+pub struct Thread {
+    pub frames: Vec<Frame>,
+    /// The `java/lang/Thread` object of the thread. The bundled class library creates the one
+    /// of the main thread when it is first asked for
+    pub object: Option<ObjRef>,
+    pub state: ThreadState,
+    /// The number of `Vm::run` loops of the thread on the Rust stack. Only the thread of the
+    /// innermost loop and threads without a loop can be scheduled
+    pub(crate) runs: usize,
+    /// The number of classes the thread is initializing, it is not preempted while it does
+    pub(crate) initializing: usize,
+    /// Whether `Unsafe.unpark` was called while the thread was not parked
+    pub(crate) permit: bool,
+    /// The thread was interrupted in `Object.wait` or `Thread.sleep`, it throws an
+    /// `InterruptedException` at `blocked_pc` when it runs again
+    pub(crate) interrupt_pending: bool,
+    /// The pc of the invoke instruction of the method that blocked the thread
+    pub(crate) blocked_pc: usize,
+}
+
+impl Thread {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the thread has started and not terminated yet
+    pub fn is_alive(&self) -> bool {
+        self.state != ThreadState::Terminated
+    }
+}
+
+/// What a thread is doing, like `java.lang.Thread.State`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThreadState {
+    #[default]
+    Runnable,
+    /// Waits to enter the monitor of the object, which it then holds `count` times
+    Blocked {
+        monitor: ObjRef,
+        count: u32,
+    },
+    /// In `Object.wait`, until it is notified, interrupted or the deadline has passed.
+    /// It then enters the monitor again
+    Waiting {
+        monitor: ObjRef,
+        count: u32,
+        deadline: Option<Instant>,
+    },
+    /// In `Thread.sleep`
+    Sleeping {
+        deadline: Instant,
+    },
+    /// In `Unsafe.park`, until it is unparked, interrupted or the deadline has passed
+    Parked {
+        deadline: Option<Instant>,
+    },
+    /// Waits until another thread has initialized the class, see JVMS §5.5.
+    /// The instruction that needs the class is executed again once it is done
+    WaitingForClass {
+        class: ClassId,
+    },
+    Terminated,
+}
+
+impl ThreadState {
+    /// The object whose monitor the thread is waiting for
+    pub fn monitor(&self) -> Option<ObjRef> {
+        match self {
+            ThreadState::Blocked { monitor, .. } | ThreadState::Waiting { monitor, .. } => {
+                Some(*monitor)
+            }
+            _ => None,
+        }
+    }
+
+    /// When the thread wakes up by itself
+    pub fn deadline(&self) -> Option<Instant> {
+        match self {
+            ThreadState::Waiting { deadline, .. } | ThreadState::Parked { deadline } => *deadline,
+            ThreadState::Sleeping { deadline } => Some(*deadline),
+            _ => None,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{LocalVariables, OperandStack};
+    use super::{LocalVariables, OperandStack, Value};
+    use crate::VmError;
 
     #[test]
-    #[ignore]
     fn operand_stack() {
-        let mut stack = OperandStack::new();
+        let mut stack = OperandStack::new(4);
 
-        stack.push(10);
-        stack.push(20);
-        stack.push(30);
-        stack.push(40);
-        stack.swap();
+        stack.push(Value::Int(10)).unwrap();
+        stack.push(Value::Int(20)).unwrap();
+        stack.push(Value::Int(30)).unwrap();
+        stack.push(Value::Int(40)).unwrap();
+        stack.swap().unwrap();
 
-        assert_eq!(stack.pop(), 30);
-        assert_eq!(stack.pop(), 40);
-        assert_eq!(stack.pop(), 20);
-        assert_eq!(stack.pop(), 10);
+        assert_eq!(stack.pop().unwrap(), Value::Int(30));
+        assert_eq!(stack.pop().unwrap(), Value::Int(40));
+        assert_eq!(stack.pop().unwrap(), Value::Int(20));
+        assert_eq!(stack.pop().unwrap(), Value::Int(10));
+    }
+
+    #[test]
+    fn operand_stack_bounds() {
+        let mut stack = OperandStack::new(3);
+
+        assert_eq!(stack.pop(), Err(VmError::OperandStackUnderflow));
+        stack.push(Value::Long(1)).unwrap();
+        assert_eq!(stack.depth(), 2);
+        assert_eq!(
+            stack.push(Value::Double(2.0)),
+            Err(VmError::OperandStackOverflow)
+        );
+        stack.push(Value::Int(3)).unwrap();
+        assert_eq!(stack.push(Value::NULL), Err(VmError::OperandStackOverflow));
+        assert_eq!(stack.dup(), Err(VmError::OperandStackOverflow));
+        assert_eq!(stack.depth(), 3);
+    }
+
+    fn stack_of(values: &[Value]) -> OperandStack {
+        let mut stack = OperandStack::new(16);
+        for value in values {
+            stack.push(*value).unwrap();
+        }
+        stack
+    }
+
+    #[test]
+    fn stack_instructions() {
+        use Value::{Double as D, Int as I, Long as L};
+
+        type Instruction = fn(&mut OperandStack) -> crate::Result<()>;
+        let cases: &[(Instruction, &[Value], &[Value])] = &[
+            (OperandStack::discard, &[I(1), I(2)], &[I(1)]),
+            (OperandStack::discard2, &[I(1), I(2), I(3)], &[I(1)]),
+            (OperandStack::discard2, &[I(1), L(2)], &[I(1)]),
+            (OperandStack::dup, &[I(1)], &[I(1), I(1)]),
+            (OperandStack::dup_x1, &[I(2), I(1)], &[I(1), I(2), I(1)]),
+            (
+                OperandStack::dup_x2,
+                &[I(3), I(2), I(1)],
+                &[I(1), I(3), I(2), I(1)],
+            ),
+            (OperandStack::dup_x2, &[L(2), I(1)], &[I(1), L(2), I(1)]),
+            (OperandStack::dup2, &[I(2), I(1)], &[I(2), I(1), I(2), I(1)]),
+            (OperandStack::dup2, &[D(1.0)], &[D(1.0), D(1.0)]),
+            (
+                OperandStack::dup2_x1,
+                &[I(3), I(2), I(1)],
+                &[I(2), I(1), I(3), I(2), I(1)],
+            ),
+            (OperandStack::dup2_x1, &[I(2), L(1)], &[L(1), I(2), L(1)]),
+            (
+                OperandStack::dup2_x2,
+                &[I(4), I(3), I(2), I(1)],
+                &[I(2), I(1), I(4), I(3), I(2), I(1)],
+            ),
+            (
+                OperandStack::dup2_x2,
+                &[I(3), I(2), L(1)],
+                &[L(1), I(3), I(2), L(1)],
+            ),
+            (
+                OperandStack::dup2_x2,
+                &[L(3), I(2), I(1)],
+                &[I(2), I(1), L(3), I(2), I(1)],
+            ),
+            (
+                OperandStack::dup2_x2,
+                &[L(2), D(1.0)],
+                &[D(1.0), L(2), D(1.0)],
+            ),
+            (OperandStack::swap, &[I(2), I(1)], &[I(1), I(2)]),
+        ];
+
+        for (instruction, before, after) in cases {
+            let mut stack = stack_of(before);
+            instruction(&mut stack).unwrap();
+            assert_eq!(stack.values(), *after, "{:?}", before);
+            let depth: u16 = after.iter().map(|v| v.category() as u16).sum();
+            assert_eq!(stack.depth(), depth);
+        }
+
+        let invalid: &[(Instruction, &[Value])] = &[
+            (OperandStack::discard, &[L(1)]),
+            (OperandStack::discard2, &[I(1), L(2), I(3)]),
+            (OperandStack::dup, &[D(1.0)]),
+            (OperandStack::dup_x1, &[L(2), I(1)]),
+            (OperandStack::dup2_x1, &[L(2), I(1), I(3)]),
+            (OperandStack::swap, &[I(1), L(2)]),
+            (OperandStack::dup_x2, &[I(1)]),
+        ];
+        for (instruction, before) in invalid {
+            let mut stack = stack_of(before);
+            assert!(instruction(&mut stack).is_err(), "{:?}", before);
+        }
     }
 
     #[test]
     fn local_vars() {
-        let mut vars = LocalVariables::new();
+        let mut vars = LocalVariables::new(5);
+
+        vars.store(1, Value::Int(546)).unwrap();
+        vars.store(2, Value::Int(100)).unwrap();
+        vars.store(3, Value::Long(100)).unwrap();
 
-        vars.store(1, 546);
-        vars.store(2, 100);
-        vars.store2(3, 100, 466);
+        assert_eq!(vars.load(1), Ok(Value::Int(546)));
+        assert_eq!(vars.load(2), Ok(Value::Int(100)));
+        assert_eq!(vars.load(3), Ok(Value::Long(100)));
+        assert!(vars.load(4).is_err());
+        assert!(vars.load(0).is_err());
+        assert!(vars.store(4, Value::Double(1.0)).is_err());
+        assert!(vars.store(5, Value::Int(1)).is_err());
 
-        assert_eq!(vars.load(1), 546);
-        assert_eq!(vars.load(3), 100);
-        assert_eq!(vars.load(4), 466);
+        // storing into the upper half of the long leaves neither half usable
+        vars.store(4, Value::Int(7)).unwrap();
+        assert!(vars.load(3).is_err());
+        assert_eq!(vars.load(4), Ok(Value::Int(7)));
+        vars.store(0, Value::Double(2.0)).unwrap();
+        vars.store(1, Value::Float(1.0)).unwrap();
+        assert!(vars.load(0).is_err());
     }
 }
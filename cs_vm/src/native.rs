@@ -0,0 +1,52 @@
+//!
+//! Native methods
+//!
+//! Methods with the `ACC_NATIVE` flag are implemented by Rust functions, which are registered
+//! by the class, name and descriptor of the method. Natives do not get a frame, they take the
+//! arguments directly and return the return value
+//!
+
+use crate::class::MethodId;
+use crate::model::Value;
+use crate::{Result, Vm, VmError};
+
+/// The implementation of a native method. The arguments include `this` for instance methods,
+/// the result is `None` for `void` methods
+pub type NativeMethod = fn(&mut Vm, &[Value]) -> Result<Option<Value>>;
+
+impl Vm {
+    /// Registers the implementation of a native method, replacing an existing one
+    pub fn register_native(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+        native: NativeMethod,
+    ) {
+        self.natives
+            .insert(format!("{}.{}{}", class, name, descriptor), native);
+    }
+
+    /// Invokes a native method. The references in the arguments are kept alive
+    /// while it runs, as the arguments are not on an operand stack anymore
+    pub(crate) fn invoke_native(
+        &mut self,
+        method: MethodId,
+        args: &[Value],
+    ) -> Result<Option<Value>> {
+        let name = self.method_name(method);
+        let native = *self
+            .natives
+            .get(&name)
+            .ok_or(VmError::UnsatisfiedLink(name))?;
+
+        let handles = self.handles.len();
+        self.handles.extend(args.iter().filter_map(|arg| match arg {
+            Value::Reference(obj) => *obj,
+            _ => None,
+        }));
+        let result = native(self, args);
+        self.handles.truncate(handles);
+        result
+    }
+}
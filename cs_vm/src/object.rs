@@ -0,0 +1,322 @@
+//!
+//! Allocation of objects and arrays, and the instructions working on them
+//!
+
+use crate::class::{
+    array_class_name, default_value, parse_field_type, ClassId, ClassKind, Field, MethodId,
+};
+use crate::heap::{Array, ObjRef, Object, ObjectData, PrimitiveArrayType};
+use crate::model::Value;
+use crate::{Result, Vm, VmError};
+use cs_model::FieldType;
+use cs_parser::{u1, u2, ClassAccessFlag};
+
+impl Vm {
+    /// Allocates a new instance of the class with all fields set to their default value
+    pub fn new_object(&mut self, class: ClassId) -> Result<ObjRef> {
+        let class_ref = self.class(class);
+        if class_ref.is_array()
+            || class_ref.access_flags
+                & (ClassAccessFlag::Abstract as u2 | ClassAccessFlag::Interface as u2)
+                != 0
+        {
+            return Err(VmError::Instantiation(class_ref.name.clone()));
+        }
+
+        let fields = class_ref
+            .instance_fields
+            .iter()
+            .map(|field| default_value(&field.descriptor))
+            .collect();
+
+        self.alloc(Object {
+            class,
+            data: ObjectData::Fields(fields),
+        })
+    }
+
+    /// Allocates a new array of the array class with all elements set to their default value
+    pub fn new_array(&mut self, class: ClassId, len: i32) -> Result<ObjRef> {
+        let len = usize::try_from(len).map_err(|_| VmError::NegativeArraySize(len))?;
+
+        let array = match &self.class(class).kind {
+            ClassKind::Array(FieldType::Object(_) | FieldType::Array(_)) => {
+                Array::new_reference(len)
+            }
+            ClassKind::Array(primitive) => Array::new(primitive_array_type(primitive), len),
+            ClassKind::Class | ClassKind::Primitive => {
+                return Err(VmError::Verify(format!(
+                    "{} is not an array class",
+                    self.class(class).name
+                )))
+            }
+        };
+
+        self.alloc(Object {
+            class,
+            data: ObjectData::Array(array),
+        })
+    }
+
+    /// Allocates a multidimensional array, `dims` may be shorter than the dimensions of the class
+    pub fn new_multi_array(&mut self, class: ClassId, dims: &[i32]) -> Result<ObjRef> {
+        if let Some(&negative) = dims.iter().find(|&&len| len < 0) {
+            return Err(VmError::NegativeArraySize(negative));
+        }
+        self.new_multi_array_unchecked(class, dims)
+    }
+
+    fn new_multi_array_unchecked(&mut self, class: ClassId, dims: &[i32]) -> Result<ObjRef> {
+        let array = self.new_array(class, dims[0])?;
+        if dims.len() > 1 {
+            let component = self.class(class).name[1..].to_string();
+            let component = self.resolve_class(&component)?;
+
+            // the array is not reachable yet, so it has to be kept alive while allocating the elements
+            self.handles.push(array);
+            let result = (0..dims[0] as usize).try_for_each(|i| {
+                let element = self.new_multi_array_unchecked(component, &dims[1..])?;
+                self.array_mut(array)?
+                    .store(i, Value::Reference(Some(element)))
+                    .expect("index is in bounds");
+                Ok(())
+            });
+            self.handles.pop();
+            result?;
+        }
+        Ok(array)
+    }
+
+    /// Boxes a value of the type with the `valueOf` method of its wrapper class,
+    /// references are returned as they are
+    pub fn box_value(&mut self, ty: &FieldType, value: Value) -> Result<Option<ObjRef>> {
+        let (wrapper, descriptor) = match ty {
+            FieldType::Object(_) | FieldType::Array(_) => return value.as_reference(),
+            FieldType::Boolean => ("java/lang/Boolean", "(Z)Ljava/lang/Boolean;"),
+            FieldType::Byte => ("java/lang/Byte", "(B)Ljava/lang/Byte;"),
+            FieldType::Char => ("java/lang/Character", "(C)Ljava/lang/Character;"),
+            FieldType::Short => ("java/lang/Short", "(S)Ljava/lang/Short;"),
+            FieldType::Int => ("java/lang/Integer", "(I)Ljava/lang/Integer;"),
+            FieldType::Long => ("java/lang/Long", "(J)Ljava/lang/Long;"),
+            FieldType::Float => ("java/lang/Float", "(F)Ljava/lang/Float;"),
+            FieldType::Double => ("java/lang/Double", "(D)Ljava/lang/Double;"),
+        };
+        let class = self.resolve_class(wrapper)?;
+        let value_of = self
+            .class(class)
+            .method_index("valueOf", descriptor)
+            .map(|index| MethodId { class, index })
+            .ok_or_else(|| VmError::NoSuchMethod(format!("{}.valueOf{}", wrapper, descriptor)))?;
+        self.invoke(value_of, &[value])?
+            .ok_or_else(|| VmError::Verify(format!("{}.valueOf returned nothing", wrapper)))?
+            .as_reference()
+    }
+
+    /// Unboxes the value of a wrapper object for a primitive type, objects and arrays stay the same
+    pub fn unbox_value(&mut self, ty: &FieldType, obj: Option<ObjRef>) -> Result<Value> {
+        if let FieldType::Object(_) | FieldType::Array(_) = ty {
+            return Ok(Value::Reference(obj));
+        }
+        let obj = obj.ok_or(VmError::NullPointer)?;
+        let class = self.heap.get(obj).class;
+        match self.class(class).instance_field_slot("value", ty) {
+            Some(slot) => self.get_field(obj, slot),
+            None => Err(self.throw_new(
+                "java/lang/IllegalArgumentException",
+                Some("argument type mismatch"),
+            )),
+        }
+    }
+
+    pub fn array(&self, obj: ObjRef) -> Result<&Array> {
+        match &self.heap.get(obj).data {
+            ObjectData::Array(array) => Ok(array),
+            ObjectData::Fields(_) => Err(VmError::Verify("Expected array, found object".into())),
+        }
+    }
+
+    pub fn array_mut(&mut self, obj: ObjRef) -> Result<&mut Array> {
+        match &mut self.heap.get_mut(obj).data {
+            ObjectData::Array(array) => Ok(array),
+            ObjectData::Fields(_) => Err(VmError::Verify("Expected array, found object".into())),
+        }
+    }
+
+    pub fn get_field(&self, obj: ObjRef, slot: usize) -> Result<Value> {
+        match &self.heap.get(obj).data {
+            ObjectData::Fields(fields) => Ok(fields[slot]),
+            ObjectData::Array(_) => Err(VmError::Verify("Expected object, found array".into())),
+        }
+    }
+
+    pub fn put_field(&mut self, obj: ObjRef, slot: usize, value: Value) -> Result<()> {
+        match &mut self.heap.get_mut(obj).data {
+            ObjectData::Fields(fields) => {
+                fields[slot] = value;
+                Ok(())
+            }
+            ObjectData::Array(_) => Err(VmError::Verify("Expected object, found array".into())),
+        }
+    }
+
+    /// Resolves a `Fieldref` of the class to the class it refers to and the slot of the instance field
+    pub(crate) fn resolve_instance_field(
+        &mut self,
+        class: ClassId,
+        index: u2,
+    ) -> Result<(ClassId, usize)> {
+        let field = self.class(class).cp_field_ref(index)?;
+        let (class_name, name) = (field.class.to_string(), field.name.to_string());
+        let descriptor = parse_field_type(field.descriptor)?;
+
+        let field_class = self.resolve_class_from(class, &class_name)?;
+        let slot = self
+            .class(field_class)
+            .instance_field_slot(&name, &descriptor)
+            .ok_or_else(|| VmError::NoSuchField(format!("{}.{}", class_name, name)))?;
+        Ok((field_class, slot))
+    }
+
+    /// Final fields may only be written by the initializer of the class that declares them,
+    /// see `putfield` and `putstatic` in JVMS §6.5. Like HotSpot, this allows any method of the
+    /// class in class files before version 53
+    pub(crate) fn check_final_write(&self, method: MethodId, field: &Field) -> Result<()> {
+        if !field.is_final() {
+            return Ok(());
+        }
+        let (kind, initializer) = match field.is_static() {
+            true => ("static", "<clinit>"),
+            false => ("non-static", "<init>"),
+        };
+        let field_name = format!("{}.{}", self.java_name(field.class), field.name);
+        if field.class != method.class {
+            return Err(VmError::IllegalAccess(format!(
+                "Update to {} final field {} attempted from a different class ({}) than the field's declaring class",
+                kind,
+                field_name,
+                self.java_name(method.class)
+            )));
+        }
+        let name = &self.method(method).name;
+        if self.class(method.class).major_version >= 53 && name != initializer {
+            return Err(VmError::IllegalAccess(format!(
+                "Update to {} final field {} attempted from a different method ({}) than the initializer method {}",
+                kind, field_name, name, initializer
+            )));
+        }
+        Ok(())
+    }
+
+    /// `new`: allocates an instance of the class referenced at `index` of the constant pool.
+    /// Returns the class
+    pub(crate) fn op_new(&mut self, current: ClassId, index: u2) -> Result<ClassId> {
+        let name = self.class(current).cp_class_name(index)?.to_string();
+        let class = self.resolve_class_from(current, &name)?;
+        self.initialize(class)?;
+        self.op_new_quick(class)?;
+        Ok(class)
+    }
+
+    /// `new_quick`: allocates an instance of the initialized class
+    pub(crate) fn op_new_quick(&mut self, class: ClassId) -> Result<()> {
+        let obj = self.new_object(class)?;
+        // the stack trace of a throwable starts where it is created
+        if self.is_throwable(class) {
+            self.fill_backtrace(obj)?;
+        }
+        self.push(Value::Reference(Some(obj)))
+    }
+
+    /// `getfield`: ..., objectref -> ..., value. Returns the slot of the field
+    pub(crate) fn op_getfield(&mut self, current: ClassId, index: u2) -> Result<usize> {
+        let (_, slot) = self.resolve_instance_field(current, index)?;
+        self.op_getfield_quick(slot)?;
+        Ok(slot)
+    }
+
+    /// `getfield_quick`: `getfield` of the field in the slot
+    pub(crate) fn op_getfield_quick(&mut self, slot: usize) -> Result<()> {
+        let obj = self.pop()?.as_reference()?.ok_or(VmError::NullPointer)?;
+        self.push(self.get_field(obj, slot)?)
+    }
+
+    /// `putfield`: ..., objectref, value -> ... Returns the slot of the field
+    pub(crate) fn op_putfield(&mut self, method: MethodId, index: u2) -> Result<usize> {
+        let (field_class, slot) = self.resolve_instance_field(method.class, index)?;
+        self.check_final_write(method, &self.class(field_class).instance_fields[slot])?;
+        self.op_putfield_quick(slot)?;
+        Ok(slot)
+    }
+
+    /// `putfield_quick`: `putfield` of the field in the slot, which may be written
+    pub(crate) fn op_putfield_quick(&mut self, slot: usize) -> Result<()> {
+        let value = self.pop()?;
+        let obj = self.pop()?.as_reference()?.ok_or(VmError::NullPointer)?;
+        self.put_field(obj, slot, value)
+    }
+
+    /// `newarray`: ..., count -> ..., arrayref
+    pub(crate) fn op_newarray(&mut self, atype: u1) -> Result<()> {
+        let ty = PrimitiveArrayType::from_atype(atype)
+            .ok_or_else(|| VmError::Verify(format!("Invalid newarray type {}", atype)))?;
+        let class = self.resolve_class(&format!("[{}", ty.descriptor()))?;
+        let len = self.pop()?.as_int()?;
+        let array = self.new_array(class, len)?;
+        self.push(Value::Reference(Some(array)))
+    }
+
+    /// `anewarray`: ..., count -> ..., arrayref. Returns the class of the array
+    pub(crate) fn op_anewarray(&mut self, current: ClassId, index: u2) -> Result<ClassId> {
+        let component = self.class(current).cp_class_name(index)?;
+        let class = self.resolve_class(&array_class_name(component))?;
+        self.op_anewarray_quick(class)?;
+        Ok(class)
+    }
+
+    /// `anewarray_quick`: `anewarray` with the class of the array
+    pub(crate) fn op_anewarray_quick(&mut self, class: ClassId) -> Result<()> {
+        let len = self.pop()?.as_int()?;
+        let array = self.new_array(class, len)?;
+        self.push(Value::Reference(Some(array)))
+    }
+
+    /// `multianewarray`: ..., count1, [count2, ...] -> ..., arrayref
+    pub(crate) fn op_multianewarray(
+        &mut self,
+        current: ClassId,
+        index: u2,
+        dimensions: u1,
+    ) -> Result<()> {
+        let name = self.class(current).cp_class_name(index)?.to_string();
+        if dimensions == 0 || name.bytes().take_while(|&c| c == b'[').count() < dimensions as usize
+        {
+            return Err(VmError::Verify(format!(
+                "Invalid dimensions {} for {}",
+                dimensions, name
+            )));
+        }
+        let class = self.resolve_class(&name)?;
+
+        let mut dims = (0..dimensions)
+            .map(|_| self.pop()?.as_int())
+            .collect::<Result<Vec<_>>>()?;
+        dims.reverse();
+
+        let array = self.new_multi_array(class, &dims)?;
+        self.push(Value::Reference(Some(array)))
+    }
+}
+
+fn primitive_array_type(ty: &FieldType) -> PrimitiveArrayType {
+    match ty {
+        FieldType::Boolean => PrimitiveArrayType::Boolean,
+        FieldType::Char => PrimitiveArrayType::Char,
+        FieldType::Float => PrimitiveArrayType::Float,
+        FieldType::Double => PrimitiveArrayType::Double,
+        FieldType::Byte => PrimitiveArrayType::Byte,
+        FieldType::Short => PrimitiveArrayType::Short,
+        FieldType::Int => PrimitiveArrayType::Int,
+        FieldType::Long => PrimitiveArrayType::Long,
+        FieldType::Object(_) | FieldType::Array(_) => unreachable!("not a primitive type"),
+    }
+}
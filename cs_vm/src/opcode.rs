@@ -0,0 +1,48 @@
+//!
+//! The opcodes of the JVM instructions, and the quick variants that the VM rewrites them to
+//!
+//! The opcodes of class files are defined in `cs_parser::opcode`
+//!
+
+pub use cs_parser::opcode::*;
+use cs_parser::u1;
+
+// the quick variants that instructions are rewritten to after resolution, see `instruction`.
+// They are not valid in class files
+pub const LDC_QUICK: u1 = 0xcb;
+pub const LDC_W_QUICK: u1 = 0xcc;
+pub const LDC2_W_QUICK: u1 = 0xcd;
+pub const GETFIELD_QUICK: u1 = 0xce;
+pub const PUTFIELD_QUICK: u1 = 0xcf;
+pub const GETSTATIC_QUICK: u1 = 0xd2;
+pub const PUTSTATIC_QUICK: u1 = 0xd3;
+pub const INVOKEVIRTUAL_QUICK: u1 = 0xd6;
+pub const INVOKENONVIRTUAL_QUICK: u1 = 0xd7;
+pub const INVOKESTATIC_QUICK: u1 = 0xd9;
+pub const INVOKEINTERFACE_QUICK: u1 = 0xda;
+pub const NEW_QUICK: u1 = 0xdd;
+pub const ANEWARRAY_QUICK: u1 = 0xde;
+pub const CHECKCAST_QUICK: u1 = 0xe0;
+pub const INSTANCEOF_QUICK: u1 = 0xe1;
+
+/// The name of the instruction with the opcode, for error messages
+pub fn name(opcode: u1) -> &'static str {
+    match opcode {
+        LDC_QUICK => "ldc_quick",
+        LDC_W_QUICK => "ldc_w_quick",
+        LDC2_W_QUICK => "ldc2_w_quick",
+        GETFIELD_QUICK => "getfield_quick",
+        PUTFIELD_QUICK => "putfield_quick",
+        GETSTATIC_QUICK => "getstatic_quick",
+        PUTSTATIC_QUICK => "putstatic_quick",
+        INVOKEVIRTUAL_QUICK => "invokevirtual_quick",
+        INVOKENONVIRTUAL_QUICK => "invokenonvirtual_quick",
+        INVOKESTATIC_QUICK => "invokestatic_quick",
+        INVOKEINTERFACE_QUICK => "invokeinterface_quick",
+        NEW_QUICK => "new_quick",
+        ANEWARRAY_QUICK => "anewarray_quick",
+        CHECKCAST_QUICK => "checkcast_quick",
+        INSTANCEOF_QUICK => "instanceof_quick",
+        _ => cs_parser::opcode::name(opcode),
+    }
+}
@@ -0,0 +1,861 @@
+//!
+//! Reflection with the class library of a JDK
+//!
+//! The `java/lang/reflect` objects are created by their Java constructors. Their `slot` is the
+//! index of the method in its class, which identifies it again when it is invoked, or the slot
+//! of the field. Generic signatures, annotations and parameter names come from the attributes of
+//! the class file, see `Metadata`, and the class library parses annotations with the constant
+//! pool of the class through `jdk/internal/reflect/ConstantPool`, whose `constantPoolOop` is the
+//! `java/lang/Class` object
+//!
+
+use crate::class::{ClassId, ClassKind, Field, FieldSlot, Metadata, MethodId};
+use crate::heap::{Array, ObjRef};
+use crate::jdk::field_slot;
+use crate::model::Value;
+use crate::runtime::{mirror, string_result, this};
+use crate::{Result, Vm, VmError};
+use cs_model::MethodType;
+use cs_parser::{
+    u1, u2, Annotation, AnnotationElementValue, AnnotationElementValueValue, AttributeInfo,
+    AttributeInfoInner, CpInfo, CpInfoInner, FieldAccessFlags,
+};
+
+impl Vm {
+    pub(crate) fn register_reflect_natives(&mut self) {
+        self.register_native(
+            "java/lang/reflect/Array",
+            "newArray",
+            "(Ljava/lang/Class;I)Ljava/lang/Object;",
+            |vm, args| {
+                let component = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+                let component = vm
+                    .mirror_class(component)
+                    .ok_or_else(|| VmError::Verify("Class object without a class".to_string()))?;
+                if vm.class(component).name == "void" {
+                    return Err(vm.throw_new("java/lang/IllegalArgumentException", None));
+                }
+                let array_class =
+                    vm.resolve_class(&format!("[{}", vm.class(component).descriptor()))?;
+                let array = vm.new_array(array_class, args[1].as_int()?)?;
+                Ok(Some(Value::Reference(Some(array))))
+            },
+        );
+        self.register_native(
+            "java/lang/reflect/Array",
+            "getLength",
+            "(Ljava/lang/Object;)I",
+            |vm, args| {
+                let array = reflected_array(vm, args[0])?;
+                Ok(Some(Value::Int(vm.array(array)?.len() as i32)))
+            },
+        );
+        self.register_native(
+            "java/lang/reflect/Array",
+            "get",
+            "(Ljava/lang/Object;I)Ljava/lang/Object;",
+            |vm, args| {
+                let array = reflected_array(vm, args[0])?;
+                let index = args[1].as_int()?;
+                let element = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| vm.array(array).ok()?.load(index));
+                let element = element.ok_or(VmError::ArrayIndexOutOfBounds {
+                    index,
+                    len: vm.array(array)?.len(),
+                })?;
+                let ClassKind::Array(component) = vm.class(vm.heap.get(array).class).kind.clone()
+                else {
+                    unreachable!("the object is an array");
+                };
+                Ok(Some(Value::Reference(vm.box_value(&component, element)?)))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getDeclaredConstructors0",
+            "(Z)[Ljava/lang/reflect/Constructor;",
+            |vm, args| {
+                let class = mirror(vm, args)?;
+                let public_only = args[1].as_int()? != 0;
+                let array = vm.reflect_methods(class, public_only, true)?;
+                Ok(Some(Value::Reference(Some(array))))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getDeclaredMethods0",
+            "(Z)[Ljava/lang/reflect/Method;",
+            |vm, args| {
+                let class = mirror(vm, args)?;
+                let public_only = args[1].as_int()? != 0;
+                let array = vm.reflect_methods(class, public_only, false)?;
+                Ok(Some(Value::Reference(Some(array))))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getDeclaredFields0",
+            "(Z)[Ljava/lang/reflect/Field;",
+            |vm, args| {
+                let class = mirror(vm, args)?;
+                let public_only = args[1].as_int()? != 0;
+                let array = vm.reflect_fields(class, public_only)?;
+                Ok(Some(Value::Reference(Some(array))))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getInterfaces0",
+            "()[Ljava/lang/Class;",
+            |vm, args| {
+                let interfaces = vm.class(mirror(vm, args)?).interfaces.clone();
+                let array = vm.class_array(&interfaces)?;
+                Ok(Some(Value::Reference(Some(array))))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getPermittedSubclasses0",
+            "()[Ljava/lang/Class;",
+            |vm, args| {
+                let class = mirror(vm, args)?;
+                let Some(subclasses) = vm.class(class).metadata.permitted_subclasses.clone() else {
+                    return Ok(Some(Value::NULL));
+                };
+                let subclasses = (subclasses.iter())
+                    .map(|subclass| vm.resolve_class(subclass))
+                    .collect::<Result<Vec<_>>>()?;
+                let array = vm.class_array(&subclasses)?;
+                Ok(Some(Value::Reference(Some(array))))
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getGenericSignature0",
+            "()Ljava/lang/String;",
+            |vm, args| {
+                let signature = vm.class(mirror(vm, args)?).metadata.signature.clone();
+                vm.optional_string(signature.as_deref()).map(Some)
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getRawAnnotations",
+            "()[B",
+            |vm, args| {
+                let annotations = vm.class(mirror(vm, args)?).metadata.annotations.clone();
+                vm.byte_array(annotations.as_deref()).map(Some)
+            },
+        );
+        self.register_native(
+            "java/lang/Class",
+            "getConstantPool",
+            "()Ljdk/internal/reflect/ConstantPool;",
+            |vm, args| {
+                let pool_class = vm.resolve_class("jdk/internal/reflect/ConstantPool")?;
+                let slot = field_slot(vm, pool_class, "constantPoolOop")?;
+                let pool = vm.new_object(pool_class)?;
+                vm.put_field(pool, slot, args[0])?;
+                Ok(Some(Value::Reference(Some(pool))))
+            },
+        );
+        self.register_constant_pool_natives();
+        self.register_native(
+            "java/lang/reflect/Executable",
+            "getParameters0",
+            "()[Ljava/lang/reflect/Parameter;",
+            |vm, args| {
+                let executable = this(args)?;
+                let method = vm.reflected_method(executable)?;
+                let parameters = match &vm.method(method).metadata.parameters {
+                    Some(parameters) => parameters.clone(),
+                    None => return Ok(Some(Value::NULL)),
+                };
+                vm.handles.push(executable);
+                let array = vm.reflect_parameters(executable, &parameters);
+                vm.handles.pop();
+                Ok(Some(Value::Reference(Some(array?))))
+            },
+        );
+        self.register_native(
+            "jdk/internal/reflect/NativeMethodAccessorImpl",
+            "invoke0",
+            "(Ljava/lang/reflect/Method;Ljava/lang/Object;[Ljava/lang/Object;)Ljava/lang/Object;",
+            |vm, args| {
+                let method = vm.reflected_method(this(args)?)?;
+                let mut method_args = vm.unbox_arguments(method, args[2].as_reference()?)?;
+                let target = if vm.method(method).is_static() {
+                    vm.initialize(method.class)?;
+                    method
+                } else {
+                    let receiver = match args[1].as_reference()? {
+                        Some(receiver) => receiver,
+                        None => return Err(vm.throw_new("java/lang/NullPointerException", None)),
+                    };
+                    let class = vm.heap.get(receiver).class;
+                    if !vm.is_assignable(class, method.class) {
+                        return Err(vm.throw_new(
+                            "java/lang/IllegalArgumentException",
+                            Some("object is not an instance of declaring class"),
+                        ));
+                    }
+                    method_args.insert(0, Value::Reference(Some(receiver)));
+                    match vm.method(method).is_private() {
+                        true => method,
+                        false => vm.select_method(class, method)?,
+                    }
+                };
+                let result = vm.invoke_reflected(target, &method_args)?;
+                match (&vm.method(method).method_descriptor.return_, result) {
+                    (MethodType::Some(ty), Some(value)) => {
+                        let ty = ty.clone();
+                        Ok(Some(Value::Reference(vm.box_value(&ty, value)?)))
+                    }
+                    _ => Ok(Some(Value::NULL)),
+                }
+            },
+        );
+        self.register_native(
+            "jdk/internal/reflect/NativeConstructorAccessorImpl",
+            "newInstance0",
+            "(Ljava/lang/reflect/Constructor;[Ljava/lang/Object;)Ljava/lang/Object;",
+            |vm, args| {
+                let constructor = this(args)?;
+                let method = vm.reflected_method(constructor)?;
+                let mut method_args = vm.unbox_arguments(method, args[1].as_reference()?)?;
+                vm.initialize(method.class)?;
+                let obj = vm.new_object(method.class)?;
+                method_args.insert(0, Value::Reference(Some(obj)));
+                vm.handles.push(obj);
+                let result = vm.invoke_reflected(method, &method_args);
+                vm.handles.pop();
+                result.map(|_| Some(Value::Reference(Some(obj))))
+            },
+        );
+    }
+
+    /// The array of `java/lang/reflect/Constructor` or `Method` objects of the declared
+    /// constructors or methods of the class. Class initializers are not reflected
+    fn reflect_methods(
+        &mut self,
+        class: ClassId,
+        public_only: bool,
+        constructors: bool,
+    ) -> Result<ObjRef> {
+        let methods = self
+            .class(class)
+            .methods
+            .iter()
+            .enumerate()
+            .filter(|(_, method)| {
+                (method.name == "<init>") == constructors
+                    && method.name != "<clinit>"
+                    && (!public_only || method.is_public())
+            })
+            .map(|(index, _)| MethodId { class, index })
+            .collect::<Vec<_>>();
+
+        let array_class = if constructors {
+            "[Ljava/lang/reflect/Constructor;"
+        } else {
+            "[Ljava/lang/reflect/Method;"
+        };
+        let array_class = self.resolve_class(array_class)?;
+        let array = self.new_array(array_class, methods.len() as i32)?;
+        self.handles.push(array);
+        let result = methods
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(index, method)| {
+                let reflected = self.reflect_method(method)?;
+                self.array_mut(array)?
+                    .store(index, Value::Reference(Some(reflected)));
+                Ok(())
+            });
+        self.handles.pop();
+        result.map(|()| array)
+    }
+
+    /// Creates the `java/lang/reflect/Constructor` or `Method` object of a method
+    fn reflect_method(&mut self, method: MethodId) -> Result<ObjRef> {
+        let handles = self.handles.len();
+        let result = self.reflect_method_rooted(method);
+        self.handles.truncate(handles);
+        result
+    }
+
+    fn reflect_method_rooted(&mut self, method: MethodId) -> Result<ObjRef> {
+        let declaring = self.class_object(method.class)?;
+        let parameter_types = self.parameter_types(method)?;
+        self.handles.push(parameter_types);
+        let exceptions = self.method(method).metadata.exceptions.clone();
+        let exceptions = (exceptions.iter())
+            .map(|exception| self.resolve_class(exception))
+            .collect::<Result<Vec<_>>>()?;
+        let exception_types = self.class_array(&exceptions)?;
+        self.handles.push(exception_types);
+        let metadata = self.method(method).metadata.clone();
+        let signature = self.optional_string(metadata.signature.as_deref())?;
+        self.handles
+            .push(signature.as_reference()?.unwrap_or(declaring));
+        let annotations = self.byte_array(metadata.annotations.as_deref())?;
+        self.handles
+            .push(annotations.as_reference()?.unwrap_or(declaring));
+        let parameter_annotations = self.byte_array(metadata.parameter_annotations.as_deref())?;
+        self.handles
+            .push(parameter_annotations.as_reference()?.unwrap_or(declaring));
+
+        let method_ref = self.method(method);
+        let (modifiers, slot) = (method_ref.access_flags as i32, method.index as i32);
+        if method_ref.name == "<init>" {
+            let constructor_class = self.resolve_class("java/lang/reflect/Constructor")?;
+            return self.construct(
+                constructor_class,
+                "(Ljava/lang/Class;[Ljava/lang/Class;[Ljava/lang/Class;IILjava/lang/String;[B[B)V",
+                &[
+                    Value::Reference(Some(declaring)),
+                    Value::Reference(Some(parameter_types)),
+                    Value::Reference(Some(exception_types)),
+                    Value::Int(modifiers),
+                    Value::Int(slot),
+                    signature,
+                    annotations,
+                    parameter_annotations,
+                ],
+            );
+        }
+
+        let (name, return_type) = (
+            method_ref.name.clone(),
+            method_ref.method_descriptor.return_.clone(),
+        );
+        let return_type = match return_type {
+            MethodType::Some(ty) => self.field_type_class(&ty)?,
+            MethodType::Void => self.primitive_class("void")?,
+        };
+        let return_type = self.class_object(return_type)?;
+        let annotation_default = self.byte_array(metadata.annotation_default.as_deref())?;
+        self.handles
+            .push(annotation_default.as_reference()?.unwrap_or(declaring));
+        // the names of reflected methods are interned, like in the JVM
+        let name = self.intern_string(&name)?;
+        let method_class = self.resolve_class("java/lang/reflect/Method")?;
+        self.construct(
+            method_class,
+            "(Ljava/lang/Class;Ljava/lang/String;[Ljava/lang/Class;Ljava/lang/Class;\
+            [Ljava/lang/Class;IILjava/lang/String;[B[B[B)V",
+            &[
+                Value::Reference(Some(declaring)),
+                Value::Reference(Some(name)),
+                Value::Reference(Some(parameter_types)),
+                Value::Reference(Some(return_type)),
+                Value::Reference(Some(exception_types)),
+                Value::Int(modifiers),
+                Value::Int(slot),
+                signature,
+                annotations,
+                parameter_annotations,
+                annotation_default,
+            ],
+        )
+    }
+
+    /// The array of `java/lang/reflect/Field` objects of the fields declared by the class
+    fn reflect_fields(&mut self, class: ClassId, public_only: bool) -> Result<ObjRef> {
+        let fields = (self.class(class).declared_fields.iter().copied())
+            .filter(|&slot| !public_only || self.declared_field(class, slot).is_public())
+            .collect::<Vec<_>>();
+        let array_class = self.resolve_class("[Ljava/lang/reflect/Field;")?;
+        let array = self.new_array(array_class, fields.len() as i32)?;
+        self.handles.push(array);
+        let result = fields
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(index, slot)| {
+                let handles = self.handles.len();
+                let reflected = self.reflect_field(class, slot);
+                self.handles.truncate(handles);
+                self.array_mut(array)?
+                    .store(index, Value::Reference(Some(reflected?)));
+                Ok(())
+            });
+        self.handles.pop();
+        result.map(|()| array)
+    }
+
+    fn declared_field(&self, class: ClassId, slot: FieldSlot) -> &Field {
+        match slot {
+            FieldSlot::Instance(slot) => &self.class(class).instance_fields[slot],
+            FieldSlot::Static(slot) => &self.class(class).static_fields[slot],
+        }
+    }
+
+    /// Creates the `java/lang/reflect/Field` object of a field, the caller restores the handles
+    fn reflect_field(&mut self, class: ClassId, slot: FieldSlot) -> Result<ObjRef> {
+        let field = self.declared_field(class, slot).clone();
+        let declaring = self.class_object(class)?;
+        let name = self.intern_string(&field.name)?;
+        self.handles.push(name);
+        let ty = self.field_type_class(&field.descriptor)?;
+        let ty = self.class_object(ty)?;
+        let signature = self.optional_string(field.metadata.signature.as_deref())?;
+        self.handles.push(signature.as_reference()?.unwrap_or(name));
+        let annotations = self.byte_array(field.metadata.annotations.as_deref())?;
+        self.handles
+            .push(annotations.as_reference()?.unwrap_or(name));
+
+        // final fields of records and hidden classes can not be set even with `setAccessible`
+        let trusted_final = field.is_final() && (field.is_static() || self.class(class).hidden);
+        let slot = match slot {
+            FieldSlot::Instance(slot) | FieldSlot::Static(slot) => slot,
+        };
+        let field_class = self.resolve_class("java/lang/reflect/Field")?;
+        self.construct(
+            field_class,
+            "(Ljava/lang/Class;Ljava/lang/String;Ljava/lang/Class;IZILjava/lang/String;[B)V",
+            &[
+                Value::Reference(Some(declaring)),
+                Value::Reference(Some(name)),
+                Value::Reference(Some(ty)),
+                Value::Int(field.access_flags as i32),
+                Value::Int(trusted_final as i32),
+                Value::Int(slot as i32),
+                signature,
+                annotations,
+            ],
+        )
+    }
+
+    /// The class and the slot of the field of a `java/lang/reflect/Field` object
+    pub(crate) fn reflected_field(&self, reflected: ObjRef) -> Result<(ClassId, FieldSlot)> {
+        let class = self.heap.get(reflected).class;
+        let declaring = self
+            .get_field(reflected, field_slot(self, class, "clazz")?)?
+            .as_reference()?
+            .ok_or(VmError::NullPointer)?;
+        let declaring = self
+            .mirror_class(declaring)
+            .ok_or_else(|| VmError::Verify("Class object without a class".to_string()))?;
+        let slot =
+            (self.get_field(reflected, field_slot(self, class, "slot")?)?).as_int()? as usize;
+        let modifiers =
+            (self.get_field(reflected, field_slot(self, class, "modifiers")?)?).as_int()?;
+        let (slot, fields) = match modifiers as u2 & FieldAccessFlags::STATIC as u2 != 0 {
+            true => (
+                FieldSlot::Static(slot),
+                &self.class(declaring).static_fields,
+            ),
+            false => (
+                FieldSlot::Instance(slot),
+                &self.class(declaring).instance_fields,
+            ),
+        };
+        match fields.get(slot_index(slot)) {
+            Some(field) if field.class == declaring => Ok((declaring, slot)),
+            _ => Err(VmError::Verify(format!(
+                "Invalid slot {} of a reflected field",
+                slot_index(slot)
+            ))),
+        }
+    }
+
+    /// The `java/lang/reflect/Parameter` objects of a method with a `MethodParameters` attribute
+    fn reflect_parameters(
+        &mut self,
+        executable: ObjRef,
+        parameters: &[(Option<String>, u2)],
+    ) -> Result<ObjRef> {
+        let array_class = self.resolve_class("[Ljava/lang/reflect/Parameter;")?;
+        let parameter_class = self.resolve_class("java/lang/reflect/Parameter")?;
+        let array = self.new_array(array_class, parameters.len() as i32)?;
+        self.handles.push(array);
+        let result = parameters
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, (name, access_flags))| {
+                let name = self.optional_string(name.as_deref())?;
+                self.handles.push(name.as_reference()?.unwrap_or(array));
+                let parameter = self.construct(
+                    parameter_class,
+                    "(Ljava/lang/String;ILjava/lang/reflect/Executable;I)V",
+                    &[
+                        name,
+                        Value::Int(*access_flags as i32),
+                        Value::Reference(Some(executable)),
+                        Value::Int(index as i32),
+                    ],
+                );
+                self.handles.pop();
+                self.array_mut(array)?
+                    .store(index, Value::Reference(Some(parameter?)));
+                Ok(())
+            });
+        self.handles.pop();
+        result.map(|()| array)
+    }
+
+    /// A `java/lang/Class[]` with the `java/lang/Class` objects of the classes
+    fn class_array(&mut self, classes: &[ClassId]) -> Result<ObjRef> {
+        let class_array = self.resolve_class("[Ljava/lang/Class;")?;
+        let array = self.new_array(class_array, classes.len() as i32)?;
+        for (index, &class) in classes.iter().enumerate() {
+            // mirrors are never collected
+            let mirror = self.class_object(class)?;
+            self.array_mut(array)?
+                .store(index, Value::Reference(Some(mirror)));
+        }
+        Ok(array)
+    }
+
+    /// A `java/lang/String` with the contents, or null
+    fn optional_string(&mut self, contents: Option<&str>) -> Result<Value> {
+        match contents {
+            Some(contents) => Ok(Value::Reference(Some(self.new_string(contents)?))),
+            None => Ok(Value::NULL),
+        }
+    }
+
+    /// A `byte[]` with the bytes, or null
+    fn byte_array(&mut self, bytes: Option<&[u1]>) -> Result<Value> {
+        let Some(bytes) = bytes else {
+            return Ok(Value::NULL);
+        };
+        let byte_array = self.resolve_class("[B")?;
+        let array = self.new_array(byte_array, bytes.len() as i32)?;
+        if let Array::Byte(elements) = self.array_mut(array)? {
+            for (element, &byte) in elements.iter_mut().zip(bytes) {
+                *element = byte as i8;
+            }
+        }
+        Ok(Value::Reference(Some(array)))
+    }
+
+    /// The natives of `jdk/internal/reflect/ConstantPool`, which read the constant pool of the
+    /// class of their `constantPoolOop`
+    fn register_constant_pool_natives(&mut self) {
+        const CONSTANT_POOL: &str = "jdk/internal/reflect/ConstantPool";
+
+        self.register_native(
+            CONSTANT_POOL,
+            "getSize0",
+            "(Ljava/lang/Object;)I",
+            |vm, args| {
+                let class = constant_pool_class(vm, args)?;
+                Ok(Some(Value::Int(
+                    vm.class(class).constant_pool.len() as i32 + 1,
+                )))
+            },
+        );
+        self.register_native(
+            CONSTANT_POOL,
+            "getTagAt0",
+            "(Ljava/lang/Object;I)B",
+            |vm, args| {
+                let (class, index) = (constant_pool_class(vm, args)?, args[2].as_int()?);
+                let tag = (index as usize)
+                    .checked_sub(1)
+                    .and_then(|index| vm.class(class).constant_pool.get(index))
+                    .map(|info| info.tag);
+                match tag {
+                    Some(tag) => Ok(Some(Value::Int(tag as i8 as i32))),
+                    None => Err(wrong_type(vm)),
+                }
+            },
+        );
+        for (name, descriptor) in [
+            ("getIntAt0", "(Ljava/lang/Object;I)I"),
+            ("getLongAt0", "(Ljava/lang/Object;I)J"),
+            ("getFloatAt0", "(Ljava/lang/Object;I)F"),
+            ("getDoubleAt0", "(Ljava/lang/Object;I)D"),
+        ] {
+            self.register_native(CONSTANT_POOL, name, descriptor, |vm, args| {
+                let (class, index) = (constant_pool_class(vm, args)?, args[2].as_int()?);
+                match vm.class(class).cp_numeric(index as u2) {
+                    Ok(value) => Ok(Some(value)),
+                    Err(_) => Err(wrong_type(vm)),
+                }
+            });
+        }
+        self.register_native(
+            CONSTANT_POOL,
+            "getUTF8At0",
+            "(Ljava/lang/Object;I)Ljava/lang/String;",
+            |vm, args| {
+                let (class, index) = (constant_pool_class(vm, args)?, args[2].as_int()?);
+                match vm.class(class).cp_utf8(index as u2).map(str::to_string) {
+                    Ok(utf8) => string_result(vm, &utf8),
+                    Err(_) => Err(wrong_type(vm)),
+                }
+            },
+        );
+        self.register_native(
+            CONSTANT_POOL,
+            "getStringAt0",
+            "(Ljava/lang/Object;I)Ljava/lang/String;",
+            |vm, args| {
+                let (class, index) = (constant_pool_class(vm, args)?, args[2].as_int()?);
+                match vm.class(class).cp_string(index as u2).map(str::to_string) {
+                    Ok(string) => Ok(Some(Value::Reference(Some(vm.intern_string(&string)?)))),
+                    Err(_) => Err(wrong_type(vm)),
+                }
+            },
+        );
+        self.register_native(
+            CONSTANT_POOL,
+            "getClassAt0",
+            "(Ljava/lang/Object;I)Ljava/lang/Class;",
+            |vm, args| {
+                let (class, index) = (constant_pool_class(vm, args)?, args[2].as_int()?);
+                if vm.class(class).cp_class_name(index as u2).is_err() {
+                    return Err(wrong_type(vm));
+                }
+                let resolved = vm.resolve_class_ref(class, index as u2)?;
+                Ok(Some(Value::Reference(Some(vm.class_object(resolved)?))))
+            },
+        );
+    }
+
+    /// The `java/lang/Class` objects of the parameters of a method
+    fn parameter_types(&mut self, method: MethodId) -> Result<ObjRef> {
+        let descriptor = self.method(method).method_descriptor.clone();
+        let class_array = self.resolve_class("[Ljava/lang/Class;")?;
+        let array = self.new_array(class_array, descriptor.parameters.len() as i32)?;
+        self.handles.push(array);
+        let result = descriptor
+            .parameters
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, parameter)| {
+                let class = self.field_type_class(parameter)?;
+                let mirror = self.class_object(class)?;
+                self.array_mut(array)?
+                    .store(index, Value::Reference(Some(mirror)));
+                Ok(())
+            });
+        self.handles.pop();
+        result.map(|()| array)
+    }
+
+    /// The method of a `java/lang/reflect/Constructor` or `Method` object
+    pub(crate) fn reflected_method(&self, reflected: ObjRef) -> Result<MethodId> {
+        let class = self.heap.get(reflected).class;
+        let declaring = self
+            .get_field(reflected, field_slot(self, class, "clazz")?)?
+            .as_reference()?
+            .ok_or(VmError::NullPointer)?;
+        let declaring = self
+            .mirror_class(declaring)
+            .ok_or_else(|| VmError::Verify("Class object without a class".to_string()))?;
+        let index = self
+            .get_field(reflected, field_slot(self, class, "slot")?)?
+            .as_int()? as usize;
+        if index >= self.class(declaring).methods.len() {
+            return Err(VmError::Verify(format!(
+                "Invalid slot {} of a reflected method",
+                index
+            )));
+        }
+        Ok(MethodId {
+            class: declaring,
+            index,
+        })
+    }
+
+    /// Unboxes the arguments of a reflective call for the parameters of the method
+    fn unbox_arguments(&mut self, method: MethodId, args: Option<ObjRef>) -> Result<Vec<Value>> {
+        let descriptor = self.method(method).method_descriptor.clone();
+        let len = match args {
+            Some(args) => self.array(args)?.len(),
+            None => 0,
+        };
+        if len != descriptor.parameters.len() {
+            return Err(self.throw_new(
+                "java/lang/IllegalArgumentException",
+                Some("wrong number of arguments"),
+            ));
+        }
+        descriptor
+            .parameters
+            .iter()
+            .enumerate()
+            .map(|(index, parameter)| {
+                let arg = match args {
+                    Some(args) => self.array(args)?.load(index).unwrap_or(Value::NULL),
+                    None => Value::NULL,
+                };
+                self.unbox_value(parameter, arg.as_reference()?)
+            })
+            .collect()
+    }
+
+    /// Invokes a method for reflection, exceptions are wrapped in an `InvocationTargetException`
+    fn invoke_reflected(&mut self, method: MethodId, args: &[Value]) -> Result<Option<Value>> {
+        match self.invoke(method, args) {
+            Err(VmError::Exception(exception)) => {
+                self.handles.push(exception);
+                let class = self.resolve_class("java/lang/reflect/InvocationTargetException");
+                let wrapped = class.and_then(|class| {
+                    self.construct(
+                        class,
+                        "(Ljava/lang/Throwable;)V",
+                        &[Value::Reference(Some(exception))],
+                    )
+                });
+                self.handles.pop();
+                Err(VmError::Exception(wrapped?))
+            }
+            result => result,
+        }
+    }
+}
+
+/// Collects the attributes of a class, field or method that reflection reads
+pub(crate) fn metadata(attributes: &[AttributeInfo], cp: &[CpInfo]) -> Metadata {
+    let mut metadata = Metadata::default();
+    for attribute in attributes {
+        match &attribute.inner {
+            AttributeInfoInner::Signature { signature_index } => {
+                metadata.signature = Some(signature_index.get(cp).to_string());
+            }
+            AttributeInfoInner::RuntimeVisibleAnnotations { annotations } => {
+                let mut bytes = (annotations.len() as u2).to_be_bytes().to_vec();
+                annotations
+                    .iter()
+                    .for_each(|annotation| write_annotation(&mut bytes, annotation));
+                metadata.annotations = Some(bytes);
+            }
+            AttributeInfoInner::RuntimeVisibleParameterAnnotations {
+                parameter_annotations,
+            } => {
+                let mut bytes = vec![parameter_annotations.len() as u1];
+                for parameter in parameter_annotations {
+                    bytes.extend((parameter.annotations.len() as u2).to_be_bytes());
+                    (parameter.annotations.iter())
+                        .for_each(|annotation| write_annotation(&mut bytes, annotation));
+                }
+                metadata.parameter_annotations = Some(bytes);
+            }
+            AttributeInfoInner::AnnotationDefault { default_value } => {
+                let mut bytes = Vec::new();
+                write_element_value(&mut bytes, default_value);
+                metadata.annotation_default = Some(bytes);
+            }
+            AttributeInfoInner::Exceptions {
+                exception_index_table,
+            } => {
+                metadata.exceptions = (exception_index_table.iter())
+                    .filter_map(|&index| cp_class_name(cp, index))
+                    .collect();
+            }
+            // nor `PermittedSubclasses`: a count, then the index of every class
+            AttributeInfoInner::Unknown { attribute_content }
+                if attribute.attribute_name_index.get(cp) == "PermittedSubclasses" =>
+            {
+                let classes = (attribute_content.get(2..).unwrap_or_default())
+                    .chunks_exact(2)
+                    .filter_map(|index| cp_class_name(cp, u2::from_be_bytes([index[0], index[1]])));
+                metadata.permitted_subclasses = Some(classes.collect());
+            }
+            // the parser does not know `MethodParameters`: a count, then the index of the name
+            // and the access flags of every parameter
+            AttributeInfoInner::Unknown { attribute_content }
+                if attribute.attribute_name_index.get(cp) == "MethodParameters" =>
+            {
+                let parameters = (attribute_content.get(1..).unwrap_or_default())
+                    .chunks_exact(4)
+                    .map(|parameter| {
+                        let name = u2::from_be_bytes([parameter[0], parameter[1]]);
+                        let name = match cp.get((name as usize).wrapping_sub(1)) {
+                            Some(CpInfo {
+                                inner: CpInfoInner::Utf8(name),
+                                ..
+                            }) => Some(name.bytes.clone()),
+                            _ => None,
+                        };
+                        (name, u2::from_be_bytes([parameter[2], parameter[3]]))
+                    });
+                metadata.parameters = Some(parameters.collect());
+            }
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// The array argument of a `java/lang/reflect/Array` native
+fn reflected_array(vm: &mut Vm, value: Value) -> Result<ObjRef> {
+    let array = value.as_reference()?.ok_or(VmError::NullPointer)?;
+    match vm.class(vm.heap.get(array).class).is_array() {
+        true => Ok(array),
+        false => Err(vm.throw_new(
+            "java/lang/IllegalArgumentException",
+            Some("Argument is not an array"),
+        )),
+    }
+}
+
+fn cp_class_name(cp: &[CpInfo], index: u2) -> Option<String> {
+    match &cp.get((index as usize).wrapping_sub(1))?.inner {
+        CpInfoInner::Class(class) => Some(class.name_index.get(cp).to_string()),
+        _ => None,
+    }
+}
+
+/// Writes an annotation in the class file format, see JVMS §4.7.16
+fn write_annotation(bytes: &mut Vec<u1>, annotation: &Annotation) {
+    bytes.extend(annotation.type_index.inner().to_be_bytes());
+    bytes.extend((annotation.element_value_pairs.len() as u2).to_be_bytes());
+    for pair in &annotation.element_value_pairs {
+        bytes.extend(pair.element_name_index.inner().to_be_bytes());
+        write_element_value(bytes, &pair.element_name_name);
+    }
+}
+
+fn write_element_value(bytes: &mut Vec<u1>, value: &AnnotationElementValue) {
+    bytes.push(value.tag);
+    match &value.value {
+        AnnotationElementValueValue::ConstValueIndex { index } => {
+            bytes.extend(index.inner().to_be_bytes());
+        }
+        AnnotationElementValueValue::EnumConstValue {
+            type_name_index,
+            const_name_index,
+        } => {
+            bytes.extend(type_name_index.inner().to_be_bytes());
+            bytes.extend(const_name_index.inner().to_be_bytes());
+        }
+        AnnotationElementValueValue::ClassInfoIndex { index } => {
+            bytes.extend(index.inner().to_be_bytes());
+        }
+        AnnotationElementValueValue::AnnotationValue { annotation } => {
+            write_annotation(bytes, annotation);
+        }
+        AnnotationElementValueValue::ArrayValue { values } => {
+            bytes.extend((values.len() as u2).to_be_bytes());
+            values
+                .iter()
+                .for_each(|value| write_element_value(bytes, value));
+        }
+    }
+}
+
+fn slot_index(slot: FieldSlot) -> usize {
+    match slot {
+        FieldSlot::Instance(slot) | FieldSlot::Static(slot) => slot,
+    }
+}
+
+/// The class whose constant pool a `jdk/internal/reflect/ConstantPool` native reads
+fn constant_pool_class(vm: &Vm, args: &[Value]) -> Result<ClassId> {
+    let mirror = args[1].as_reference()?.ok_or(VmError::NullPointer)?;
+    vm.mirror_class(mirror)
+        .ok_or_else(|| VmError::Verify("Constant pool without a class".to_string()))
+}
+
+fn wrong_type(vm: &mut Vm) -> VmError {
+    vm.throw_new(
+        "java/lang/IllegalArgumentException",
+        Some("Wrong type at constant pool index"),
+    )
+}
@@ -0,0 +1,382 @@
+//!
+//! The bundled class library
+//!
+//! A minimal `java.base` written in Java, so that simple programs run without a JDK.
+//! The sources are in `runtime`, the class files are compiled with
+//! `javac --patch-module java.base=. -XDstringConcat=inline -d . $(find . -name '*.java')`.
+//! String concatenation uses `StringBuilder`, because `invokedynamic` would need `java.lang.invoke`.
+//! The native methods of these classes are implemented here
+//!
+
+use crate::class::{component_class_name, ClassId};
+use crate::heap::{Array, ObjRef, ObjectData};
+use crate::model::Value;
+use crate::{Result, Vm, VmError};
+use std::fmt::{Display, LowerExp};
+use std::io::Write;
+
+macro_rules! runtime_classes {
+    ($($name:literal),* $(,)?) => {
+        &[$(($name, include_bytes!(concat!("../runtime/", $name, ".class")) as &[u8])),*]
+    };
+}
+
+pub(crate) static CLASSES: &[(&str, &[u8])] = runtime_classes![
+    "java/io/PrintStream",
+    "java/lang/AbstractMethodError",
+    "java/lang/ArithmeticException",
+    "java/lang/ArrayIndexOutOfBoundsException",
+    "java/lang/ArrayStoreException",
+    "java/lang/CharSequence",
+    "java/lang/Class",
+    "java/lang/ClassCastException",
+    "java/lang/CloneNotSupportedException",
+    "java/lang/Cloneable",
+    "java/lang/Comparable",
+    "java/lang/Double",
+    "java/lang/Error",
+    "java/lang/Exception",
+    "java/lang/ExceptionInInitializerError",
+    "java/lang/Float",
+    "java/lang/IllegalAccessError",
+    "java/lang/IllegalArgumentException",
+    "java/lang/IllegalMonitorStateException",
+    "java/lang/IllegalStateException",
+    "java/lang/IllegalThreadStateException",
+    "java/lang/IncompatibleClassChangeError",
+    "java/lang/IndexOutOfBoundsException",
+    "java/lang/InstantiationError",
+    "java/lang/Integer",
+    "java/lang/InterruptedException",
+    "java/lang/LinkageError",
+    "java/lang/Long",
+    "java/lang/Math",
+    "java/lang/NegativeArraySizeException",
+    "java/lang/NoClassDefFoundError",
+    "java/lang/NoSuchFieldError",
+    "java/lang/NoSuchMethodError",
+    "java/lang/NullPointerException",
+    "java/lang/Number",
+    "java/lang/NumberFormatException",
+    "java/lang/Object",
+    "java/lang/OutOfMemoryError",
+    "java/lang/Runnable",
+    "java/lang/RuntimeException",
+    "java/lang/StackOverflowError",
+    "java/lang/String",
+    "java/lang/StringBuilder",
+    "java/lang/StringIndexOutOfBoundsException",
+    "java/lang/System",
+    "java/lang/Thread",
+    "java/lang/Throwable",
+    "java/lang/UnsatisfiedLinkError",
+    "java/lang/VirtualMachineError",
+];
+
+/// The class file of a class of the bundled class library
+pub(crate) fn class_file(name: &str) -> Option<&'static [u8]> {
+    CLASSES
+        .iter()
+        .find(|(class, _)| *class == name)
+        .map(|(_, bytes)| *bytes)
+}
+
+/// Registers natives of `java/lang/Math` or `java/lang/StrictMath` that take a `double`
+/// and return a `double`
+macro_rules! math_natives {
+    ($vm:ident, $class:literal, $($name:literal => $function:path),* $(,)?) => {
+        $($vm.register_native($class, $name, "(D)D", |_, args| {
+            Ok(Some(Value::Double($function(args[0].as_double()?))))
+        });)*
+    };
+}
+pub(crate) use math_natives;
+
+impl Vm {
+    /// Registers the native methods of the bundled class library
+    pub(crate) fn register_runtime_natives(&mut self) {
+        self.register_native(
+            "java/lang/Object",
+            "getClass",
+            "()Ljava/lang/Class;",
+            |vm, args| {
+                let class = vm.heap.get(this(args)?).class;
+                Ok(Some(Value::Reference(Some(vm.class_object(class)?))))
+            },
+        );
+        self.register_native("java/lang/Object", "hashCode", "()I", |_, args| {
+            Ok(Some(Value::Int(identity_hash(this(args)?))))
+        });
+        self.register_native(
+            "java/lang/Object",
+            "clone",
+            "()Ljava/lang/Object;",
+            object_clone,
+        );
+
+        self.register_native(
+            "java/lang/String",
+            "intern",
+            "()Ljava/lang/String;",
+            |vm, args| {
+                let string = vm.intern(this(args)?)?;
+                Ok(Some(Value::Reference(Some(string))))
+            },
+        );
+
+        self.register_native("java/lang/Class", "isInterface", "()Z", |vm, args| {
+            let class = mirror(vm, args)?;
+            Ok(Some(Value::Int(vm.class(class).is_interface() as i32)))
+        });
+        self.register_native("java/lang/Class", "isArray", "()Z", |vm, args| {
+            let class = mirror(vm, args)?;
+            Ok(Some(Value::Int(vm.class(class).is_array() as i32)))
+        });
+        self.register_native("java/lang/Class", "isPrimitive", "()Z", |vm, args| {
+            let class = mirror(vm, args)?;
+            Ok(Some(Value::Int(vm.class(class).is_primitive() as i32)))
+        });
+
+        self.register_native(
+            "java/lang/Throwable",
+            "printStackTrace",
+            "()V",
+            |vm, args| {
+                let trace = vm.format_stack_trace(this(args)?);
+                let _ = vm.stderr.write_all(trace.as_bytes());
+                Ok(None)
+            },
+        );
+
+        self.register_native(
+            "java/lang/Double",
+            "toString",
+            "(D)Ljava/lang/String;",
+            |vm, args| {
+                let value = args[0].as_double()?;
+                string_result(vm, &format_floating(value, value))
+            },
+        );
+        self.register_native(
+            "java/lang/Float",
+            "toString",
+            "(F)Ljava/lang/String;",
+            |vm, args| {
+                let value = args[0].as_float()?;
+                string_result(vm, &format_floating(value, value as f64))
+            },
+        );
+        self.register_native(
+            "java/lang/Double",
+            "doubleToRawLongBits",
+            "(D)J",
+            |_, args| Ok(Some(Value::Long(args[0].as_double()?.to_bits() as i64))),
+        );
+        self.register_native("java/lang/Float", "floatToRawIntBits", "(F)I", |_, args| {
+            Ok(Some(Value::Int(args[0].as_float()?.to_bits() as i32)))
+        });
+
+        math_natives!(self, "java/lang/Math",
+            "sqrt" => f64::sqrt,
+            "cbrt" => f64::cbrt,
+            "exp" => f64::exp,
+            "log" => f64::ln,
+            "log10" => f64::log10,
+            "sin" => f64::sin,
+            "cos" => f64::cos,
+            "tan" => f64::tan,
+            "floor" => f64::floor,
+            "ceil" => f64::ceil,
+            "rint" => f64::round_ties_even,
+        );
+        self.register_native("java/lang/Math", "pow", "(DD)D", |_, args| {
+            let (a, b) = (args[0].as_double()?, args[1].as_double()?);
+            Ok(Some(Value::Double(a.powf(b))))
+        });
+        self.register_native("java/lang/Math", "atan2", "(DD)D", |_, args| {
+            let (y, x) = (args[0].as_double()?, args[1].as_double()?);
+            Ok(Some(Value::Double(y.atan2(x))))
+        });
+
+        self.register_native(
+            "java/lang/System",
+            "arraycopy",
+            "(Ljava/lang/Object;ILjava/lang/Object;II)V",
+            array_copy,
+        );
+        self.register_native("java/lang/System", "currentTimeMillis", "()J", |vm, _| {
+            Ok(Some(Value::Long(vm.clock.since_epoch().as_millis() as i64)))
+        });
+        self.register_native("java/lang/System", "nanoTime", "()J", |vm, _| {
+            Ok(Some(Value::Long(vm.clock.since_epoch().as_nanos() as i64)))
+        });
+        self.register_native(
+            "java/lang/System",
+            "identityHashCode",
+            "(Ljava/lang/Object;)I",
+            |_, args| {
+                Ok(Some(Value::Int(
+                    args[0].as_reference()?.map_or(0, identity_hash),
+                )))
+            },
+        );
+
+        self.register_native(
+            "java/io/PrintStream",
+            "write",
+            "(ILjava/lang/String;)V",
+            |vm, args| {
+                let string = args[1].as_reference()?.ok_or(VmError::NullPointer)?;
+                let string = vm.string_value(string)?;
+                // like `PrintStream`, errors are ignored
+                let _ = match args[0].as_int()? {
+                    2 => vm.stderr.write_all(string.as_bytes()),
+                    _ => vm.stdout.write_all(string.as_bytes()),
+                };
+                Ok(None)
+            },
+        );
+    }
+}
+
+/// The receiver of an instance method
+pub(crate) fn this(args: &[Value]) -> Result<ObjRef> {
+    args[0].as_reference()?.ok_or(VmError::NullPointer)
+}
+
+/// The class of the receiver of a method of `java/lang/Class`
+pub(crate) fn mirror(vm: &Vm, args: &[Value]) -> Result<ClassId> {
+    vm.mirror_class(this(args)?)
+        .ok_or_else(|| VmError::Verify("Class object without a class".to_string()))
+}
+
+pub(crate) fn string_result(vm: &mut Vm, string: &str) -> Result<Option<Value>> {
+    Ok(Some(Value::Reference(Some(vm.new_string(string)?))))
+}
+
+/// The identity hash code of an object, objects never move so it is derived from the reference
+pub(crate) fn identity_hash(obj: ObjRef) -> i32 {
+    ((obj.index() as u32).wrapping_mul(0x9E37_79B9) >> 1) as i32
+}
+
+/// Formats a floating point number like `Double.toString`: the shortest digits that identify the
+/// number, in scientific notation if the magnitude is less than 10^-3 or at least 10^7
+fn format_floating<T: Display + LowerExp>(value: T, double: f64) -> String {
+    if double.is_nan() {
+        return "NaN".to_string();
+    }
+    if double.is_infinite() {
+        return if double > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        }
+        .to_string();
+    }
+
+    if double == 0.0 || (1e-3..1e7).contains(&double.abs()) {
+        let decimal = value.to_string();
+        if decimal.contains('.') {
+            decimal
+        } else {
+            decimal + ".0"
+        }
+    } else {
+        let scientific = format!("{:e}", value);
+        let (mantissa, exponent) = scientific.split_once('e').expect("scientific notation");
+        if mantissa.contains('.') {
+            format!("{}E{}", mantissa, exponent)
+        } else {
+            format!("{}.0E{}", mantissa, exponent)
+        }
+    }
+}
+
+/// `Object.clone`: a shallow copy of arrays and objects that implement `Cloneable`
+fn object_clone(vm: &mut Vm, args: &[Value]) -> Result<Option<Value>> {
+    let obj = this(args)?;
+    let class = vm.heap.get(obj).class;
+    let cloneable = vm.resolve_class("java/lang/Cloneable")?;
+    if !vm.is_assignable(class, cloneable) {
+        let name = vm.class(class).name.replace('/', ".");
+        return Err(vm.throw_new("java/lang/CloneNotSupportedException", Some(&name)));
+    }
+    let copy = vm.heap.get(obj).clone();
+    Ok(Some(Value::Reference(Some(vm.alloc(copy)?))))
+}
+
+/// `System.arraycopy`, which also works if both arrays are the same
+fn array_copy(vm: &mut Vm, args: &[Value]) -> Result<Option<Value>> {
+    let src = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+    let src_pos = args[1].as_int()?;
+    let dest = args[2].as_reference()?.ok_or(VmError::NullPointer)?;
+    let dest_pos = args[3].as_int()?;
+    let length = args[4].as_int()?;
+
+    let (src_class, dest_class) = (vm.heap.get(src).class, vm.heap.get(dest).class);
+    let type_name = |class| vm.class(class).name.replace('/', ".");
+    let (src_array, dest_array) = match (&vm.heap.get(src).data, &vm.heap.get(dest).data) {
+        (ObjectData::Array(src), ObjectData::Array(dest)) => (src, dest),
+        (ObjectData::Array(_), _) => {
+            return Err(VmError::ArrayStore(format!(
+                "arraycopy: destination type {} is not an array",
+                type_name(dest_class)
+            )))
+        }
+        _ => {
+            return Err(VmError::ArrayStore(format!(
+                "arraycopy: source type {} is not an array",
+                type_name(src_class)
+            )))
+        }
+    };
+    if std::mem::discriminant(src_array) != std::mem::discriminant(dest_array) {
+        return Err(VmError::ArrayStore(format!(
+            "arraycopy: type mismatch: can not copy {} into {}",
+            type_name(src_class),
+            type_name(dest_class)
+        )));
+    }
+
+    for (pos, len) in [(src_pos, src_array.len()), (dest_pos, dest_array.len())] {
+        let end = pos as i64 + length as i64;
+        if pos < 0 || length < 0 || end > len as i64 {
+            let index = if pos < 0 { pos } else { end as i32 - 1 };
+            return Err(VmError::ArrayIndexOutOfBounds { index, len });
+        }
+    }
+
+    let values = (0..length as usize)
+        .map(|i| {
+            src_array
+                .load(src_pos as usize + i)
+                .expect("the range was checked")
+        })
+        .collect::<Vec<_>>();
+
+    // the elements of reference arrays are only checked if the array types are not compatible
+    let component =
+        if matches!(dest_array, Array::Reference(_)) && !vm.is_assignable(src_class, dest_class) {
+            let name = component_class_name(&vm.class(dest_class).name).to_string();
+            Some(vm.resolve_class(&name)?)
+        } else {
+            None
+        };
+
+    for (i, value) in values.into_iter().enumerate() {
+        if let (Some(component), Value::Reference(Some(obj))) = (component, value) {
+            let class = vm.heap.get(obj).class;
+            if !vm.is_assignable(class, component) {
+                return Err(VmError::ArrayStore(format!(
+                    "arraycopy: element type mismatch: can not store {} into {}",
+                    vm.class(class).name.replace('/', "."),
+                    vm.class(dest_class).name.replace('/', ".")
+                )));
+            }
+        }
+        vm.array_mut(dest)?
+            .store(dest_pos as usize + i, value)
+            .expect("the array types and range were checked");
+    }
+    Ok(None)
+}
@@ -0,0 +1,116 @@
+//!
+//! Conversion between Rust strings and `java/lang/String` objects
+//!
+//! Strings have the layout of the JDK: a `byte[] value` with a `coder`, which is `LATIN1`
+//! if all chars fit into one byte, and `UTF16` with two bytes per char in little endian order otherwise.
+//!
+//! String literals are interned, so every `ldc` of the same contents results in the same object,
+//! see JVMS §5.1. The table is keyed by the UTF-16 chars, which keeps unpaired surrogates apart
+//!
+
+use crate::heap::{Array, ObjRef};
+use crate::model::Value;
+use crate::{Result, Vm, VmError};
+use cs_model::FieldType;
+
+const LATIN1: i32 = 0;
+const UTF16: i32 = 1;
+
+impl Vm {
+    /// Allocates a `java/lang/String` with the contents
+    pub fn new_string(&mut self, contents: &str) -> Result<ObjRef> {
+        self.new_string_from_chars(&contents.encode_utf16().collect::<Vec<_>>())
+    }
+
+    /// The interned `java/lang/String` with the contents, which is allocated if there is none yet
+    pub fn intern_string(&mut self, contents: &str) -> Result<ObjRef> {
+        let chars = contents.encode_utf16().collect::<Vec<_>>();
+        if let Some(&string) = self.strings.get(&chars) {
+            return Ok(string);
+        }
+        let string = self.new_string_from_chars(&chars)?;
+        self.strings.insert(chars, string);
+        Ok(string)
+    }
+
+    /// `String.intern`: the interned string with the same contents, which is the string itself
+    /// if there was none before
+    pub fn intern(&mut self, string: ObjRef) -> Result<ObjRef> {
+        let chars = self.string_chars(string)?;
+        Ok(*self.strings.entry(chars).or_insert(string))
+    }
+
+    pub(crate) fn new_string_from_chars(&mut self, chars: &[u16]) -> Result<ObjRef> {
+        let (coder, bytes) = if chars.iter().all(|&c| c <= 0xFF) {
+            (
+                LATIN1,
+                chars.iter().map(|&c| c as u8 as i8).collect::<Vec<_>>(),
+            )
+        } else {
+            let bytes = chars.iter().flat_map(|c| c.to_le_bytes()).map(|b| b as i8);
+            (UTF16, bytes.collect())
+        };
+
+        let string = self.resolve_class("java/lang/String")?;
+        self.initialize(string)?;
+        let (value_slot, coder_slot) = self.string_slots()?;
+
+        let byte_array = self.resolve_class("[B")?;
+        let value = self.new_array(byte_array, bytes.len() as i32)?;
+        if let Array::Byte(elements) = self.array_mut(value)? {
+            elements.copy_from_slice(&bytes);
+        }
+        self.handles.push(value);
+        let obj = self.new_object(string);
+        self.handles.pop();
+        let obj = obj?;
+
+        self.put_field(obj, value_slot, Value::Reference(Some(value)))?;
+        self.put_field(obj, coder_slot, Value::Int(coder))?;
+        Ok(obj)
+    }
+
+    /// The contents of a `java/lang/String` object
+    pub fn string_value(&self, string: ObjRef) -> Result<String> {
+        Ok(String::from_utf16_lossy(&self.string_chars(string)?))
+    }
+
+    /// The UTF-16 chars of a `java/lang/String` object
+    pub(crate) fn string_chars(&self, string: ObjRef) -> Result<Vec<u16>> {
+        let (value_slot, coder_slot) = self.string_slots()?;
+        let value = self
+            .get_field(string, value_slot)?
+            .as_reference()?
+            .ok_or(VmError::NullPointer)?;
+        let coder = self.get_field(string, coder_slot)?.as_int()?;
+
+        let bytes = match self.array(value)? {
+            Array::Byte(bytes) => bytes,
+            _ => return Err(VmError::Verify("String.value is not a byte[]".to_string())),
+        };
+        match coder {
+            LATIN1 => Ok(bytes.iter().map(|&b| b as u8 as u16).collect()),
+            UTF16 => Ok(bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0] as u8, c[1] as u8]))
+                .collect()),
+            _ => Err(VmError::Verify(format!("Invalid String coder {}", coder))),
+        }
+    }
+
+    /// The slots of the `value` and `coder` fields of `java/lang/String`
+    fn string_slots(&self) -> Result<(usize, usize)> {
+        let string = self
+            .loaded_class("java/lang/String")
+            .ok_or_else(|| VmError::ClassNotFound("java/lang/String".to_string()))?;
+        let class = self.class(string);
+        let value = FieldType::Array(Box::new(FieldType::Byte));
+        match (
+            class.instance_field_slot("value", &value),
+            class.instance_field_slot("coder", &FieldType::Byte),
+        ) {
+            (Some(value), Some(coder)) => Ok((value, coder)),
+            _ => Err(VmError::NoSuchField("java/lang/String.value".to_string())),
+        }
+    }
+}
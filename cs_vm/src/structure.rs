@@ -0,0 +1,366 @@
+//!
+//! Structured control flow recovered from the branches of a method
+//!
+//! WebAssembly has no `goto`, only blocks and loops that code branches out of. The basic blocks
+//! of a method are placed by their dominator tree, like in "Beyond Relooper" (Ramsey, 2022): a
+//! loop header starts a `loop` that its back edges branch to, a block with several forward
+//! predecessors follows a `block` that they branch out of, and every other block is placed where
+//! its only predecessor branches to it. This works for the reducible control flow of javac
+//!
+
+use crate::class::Code;
+use crate::instruction::{Instruction, Switch};
+use crate::opcode::*;
+use crate::{Result, VmError};
+use cs_parser::u1;
+
+/// A structured construct, for the instructions of the basic blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Node {
+    /// The instructions of the basic block from `start` to `end`. The last one returns, or
+    /// branches to the targets of the nodes that follow
+    Code {
+        start: usize,
+        end: usize,
+    },
+    Block(Vec<Node>),
+    Loop(Vec<Node>),
+    /// Takes the conditional branch that ends the code before if the condition is true
+    If {
+        then: Vec<Node>,
+        else_: Vec<Node>,
+    },
+    /// The nodes of the switch that ends the code before, by `successors`
+    Switch(Vec<Vec<Node>>),
+    /// Branches out of the enclosing constructs, 0 is the innermost
+    Br(u32),
+}
+
+/// Whether the instruction ends a basic block
+pub(crate) fn is_terminator(opcode: u1) -> bool {
+    matches!(
+        opcode,
+        IFEQ..=RETURN | ATHROW | IFNULL | IFNONNULL | GOTO_W | JSR_W
+    )
+}
+
+/// Whether the instruction is a conditional branch
+pub(crate) fn is_conditional(opcode: u1) -> bool {
+    matches!(opcode, IFEQ..=IF_ACMPNE | IFNULL | IFNONNULL)
+}
+
+/// The pcs the code continues at after the instruction, the target of a conditional branch
+/// before the next instruction. The targets of a switch are distinct, the default first
+pub(crate) fn successors(code: &Code, instruction: Instruction) -> Vec<usize> {
+    let next = instruction.next as usize;
+    match instruction.opcode {
+        opcode if is_conditional(opcode) => vec![instruction.a as usize, next],
+        GOTO | GOTO_W => vec![instruction.a as usize],
+        TABLESWITCH | LOOKUPSWITCH => {
+            let (default, targets) = match &code.switches[instruction.a as usize] {
+                Switch::Table {
+                    targets, default, ..
+                } => (*default, targets.clone()),
+                Switch::Lookup { pairs, default } => {
+                    (*default, pairs.iter().map(|&(_, target)| target).collect())
+                }
+            };
+            let mut successors = vec![default];
+            for target in targets {
+                if !successors.contains(&target) {
+                    successors.push(target);
+                }
+            }
+            successors
+        }
+        IRETURN..=RETURN | ATHROW | JSR | RET | JSR_W => Vec::new(),
+        _ => vec![next],
+    }
+}
+
+/// The label a branch out of a construct goes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Label {
+    /// The start of the loop with this header
+    Loop(usize),
+    /// The end of the block that this block follows
+    Follow(usize),
+    /// The end of an `if` or a switch case
+    Other,
+}
+
+/// The basic blocks of a method with their dominator tree
+struct Graph {
+    /// The pcs of the blocks, from the first instruction to the end of the last one
+    ranges: Vec<(usize, usize)>,
+    /// The opcode of the last instruction of every block
+    last: Vec<u1>,
+    successors: Vec<Vec<usize>>,
+    /// The reverse postorder number of every block, `None` if it is unreachable
+    order: Vec<Option<usize>>,
+    /// The blocks in reverse postorder
+    reverse_postorder: Vec<usize>,
+    dominators: Vec<usize>,
+    loop_headers: Vec<bool>,
+    merges: Vec<bool>,
+    /// The blocks immediately dominated by every block
+    children: Vec<Vec<usize>>,
+}
+
+/// Arranges the basic blocks of the code into structured constructs
+pub(crate) fn structure(code: &Code) -> Result<Vec<Node>> {
+    let graph = Graph::new(code)?;
+    let mut context = Vec::new();
+    Ok(graph.tree(0, &mut context))
+}
+
+impl Graph {
+    fn new(code: &Code) -> Result<Self> {
+        let mut leaders = vec![false; code.code.len() + 1];
+        leaders[0] = true;
+        let mut pc = 0;
+        while let Some(instruction) = code.instruction(pc) {
+            if matches!(instruction.opcode, JSR | RET | JSR_W) {
+                return Err(VmError::Unsupported("subroutines".to_string()));
+            }
+            if is_terminator(instruction.opcode) {
+                leaders[instruction.next as usize] = true;
+                for target in successors(code, instruction) {
+                    *leaders.get_mut(target).ok_or_else(invalid_branch)? = true;
+                }
+            }
+            pc = instruction.next as usize;
+        }
+        let starts = (0..code.code.len())
+            .filter(|&pc| leaders[pc])
+            .collect::<Vec<_>>();
+        let block_at = |pc: usize| starts.binary_search(&pc).map_err(|_| invalid_branch());
+
+        let mut ranges = Vec::new();
+        let mut last = Vec::new();
+        let mut successor_blocks = Vec::new();
+        for (index, &start) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).copied().unwrap_or(code.code.len());
+            let mut pc = start;
+            let mut instruction = code.instruction(pc).ok_or_else(invalid_branch)?;
+            while (instruction.next as usize) < end {
+                pc = instruction.next as usize;
+                instruction = code.instruction(pc).ok_or_else(invalid_branch)?;
+            }
+            let successors = match is_terminator(instruction.opcode) {
+                true => successors(code, instruction),
+                // falling off the end of the code is caught here
+                false => vec![end],
+            };
+            ranges.push((start, end));
+            last.push(instruction.opcode);
+            successor_blocks.push(
+                (successors.into_iter())
+                    .map(block_at)
+                    .collect::<Result<Vec<_>>>()?,
+            );
+        }
+
+        let mut graph = Graph {
+            order: vec![None; ranges.len()],
+            reverse_postorder: Vec::new(),
+            dominators: vec![0; ranges.len()],
+            loop_headers: vec![false; ranges.len()],
+            merges: vec![false; ranges.len()],
+            children: vec![Vec::new(); ranges.len()],
+            ranges,
+            last,
+            successors: successor_blocks,
+        };
+        graph.order();
+        graph.dominate();
+        graph.classify()?;
+        Ok(graph)
+    }
+
+    /// Numbers the reachable blocks in reverse postorder
+    fn order(&mut self) {
+        let mut visited = vec![false; self.ranges.len()];
+        let mut postorder = Vec::new();
+        // the blocks on the path from the entry with the index of their next successor
+        let mut path = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = path.last_mut() {
+            let block = *block;
+            match self.successors[block].get(*next) {
+                Some(&successor) => {
+                    *next += 1;
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        path.push((successor, 0));
+                    }
+                }
+                None => {
+                    postorder.push(block);
+                    path.pop();
+                }
+            }
+        }
+        postorder.reverse();
+        for (number, &block) in postorder.iter().enumerate() {
+            self.order[block] = Some(number);
+        }
+        self.reverse_postorder = postorder;
+    }
+
+    fn number(&self, block: usize) -> usize {
+        self.order[block].expect("the block is reachable")
+    }
+
+    /// The immediate dominators, see "A Simple, Fast Dominance Algorithm" (Cooper et al.)
+    fn dominate(&mut self) {
+        let mut predecessors = vec![Vec::new(); self.ranges.len()];
+        for &block in &self.reverse_postorder {
+            for &successor in &self.successors[block] {
+                predecessors[successor].push(block);
+            }
+        }
+        let mut dominators = vec![None; self.ranges.len()];
+        dominators[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &self.reverse_postorder[1..] {
+                let mut processed = predecessors[block]
+                    .iter()
+                    .filter(|&&predecessor| dominators[predecessor].is_some());
+                let first = *processed.next().expect("a predecessor comes first");
+                let dominator = processed.fold(first, |mut a, &b| {
+                    let mut b = b;
+                    while a != b {
+                        while self.number(a) > self.number(b) {
+                            a = dominators[a].expect("processed");
+                        }
+                        while self.number(b) > self.number(a) {
+                            b = dominators[b].expect("processed");
+                        }
+                    }
+                    a
+                });
+                if dominators[block] != Some(dominator) {
+                    dominators[block] = Some(dominator);
+                    changed = true;
+                }
+            }
+        }
+        for &block in &self.reverse_postorder {
+            self.dominators[block] = dominators[block].expect("reachable");
+        }
+    }
+
+    fn dominates(&self, dominator: usize, mut block: usize) -> bool {
+        loop {
+            if block == dominator {
+                return true;
+            }
+            if block == 0 {
+                return false;
+            }
+            block = self.dominators[block];
+        }
+    }
+
+    /// Finds the loop headers, the merge blocks and the dominator tree
+    fn classify(&mut self) -> Result<()> {
+        let mut forward_edges = vec![0; self.ranges.len()];
+        for &block in &self.reverse_postorder {
+            for &successor in &self.successors[block] {
+                if self.number(successor) > self.number(block) {
+                    forward_edges[successor] += 1;
+                } else if self.dominates(successor, block) {
+                    self.loop_headers[successor] = true;
+                } else {
+                    return Err(VmError::Unsupported("irreducible control flow".to_string()));
+                }
+            }
+        }
+        for &block in &self.reverse_postorder[1..] {
+            self.merges[block] = forward_edges[block] > 1;
+            self.children[self.dominators[block]].push(block);
+        }
+        Ok(())
+    }
+
+    /// The constructs of the block and the blocks it dominates
+    fn tree(&self, block: usize, context: &mut Vec<Label>) -> Vec<Node> {
+        let mut merges = (self.children[block].iter().copied())
+            .filter(|&child| self.merges[child])
+            .collect::<Vec<_>>();
+        // the block that is followed last encloses the others
+        merges.sort_by_key(|&child| std::cmp::Reverse(self.number(child)));
+        if self.loop_headers[block] {
+            context.push(Label::Loop(block));
+            let body = self.within(block, &merges, context);
+            context.pop();
+            vec![Node::Loop(body)]
+        } else {
+            self.within(block, &merges, context)
+        }
+    }
+
+    /// The code of the block inside blocks that the merge blocks it dominates follow
+    fn within(&self, block: usize, merges: &[usize], context: &mut Vec<Label>) -> Vec<Node> {
+        if let Some((&merge, inner)) = merges.split_first() {
+            context.push(Label::Follow(merge));
+            let mut nodes = vec![Node::Block(self.within(block, inner, context))];
+            context.pop();
+            nodes.extend(self.tree(merge, context));
+            return nodes;
+        }
+
+        let (start, end) = self.ranges[block];
+        let mut nodes = vec![Node::Code { start, end }];
+        let successors = &self.successors[block];
+        match self.last[block] {
+            opcode if is_conditional(opcode) => {
+                context.push(Label::Other);
+                let then = self.branch(block, successors[0], context);
+                let else_ = self.branch(block, successors[1], context);
+                context.pop();
+                nodes.push(Node::If { then, else_ });
+            }
+            TABLESWITCH | LOOKUPSWITCH => {
+                let cases = successors.len();
+                let cases = (successors.iter().enumerate())
+                    .map(|(case, &successor)| {
+                        let enclosing = cases - 1 - case;
+                        context.extend(std::iter::repeat_n(Label::Other, enclosing));
+                        let nodes = self.branch(block, successor, context);
+                        context.truncate(context.len() - enclosing);
+                        nodes
+                    })
+                    .collect();
+                nodes.push(Node::Switch(cases));
+            }
+            _ => {
+                if let Some(&successor) = successors.first() {
+                    nodes.extend(self.branch(block, successor, context));
+                }
+            }
+        }
+        nodes
+    }
+
+    fn branch(&self, from: usize, to: usize, context: &mut Vec<Label>) -> Vec<Node> {
+        let label = if self.number(to) <= self.number(from) {
+            Label::Loop(to)
+        } else if self.merges[to] {
+            Label::Follow(to)
+        } else {
+            return self.tree(to, context);
+        };
+        let depth = (context.iter().rev())
+            .position(|&enclosing| enclosing == label)
+            .expect("the target encloses the branch");
+        vec![Node::Br(depth as u32)]
+    }
+}
+
+fn invalid_branch() -> VmError {
+    VmError::Verify("Branch into the middle of an instruction or out of the code".to_string())
+}
@@ -0,0 +1,1719 @@
+use super::*;
+use cs_model::FieldType;
+use cs_parser::CpInfoInner;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+fn test_vm() -> Vm {
+    test_vm_with(VmOptions::default())
+}
+
+fn test_vm_with(options: VmOptions) -> Vm {
+    let mut vm = Vm::with_options(options);
+    vm.add_class_path(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata"));
+    vm.thread_mut().frames.push(test_frame());
+    vm
+}
+
+/// A frame to execute single instructions in, it does not belong to a real method
+fn test_frame() -> Frame {
+    let code = Code::new(16, 16, Vec::new(), Vec::new(), Vec::new());
+    let method = MethodId {
+        class: ClassId(0),
+        index: 0,
+    };
+    Frame::new(method, Rc::new(code))
+}
+
+/// Finds the index of the `Fieldref` with the name in the constant pool of the class
+fn field_ref_index(vm: &Vm, class: ClassId, name: &str) -> u16 {
+    let class = vm.class(class);
+    (1..=class.constant_pool.len() as u16)
+        .find(|&i| {
+            matches!(class.cp_entry(i), Ok(CpInfoInner::Fieldref(_)))
+                && class.cp_field_ref(i).unwrap().name == name
+        })
+        .unwrap()
+}
+
+#[test]
+fn object_layout() {
+    let mut vm = test_vm();
+    let point3 = vm.resolve_class("Point3").unwrap();
+    let point = vm.loaded_class("Point").unwrap();
+
+    let class = vm.class(point3);
+    let fields = class
+        .instance_fields
+        .iter()
+        .map(|field| (field.name.as_str(), field.descriptor.clone(), field.class))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        fields,
+        vec![
+            ("x", FieldType::Int, point),
+            ("y", FieldType::Long, point),
+            ("next", FieldType::Object("Point".to_string()), point),
+            ("z", FieldType::Double, point3),
+            ("flag", FieldType::Byte, point3),
+            ("x", FieldType::Int, point3),
+        ]
+    );
+    assert_eq!(class.instance_field_slot("x", &FieldType::Int), Some(5));
+    assert_eq!(vm.class(point).static_fields[0].name, "count");
+    assert_eq!(vm.class(point).static_values, vec![Value::Int(0)]);
+}
+
+#[test]
+fn new_object_has_default_values() {
+    let mut vm = test_vm();
+    let point3 = vm.resolve_class("Point3").unwrap();
+    let obj = vm.new_object(point3).unwrap();
+
+    let fields = (0..6)
+        .map(|slot| vm.get_field(obj, slot).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fields,
+        vec![
+            Value::Int(0),
+            Value::Long(0),
+            Value::NULL,
+            Value::Double(0.0),
+            Value::Int(0),
+            Value::Int(0),
+        ]
+    );
+}
+
+#[test]
+fn getfield_putfield() {
+    let mut vm = test_vm();
+    let point3 = vm.resolve_class("Point3").unwrap();
+    let obj = vm.new_object(point3).unwrap();
+
+    let y = field_ref_index(&vm, point3, "y");
+    vm.push(Value::Reference(Some(obj))).unwrap();
+    vm.push(Value::Long(-7)).unwrap();
+    let method = MethodId {
+        class: point3,
+        index: 0,
+    };
+    vm.op_putfield(method, y).unwrap();
+    assert_eq!(vm.get_field(obj, 1).unwrap(), Value::Long(-7));
+
+    vm.push(Value::Reference(Some(obj))).unwrap();
+    vm.op_getfield(point3, y).unwrap();
+    assert_eq!(vm.pop().unwrap(), Value::Long(-7));
+
+    // `Point3.x` shadows `Point.x`
+    vm.put_field(obj, 0, Value::Int(1)).unwrap();
+    vm.put_field(obj, 5, Value::Int(3)).unwrap();
+    let x_indices = (1..=vm.class(point3).constant_pool.len() as u16)
+        .filter(|&i| {
+            matches!(vm.class(point3).cp_entry(i), Ok(CpInfoInner::Fieldref(_)))
+                && vm.class(point3).cp_field_ref(i).unwrap().name == "x"
+        })
+        .collect::<Vec<_>>();
+    let values = x_indices
+        .iter()
+        .map(|&index| {
+            let class = vm
+                .class(point3)
+                .cp_field_ref(index)
+                .unwrap()
+                .class
+                .to_string();
+            vm.push(Value::Reference(Some(obj))).unwrap();
+            vm.op_getfield(point3, index).unwrap();
+            (class, vm.pop().unwrap())
+        })
+        .collect::<Vec<_>>();
+    assert!(values.contains(&("Point".to_string(), Value::Int(1))));
+    assert!(values.contains(&("Point3".to_string(), Value::Int(3))));
+
+    vm.push(Value::NULL).unwrap();
+    assert_eq!(vm.op_getfield(point3, y), Err(VmError::NullPointer));
+}
+
+#[test]
+fn primitive_arrays() {
+    let mut vm = test_vm();
+
+    for atype in 4..=11 {
+        vm.push(Value::Int(3)).unwrap();
+        vm.op_newarray(atype).unwrap();
+        let array = vm.pop().unwrap().as_reference().unwrap().unwrap();
+        let ty = PrimitiveArrayType::from_atype(atype).unwrap();
+        assert_eq!(vm.array(array).unwrap(), &Array::new(ty, 3));
+        assert_eq!(
+            vm.class(vm.heap.get(array).class).name,
+            format!("[{}", ty.descriptor())
+        );
+    }
+
+    vm.push(Value::Int(-1)).unwrap();
+    assert_eq!(vm.op_newarray(10), Err(VmError::NegativeArraySize(-1)));
+
+    let mut bytes = Array::new(PrimitiveArrayType::Byte, 1);
+    bytes.store(0, Value::Int(0x1ff)).unwrap();
+    assert_eq!(bytes.load(0), Some(Value::Int(-1)));
+    let mut chars = Array::new(PrimitiveArrayType::Char, 1);
+    chars.store(0, Value::Int(-1)).unwrap();
+    assert_eq!(chars.load(0), Some(Value::Int(0xffff)));
+    assert_eq!(chars.load(1), None);
+}
+
+#[test]
+fn reference_arrays() {
+    let mut vm = test_vm();
+    let point3 = vm.resolve_class("Point3").unwrap();
+    let array_class = vm.resolve_class("[LPoint;").unwrap();
+    let array = vm.new_array(array_class, 2).unwrap();
+    let obj = vm.new_object(point3).unwrap();
+
+    vm.array_mut(array)
+        .unwrap()
+        .store(1, Value::Reference(Some(obj)))
+        .unwrap();
+    assert_eq!(
+        vm.array(array).unwrap(),
+        &Array::Reference(vec![None, Some(obj)].into())
+    );
+    assert_eq!(
+        vm.class(array_class).kind,
+        ClassKind::Array(FieldType::Object("Point".to_string()))
+    );
+}
+
+#[test]
+fn multi_arrays() {
+    let mut vm = test_vm();
+    let class = vm.resolve_class("[[[I").unwrap();
+
+    let array = vm.new_multi_array(class, &[2, 3]).unwrap();
+    let Array::Reference(outer) = vm.array(array).unwrap().clone() else {
+        panic!("outer array is not a reference array");
+    };
+    assert_eq!(outer.len(), 2);
+    for inner in outer.iter() {
+        let inner = inner.unwrap();
+        assert_eq!(vm.class(vm.heap.get(inner).class).name, "[[I");
+        assert_eq!(
+            vm.array(inner).unwrap(),
+            &Array::Reference(vec![None; 3].into())
+        );
+    }
+
+    let objects = vm.heap.len();
+    assert_eq!(
+        vm.new_multi_array(class, &[0, -1]),
+        Err(VmError::NegativeArraySize(-1))
+    );
+    assert_eq!(vm.heap.len(), objects);
+}
+
+#[test]
+fn gc_collects_unreachable_objects() {
+    let mut vm = test_vm();
+    let point = vm.resolve_class("Point").unwrap();
+    let new = |vm: &mut Vm| vm.new_object(point).unwrap();
+
+    let on_stack = new(&mut vm);
+    let in_local = new(&mut vm);
+    let in_static = new(&mut vm);
+    let in_field = new(&mut vm);
+    let in_other_thread = new(&mut vm);
+    let garbage = new(&mut vm);
+    let cycle_a = new(&mut vm);
+    let cycle_b = new(&mut vm);
+
+    vm.push(Value::Reference(Some(on_stack))).unwrap();
+    vm.frame_mut()
+        .locals
+        .store(0, Value::Reference(Some(in_local)))
+        .unwrap();
+    vm.class_mut(point).static_values[0] = Value::Reference(Some(in_static));
+    vm.put_field(in_local, 2, Value::Reference(Some(in_field)))
+        .unwrap();
+    let mut other = Thread::new();
+    other.frames.push(test_frame());
+    other
+        .frames
+        .last_mut()
+        .unwrap()
+        .stack
+        .push(Value::Reference(Some(in_other_thread)))
+        .unwrap();
+    vm.threads.push(other);
+    vm.put_field(garbage, 2, Value::Reference(Some(on_stack)))
+        .unwrap();
+    vm.put_field(cycle_a, 2, Value::Reference(Some(cycle_b)))
+        .unwrap();
+    vm.put_field(cycle_b, 2, Value::Reference(Some(cycle_a)))
+        .unwrap();
+
+    assert_eq!(vm.heap.len(), 8);
+    let used = vm.heap.used();
+    assert_eq!(vm.gc(), 3);
+    assert_eq!(vm.heap.len(), 5);
+    assert_eq!(vm.heap.used(), used / 8 * 5);
+    for obj in [on_stack, in_local, in_static, in_field, in_other_thread] {
+        assert_eq!(vm.heap.get(obj).class, point);
+    }
+
+    // references in arrays keep their elements alive
+    let array_class = vm.resolve_class("[LPoint;").unwrap();
+    let array = vm.new_array(array_class, 1).unwrap();
+    let element = vm.new_object(point).unwrap();
+    vm.array_mut(array)
+        .unwrap()
+        .store(0, Value::Reference(Some(element)))
+        .unwrap();
+    vm.put_field(in_field, 2, Value::Reference(Some(array)))
+        .unwrap();
+    vm.new_object(point).unwrap();
+    assert_eq!(vm.gc(), 1);
+    assert_eq!(vm.heap.len(), 7);
+    assert_eq!(vm.heap.get(element).class, point);
+}
+
+#[test]
+fn out_of_memory() {
+    let mut vm = test_vm_with(VmOptions {
+        max_heap: 1000,
+        ..VmOptions::default()
+    });
+    let class = vm.resolve_class("[I").unwrap();
+
+    // garbage is collected when the heap is full
+    for _ in 0..100 {
+        vm.new_array(class, 10).unwrap();
+    }
+    assert!(vm.heap.used() <= 1000);
+
+    let mut kept = Vec::new();
+    let err = loop {
+        match vm.new_array(class, 10) {
+            Ok(array) => {
+                kept.push(array);
+                vm.handles.push(array);
+            }
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(err, VmError::OutOfMemory);
+    assert_eq!(kept.len(), 1000 / (16 + 40));
+
+    vm.handles.clear();
+    vm.new_array(class, 10).unwrap();
+    assert_eq!(vm.heap.len(), 1);
+}
+
+#[test]
+fn gc_stress() {
+    let mut vm = test_vm_with(VmOptions {
+        gc_stress: true,
+        ..VmOptions::default()
+    });
+    let point = vm.resolve_class("Point").unwrap();
+
+    let kept = vm.new_object(point).unwrap();
+    vm.push(Value::Reference(Some(kept))).unwrap();
+    for _ in 0..10 {
+        vm.new_object(point).unwrap();
+        assert!(vm.heap.len() <= 2);
+    }
+
+    // the outer arrays must survive the allocation of their elements
+    let class = vm.resolve_class("[[[J").unwrap();
+    let array = vm.new_multi_array(class, &[3, 2, 4]).unwrap();
+    vm.push(Value::Reference(Some(array))).unwrap();
+    vm.gc();
+    assert_eq!(vm.heap.len(), 1 + 1 + 3 + 3 * 2);
+    let Array::Reference(outer) = vm.array(array).unwrap().clone() else {
+        panic!("outer array is not a reference array");
+    };
+    for middle in outer.iter() {
+        let Array::Reference(inner) = vm.array(middle.unwrap()).unwrap().clone() else {
+            panic!("middle array is not a reference array");
+        };
+        for inner in inner.iter() {
+            assert_eq!(
+                vm.array(inner.unwrap()).unwrap(),
+                &Array::Long(vec![0; 4].into())
+            );
+        }
+    }
+}
+
+/// Invokes a static method of the class in the test data
+fn call(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Value> {
+    let class = vm.resolve_class(class)?;
+    let method = vm.lookup_method(class, name, descriptor).unwrap();
+    vm.invoke(method, args).map(|value| value.unwrap())
+}
+
+/// The name of the class of the exception that a call threw
+fn thrown(vm: &Vm, result: Result<Value>) -> String {
+    match result {
+        Err(VmError::Exception(exception)) => vm.class(vm.heap.get(exception).class).name.clone(),
+        other => panic!("expected an exception, got {:?}", other),
+    }
+}
+
+#[test]
+fn invoke_static() {
+    let mut vm = test_vm();
+
+    let fib = call(&mut vm, "Calls", "fib", "(I)I", &[Value::Int(15)]);
+    assert_eq!(fib, Ok(Value::Int(610)));
+    let loop_sum = call(&mut vm, "Calls", "loop", "(I)I", &[Value::Int(100)]);
+    assert_eq!(loop_sum, Ok(Value::Int(4950)));
+
+    // long and double take two slots, `this` is not passed
+    let args = [
+        Value::Int(1),
+        Value::Long(2),
+        Value::Double(3.5),
+        Value::Float(4.5),
+        Value::NULL,
+        Value::Long(5),
+    ];
+    let slots = call(
+        &mut vm,
+        "Calls",
+        "slots",
+        "(IJDFLjava/lang/Object;J)J",
+        &args,
+    );
+    assert_eq!(slots, Ok(Value::Long(16)));
+
+    // the frame of the test is still there
+    assert_eq!(vm.thread().frames.len(), 1);
+}
+
+#[test]
+fn invoke_virtual_special_interface() {
+    let mut vm = test_vm();
+
+    assert_eq!(
+        call(&mut vm, "Calls", "shapes", "()I", &[]),
+        Ok(Value::Int(18081007))
+    );
+    assert_eq!(
+        call(&mut vm, "Calls", "kinds", "()I", &[]),
+        Ok(Value::Int(53))
+    );
+
+    let calls = vm.resolve_class("Calls").unwrap();
+    let init = vm.lookup_method(calls, "<init>", "()V").unwrap();
+    let obj = vm.new_object(calls).unwrap();
+    vm.push(Value::Reference(Some(obj))).unwrap();
+    assert_eq!(vm.invoke(init, &[Value::Reference(Some(obj))]), Ok(None));
+    let get_value = vm.lookup_method(calls, "getValue", "()I").unwrap();
+    assert_eq!(
+        vm.invoke(get_value, &[Value::Reference(Some(obj))]),
+        Ok(Some(Value::Int(7)))
+    );
+
+    let base = vm.resolve_class("Base").unwrap();
+    let side = vm.lookup_method(base, "side", "()I").unwrap();
+    assert_eq!(
+        vm.invoke(side, &[Value::Reference(Some(obj))]),
+        Err(VmError::AbstractMethod("Base.side()I".to_string()))
+    );
+}
+
+#[test]
+fn instructions() {
+    let mut vm = test_vm();
+
+    let switches = [0, 1, 2, 1000, -5]
+        .map(|key| call(&mut vm, "Calls", "switches", "(I)I", &[Value::Int(key)]));
+    assert_eq!(
+        switches,
+        [104, 114, 122, 193, 194].map(|result| Ok(Value::Int(result)))
+    );
+    assert_eq!(
+        call(
+            &mut vm,
+            "Calls",
+            "math",
+            "(JI)D",
+            &[Value::Long(-7), Value::Int(33)]
+        ),
+        Ok(Value::Double(-2147418113.5))
+    );
+    assert_eq!(
+        call(&mut vm, "Calls", "arrays", "()I", &[]),
+        Ok(Value::Int(118))
+    );
+    let divide = call(
+        &mut vm,
+        "Calls",
+        "divide",
+        "(II)I",
+        &[Value::Int(1), Value::Int(0)],
+    );
+    assert_eq!(thrown(&vm, divide), "java/lang/ArithmeticException");
+    let store = call(&mut vm, "Calls", "storeWrongType", "()V", &[]);
+    assert_eq!(thrown(&vm, store), "java/lang/ArrayStoreException");
+
+    let circle = vm.resolve_class("Circle").unwrap();
+    let circle = vm.new_object(circle).unwrap();
+    let cast = call(
+        &mut vm,
+        "Calls",
+        "cast",
+        "(Ljava/lang/Object;)Ljava/lang/Object;",
+        &[Value::Reference(Some(circle))],
+    );
+    assert_eq!(thrown(&vm, cast), "java/lang/ClassCastException");
+    assert_eq!(vm.thread().frames.len(), 1);
+}
+
+#[test]
+fn quickening() {
+    let mut vm = test_vm();
+    // the second call runs the quickened instructions
+    for _ in 0..2 {
+        assert_eq!(
+            call(&mut vm, "Calls", "shapes", "()I", &[]),
+            Ok(Value::Int(18081007))
+        );
+    }
+
+    let calls = vm.resolve_class("Calls").unwrap();
+    let shapes = vm.lookup_method(calls, "shapes", "()I").unwrap();
+    let code = vm.method(shapes).code.clone().unwrap();
+    let opcode_at = |pc| code.instruction(pc).map(|instruction| instruction.opcode);
+    assert_eq!(opcode_at(1), Some(opcode::ANEWARRAY_QUICK));
+    assert_eq!(opcode_at(6), Some(opcode::NEW_QUICK));
+    assert_eq!(opcode_at(11), Some(opcode::INVOKENONVIRTUAL_QUICK));
+    // `iload 4` and `iload_3` decode to the same instruction
+    assert_eq!(opcode_at(45), Some(opcode::ISTORE));
+    assert_eq!(opcode_at(49), Some(opcode::ILOAD));
+    assert_eq!(opcode_at(50), Some(opcode::IF_ICMPGE));
+    assert_eq!(code.instruction(50).unwrap().a, 79);
+    assert_eq!(opcode_at(46), None);
+    // the bytes stay as they are
+    assert_eq!(code.code[1], opcode::ANEWARRAY);
+}
+
+#[test]
+fn interpret_bytes() {
+    let mut vm = test_vm_with(VmOptions {
+        interpret: InterpretMode::Bytes,
+        jit: JitMode::Disabled,
+        ..VmOptions::default()
+    });
+    for _ in 0..2 {
+        assert_eq!(
+            call(&mut vm, "Calls", "shapes", "()I", &[]),
+            Ok(Value::Int(18081007))
+        );
+    }
+    let switches = [0, 1, 2, 1000, -5]
+        .map(|key| call(&mut vm, "Calls", "switches", "(I)I", &[Value::Int(key)]));
+    assert_eq!(
+        switches,
+        [104, 114, 122, 193, 194].map(|result| Ok(Value::Int(result)))
+    );
+
+    vm.thread_mut().frames.clear();
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+    vm.run_main("Hot", &[]).unwrap();
+    assert_eq!(out.text(), HOT_OUT);
+}
+
+#[test]
+fn inline_caches() {
+    let mut vm = test_vm();
+    let cache = |vm: &Vm, caller: &str| {
+        let caches = vm.inline_caches().iter();
+        caches
+            .filter(|cache| vm.method_name(cache.caller) == caller)
+            .map(|cache| (cache.state.clone(), cache.hits, cache.misses))
+            .collect::<Vec<_>>()
+    };
+    let count = |vm: &mut Vm, skip| {
+        let args = [Value::Int(10), Value::Int(skip)];
+        call(vm, "Caches", "count", "(IZ)I", &args)
+    };
+    let caches = vm.resolve_class("Caches").unwrap();
+    let counter = vm.resolve_class("Caches$Counter").unwrap();
+    let next = vm.lookup_method(counter, "next", "()I").unwrap();
+
+    // no subclass of `Counter` is loaded yet
+    assert_eq!(count(&mut vm, 0), Ok(Value::Int(55)));
+    let unique = CacheState::Unique(next);
+    assert_eq!(cache(&vm, "Caches.count(IZ)I"), [(unique, 10, 0)]);
+
+    assert_eq!(count(&mut vm, 1), Ok(Value::Int(550)));
+    let anonymous = vm.loaded_class("Caches$1").unwrap();
+    let overriding = vm.lookup_method(anonymous, "next", "()I").unwrap();
+    let monomorphic = CacheState::Receivers(vec![(anonymous, overriding)]);
+    assert_eq!(cache(&vm, "Caches.count(IZ)I"), [(monomorphic, 19, 1)]);
+    assert_eq!(vm.inline_caches()[0].invalidations, 1);
+
+    assert_eq!(count(&mut vm, 0), Ok(Value::Int(55)));
+    let polymorphic = CacheState::Receivers(vec![(anonymous, overriding), (counter, next)]);
+    assert_eq!(cache(&vm, "Caches.count(IZ)I"), [(polymorphic, 28, 2)]);
+
+    let sides = |vm: &mut Vm, kinds| call(vm, "Caches", "sides", "(I)I", &[Value::Int(kinds)]);
+    assert_eq!(sides(&mut vm, 1), Ok(Value::Int(300)));
+    let [(CacheState::Receivers(receivers), 99, 1)] = &cache(&vm, "Caches.sides(I)I")[..] else {
+        panic!("expected a monomorphic cache")
+    };
+    assert_eq!(receivers.len(), 1);
+    // the fifth shape does not fit into the cache anymore
+    assert_eq!(sides(&mut vm, 5), Ok(Value::Int(360)));
+    let [(CacheState::Megamorphic, hits, misses)] = cache(&vm, "Caches.sides(I)I")[..] else {
+        panic!("expected a megamorphic cache")
+    };
+    assert_eq!(hits + misses, 200);
+    assert!(misses > 20);
+
+    let sizes = call(&mut vm, "Caches", "sizes", "()I", &[]);
+    assert_eq!(sizes, Ok(Value::Int(505)));
+    let [(CacheState::Receivers(receivers), 8, 2)] = &cache(&vm, "Caches.sizes()I")[..] else {
+        panic!("expected a polymorphic cache")
+    };
+    assert_eq!(receivers.len(), 2);
+    let site = vm.inline_caches().last().unwrap();
+    assert_eq!(site.caller.class, caches);
+    assert_eq!(site.hit_rate(), 0.8);
+}
+
+#[test]
+fn static_initializer() {
+    let mut vm = test_vm();
+    let calls = vm.resolve_class("Calls").unwrap();
+    assert_eq!(vm.class(calls).init_state, InitState::Uninitialized);
+
+    vm.initialize(calls).unwrap();
+    assert_eq!(vm.class(calls).init_state, InitState::Initialized);
+    let values = vm.class(calls).static_values.clone();
+    assert_eq!(
+        values,
+        vec![Value::Long(10_000_000_000), Value::Int(42), Value::Int(43)]
+    );
+}
+
+#[test]
+fn initializer_exceptions() {
+    let mut vm = test_vm();
+    let failing = call(&mut vm, "Initializers", "failing", "()I", &[]);
+    assert_eq!(failing, Ok(Value::Int(111)));
+
+    // errors are not wrapped
+    let fatal = call(&mut vm, "Initializers", "fatal", "()I", &[]);
+    assert_eq!(thrown(&vm, fatal), "java/lang/StackOverflowError");
+    let fatal = call(&mut vm, "Initializers", "fatal", "()I", &[]);
+    assert_eq!(thrown(&vm, fatal), "java/lang/NoClassDefFoundError");
+}
+
+#[test]
+fn stack_overflow() {
+    let mut vm = test_vm_with(VmOptions {
+        max_stack_depth: 100,
+        ..VmOptions::default()
+    });
+
+    let result = call(&mut vm, "Calls", "recurse", "(I)I", &[Value::Int(0)]);
+    assert_eq!(thrown(&vm, result), "java/lang/StackOverflowError");
+    assert_eq!(vm.thread().frames.len(), 1);
+
+    // deep recursion does not use the Rust stack
+    let mut vm = test_vm_with(VmOptions {
+        max_stack_depth: 100_000,
+        ..VmOptions::default()
+    });
+    let result = call(&mut vm, "Calls", "recurse", "(I)I", &[Value::Int(0)]);
+    assert_eq!(thrown(&vm, result), "java/lang/StackOverflowError");
+}
+
+#[test]
+fn vtable_layout() {
+    let mut vm = test_vm();
+    let puppy = vm.resolve_class("Puppy").unwrap();
+    // the vtable starts with the methods of `java/lang/Object`
+    let object = vm.loaded_class("java/lang/Object").unwrap();
+    let inherited = vm.class(object).vtable.len();
+
+    let names = |vm: &Vm, methods: &[MethodId]| {
+        methods
+            .iter()
+            .map(|&method| vm.method_name(method))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(&vm, &vm.class(puppy).vtable)[inherited..],
+        [
+            "Puppy.sound()I",
+            "Animal.callSecret()I",
+            "Animal.id()I",
+            "Dog.secret()I",
+            "Runner.legs()I",
+        ]
+    );
+    let dog = vm.loaded_class("Dog").unwrap();
+    assert_eq!(
+        names(&vm, &vm.class(dog).vtable)[inherited + 3..],
+        ["Dog.secret()I", "Walker.legs()I"]
+    );
+
+    // the slots of overriding methods are the same as the ones of the overridden methods
+    let animal = vm.loaded_class("Animal").unwrap();
+    let sound = vm.lookup_method(animal, "sound", "()I").unwrap();
+    let puppy_sound = vm.lookup_method(puppy, "sound", "()I").unwrap();
+    assert_eq!(vm.method(sound).vtable_index, Some(inherited));
+    assert_eq!(vm.method(puppy_sound).vtable_index, Some(inherited));
+    let secret = vm.class(animal).method_index("secret", "()I").unwrap();
+    assert_eq!(vm.class(animal).methods[secret].vtable_index, None);
+
+    let itable = vm
+        .class(puppy)
+        .itable
+        .iter()
+        .map(|entry| {
+            (
+                vm.class(entry.interface).name.as_str(),
+                names(&vm, &entry.methods),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert!(itable.contains(&("Walker", vec!["Runner.legs()I".to_string()])));
+    assert!(itable.contains(&("Runner", vec!["Runner.legs()I".to_string()])));
+}
+
+#[test]
+fn virtual_dispatch() {
+    let mut vm = test_vm();
+
+    let results = [
+        ("overriding", 125),
+        ("privateMethods", 98),
+        ("finalMethods", 4),
+        ("defaults", 455),
+    ]
+    .map(|(name, result)| (call(&mut vm, "Dispatch", name, "()I", &[]), result));
+    for (value, expected) in results {
+        assert_eq!(value, Ok(Value::Int(expected)));
+    }
+
+    // `Dog.legs` resolves to `Walker.legs`, which `Puppy` overrides through `Runner`
+    for (class, expected) in [("Dog", 4), ("Puppy", 5)] {
+        let class = vm.resolve_class(class).unwrap();
+        let obj = vm.new_object(class).unwrap();
+        let legs = call(
+            &mut vm,
+            "Dispatch",
+            "inheritedDefault",
+            "(LDog;)I",
+            &[Value::Reference(Some(obj))],
+        );
+        assert_eq!(legs, Ok(Value::Int(expected)));
+    }
+}
+
+#[test]
+fn abstract_method_error() {
+    let mut vm = test_vm();
+
+    // `Nameless` was compiled when `Named` did not have any methods
+    let nameless = vm.resolve_class("Nameless").unwrap();
+    let obj = vm.new_object(nameless).unwrap();
+    let result = call(
+        &mut vm,
+        "Dispatch",
+        "callName",
+        "(LNamed;)I",
+        &[Value::Reference(Some(obj))],
+    );
+    assert_eq!(thrown(&vm, result), "java/lang/AbstractMethodError");
+
+    // an object that does not implement the interface at all
+    let animal = vm.resolve_class("Animal").unwrap();
+    let obj = vm.new_object(animal).unwrap();
+    let result = call(
+        &mut vm,
+        "Dispatch",
+        "callName",
+        "(LNamed;)I",
+        &[Value::Reference(Some(obj))],
+    );
+    assert_eq!(
+        thrown(&vm, result),
+        "java/lang/IncompatibleClassChangeError"
+    );
+}
+
+#[test]
+fn invalid_overrides() {
+    let mut vm = test_vm();
+
+    assert_eq!(
+        vm.resolve_class("Child"),
+        Err(VmError::Verify(
+            "Child overrides final method Parent.value()I".to_string()
+        ))
+    );
+    assert_eq!(vm.loaded_class("Child"), None);
+
+    // conflicting default methods only fail when they are invoked
+    let both = vm.resolve_class("Both").unwrap();
+    let obj = vm.new_object(both).unwrap();
+    let hash = vm.lookup_method(both, "hashCode", "()I").unwrap();
+    assert!(vm.select_method(both, hash).is_ok());
+    let result = call(
+        &mut vm,
+        "Dispatch",
+        "callSide",
+        "(LLeft;)I",
+        &[Value::Reference(Some(obj))],
+    );
+    assert_eq!(
+        thrown(&vm, result),
+        "java/lang/IncompatibleClassChangeError"
+    );
+}
+
+#[test]
+fn package_private_overrides() {
+    let mut vm = test_vm();
+    let mut call_value = |class: &str, receiver: &str| {
+        let receiver = vm.resolve_class(receiver).unwrap();
+        let obj = vm.new_object(receiver).unwrap();
+        let descriptor = format!("(L{};)I", class);
+        call(
+            &mut vm,
+            class,
+            "callValue",
+            &descriptor,
+            &[Value::Reference(Some(obj))],
+        )
+    };
+
+    // `other/Sub.value` does not override `pkg/Base.value`, `pkg/Deep.value` only overrides that
+    assert_eq!(call_value("pkg/Base", "other/Sub"), Ok(Value::Int(1)));
+    assert_eq!(call_value("other/Sub", "other/Sub"), Ok(Value::Int(2)));
+    assert_eq!(call_value("pkg/Base", "pkg/Deep"), Ok(Value::Int(3)));
+    assert_eq!(call_value("other/Sub", "pkg/Deep"), Ok(Value::Int(2)));
+}
+
+#[test]
+fn catch_exceptions() {
+    let mut vm = test_vm();
+
+    let subclass = call(&mut vm, "Exceptions", "catchSubclass", "()I", &[]);
+    assert_eq!(subclass, Ok(Value::Int(2)));
+    let finally = call(&mut vm, "Exceptions", "finallyRuns", "()I", &[]);
+    assert_eq!(finally, Ok(Value::Int(111)));
+    let callee = call(&mut vm, "Exceptions", "catchFromCallee", "()I", &[]);
+    assert_eq!(callee, Ok(Value::Int(-1)));
+
+    // exceptions that the VM throws are caught like the ones thrown by Java code
+    let int_array = vm.resolve_class("[I").unwrap();
+    let array = vm.new_array(int_array, 2).unwrap();
+    let args = [Value::Reference(Some(array)), Value::Reference(Some(array))];
+    let vm_exceptions = call(
+        &mut vm,
+        "Exceptions",
+        "vmExceptions",
+        "([ILjava/lang/Object;)I",
+        &args,
+    );
+    assert_eq!(vm_exceptions, Ok(Value::Int(1111)));
+    let rethrow = call(&mut vm, "Exceptions", "rethrow", "()I", &[]);
+    assert_eq!(thrown(&vm, rethrow), "java/lang/IllegalStateException");
+    let exceptions = vm.loaded_class("Exceptions").unwrap();
+    assert_eq!(
+        vm.class(exceptions).static_values,
+        vec![Value::Int(1), Value::NULL]
+    );
+    assert_eq!(vm.thread().frames.len(), 1);
+}
+
+#[test]
+fn stack_traces() {
+    let mut vm = Vm::new();
+    vm.add_class_path(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata"));
+
+    let uncaught = call(&mut vm, "Exceptions", "uncaught", "()V", &[]);
+    let exception = match uncaught {
+        Err(VmError::Exception(exception)) => exception,
+        other => panic!("expected an exception, got {:?}", other),
+    };
+    assert!(vm.thread().frames.is_empty());
+
+    let trace = vm.stack_trace(exception);
+    assert_eq!(
+        trace[0],
+        StackTraceElement {
+            class: "Exceptions".to_string(),
+            method: "wrap".to_string(),
+            file: Some("Exceptions.java".to_string()),
+            line: Some(89),
+            native: false,
+        }
+    );
+    assert_eq!(
+        vm.format_stack_trace(exception),
+        "java.lang.RuntimeException: java.lang.IllegalStateException
+\tat Exceptions.wrap(Exceptions.java:89)
+\tat Exceptions.uncaught(Exceptions.java:82)
+Caused by: java.lang.IllegalStateException
+\tat Exceptions.throwDeep(Exceptions.java:58)
+\tat Exceptions.throwDeep(Exceptions.java:60)
+\tat Exceptions.wrap(Exceptions.java:87)
+\t... 1 more
+"
+    );
+
+    // the trace starts where the throwable is created, not where it is thrown
+    let created = call(
+        &mut vm,
+        "Exceptions",
+        "created",
+        "()Ljava/lang/Throwable;",
+        &[],
+    );
+    let created = created.unwrap().as_reference().unwrap().unwrap();
+    assert_eq!(
+        vm.format_stack_trace(created),
+        "java.lang.Error\n\tat Exceptions.created(Exceptions.java:94)\n"
+    );
+}
+
+/// A writer for capturing the output of the VM
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The output of `Hello` with the argument `World`, the same as with the JVM
+const HELLO_OUT: &str = "Hello, World!
+,4,3,2,1,0
+true false true 1127
+0.3333333333333333 1.0E10 100.0 1.4142135623730951 -9223372036854775808
+ffffffff -123 7
+3 9 class java.lang.Object
+";
+const HELLO_ERR: &str = "java.lang.NumberFormatException: For input string: \"x1\"\n";
+
+#[test]
+fn run_main() {
+    let mut vm = Vm::with_options(VmOptions {
+        gc_stress: true,
+        ..VmOptions::default()
+    });
+    vm.add_class_path(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata"));
+    let (out, err) = (Output::default(), Output::default());
+    vm.set_stdout(out.clone());
+    vm.set_stderr(err.clone());
+
+    vm.run_main("Hello", &["World".to_string()]).unwrap();
+    assert_eq!(out.text(), HELLO_OUT);
+    assert_eq!(err.text(), HELLO_ERR);
+
+    // an uncaught exception is printed
+    let result = vm.run_main("Hello", &[]);
+    assert!(matches!(result, Err(VmError::Exception(_))));
+    assert!(err.text().ends_with(
+        "Exception in thread \"main\" java.lang.ArrayIndexOutOfBoundsException: \
+        Index 0 out of bounds for length 0\n\tat Hello.main(Hello.java:6)\n"
+    ));
+}
+
+#[test]
+fn native_methods() {
+    let mut vm = test_vm();
+    vm.register_native("Natives", "add", "(II)I", |_, args| {
+        Ok(Some(Value::Int(args[0].as_int()? + args[1].as_int()?)))
+    });
+
+    let add = call(&mut vm, "Natives", "callAdd", "()I", &[]);
+    assert_eq!(add, Ok(Value::Int(43)));
+    // natives can also be invoked from Rust
+    let add = call(
+        &mut vm,
+        "Natives",
+        "add",
+        "(II)I",
+        &[Value::Int(1), Value::Int(2)],
+    );
+    assert_eq!(add, Ok(Value::Int(3)));
+    let missing = call(&mut vm, "Natives", "missing", "()V", &[]);
+    assert_eq!(
+        missing,
+        Err(VmError::UnsatisfiedLink("Natives.missing()V".to_string()))
+    );
+}
+
+#[test]
+fn strings() {
+    let mut vm = test_vm();
+    for contents in ["", "Latin-1 é", "UTF-16 € 😀"] {
+        let string = vm.new_string(contents).unwrap();
+        assert_eq!(vm.string_value(string).unwrap(), contents);
+    }
+
+    let string = vm.new_string("héllo €").unwrap();
+    let string_class = vm.loaded_class("java/lang/String").unwrap();
+    let length = vm.lookup_method(string_class, "length", "()I").unwrap();
+    let length = vm.invoke(length, &[Value::Reference(Some(string))]);
+    assert_eq!(length, Ok(Some(Value::Int(7))));
+}
+
+/// The output of `Threads`, the same as with the JVM
+const THREADS_OUT: &str = "count 2000
+sum 55 producer alive false
+interrupted 2 false false
+interrupted 3 false false
+slept true
+not owner false
+interrupted true false
+main done main
+last last
+";
+
+#[test]
+fn threads() {
+    // a short time slice switches threads in the middle of everything, a seeded one at random
+    let seeded = (0..8).map(|seed| (50, Some(seed)));
+    for (time_slice, schedule_seed) in [(1, None), (7, None), (1000, None)]
+        .into_iter()
+        .chain(seeded)
+    {
+        let mut vm = Vm::with_options(VmOptions {
+            time_slice,
+            gc_stress: time_slice == 7,
+            schedule_seed,
+            ..VmOptions::default()
+        });
+        vm.add_class_path(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata"));
+        let (out, err) = (Output::default(), Output::default());
+        vm.set_stdout(out.clone());
+        vm.set_stderr(err.clone());
+
+        vm.run_main("Threads", &[]).unwrap();
+        assert_eq!(out.text(), THREADS_OUT);
+        assert!(err.text().starts_with(
+            "Exception in thread \"failing\" java.lang.IllegalStateException: failed\n\
+            \tat Threads$Task.run(Threads.java:125)\n"
+        ));
+    }
+}
+
+/// Runs `Race` with the seeded scheduler, returns the output
+fn race(seed: u64) -> String {
+    let mut vm = Vm::with_options(VmOptions {
+        time_slice: 20,
+        schedule_seed: Some(seed),
+        ..VmOptions::default()
+    });
+    vm.add_class_path(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata"));
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+    vm.run_main("Race", &[]).unwrap();
+    out.text()
+}
+
+#[test]
+fn seeded_scheduler() {
+    let outputs = (0..16).map(race).collect::<Vec<_>>();
+    // every seed is reproducible
+    for (seed, output) in outputs.iter().enumerate() {
+        assert_eq!(&race(seed as u64), output);
+        // a minute of sleeping takes no real time
+        assert!(output.ends_with(" true\n"), "{}", output);
+    }
+    // the unsynchronized increments are lost in some interleavings
+    assert!(outputs.contains(&"300 true\n".to_string()));
+    assert!(outputs.iter().any(|output| output != "300 true\n"));
+}
+
+const HOT_OUT: &str = "sum 1368594880\n\
+    longs 7058374807337849037\n\
+    collatz 215015\n\
+    divisions -2125787902\n\
+    narrow -2029526252\n\
+    zero / by zero\n\
+    calls 24995000\n\
+    compare 71\n";
+
+#[test]
+fn jit() {
+    for mode in [JitMode::Disabled, JitMode::Enabled, JitMode::Forced] {
+        let mut vm = test_vm_with(VmOptions {
+            jit: mode,
+            ..VmOptions::default()
+        });
+        vm.thread_mut().frames.clear();
+        let out = Output::default();
+        vm.set_stdout(out.clone());
+
+        vm.run_main("Hot", &[]).unwrap();
+        assert_eq!(out.text(), HOT_OUT, "{:?}", mode);
+        if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            assert_eq!(vm.jit.compilations > 0, mode != JitMode::Disabled);
+            // the division by zero in `Hot.zero` deoptimizes
+            assert_eq!(vm.jit.deoptimizations > 0, mode != JitMode::Disabled);
+        }
+    }
+}
+
+/// The class files in `testdata` whose names start with the prefix
+fn class_files(prefix: &str) -> Vec<Vec<u8>> {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+    let mut paths = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            name.starts_with(prefix) && name.ends_with(".class")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| std::fs::read(path).unwrap())
+        .collect()
+}
+
+#[test]
+fn aot_reachability() {
+    let closed = reachability(&class_files("Closed"), "Closed").unwrap();
+    assert!(closed.classes.contains("Closed$Never"));
+    assert!(closed.methods.contains("Closed$Square.area()I"));
+    // `Never` is loaded for `instanceof`, but never instantiated
+    assert!(!closed.methods.contains("Closed$Never.area()I"));
+    assert_eq!(
+        closed.unsupported.iter().collect::<Vec<_>>(),
+        ["Closed.main([Ljava/lang/String;)V: reflection with java/lang/Class.forName"]
+    );
+    let Err(VmError::Unsupported(message)) = Image::compile(&class_files("Closed"), "Closed")
+    else {
+        panic!("reflection was compiled");
+    };
+    assert!(message.contains("Class.forName"), "{}", message);
+
+    let lambdas = reachability(&class_files("Lambdas"), "Lambdas").unwrap();
+    assert!(lambdas.unsupported.iter().any(|what| what.starts_with(
+        "Lambdas.main([Ljava/lang/String;)V: invokedynamic with the bootstrap method \
+         java/lang/invoke/LambdaMetafactory.metafactory"
+    )));
+}
+
+#[test]
+fn aot_image() {
+    let image = Image::compile(&class_files("Hot"), "Hot").unwrap();
+    assert_eq!(image.classes, class_files("Hot"));
+    let image = Image::from_bytes(&image.to_bytes()).unwrap();
+    assert_eq!(
+        Image::from_bytes(&image.to_bytes()[1..]).err(),
+        Some(VmError::ClassFormat("Invalid image".to_string()))
+    );
+
+    let mut vm = Vm::new();
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+    vm.run_image(&image, &[]).unwrap();
+    assert_eq!(out.text(), HOT_OUT);
+    if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+        let compiled = image
+            .methods
+            .iter()
+            .map(|method| format!("{}.{}{}", method.class, method.name, method.descriptor));
+        let compiled = compiled.collect::<Vec<_>>();
+        assert!(
+            compiled.contains(&"Hot.sum(I)I".to_string()),
+            "{:?}",
+            compiled
+        );
+        assert!(
+            compiled.contains(&"Hot.collatz(J)I".to_string()),
+            "{:?}",
+            compiled
+        );
+        assert!(vm.jit.entries > 0);
+    }
+
+    // the image of the runtime is replaced, a trailer with a length past the start is an error
+    let path = std::env::temp_dir().join(format!("coldsquare-aot-{}", std::process::id()));
+    let mut runtime = b"runtime".to_vec();
+    runtime.extend_from_slice(b"old image");
+    runtime.extend(9u64.to_le_bytes());
+    runtime.extend_from_slice(b"CSQIMAGE");
+    image.write_executable(&runtime, &path).unwrap();
+    let written = std::fs::read(&path).unwrap();
+    assert!(written.starts_with(b"runtime"));
+    assert_eq!(
+        Image::from_bytes(&written[7..written.len() - 16]),
+        Ok(image.clone())
+    );
+
+    runtime[16..24].copy_from_slice(&17u64.to_le_bytes());
+    let err = image.write_executable(&runtime, &path).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    runtime[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(image.write_executable(&runtime, &path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Instantiates the module in argv[2] with natives that record their calls, then prints what the
+/// exports of `Wasm` return
+const WASM_HOST: &str = r#"
+const out = [];
+const natives = new Proxy({}, { get: (_, name) => (...args) => out.push(name + " " + args.join(" ")) });
+WebAssembly.instantiate(require("fs").readFileSync(process.argv[2]), { natives }).then(({ instance }) => {
+  const e = instance.exports;
+  out.push("gcd " + e.gcd(1071, 462) + " " + e.gcd(17, 5));
+  out.push("factorial " + e.factorial(20) + " " + e.factorial(25));
+  out.push("primes " + e.primes(10000));
+  out.push("table " + [-1, 0, 1, 2, 3, 4, 5, 6, 7].map(e.table).join(" "));
+  out.push("lookup " + [-1000, 7, 100000, 8].map(e.lookup).join(" "));
+  out.push("areas " + e.areas(10));
+  out.push("divide " + e["divide(II)I"](-2147483648, -1) + " " + e["divide(II)I"](-7, 2));
+  out.push("divide " + e["divide(JJ)J"](-9223372036854775808n, -1n) + " " + e["divide(JJ)J"](-7n, 2n));
+  out.push("next " + e.next() + " " + e.next());
+  out.push("compare " + [[1, 2], [2, 1], [1, 1], [NaN, 1]].map(([a, b]) => e.compare(a, b)).join(" "));
+  out.push("narrow " + e.narrow(123456789) + " " + e.narrow(-42));
+  e.report();
+  try {
+    e["divide(II)I"](1, 0);
+  } catch (err) {
+    out.push("trap " + (err instanceof WebAssembly.RuntimeError));
+  }
+  console.log(out.join("\n"));
+});
+"#;
+
+/// What `java` prints for the same calls, with natives that print their name
+const WASM_OUT: &str = "gcd 21 1
+factorial 2432902008176640000 7034535277573963776
+primes 1229
+table -1 0 8 7 7 6 -1 0 -1
+lookup 2 20 5 8
+areas 219.75
+divide -2147483648 -4
+divide -9223372036854775808 -3
+next 100 101
+compare -1 1 0 2
+narrow -1585003740 -5374121
+Wasm.print(I)V 21
+Wasm.print(J)V 2432902008176640000
+Wasm.print(D)V 76.75
+Wasm.print(I)V 49
+Wasm.print(I)V 1
+Wasm.print(I)V 0
+Wasm.print(I)V 3
+trap true
+";
+
+#[test]
+fn wasm_module() {
+    let module = compile_wasm(&class_files("Wasm"), "Wasm").unwrap();
+    assert_eq!(&module[..8], b"\0asm\x01\0\0\0");
+    assert_eq!(
+        compile_wasm(&class_files("Hot"), "Hot"),
+        Err(VmError::Unsupported(
+            "Hot can not be compiled to WebAssembly:\n  Hot.main([Ljava/lang/String;)V: exception handlers"
+                .to_string()
+        ))
+    );
+}
+
+#[test]
+#[ignore = "needs node"]
+fn wasm_node() {
+    let module = compile_wasm(&class_files("Wasm"), "Wasm").unwrap();
+    let dir = std::env::temp_dir().join(format!("coldsquare-wasm-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (path, host) = (dir.join("Wasm.wasm"), dir.join("host.js"));
+    std::fs::write(&path, &module).unwrap();
+    std::fs::write(&host, WASM_HOST).unwrap();
+    let output = std::process::Command::new("node")
+        .arg(&host)
+        .arg(&path)
+        .output();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let output = output.expect("node");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), WASM_OUT);
+}
+
+/// Runs `Litmus` and returns the counts of the outcomes of every test
+fn litmus(seed: u64) -> Vec<(String, [u32; 4])> {
+    let mut vm = Vm::with_options(VmOptions {
+        time_slice: 4,
+        schedule_seed: Some(seed),
+        ..VmOptions::default()
+    });
+    vm.add_class_path(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata"));
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+    vm.run_main("Litmus", &[]).unwrap();
+    out.text()
+        .lines()
+        .map(|line| {
+            let mut parts = line.split(' ');
+            let name = parts.next().unwrap().to_string();
+            let mut counts = [0; 4];
+            for (count, part) in counts.iter_mut().zip(parts) {
+                *count = part[3..].parse().unwrap();
+            }
+            (name, counts)
+        })
+        .collect()
+}
+
+#[test]
+fn memory_model() {
+    let mut totals = HashMap::<String, [u32; 4]>::new();
+    for seed in 0..4 {
+        for (name, counts) in litmus(seed) {
+            let total = totals.entry(name).or_default();
+            for (total, count) in total.iter_mut().zip(counts) {
+                *total += count;
+            }
+        }
+    }
+    // the outcomes are indexed by `r1 r2`
+    let forbidden = [("mp", 0b10), ("sb", 0b00), ("wide", 0b10), ("final", 0b10)];
+    for (name, outcome) in forbidden {
+        assert_eq!(totals[name][outcome], 0, "{} {:?}", name, totals[name]);
+    }
+    // the threads did interleave
+    for name in ["mp", "sb", "wide", "final"] {
+        let seen = totals[name].iter().filter(|&&count| count > 0).count();
+        assert!(seen >= 2, "{} {:?}", name, totals[name]);
+    }
+}
+
+#[test]
+fn final_fields() {
+    let mut vm = test_vm();
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+
+    // `FrozenWriter` was compiled when the fields of `Frozen` were not final
+    vm.run_main("Finals", &[]).unwrap();
+    assert_eq!(
+        out.text(),
+        "value 1 limits 2\n\
+        Update to non-static final field Frozen.value attempted from a different class \
+        (FrozenWriter) than the field's declaring class\n\
+        Update to static final field Frozen.LIMITS attempted from a different class \
+        (FrozenWriter) than the field's declaring class\n"
+    );
+}
+
+#[test]
+fn deadlock() {
+    let mut vm = test_vm();
+    vm.thread_mut().frames.clear();
+    let err = Output::default();
+    vm.set_stderr(err.clone());
+
+    let dump = match vm.run_main("Deadlock", &[]) {
+        Err(VmError::Deadlock(dump)) => dump,
+        result => panic!("expected a deadlock, got {:?}", result),
+    };
+    assert_eq!(err.text(), dump);
+    let class = vm.class(vm.loaded_class("Deadlock").unwrap());
+    let lock = |name| {
+        let slot = class
+            .static_fields
+            .iter()
+            .position(|field| field.name == name);
+        let obj = class.static_values[slot.unwrap()].as_reference().unwrap();
+        format!(
+            "java.lang.Object@{:x}",
+            runtime::identity_hash(obj.unwrap())
+        )
+    };
+    assert_eq!(
+        dump,
+        format!(
+            "Found a deadlock, threads wait for monitors held by each other:\n\n\
+            \"main\" blocked on {} held by \"other\"\n\
+            \tat Deadlock.lockBoth(Deadlock.java:12)\n\
+            \tat Deadlock.main(Deadlock.java:23)\n\n\
+            \"other\" blocked on {} held by \"main\"\n\
+            \tat Deadlock.lockBoth(Deadlock.java:12)\n\
+            \tat Deadlock$1.run(Deadlock.java:20)\n\n",
+            lock("second"),
+            lock("first")
+        )
+    );
+}
+
+#[test]
+fn class_initialization_threads() {
+    // the reader waits until the initializer has run, whichever thread runs it
+    for (time_slice, schedule_seed) in [(1, None), (1000, None), (50, Some(1)), (50, Some(2))] {
+        let mut vm = test_vm_with(VmOptions {
+            time_slice,
+            schedule_seed,
+            ..VmOptions::default()
+        });
+        let race = call(&mut vm, "ClassInit", "race", "()I", &[]);
+        assert_eq!(race, Ok(Value::Int(84)));
+    }
+
+    let mut vm = test_vm();
+    let err = Output::default();
+    vm.set_stderr(err.clone());
+    let dump = match call(&mut vm, "ClassInit", "deadlock", "()V", &[]) {
+        Err(VmError::Deadlock(dump)) => dump,
+        result => panic!("expected a deadlock, got {:?}", result),
+    };
+    assert_eq!(err.text(), dump);
+    assert!(dump.starts_with("Found a deadlock, no thread can run:\n\n\"main\" sleeping\n"));
+    assert!(dump.ends_with(
+        "\"other\" waiting for the initialization of ClassInit$First held by \"main\"\n\
+        \tat ClassInit$Second.<clinit>(ClassInit.java:30)\n\
+        \tat ClassInit$2.run(ClassInit.java:50)\n\n"
+    ));
+}
+
+#[test]
+fn boot_image() {
+    // an image with the bundled class library instead of the one of a JDK
+    let resources = runtime::CLASSES
+        .iter()
+        .map(|(name, bytes)| (format!("/java.base/{}.class", name), *bytes))
+        .collect::<Vec<_>>();
+    let resources = resources
+        .iter()
+        .map(|(name, bytes)| (name.as_str(), *bytes))
+        .collect::<Vec<_>>();
+    let image = JImage::from_bytes(jimage::tests::build(&resources)).unwrap();
+
+    let mut vm = Vm::new();
+    vm.set_boot_image(image);
+    vm.add_class_path(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata"));
+    let (out, err) = (Output::default(), Output::default());
+    vm.set_stdout(out.clone());
+    vm.set_stderr(err.clone());
+
+    vm.run_main("Hello", &["World".to_string()]).unwrap();
+    assert_eq!(out.text(), HELLO_OUT);
+    assert_eq!(err.text(), HELLO_ERR);
+    assert!(matches!(
+        vm.resolve_class("java/util/List"),
+        Err(VmError::ClassNotFound(_))
+    ));
+}
+
+/// A VM with the initialized class library of the JDK in `JAVA_HOME`. The tests with it are
+/// ignored, run them with `cargo test -- --ignored`
+fn jdk_vm() -> Vm {
+    let java_home = std::env::var_os("JAVA_HOME").expect("JAVA_HOME is not set");
+    let modules = std::path::Path::new(&java_home).join("lib/modules");
+    let image = JImage::open(&modules)
+        .unwrap_or_else(|err| panic!("Could not open {}: {}", modules.display(), err));
+
+    let mut vm = Vm::new();
+    vm.set_boot_image(image);
+    vm.add_class_path(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata"));
+    vm.initialize_jdk().unwrap();
+    vm
+}
+
+#[test]
+#[ignore = "needs JAVA_HOME"]
+fn jdk_class_library() {
+    let mut vm = jdk_vm();
+    let (out, err) = (Output::default(), Output::default());
+    vm.set_stdout(out.clone());
+    vm.set_stderr(err.clone());
+
+    vm.run_main("Hello", &["World".to_string()]).unwrap();
+    assert_eq!(out.text(), HELLO_OUT);
+    assert_eq!(err.text(), HELLO_ERR);
+}
+
+#[test]
+fn constants() {
+    let mut vm = test_vm();
+    let same = call(&mut vm, "Constants", "sameLiterals", "()I", &[]);
+    assert_eq!(same, Ok(Value::Int(1111)));
+    let same = call(&mut vm, "Constants", "sameClasses", "()I", &[]);
+    assert_eq!(same, Ok(Value::Int(111)));
+    let wide = call(&mut vm, "Constants", "wide", "()J", &[]);
+    assert_eq!(wide, Ok(Value::Long(1_239_567_890_123)));
+
+    let class = call(
+        &mut vm,
+        "Constants",
+        "stringClass",
+        "()Ljava/lang/Class;",
+        &[],
+    );
+    let class = class.unwrap().as_reference().unwrap().unwrap();
+    assert_eq!(vm.mirror_class(class), vm.loaded_class("java/lang/String"));
+
+    // literals survive a collection, as the same object
+    let literal = call(&mut vm, "Constants", "literal", "()Ljava/lang/String;", &[]).unwrap();
+    vm.gc();
+    assert_eq!(
+        vm.intern_string("hello").map(|s| Value::Reference(Some(s))),
+        Ok(literal)
+    );
+    let copy = vm.new_string("hello").unwrap();
+    assert_ne!(Value::Reference(Some(copy)), literal);
+    assert_eq!(
+        vm.intern(copy).map(|s| Value::Reference(Some(s))),
+        Ok(literal)
+    );
+}
+
+#[test]
+#[ignore = "needs JAVA_HOME"]
+fn jdk_method_types() {
+    let mut vm = jdk_vm();
+    let method_type = vm.method_type("(I[Ljava/lang/String;)V").unwrap();
+    // method types are interned by the class library
+    assert_eq!(vm.method_type("(I[Ljava/lang/String;)V"), Ok(method_type));
+
+    let class = vm.heap.get(method_type).class;
+    let to_string = vm
+        .lookup_method(class, "toString", "()Ljava/lang/String;")
+        .unwrap();
+    let string = vm.invoke(to_string, &[Value::Reference(Some(method_type))]);
+    let string = string.unwrap().unwrap().as_reference().unwrap().unwrap();
+    assert_eq!(vm.string_value(string).unwrap(), "(int,String[])void");
+}
+
+#[test]
+#[ignore = "needs JAVA_HOME"]
+fn jdk_lambdas() {
+    let mut vm = jdk_vm();
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+
+    vm.run_main("Lambdas", &[]).unwrap();
+    assert_eq!(
+        out.text(),
+        "3 13\nAlice greets Bob, Carol greets Dave\na;b;c;\nrun Alice\ntrue\n"
+    );
+}
+
+#[test]
+#[ignore = "needs JAVA_HOME"]
+fn jdk_threads() {
+    let mut vm = jdk_vm();
+    let (out, err) = (Output::default(), Output::default());
+    vm.set_stdout(out.clone());
+    vm.set_stderr(err.clone());
+
+    // the reference handler and the cleaner of the JDK keep waiting, they are daemons
+    vm.run_main("Threads", &[]).unwrap();
+    assert_eq!(out.text(), THREADS_OUT);
+    assert!(err
+        .text()
+        .starts_with("Exception in thread \"failing\" java.lang.IllegalStateException: failed\n"));
+}
+
+#[test]
+#[ignore = "needs JAVA_HOME"]
+fn jdk_dynamic_constants() {
+    let mut vm = jdk_vm();
+    let calls = |vm: &mut Vm| {
+        let class = vm.resolve_class("CondyBootstraps").unwrap();
+        let (_, slot) = vm
+            .find_static_field(class, "calls", &FieldType::Int)
+            .unwrap();
+        vm.class(class).static_values[slot]
+    };
+
+    // the bootstrap method only runs the first time
+    for _ in 0..2 {
+        let answer = call(&mut vm, "Condy", "answer", "()I", &[]);
+        assert_eq!(answer, Ok(Value::Int(42)));
+    }
+    let greeting = call(&mut vm, "Condy", "greeting", "()Ljava/lang/String;", &[]).unwrap();
+    let greeting = greeting.as_reference().unwrap().unwrap();
+    assert_eq!(vm.string_value(greeting).unwrap(), "Hello, condycondycondy");
+    vm.gc();
+    let again = call(&mut vm, "Condy", "greeting", "()Ljava/lang/String;", &[]);
+    assert_eq!(again, Ok(Value::Reference(Some(greeting))));
+
+    // a failure is remembered and thrown again
+    let failed = call(&mut vm, "Condy", "fail", "()Ljava/lang/Object;", &[]);
+    assert_eq!(
+        thrown(&vm, failed.clone()),
+        "java/lang/BootstrapMethodError"
+    );
+    assert_eq!(
+        call(&mut vm, "Condy", "fail", "()Ljava/lang/Object;", &[]),
+        failed
+    );
+    assert_eq!(calls(&mut vm), Value::Int(3));
+
+    // the constant is its own static argument
+    let cycle = call(&mut vm, "Condy", "cycle", "()Ljava/lang/Object;", &[]);
+    assert_eq!(thrown(&vm, cycle.clone()), "java/lang/StackOverflowError");
+    assert_eq!(
+        call(&mut vm, "Condy", "cycle", "()Ljava/lang/Object;", &[]),
+        cycle
+    );
+    assert_eq!(calls(&mut vm), Value::Int(3));
+}
+
+#[test]
+#[ignore = "needs JAVA_HOME"]
+fn jdk_method_handles() {
+    let mut vm = jdk_vm();
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+
+    // all nine reference kinds, from `MethodHandles.Lookup` and from constants
+    vm.run_main("Handles", &[]).unwrap();
+    assert_eq!(
+        out.text(),
+        "6 10 handles 6 described 42 Hello Alice 6\n\
+        6 20 handles 6 - 42 Hello Alice 6\n\
+        (HandleConstants)String described\n\
+        WrongMethodTypeException\n\
+        42 10 Hello Bob 1\n"
+    );
+
+    let class = vm.resolve_class("Handles").unwrap();
+    let twice = vm.lookup_method(class, "twice", "(I)I").unwrap();
+    let twice = vm.method_handle(twice).unwrap();
+    assert_eq!(
+        vm.invoke_method_handle(twice, &[Value::Int(21)]),
+        Ok(Some(Value::Int(42)))
+    );
+    let wrong = vm.invoke_method_handle(twice, &[]);
+    assert_eq!(
+        thrown(&vm, wrong.map(|value| value.unwrap_or(Value::NULL))),
+        "java/lang/invoke/WrongMethodTypeException"
+    );
+
+    let constructor = vm.lookup_method(class, "<init>", "(I)V").unwrap();
+    let constructor = vm.method_handle(constructor).unwrap();
+    vm.handles.push(constructor);
+    let handles = vm.invoke_method_handle(constructor, &[Value::Int(7)]);
+    let handles = handles.unwrap().unwrap().as_reference().unwrap().unwrap();
+    assert_eq!(vm.class(vm.heap.get(handles).class).name, "Handles");
+    vm.handles.push(handles);
+
+    let greeter = vm.resolve_class("Greeter").unwrap();
+    let greet = vm
+        .lookup_method(greeter, "greet", "(Ljava/lang/String;)Ljava/lang/String;")
+        .unwrap();
+    let greet = vm.method_handle(greet).unwrap();
+    vm.handles.push(greet);
+    let name = vm.new_string("Bob").unwrap();
+    let greeting = vm.invoke_method_handle(
+        greet,
+        &[
+            Value::Reference(Some(handles)),
+            Value::Reference(Some(name)),
+        ],
+    );
+    let greeting = greeting.unwrap().unwrap().as_reference().unwrap().unwrap();
+    assert_eq!(vm.string_value(greeting).unwrap(), "Hello Bob 7");
+}
+
+/// The output of `Reflection`, the same as with the JVM
+const REFLECTION_OUT: &str = "public Reflection [T] java.lang.Comparable<T>
+public static final java.lang.String NAME
+static int counter
+private java.util.List<T> items
+protected final java.util.Map<java.lang.String, ? super java.lang.Integer> counts
+public transient volatile long stamp
+public static int Reflection.add(int,int) left right
+static void Reflection.fail(java.lang.String) message
+public <E extends java.lang.Exception> T Reflection.first(java.util.List<? extends T>) \
+throws E,java.lang.InterruptedException list
+public static void Reflection.main(java.lang.String[]) throws java.lang.Exception args
+private java.lang.String Reflection.secret(java.lang.String) prefix
+42 marked
+counter 7 7 reflection
+123 123
+10 10
+java.lang.IllegalStateException: failed
+object is not an instance of declaring class
+IllegalAccessException
+";
+
+#[test]
+#[ignore = "needs JAVA_HOME"]
+fn jdk_reflection() {
+    let mut vm = jdk_vm();
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+
+    vm.run_main("Reflection", &[]).unwrap();
+    assert_eq!(out.text(), REFLECTION_OUT);
+
+    // the annotations are kept as in the class file, for the class library to parse
+    let class = vm.resolve_class("Reflection").unwrap();
+    let annotations = vm.class(class).metadata.annotations.clone().unwrap();
+    let type_index = u16::from_be_bytes([annotations[2], annotations[3]]);
+    assert_eq!(annotations[..2], [0, 1]);
+    assert_eq!(vm.class(class).cp_utf8(type_index), Ok("LMarked;"));
+    let add = vm.lookup_method(class, "add", "(II)I").unwrap();
+    let metadata = &vm.method(add).metadata;
+    let names = metadata.parameters.as_ref().unwrap();
+    assert_eq!(names[0], (Some("left".to_string()), 0));
+    assert!(metadata.annotations.is_some() && metadata.signature.is_none());
+}
+
+/// The output of `Annotations`, the same as with the JVM
+const ANNOTATIONS_OUT: &str = "Ann 2024 HIGH [a, b] String [1, 2]
+null 1 true 0
+Bob 1999 LOW 0 x 0.5
+Cid y 2.0 true
+2 Dee 0
+Info true false true
+@Info(numbers={}, type=java.lang.Object.class, level=LOW, tags={}, letter='x', ratio=0.5, \
+author=@Author(year=1999, name=\"Bob\"))
+LOW
+";
+
+#[test]
+#[ignore = "needs JAVA_HOME"]
+fn jdk_annotations() {
+    let mut vm = jdk_vm();
+    let out = Output::default();
+    vm.set_stdout(out.clone());
+
+    vm.run_main("Annotations", &[]).unwrap();
+    assert_eq!(out.text(), ANNOTATIONS_OUT);
+
+    // annotations with the class retention are only in the class file
+    let annotated = vm.resolve_class("Annotated").unwrap();
+    let annotations = vm.class(annotated).metadata.annotations.clone().unwrap();
+    assert_eq!(annotations[..2], [0, 1]);
+}
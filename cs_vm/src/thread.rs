@@ -0,0 +1,821 @@
+//!
+//! Threads and monitors
+//!
+//! Java threads are green threads of the interpreter. Every `java/lang/Thread` that is started
+//! gets its own call stack, and the interpreter loop switches to another thread when the current
+//! one blocks or after `VmOptions::time_slice` instructions. With `VmOptions::schedule_seed`,
+//! the length of the slices and the next thread are chosen by a seeded pseudo-random generator
+//! instead, which makes runs reproducible.
+//!
+//! Natives run on the Rust stack of the loop that called them, so while a thread runs a loop
+//! nested in a native, the threads with loops further down the Rust stack can not be scheduled
+//! until it returns.
+//!
+//! Every object has a monitor, which is created when the object is first locked or waited on.
+//! A thread that blocks in `Object.wait`, `Thread.sleep` or `Unsafe.park` returns from the native
+//! and is not scheduled until it is woken up. A thread that needs a class which another thread
+//! is initializing waits until the initializer is done, like it waits for a monitor. If no thread
+//! can run anymore, or threads wait for monitors held by each other, the VM reports a deadlock
+//! with the stacks of all threads
+//!
+//! Threads only switch between instructions and all of them share one heap, so every field
+//! access is sequentially consistent, which is stronger than the Java memory model requires.
+//! Compiled code exits to the interpreter for field accesses, so no value of a field is kept in
+//! a register across a switch. Volatile fields therefore need no special handling, `long` and
+//! `double` values are never torn, and the final fields of an object are visible to every thread
+//! once its constructor has written them. Writes to final fields outside of the initializer of
+//! their class throw an `IllegalAccessError`
+//!
+
+use crate::class::InitState;
+use crate::heap::ObjRef;
+use crate::jdk::field_slot;
+use crate::model::{Thread, ThreadState, Value};
+use crate::runtime::{identity_hash, this};
+use crate::{Result, Vm, VmError};
+use cs_model::FieldType;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// The monitor of an object
+#[derive(Debug, Default)]
+pub(crate) struct Monitor {
+    /// The index of the thread that holds the monitor
+    owner: Option<usize>,
+    /// How often the owner entered the monitor
+    count: u32,
+    /// The threads in `Object.wait`, in the order they started waiting
+    waiters: VecDeque<usize>,
+}
+
+/// The pseudo-random generator of the seeded scheduler, SplitMix64
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// The `JVMTI_THREAD_STATE` flags of `Thread.threadStatus` in the JDK
+const THREAD_ALIVE: i32 = 0x0001;
+const THREAD_TERMINATED: i32 = 0x0002;
+const THREAD_RUNNABLE: i32 = 0x0004;
+const THREAD_WAITING_INDEFINITELY: i32 = 0x0010;
+const THREAD_WAITING_WITH_TIMEOUT: i32 = 0x0020;
+const THREAD_SLEEPING: i32 = 0x0040;
+const THREAD_WAITING: i32 = 0x0080;
+const THREAD_IN_OBJECT_WAIT: i32 = 0x0100;
+const THREAD_PARKED: i32 = 0x0200;
+const THREAD_BLOCKED_ON_MONITOR_ENTER: i32 = 0x0400;
+
+impl Vm {
+    /// Runs the other threads until all threads that are not daemons have terminated, like the
+    /// JVM does before it exits. `Vm::run_main` does this after `main` returned
+    pub fn wait_for_threads(&mut self) -> Result<()> {
+        // the current thread waits, even if it has frames left
+        let current = self.current_thread;
+        self.threads[current].runs += 1;
+        let result = self.run_other_threads(current);
+        self.current_thread = current;
+        self.threads[current].runs -= 1;
+        result
+    }
+
+    fn run_other_threads(&mut self, current: usize) -> Result<()> {
+        loop {
+            let running = self.threads.iter().enumerate().any(|(index, thread)| {
+                index != current && thread.is_alive() && !self.is_daemon(thread)
+            });
+            if !running {
+                return Ok(());
+            }
+            self.schedule(None)?;
+            self.step_thread()?;
+        }
+    }
+
+    /// Executes an instruction of a thread that has no loop of its own, which
+    /// terminates when its last frame returns or an exception is not caught
+    pub(crate) fn step_thread(&mut self) -> Result<()> {
+        if self.thread().frames.is_empty() {
+            // the thread was blocked on the monitor of its object while terminating
+            return self.terminate_thread(true);
+        }
+        match self.step_current() {
+            Ok(None) | Err(VmError::Blocked) => Ok(()),
+            Ok(Some(_)) if self.thread().frames.is_empty() => self.exit_thread(None),
+            Ok(Some(value)) => self.complete_invoke(value),
+            Err(err) => {
+                let exception = self.exception_from_error(err)?;
+                match self.dispatch_exception(exception, 0) {
+                    Err(VmError::Exception(exception)) => self.exit_thread(Some(exception)),
+                    result => result,
+                }
+            }
+        }
+    }
+
+    /// Executes an instruction of the current thread, or throws the `InterruptedException`
+    /// of an interrupted `Object.wait` or `Thread.sleep`
+    pub(crate) fn step_current(&mut self) -> Result<Option<Option<Value>>> {
+        if !self.thread().interrupt_pending {
+            return self.step();
+        }
+        self.thread_mut().interrupt_pending = false;
+        self.take_interrupt(self.current_thread)?;
+        let pc = self.thread().blocked_pc;
+        if let Some(frame) = self.thread_mut().frames.last_mut() {
+            frame.pc = pc;
+        }
+        Err(self.throw_new("java/lang/InterruptedException", None))
+    }
+
+    /// Chooses the thread that executes the next instruction. `owner` is the thread of the
+    /// innermost `Vm::run` loop, the other threads with a loop can not run.
+    /// The current thread keeps running until its time slice is used up or it blocks
+    pub(crate) fn schedule(&mut self, owner: Option<usize>) -> Result<()> {
+        self.clock.tick();
+        let current = self.current_thread;
+        let thread = &self.threads[current];
+        if thread.state == ThreadState::Runnable
+            && (self.slice > 0 || thread.initializing > 0)
+            && self.can_schedule(current, owner)
+        {
+            self.slice = self.slice.saturating_sub(1);
+            return Ok(());
+        }
+        self.switch_thread(owner)
+    }
+
+    fn can_schedule(&self, thread: usize, owner: Option<usize>) -> bool {
+        let thread_ref = &self.threads[thread];
+        (owner == Some(thread) || thread_ref.runs == 0) && is_active(thread_ref)
+    }
+
+    /// Switches to the next thread that can run, in turn or starting at a random thread if the
+    /// scheduler is seeded. Waits for the earliest deadline if all threads are blocked
+    fn switch_thread(&mut self, owner: Option<usize>) -> Result<()> {
+        loop {
+            let now = self.clock.now();
+            let count = self.threads.len();
+            let start = match &mut self.rng {
+                Some(rng) => rng.below(count),
+                None => self.current_thread + 1,
+            };
+            for thread in (0..count).map(|offset| (start + offset) % count) {
+                if self.can_schedule(thread, owner) && self.wake(thread, now) {
+                    self.current_thread = thread;
+                    self.slice = match &mut self.rng {
+                        Some(rng) => rng.below(self.options.time_slice.max(1)) + 1,
+                        None => self.options.time_slice,
+                    };
+                    return Ok(());
+                }
+            }
+
+            if self.has_monitor_cycle() {
+                return Err(self.deadlock("threads wait for monitors held by each other"));
+            }
+            let deadline = (0..count)
+                .filter(|&thread| self.can_schedule(thread, owner))
+                .filter_map(|thread| self.threads[thread].state.deadline())
+                .min();
+            match deadline {
+                Some(deadline) => self.clock.wait_until(deadline),
+                None => return Err(self.deadlock("no thread can run")),
+            }
+        }
+    }
+
+    /// Makes the thread runnable if it can continue now. A blocked thread enters the monitor
+    fn wake(&mut self, thread: usize, now: Instant) -> bool {
+        let state = self.threads[thread].state;
+        let expired = state.deadline().is_some_and(|deadline| deadline <= now);
+        match state {
+            ThreadState::Runnable => true,
+            ThreadState::Blocked { monitor, count } => self.try_enter(thread, monitor, count),
+            ThreadState::Waiting { monitor, count, .. } if expired => {
+                self.stop_waiting(thread, monitor, count);
+                self.try_enter(thread, monitor, count)
+            }
+            ThreadState::Sleeping { .. } | ThreadState::Parked { .. } if expired => {
+                self.set_thread_state(thread, ThreadState::Runnable);
+                true
+            }
+            ThreadState::WaitingForClass { class } => {
+                let done = !matches!(self.class(class).init_state, InitState::Initializing(_));
+                if done {
+                    self.set_thread_state(thread, ThreadState::Runnable);
+                }
+                done
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether some threads are blocked on monitors that are held by each other. A class that
+    /// is being initialized counts as a monitor held by the thread that runs its initializer
+    fn has_monitor_cycle(&self) -> bool {
+        let holder = |thread: usize| self.holder(self.threads[thread].state);
+        (0..self.threads.len()).any(|start| {
+            let mut thread = start;
+            (0..self.threads.len()).any(|_| match holder(thread) {
+                Some(owner) => {
+                    thread = owner;
+                    owner == start
+                }
+                None => false,
+            })
+        })
+    }
+
+    /// The thread that the thread in the state waits for
+    fn holder(&self, state: ThreadState) -> Option<usize> {
+        match state {
+            ThreadState::Blocked { monitor, .. } => self
+                .monitors
+                .get(&monitor)
+                .and_then(|monitor| monitor.owner),
+            ThreadState::WaitingForClass { class } => match self.class(class).init_state {
+                InitState::Initializing(thread) => Some(thread),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Reports a deadlock with the stacks of all threads on `System.err`
+    fn deadlock(&mut self, reason: &str) -> VmError {
+        let dump = format!("Found a deadlock, {}:\n\n{}", reason, self.thread_dump());
+        let _ = write!(self.stderr, "{}", dump);
+        VmError::Deadlock(dump)
+    }
+
+    /// Describes the threads with a stack and what they are waiting for,
+    /// like a thread dump of the JVM
+    pub fn thread_dump(&self) -> String {
+        let mut out = String::new();
+        for (index, thread) in self.threads.iter().enumerate() {
+            if !is_active(thread) {
+                continue;
+            }
+            out.push_str(&format!("\"{}\" ", self.thread_name(index)));
+            match thread.state {
+                ThreadState::Runnable => out.push_str("runnable"),
+                ThreadState::Blocked { monitor, .. } => {
+                    out.push_str(&format!("blocked on {}", self.describe_object(monitor)))
+                }
+                ThreadState::Waiting { monitor, .. } => {
+                    out.push_str(&format!("waiting on {}", self.describe_object(monitor)))
+                }
+                ThreadState::Sleeping { .. } => out.push_str("sleeping"),
+                ThreadState::Parked { .. } => out.push_str("parked"),
+                ThreadState::WaitingForClass { class } => out.push_str(&format!(
+                    "waiting for the initialization of {}",
+                    self.java_name(class)
+                )),
+                ThreadState::Terminated => unreachable!("terminated threads are skipped"),
+            }
+            if let Some(holder) = self.holder(thread.state) {
+                out.push_str(&format!(" held by \"{}\"", self.thread_name(holder)));
+            }
+            out.push('\n');
+            for frame in thread.frames.iter().rev() {
+                let element = self.stack_trace_element(frame.method, frame.pc);
+                out.push_str(&format!("\tat {}\n", element));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn describe_object(&self, obj: ObjRef) -> String {
+        let class = self.heap.get(obj).class;
+        format!("{}@{:x}", self.java_name(class), identity_hash(obj))
+    }
+
+    /// The name of the `java/lang/Thread` object of the thread
+    pub fn thread_name(&self, thread: usize) -> String {
+        let name = self.threads[thread].object.and_then(|object| {
+            let slot = field_slot(self, self.heap.get(object).class, "name").ok()?;
+            let name = self.get_field(object, slot).ok()?.as_reference().ok()??;
+            self.string_value(name).ok()
+        });
+        match name {
+            Some(name) => name,
+            None if thread == 0 => "main".to_string(),
+            None => format!("Thread-{}", thread),
+        }
+    }
+
+    fn is_daemon(&self, thread: &Thread) -> bool {
+        thread.object.is_some_and(|object| {
+            let class = self.heap.get(object).class;
+            self.class(class)
+                .instance_field_slot("daemon", &FieldType::Boolean)
+                .and_then(|slot| self.get_field(object, slot).ok())
+                == Some(Value::Int(1))
+        })
+    }
+
+    /// Changes the state of a thread. The JDK reads it from `Thread.threadStatus`, and whether
+    /// the thread is alive from `Thread.eetop`, which points to the native thread in HotSpot
+    pub(crate) fn set_thread_state(&mut self, thread: usize, state: ThreadState) {
+        self.threads[thread].state = state;
+        let object = match self.threads[thread].object {
+            Some(object) => object,
+            None => return,
+        };
+        let class = self.class(self.heap.get(object).class);
+        let status = class.instance_field_slot("threadStatus", &FieldType::Int);
+        let eetop = class.instance_field_slot("eetop", &FieldType::Long);
+        if let Some(slot) = status {
+            let _ = self.put_field(object, slot, Value::Int(thread_status(state)));
+        }
+        if let Some(slot) = eetop {
+            let eetop = match state {
+                ThreadState::Terminated => 0,
+                _ => thread as i64 + 1,
+            };
+            let _ = self.put_field(object, slot, Value::Long(eetop));
+        }
+    }
+
+    /// Blocks the current thread in a native, which was invoked at the pc of the top frame
+    pub(crate) fn block(&mut self, state: ThreadState) {
+        let pc = self.thread().frames.last().map_or(0, |frame| frame.pc);
+        self.thread_mut().blocked_pc = pc;
+        self.set_thread_state(self.current_thread, state);
+        self.slice = 0;
+    }
+
+    /// Starts a thread for the `java/lang/Thread` object, which runs its `run` method
+    fn start_thread(&mut self, object: ObjRef) -> Result<()> {
+        let class = self.heap.get(object).class;
+        let run = self
+            .lookup_method(class, "run", "()V")
+            .ok_or_else(|| VmError::NoSuchMethod(format!("{}.run()V", self.class(class).name)))?;
+        let mut frame = self.new_frame(run, &[Value::Reference(Some(object))])?;
+        let synchronized = self.method(run).is_synchronized();
+        if synchronized {
+            frame.monitor = Some(object);
+        }
+
+        let mut thread = Thread::new();
+        thread.frames.push(frame);
+        thread.object = Some(object);
+        // the slots of terminated threads are reused
+        let index = match self
+            .threads
+            .iter()
+            .position(|thread| !thread.is_alive() && thread.runs == 0)
+        {
+            Some(index) => {
+                self.threads[index] = thread;
+                index
+            }
+            None => {
+                self.threads.push(thread);
+                self.threads.len() - 1
+            }
+        };
+        let state = match synchronized {
+            true => ThreadState::Blocked {
+                monitor: object,
+                count: 1,
+            },
+            false => ThreadState::Runnable,
+        };
+        self.set_thread_state(index, state);
+        Ok(())
+    }
+
+    /// Terminates the current thread after its last frame returned. An exception that was not
+    /// caught is printed
+    fn exit_thread(&mut self, exception: Option<ObjRef>) -> Result<()> {
+        if let Some(exception) = exception {
+            self.print_uncaught(exception);
+        }
+        let thread = self.current_thread;
+        if let Some(object) = self.threads[thread].object {
+            // `Thread.exit` of the JDK removes the thread from its group
+            let class = self.heap.get(object).class;
+            if let Some(exit) = self.lookup_method(class, "exit", "()V") {
+                match self.invoke(exit, &[Value::Reference(Some(object))]) {
+                    Ok(_) | Err(VmError::Exception(_)) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        self.terminate_thread(false)
+    }
+
+    /// Threads that `join` the current thread wait on its object, they are notified while
+    /// holding the monitor like in Java, so that they can not miss it. If another thread holds
+    /// the monitor, the current thread is blocked and terminates once it `entered` it
+    fn terminate_thread(&mut self, entered: bool) -> Result<()> {
+        let thread = self.current_thread;
+        let object = match self.threads[thread].object {
+            Some(object) => object,
+            None => {
+                self.set_thread_state(thread, ThreadState::Terminated);
+                return Ok(());
+            }
+        };
+        if !entered {
+            self.monitor_enter(object);
+            if self.threads[thread].state != ThreadState::Runnable {
+                return Ok(());
+            }
+        }
+
+        self.set_thread_state(thread, ThreadState::Terminated);
+        self.threads[thread].object = None;
+        self.notify_waiters(object, true);
+        self.monitor_exit(object)
+    }
+
+    /// The index of the thread of a `java/lang/Thread` object, if it is alive
+    fn thread_index(&self, object: ObjRef) -> Option<usize> {
+        self.threads
+            .iter()
+            .position(|thread| thread.object == Some(object) && thread.is_alive())
+    }
+
+    /// The `java/lang/Thread` object of the current thread. The bundled class library
+    /// does not create one for the main thread, so it is allocated when it is first needed
+    fn current_thread_object(&mut self) -> Result<ObjRef> {
+        if let Some(object) = self.thread().object {
+            return Ok(object);
+        }
+        let class = self.resolve_class("java/lang/Thread")?;
+        self.initialize(class)?;
+        let object = self.new_object(class)?;
+        self.thread_mut().object = Some(object);
+        let name = self.new_string(&self.thread_name(self.current_thread))?;
+        let slot = field_slot(self, class, "name")?;
+        self.put_field(object, slot, Value::Reference(Some(name)))?;
+        Ok(object)
+    }
+
+    /// Clears the `interrupted` field of the `java/lang/Thread` object of a thread,
+    /// returns whether it was set
+    fn take_interrupt(&mut self, thread: usize) -> Result<bool> {
+        let object = match self.threads[thread].object {
+            Some(object) => object,
+            None => return Ok(false),
+        };
+        let class = self.heap.get(object).class;
+        let slot = match self
+            .class(class)
+            .instance_field_slot("interrupted", &FieldType::Boolean)
+        {
+            Some(slot) => slot,
+            None => return Ok(false),
+        };
+        let interrupted = self.get_field(object, slot)? == Value::Int(1);
+        self.put_field(object, slot, Value::Int(0))?;
+        Ok(interrupted)
+    }
+
+    /// Wakes a thread that waits, sleeps or is parked after it was interrupted
+    fn interrupt(&mut self, thread: usize) {
+        match self.threads[thread].state {
+            ThreadState::Waiting { monitor, count, .. } => {
+                self.stop_waiting(thread, monitor, count);
+                self.threads[thread].interrupt_pending = true;
+            }
+            ThreadState::Sleeping { .. } => {
+                self.set_thread_state(thread, ThreadState::Runnable);
+                self.threads[thread].interrupt_pending = true;
+            }
+            ThreadState::Parked { .. } => self.set_thread_state(thread, ThreadState::Runnable),
+            _ => {}
+        }
+    }
+
+    /// Enters the monitor of the object with the current thread. If another thread holds it,
+    /// the current thread is blocked until the monitor is free
+    pub(crate) fn monitor_enter(&mut self, obj: ObjRef) {
+        let thread = self.current_thread;
+        let monitor = self.monitors.entry(obj).or_default();
+        match monitor.owner {
+            None => {
+                monitor.owner = Some(thread);
+                monitor.count = 1;
+            }
+            Some(owner) if owner == thread => monitor.count += 1,
+            Some(_) => self.block(ThreadState::Blocked {
+                monitor: obj,
+                count: 1,
+            }),
+        }
+    }
+
+    /// Exits the monitor of the object, which the current thread has to hold
+    pub(crate) fn monitor_exit(&mut self, obj: ObjRef) -> Result<()> {
+        self.check_owner(obj)?;
+        let monitor = self
+            .monitors
+            .get_mut(&obj)
+            .expect("the thread holds the monitor");
+        monitor.count -= 1;
+        if monitor.count == 0 {
+            monitor.owner = None;
+            if monitor.waiters.is_empty() {
+                self.monitors.remove(&obj);
+            }
+        }
+        Ok(())
+    }
+
+    fn try_enter(&mut self, thread: usize, obj: ObjRef, count: u32) -> bool {
+        let monitor = self.monitors.entry(obj).or_default();
+        if monitor.owner.is_some() {
+            return false;
+        }
+        monitor.owner = Some(thread);
+        monitor.count = count;
+        self.set_thread_state(thread, ThreadState::Runnable);
+        true
+    }
+
+    fn check_owner(&mut self, obj: ObjRef) -> Result<()> {
+        let owner = self.monitors.get(&obj).and_then(|monitor| monitor.owner);
+        if owner != Some(self.current_thread) {
+            return Err(self.throw_new(
+                "java/lang/IllegalMonitorStateException",
+                Some("current thread is not owner"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Releases the monitor of the object and waits until it is notified, see `Object.wait`.
+    /// A timeout of zero waits forever
+    fn wait(&mut self, obj: ObjRef, timeout: i64) -> Result<()> {
+        self.check_owner(obj)?;
+        if timeout < 0 {
+            return Err(self.throw_new(
+                "java/lang/IllegalArgumentException",
+                Some("timeout value is negative"),
+            ));
+        }
+        let thread = self.current_thread;
+        if self.take_interrupt(thread)? {
+            return Err(self.throw_new("java/lang/InterruptedException", None));
+        }
+
+        let monitor = self
+            .monitors
+            .get_mut(&obj)
+            .expect("the thread holds the monitor");
+        let count = std::mem::take(&mut monitor.count);
+        monitor.owner = None;
+        monitor.waiters.push_back(thread);
+        let deadline =
+            (timeout > 0).then(|| self.clock.now() + Duration::from_millis(timeout as u64));
+        self.block(ThreadState::Waiting {
+            monitor: obj,
+            count,
+            deadline,
+        });
+        Ok(())
+    }
+
+    /// Removes a waiting thread from the wait set, it then has to enter the monitor again
+    fn stop_waiting(&mut self, thread: usize, obj: ObjRef, count: u32) {
+        if let Some(monitor) = self.monitors.get_mut(&obj) {
+            monitor.waiters.retain(|&waiter| waiter != thread);
+        }
+        self.set_thread_state(
+            thread,
+            ThreadState::Blocked {
+                monitor: obj,
+                count,
+            },
+        );
+    }
+
+    /// Wakes the first or all threads that wait on the object
+    fn notify_waiters(&mut self, obj: ObjRef, all: bool) {
+        let waiters = match self.monitors.get_mut(&obj) {
+            Some(monitor) if all => monitor.waiters.drain(..).collect(),
+            Some(monitor) => monitor.waiters.pop_front().into_iter().collect(),
+            None => Vec::new(),
+        };
+        for waiter in waiters {
+            if let ThreadState::Waiting { count, .. } = self.threads[waiter].state {
+                self.set_thread_state(
+                    waiter,
+                    ThreadState::Blocked {
+                        monitor: obj,
+                        count,
+                    },
+                );
+            }
+        }
+        let unused = self
+            .monitors
+            .get(&obj)
+            .is_some_and(|monitor| monitor.owner.is_none() && monitor.waiters.is_empty());
+        if unused {
+            self.monitors.remove(&obj);
+        }
+    }
+
+    /// Parks the current thread, see `Unsafe.park`. The time is the deadline in milliseconds
+    /// since the epoch if it is absolute, or a relative timeout in nanoseconds otherwise,
+    /// where zero waits forever
+    fn park(&mut self, absolute: bool, time: i64) -> Result<()> {
+        let thread = self.current_thread;
+        if std::mem::take(&mut self.threads[thread].permit) {
+            return Ok(());
+        }
+        let interrupted = self.threads[thread].object.and_then(|object| {
+            let class = self.heap.get(object).class;
+            let slot = self
+                .class(class)
+                .instance_field_slot("interrupted", &FieldType::Boolean)?;
+            self.get_field(object, slot).ok()
+        });
+        if interrupted == Some(Value::Int(1)) {
+            return Ok(());
+        }
+
+        let deadline = if absolute {
+            let now = self.clock.since_epoch().as_millis() as i64;
+            if time <= now {
+                return Ok(());
+            }
+            Some(self.clock.now() + Duration::from_millis((time - now) as u64))
+        } else if time < 0 {
+            return Ok(());
+        } else {
+            (time > 0).then(|| self.clock.now() + Duration::from_nanos(time as u64))
+        };
+        self.block(ThreadState::Parked { deadline });
+        Ok(())
+    }
+
+    /// Pops the top frame of the current thread, exiting the monitor of a `synchronized` method
+    pub(crate) fn pop_frame(&mut self) {
+        if let Some(monitor) = self
+            .thread_mut()
+            .frames
+            .pop()
+            .and_then(|frame| frame.monitor)
+        {
+            // the monitor can only be held by another thread after invalid `monitorexit`s
+            let _ = self.monitor_exit(monitor);
+        }
+    }
+
+    /// Pops frames until only `base` frames are left
+    pub(crate) fn pop_frames(&mut self, base: usize) {
+        while self.thread().frames.len() > base {
+            self.pop_frame();
+        }
+    }
+
+    pub(crate) fn register_thread_natives(&mut self) {
+        self.register_native("java/lang/Object", "wait", "(J)V", |vm, args| {
+            vm.wait(this(args)?, args[1].as_long()?)?;
+            Ok(None)
+        });
+        self.register_native("java/lang/Object", "notify", "()V", |vm, args| {
+            let obj = this(args)?;
+            vm.check_owner(obj)?;
+            vm.notify_waiters(obj, false);
+            Ok(None)
+        });
+        self.register_native("java/lang/Object", "notifyAll", "()V", |vm, args| {
+            let obj = this(args)?;
+            vm.check_owner(obj)?;
+            vm.notify_waiters(obj, true);
+            Ok(None)
+        });
+
+        const THREAD: &str = "java/lang/Thread";
+        self.register_native(THREAD, "currentThread", "()Ljava/lang/Thread;", |vm, _| {
+            Ok(Some(Value::Reference(Some(vm.current_thread_object()?))))
+        });
+        self.register_native(THREAD, "isAlive", "()Z", |vm, args| {
+            let alive = vm.thread_index(this(args)?).is_some();
+            Ok(Some(Value::Int(alive as i32)))
+        });
+        self.register_native(THREAD, "start0", "()V", |vm, args| {
+            let object = this(args)?;
+            if vm.thread_index(object).is_some() {
+                return Err(vm.throw_new("java/lang/IllegalThreadStateException", None));
+            }
+            vm.start_thread(object)?;
+            Ok(None)
+        });
+        self.register_native(THREAD, "yield", "()V", |vm, _| {
+            vm.slice = 0;
+            Ok(None)
+        });
+        self.register_native(THREAD, "sleep", "(J)V", |vm, args| {
+            let millis = args[0].as_long()?;
+            if millis < 0 {
+                return Err(vm.throw_new(
+                    "java/lang/IllegalArgumentException",
+                    Some("timeout value is negative"),
+                ));
+            }
+            if vm.take_interrupt(vm.current_thread)? {
+                return Err(
+                    vm.throw_new("java/lang/InterruptedException", Some("sleep interrupted"))
+                );
+            }
+            match millis {
+                0 => vm.slice = 0,
+                _ => vm.block(ThreadState::Sleeping {
+                    deadline: vm.clock.now() + Duration::from_millis(millis as u64),
+                }),
+            }
+            Ok(None)
+        });
+        self.register_native(THREAD, "interrupt0", "()V", |vm, args| {
+            if let Some(thread) = vm.thread_index(this(args)?) {
+                vm.interrupt(thread);
+            }
+            Ok(None)
+        });
+        self.register_native(THREAD, "clearInterruptEvent", "()V", |_, _| Ok(None));
+        self.register_native(THREAD, "setPriority0", "(I)V", |_, _| Ok(None));
+        self.register_native(THREAD, "holdsLock", "(Ljava/lang/Object;)Z", |vm, args| {
+            let obj = args[0].as_reference()?.ok_or(VmError::NullPointer)?;
+            let owner = vm.monitors.get(&obj).and_then(|monitor| monitor.owner);
+            Ok(Some(Value::Int((owner == Some(vm.current_thread)) as i32)))
+        });
+
+        const UNSAFE: &str = "jdk/internal/misc/Unsafe";
+        self.register_native(UNSAFE, "park", "(ZJ)V", |vm, args| {
+            vm.park(args[1].as_int()? != 0, args[2].as_long()?)?;
+            Ok(None)
+        });
+        self.register_native(UNSAFE, "unpark", "(Ljava/lang/Object;)V", |vm, args| {
+            let thread = args[1]
+                .as_reference()?
+                .and_then(|object| vm.thread_index(object));
+            if let Some(thread) = thread {
+                match vm.threads[thread].state {
+                    ThreadState::Parked { .. } => {
+                        vm.set_thread_state(thread, ThreadState::Runnable)
+                    }
+                    _ => vm.threads[thread].permit = true,
+                }
+            }
+            Ok(None)
+        });
+    }
+}
+
+/// Whether the thread still has to run. A thread without frames may still have to enter
+/// the monitor of its object to terminate
+fn is_active(thread: &Thread) -> bool {
+    thread.is_alive()
+        && (!thread.frames.is_empty() || matches!(thread.state, ThreadState::Blocked { .. }))
+}
+
+/// The value of `Thread.threadStatus` for the state of a thread
+fn thread_status(state: ThreadState) -> i32 {
+    let timeout = |deadline: Option<Instant>| match deadline {
+        Some(_) => THREAD_WAITING_WITH_TIMEOUT,
+        None => THREAD_WAITING_INDEFINITELY,
+    };
+    match state {
+        ThreadState::Runnable => THREAD_ALIVE | THREAD_RUNNABLE,
+        ThreadState::Blocked { .. } => THREAD_ALIVE | THREAD_BLOCKED_ON_MONITOR_ENTER,
+        ThreadState::Waiting { deadline, .. } => {
+            THREAD_ALIVE | THREAD_WAITING | THREAD_IN_OBJECT_WAIT | timeout(deadline)
+        }
+        ThreadState::Sleeping { .. } => {
+            THREAD_ALIVE | THREAD_WAITING | THREAD_SLEEPING | THREAD_WAITING_WITH_TIMEOUT
+        }
+        ThreadState::Parked { deadline } => {
+            THREAD_ALIVE | THREAD_WAITING | THREAD_PARKED | timeout(deadline)
+        }
+        ThreadState::WaitingForClass { .. } => {
+            THREAD_ALIVE | THREAD_WAITING | THREAD_WAITING_INDEFINITELY
+        }
+        ThreadState::Terminated => THREAD_TERMINATED,
+    }
+}
@@ -0,0 +1,226 @@
+//!
+//! Method tables for virtual and interface calls
+//!
+//! Every class gets a vtable when it is defined. It starts with the vtable of the superclass,
+//! where overriding methods replace the slot of the method they override, and new methods are
+//! appended. A package private method is only overridden in its package, so a class can have
+//! several slots with the same name and descriptor. Interface methods that no class in the
+//! hierarchy implements get the selected default method, or the abstract interface method if
+//! there is none. Conflicting default methods only fail when they are invoked.
+//! The itable maps the methods of each implemented interface to their slot in the vtable
+//!
+
+use crate::class::{ClassId, MethodId};
+use crate::{Result, Vm, VmError};
+
+/// The implementations of the methods of an interface in a class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItableEntry {
+    pub interface: ClassId,
+    /// The selected methods, in the order of the `vtable` of the interface
+    pub methods: Vec<MethodId>,
+}
+
+impl Vm {
+    /// Builds the vtable and itable of a class that was just defined, see JVMS §5.4.5 and §5.4.6
+    pub(crate) fn link_methods(&mut self, id: ClassId) -> Result<()> {
+        if self.class(id).is_interface() {
+            return self.link_interface_methods(id);
+        }
+
+        let mut vtable = match self.class(id).super_class {
+            Some(super_class) => self.class(super_class).vtable.clone(),
+            None => Vec::new(),
+        };
+
+        for index in 0..self.class(id).methods.len() {
+            let method = &self.class(id).methods[index];
+            if method.is_static() || method.is_private() || method.name.starts_with('<') {
+                continue;
+            }
+
+            let overridden = (0..vtable.len())
+                .filter(|&slot| {
+                    let overridden = self.method(vtable[slot]);
+                    overridden.name == method.name
+                        && overridden.descriptor == method.descriptor
+                        && self.can_override(id, vtable[slot])
+                })
+                .collect::<Vec<_>>();
+            for &slot in &overridden {
+                if self.method(vtable[slot]).is_final() {
+                    return Err(VmError::Verify(format!(
+                        "{} overrides final method {}",
+                        self.class(id).name,
+                        self.method_name(vtable[slot])
+                    )));
+                }
+                vtable[slot] = MethodId { class: id, index };
+            }
+            let slot = match overridden.first() {
+                Some(&slot) => slot,
+                None => {
+                    vtable.push(MethodId { class: id, index });
+                    vtable.len() - 1
+                }
+            };
+            self.class_mut(id).methods[index].vtable_index = Some(slot);
+        }
+
+        // interface methods that are not implemented by a class, including defaults that were
+        // inherited from the superclass but may now be overridden by a more specific interface
+        let interfaces = self.all_interfaces(id);
+        let mut conflicting_defaults = Vec::new();
+        for &interface in &interfaces {
+            for &method in &self.class(interface).vtable {
+                let method = self.method(method);
+                let slot = self.vtable_slot(&vtable, &method.name, &method.descriptor);
+                if let Some(slot) = slot {
+                    if !self.class(vtable[slot].class).is_interface() {
+                        continue;
+                    }
+                }
+
+                let (selected, conflict) =
+                    self.select_default(id, &method.name, &method.descriptor);
+                if conflict {
+                    conflicting_defaults.push(selected);
+                }
+                match slot {
+                    Some(slot) => vtable[slot] = selected,
+                    None => vtable.push(selected),
+                }
+            }
+        }
+
+        let itable = interfaces
+            .iter()
+            .map(|&interface| ItableEntry {
+                interface,
+                methods: self
+                    .class(interface)
+                    .vtable
+                    .iter()
+                    .map(|&method| {
+                        let method = self.method(method);
+                        let slot = self
+                            .vtable_slot(&vtable, &method.name, &method.descriptor)
+                            .expect("all interface methods are in the vtable");
+                        vtable[slot]
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let class = self.class_mut(id);
+        class.vtable = vtable;
+        class.itable = itable;
+        class.conflicting_defaults = conflicting_defaults;
+        self.invalidate_inline_caches(id);
+        Ok(())
+    }
+
+    /// The vtable of an interface only contains its own instance methods
+    fn link_interface_methods(&mut self, id: ClassId) -> Result<()> {
+        let class = self.class_mut(id);
+        for (index, method) in class.methods.iter_mut().enumerate() {
+            if method.is_static() || method.is_private() || method.name.starts_with('<') {
+                continue;
+            }
+            method.vtable_index = Some(class.vtable.len());
+            class.vtable.push(MethodId { class: id, index });
+        }
+        Ok(())
+    }
+
+    /// The slot of the method with the name and descriptor that an interface method selects.
+    /// If package private methods left several, the one of the most specific class
+    fn vtable_slot(&self, vtable: &[MethodId], name: &str, descriptor: &str) -> Option<usize> {
+        (0..vtable.len())
+            .filter(|&slot| {
+                let method = self.method(vtable[slot]);
+                method.name == name && method.descriptor == descriptor
+            })
+            .reduce(|slot, other| {
+                let (class, other_class) = (vtable[slot].class, vtable[other].class);
+                match self.class(class).is_interface() || self.is_subclass(other_class, class) {
+                    true => other,
+                    false => slot,
+                }
+            })
+    }
+
+    /// Whether a method of the class can override the method, see JVMS §5.4.5. A package private
+    /// method can only be overridden in its runtime package, there is only one class loader
+    fn can_override(&self, class: ClassId, overridden: MethodId) -> bool {
+        let method = self.method(overridden);
+        let package = |class: ClassId| {
+            let name = self.class(class).name.as_str();
+            name.rsplit_once('/').map_or("", |(package, _)| package)
+        };
+        method.is_public() || method.is_protected() || package(class) == package(overridden.class)
+    }
+
+    /// Selects the method for an interface method that no class in the hierarchy implements.
+    /// If no default method exists, this is an abstract method, which fails when it is invoked.
+    /// If several default methods conflict, one of them is returned with `true`, and invoking it
+    /// fails as well
+    fn select_default(&self, class: ClassId, name: &str, descriptor: &str) -> (MethodId, bool) {
+        let candidates = self.maximally_specific_methods(class, name, descriptor);
+        let defaults = candidates
+            .iter()
+            .filter(|&&method| !self.method(method).is_abstract())
+            .collect::<Vec<_>>();
+        match defaults[..] {
+            [] => (candidates[0], false),
+            [&method] => (method, false),
+            [&method, ..] => (method, true),
+        }
+    }
+
+    /// Selects the method that an `invokevirtual` or `invokeinterface` of the resolved method
+    /// invokes on an object of the class
+    pub(crate) fn select_method(&self, class: ClassId, resolved: MethodId) -> Result<MethodId> {
+        let slot = match self.method(resolved).vtable_index {
+            Some(slot) => slot,
+            // private methods can not be overridden
+            None => return Ok(resolved),
+        };
+
+        let class_ref = self.class(class);
+        let selected = if self.class(resolved.class).is_interface() {
+            class_ref
+                .itable
+                .iter()
+                .find(|entry| entry.interface == resolved.class)
+                .map(|entry| entry.methods[slot])
+                .ok_or_else(|| {
+                    VmError::IncompatibleClassChange(format!(
+                        "{} does not implement {}",
+                        class_ref.name,
+                        self.class(resolved.class).name
+                    ))
+                })?
+        } else {
+            *class_ref.vtable.get(slot).ok_or_else(|| {
+                VmError::Verify(format!(
+                    "{} is not a subclass of {}",
+                    class_ref.name,
+                    self.class(resolved.class).name
+                ))
+            })?
+        };
+
+        if class_ref.conflicting_defaults.contains(&selected) {
+            let method = self.method(selected);
+            return Err(VmError::IncompatibleClassChange(format!(
+                "Conflicting default methods for {}{} in {}",
+                method.name, method.descriptor, class_ref.name
+            )));
+        }
+        if self.method(selected).is_abstract() {
+            return Err(VmError::AbstractMethod(self.method_name(selected)));
+        }
+        Ok(selected)
+    }
+}
@@ -0,0 +1,1508 @@
+//!
+//! Compilation of class files to WebAssembly
+//!
+//! The methods that the public static methods of a class can reach in a closed world, see `aot`,
+//! are translated to a module that exports them. The operand stack and the local variables become
+//! wasm locals, one per slot and type, and the branches are arranged into blocks and loops, see
+//! `structure`. Native methods are imported from the module `natives`, by their name like
+//! `Utils.print(I)V`.
+//!
+//! References are addresses in the exported linear memory, where a bump allocator places objects
+//! that are never freed: an object is its class id followed by 8 bytes for every field, an array
+//! its class id, its length and its elements. Classes are initialized before their first use,
+//! like in the VM, and virtual calls compare the class id with the classes that are instantiated.
+//! Exceptions trap, so methods with exception handlers can not be compiled, and neither can
+//! strings, `invokedynamic`, `multianewarray`, `frem` and `drem`
+//!
+
+use crate::aot::Analysis;
+use crate::class::{array_class_name, parse_field_type, ClassId, ClassKind, Code, MethodId};
+use crate::instruction::{Instruction, Switch, INVALID};
+use crate::model::Value;
+use crate::opcode::{self, *};
+use crate::structure::{self, Node};
+use crate::{Result, Vm, VmError};
+use cs_model::{FieldType, MethodType};
+use cs_parser::{u1, u2};
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+/// The address of the first static field, the bytes before it are never an object
+const STATICS: u32 = 8;
+/// The bytes before the fields of an object and the elements of an array
+const HEADER: u32 = 8;
+/// The global with the address the next object is allocated at
+const HEAP: u32 = 0;
+
+// the wasm instructions
+const UNREACHABLE: u8 = 0x00;
+const BLOCK: u8 = 0x02;
+const LOOP: u8 = 0x03;
+const IF: u8 = 0x04;
+const ELSE: u8 = 0x05;
+const END: u8 = 0x0b;
+const BR: u8 = 0x0c;
+const BR_IF: u8 = 0x0d;
+const BR_TABLE: u8 = 0x0e;
+const RETURN_: u8 = 0x0f;
+const CALL: u8 = 0x10;
+const LOCAL_GET: u8 = 0x20;
+const LOCAL_SET: u8 = 0x21;
+const LOCAL_TEE: u8 = 0x22;
+const GLOBAL_GET: u8 = 0x23;
+const GLOBAL_SET: u8 = 0x24;
+const I32_LOAD: u8 = 0x28;
+const I32_STORE: u8 = 0x36;
+const MEMORY_SIZE: u8 = 0x3f;
+const MEMORY_GROW: u8 = 0x40;
+const I32_CONST: u8 = 0x41;
+const I64_CONST: u8 = 0x42;
+const F32_CONST: u8 = 0x43;
+const F64_CONST: u8 = 0x44;
+const I32_EQZ: u8 = 0x45;
+const I32_EQ: u8 = 0x46;
+const I32_NE: u8 = 0x47;
+const I32_LT_S: u8 = 0x48;
+const I32_GT_S: u8 = 0x4a;
+const I32_GT_U: u8 = 0x4b;
+const I32_LE_S: u8 = 0x4c;
+const I32_GE_S: u8 = 0x4e;
+const I32_GE_U: u8 = 0x4f;
+const I32_ADD: u8 = 0x6a;
+const I32_SUB: u8 = 0x6b;
+const I32_MUL: u8 = 0x6c;
+const I32_AND: u8 = 0x71;
+const I32_OR: u8 = 0x72;
+const I32_SHL: u8 = 0x74;
+const I32_SHR_U: u8 = 0x76;
+/// Followed by the number of a saturating truncation
+const TRUNC_SAT: u8 = 0xfc;
+const EMPTY: u8 = 0x40;
+
+/// The type of a wasm value, references are addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl Kind {
+    fn of(ty: &FieldType) -> Kind {
+        match ty {
+            FieldType::Long => Kind::I64,
+            FieldType::Float => Kind::F32,
+            FieldType::Double => Kind::F64,
+            _ => Kind::I32,
+        }
+    }
+
+    /// The kind of the `i`, `l`, `f`, `d` or `a` variant of an instruction
+    fn nth(n: u1) -> Kind {
+        [Kind::I32, Kind::I64, Kind::F32, Kind::F64, Kind::I32][n as usize]
+    }
+
+    fn value_type(self) -> u8 {
+        match self {
+            Kind::I32 => 0x7f,
+            Kind::I64 => 0x7e,
+            Kind::F32 => 0x7d,
+            Kind::F64 => 0x7c,
+        }
+    }
+
+    /// The slots of the operand stack that the value takes in the JVM
+    fn words(self) -> usize {
+        match self {
+            Kind::I64 | Kind::F64 => 2,
+            _ => 1,
+        }
+    }
+
+    /// The wasm instruction for a binary operation of the kind, from the ones for i32, i64,
+    /// f32 and f64
+    fn op(self, ops: [u8; 4]) -> u8 {
+        ops[self as usize]
+    }
+}
+
+/// How a field or array element of a type is stored: its size, load and store instructions
+fn storage(ty: &FieldType) -> (u32, u8, u8) {
+    match ty {
+        FieldType::Boolean => (1, 0x2d, 0x3a),
+        FieldType::Byte => (1, 0x2c, 0x3a),
+        FieldType::Char => (2, 0x2f, 0x3b),
+        FieldType::Short => (2, 0x2e, 0x3b),
+        FieldType::Long => (8, 0x29, 0x37),
+        FieldType::Float => (4, 0x2a, 0x38),
+        FieldType::Double => (8, 0x2b, 0x39),
+        _ => (4, I32_LOAD, I32_STORE),
+    }
+}
+
+/// The functions of the module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Function {
+    /// A compiled method, or the import of a native one
+    Method(MethodId),
+    /// Initializes the class of an exported method, then invokes it
+    Export(MethodId),
+    /// Selects the method of a virtual call by the class of the receiver
+    Dispatch(MethodId),
+    /// Initializes the class and its superclasses once
+    Initialize(ClassId),
+    InstanceOf(ClassId),
+    /// `(size, class) -> object`
+    Alloc,
+    /// `(length, element size, class) -> array`
+    NewArray,
+    /// `(array, index, element size) -> address`, checks the reference and the index
+    Element,
+    /// `(object, offset) -> address`, checks the reference
+    Field,
+    /// `idiv` or `ldiv`, which wraps around for the minimum value divided by -1
+    Div(Kind),
+}
+
+impl Function {
+    /// Whether the code of the function depends on the loaded classes
+    fn tests_classes(self) -> bool {
+        matches!(self, Function::Dispatch(_) | Function::InstanceOf(_))
+    }
+}
+
+/// A module while its functions are compiled
+struct Module {
+    vm: Vm,
+    /// The classes whose objects can exist, besides arrays
+    instantiated: Vec<ClassId>,
+    types: Vec<(Vec<Kind>, Option<Kind>)>,
+    /// The names and types of the natives
+    imports: Vec<(String, u32)>,
+    functions: HashMap<Function, u32>,
+    /// The types and code of the functions after the imports, empty until the code is generated
+    bodies: Vec<(u32, Vec<u8>)>,
+    pending: Vec<Function>,
+    /// The initial values of the mutable i32 globals, the first one is `HEAP`
+    globals: Vec<i32>,
+    /// The globals that are set once a class is initialized
+    initialized: HashMap<ClassId, u32>,
+    /// The addresses of the static fields, by class and slot
+    statics: HashMap<(ClassId, usize), u32>,
+    /// The initial bytes of the static fields
+    data: Vec<u8>,
+    exports: Vec<(String, u32)>,
+    errors: BTreeSet<String>,
+}
+
+/// Compiles the public static methods of the class, and what they reach in the program with the
+/// class files, to a WebAssembly module. Returns `VmError::Unsupported` with everything that
+/// keeps it from being compiled
+pub fn compile_wasm(class_files: &[Vec<u1>], class: &str) -> Result<Vec<u1>> {
+    let mut analysis = Analysis::new(class_files)?;
+    let class_id = analysis.vm.resolve_class(class)?;
+    let exported = (0..analysis.vm.class(class_id).methods.len())
+        .map(|index| MethodId {
+            class: class_id,
+            index,
+        })
+        .filter(|&id| {
+            let method = analysis.vm.method(id);
+            method.is_static() && method.is_public() && method.name != "<clinit>"
+        })
+        .collect::<Vec<_>>();
+    for &method in &exported {
+        analysis.analyze(method);
+    }
+    if !analysis.unsupported.is_empty() {
+        return Err(unsupported(class, analysis.unsupported));
+    }
+
+    let mut module = Module {
+        vm: analysis.vm,
+        instantiated: analysis.instantiated,
+        types: Vec::new(),
+        imports: Vec::new(),
+        functions: HashMap::new(),
+        bodies: Vec::new(),
+        pending: Vec::new(),
+        globals: vec![0],
+        initialized: HashMap::new(),
+        statics: HashMap::new(),
+        data: Vec::new(),
+        exports: Vec::new(),
+        errors: BTreeSet::new(),
+    };
+    for &method in &analysis.methods {
+        if module.vm.method(method).is_native() {
+            let (params, result) = module.signature(method);
+            let ty = module.type_index(params, result);
+            let index = module.imports.len() as u32;
+            module.imports.push((module.vm.method_name(method), ty));
+            module.functions.insert(Function::Method(method), index);
+        }
+    }
+    for &method in &exported {
+        let method_ref = module.vm.method(method);
+        let overloaded = exported
+            .iter()
+            .any(|&other| other != method && module.vm.method(other).name == method_ref.name);
+        let name = match overloaded {
+            true => format!("{}{}", method_ref.name, method_ref.descriptor),
+            false => method_ref.name.clone(),
+        };
+        let function = module.function(Function::Export(method));
+        module.exports.push((name, function));
+    }
+    module.generate();
+    if !module.errors.is_empty() {
+        return Err(unsupported(class, module.errors));
+    }
+    Ok(module.finish())
+}
+
+fn unsupported(class: &str, what: BTreeSet<String>) -> VmError {
+    let what = what.into_iter().collect::<Vec<_>>();
+    VmError::Unsupported(format!(
+        "{} can not be compiled to WebAssembly:\n  {}",
+        class,
+        what.join("\n  ")
+    ))
+}
+
+impl Module {
+    /// The index of the function, its code is generated later
+    fn function(&mut self, function: Function) -> u32 {
+        if let Some(&index) = self.functions.get(&function) {
+            return index;
+        }
+        let index = (self.imports.len() + self.bodies.len()) as u32;
+        self.bodies.push((0, Vec::new()));
+        self.functions.insert(function, index);
+        self.pending.push(function);
+        index
+    }
+
+    /// Generates the code of all functions. The class tests are generated again until no
+    /// class is loaded while they are generated
+    fn generate(&mut self) {
+        loop {
+            while let Some(function) = self.pending.pop() {
+                if !function.tests_classes() {
+                    self.generate_function(function);
+                }
+            }
+            let classes = self.vm.classes.len();
+            let tests = (self.functions.keys().copied())
+                .filter(|function| function.tests_classes())
+                .collect::<Vec<_>>();
+            for function in tests {
+                self.generate_function(function);
+            }
+            if self.pending.is_empty() && self.vm.classes.len() == classes {
+                return;
+            }
+        }
+    }
+
+    fn generate_function(&mut self, function: Function) {
+        let (params, result) = match function {
+            Function::Method(method) | Function::Export(method) | Function::Dispatch(method) => {
+                self.signature(method)
+            }
+            Function::Initialize(_) => (Vec::new(), None),
+            Function::InstanceOf(_) => (vec![Kind::I32], Some(Kind::I32)),
+            Function::Alloc | Function::Field => (vec![Kind::I32; 2], Some(Kind::I32)),
+            Function::NewArray | Function::Element => (vec![Kind::I32; 3], Some(Kind::I32)),
+            Function::Div(kind) => (vec![kind; 2], Some(kind)),
+        };
+        let mut body = Body::new(params.len());
+        let generated = match function {
+            Function::Method(method) => self.method(method, &mut body),
+            Function::Export(method) => {
+                self.initialize(&mut body, method.class, None);
+                for param in 0..params.len() as u32 {
+                    body.op_u(LOCAL_GET, param);
+                }
+                let method = self.function(Function::Method(method));
+                body.op_u(CALL, method);
+                Ok(())
+            }
+            Function::Dispatch(method) => self.dispatch(method, &params, &mut body),
+            Function::Initialize(class) => self.initializer(class, &mut body),
+            Function::InstanceOf(class) => {
+                self.instance_of(class, &mut body);
+                Ok(())
+            }
+            Function::Alloc => {
+                alloc(&mut body);
+                Ok(())
+            }
+            Function::NewArray => {
+                let alloc = self.function(Function::Alloc);
+                new_array(&mut body, alloc);
+                Ok(())
+            }
+            Function::Element => {
+                element(&mut body);
+                Ok(())
+            }
+            Function::Field => {
+                body.null_check(0);
+                body.code.extend([LOCAL_GET, 0, LOCAL_GET, 1, I32_ADD]);
+                Ok(())
+            }
+            Function::Div(kind) => {
+                div(&mut body, kind);
+                Ok(())
+            }
+        };
+        if let Err(err) = generated {
+            let what = match err {
+                VmError::Unsupported(what) => what,
+                err => err.to_string(),
+            };
+            let name = match function {
+                Function::Method(method) => self.vm.method_name(method),
+                _ => format!("{:?}", function),
+            };
+            self.errors.insert(format!("{}: {}", name, what));
+            body = Body::new(params.len());
+            body.code.push(UNREACHABLE);
+        }
+        let ty = self.type_index(params, result);
+        let index = self.functions[&function] as usize - self.imports.len();
+        self.bodies[index] = (ty, body.finish());
+    }
+
+    fn type_index(&mut self, params: Vec<Kind>, result: Option<Kind>) -> u32 {
+        let ty = (params, result);
+        match self.types.iter().position(|other| other == &ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// The parameters, with the receiver of instance methods, and the result of the method
+    fn signature(&self, method: MethodId) -> (Vec<Kind>, Option<Kind>) {
+        let method = self.vm.method(method);
+        let receiver = (!method.is_static()).then_some(Kind::I32);
+        let params = receiver
+            .into_iter()
+            .chain(method.method_descriptor.parameters.iter().map(Kind::of))
+            .collect();
+        let result = match &method.method_descriptor.return_ {
+            MethodType::Some(ty) => Some(Kind::of(ty)),
+            MethodType::Void => None,
+        };
+        (params, result)
+    }
+
+    /// Whether the class or a superclass has a static initializer
+    fn needs_initialization(&self, class: ClassId) -> bool {
+        let mut class = Some(class);
+        while let Some(id) = class {
+            let class_ref = self.vm.class(id);
+            if class_ref.method_index("<clinit>", "()V").is_some() {
+                return true;
+            }
+            class = class_ref.super_class;
+        }
+        false
+    }
+
+    /// Initializes the class, unless the code runs in it or in a subclass
+    fn initialize(&mut self, body: &mut Body, class: ClassId, current: Option<ClassId>) {
+        let initialized = current.is_some_and(|current| self.vm.is_subclass(current, class));
+        if !initialized && self.needs_initialization(class) {
+            let initialize = self.function(Function::Initialize(class));
+            body.op_u(CALL, initialize);
+        }
+    }
+
+    fn initializer(&mut self, class: ClassId, body: &mut Body) -> Result<()> {
+        let global = self.globals.len() as u32;
+        self.globals.push(0);
+        self.initialized.insert(class, global);
+        body.op_u(GLOBAL_GET, global);
+        body.code.extend([I32_EQZ, IF, EMPTY, I32_CONST, 1]);
+        body.op_u(GLOBAL_SET, global);
+        if let Some(super_class) = self.vm.class(class).super_class {
+            self.initialize(body, super_class, None);
+        }
+        if let Some(index) = self.vm.class(class).method_index("<clinit>", "()V") {
+            let clinit = self.function(Function::Method(MethodId { class, index }));
+            body.op_u(CALL, clinit);
+        }
+        body.code.push(END);
+        Ok(())
+    }
+
+    /// The methods that the receivers of a virtual call select, with their classes
+    fn targets(&self, resolved: MethodId) -> Vec<(MethodId, Vec<ClassId>)> {
+        let arrays = (0..self.vm.classes.len() as u32)
+            .map(ClassId)
+            .filter(|&class| self.vm.class(class).is_array());
+        let mut targets: Vec<(MethodId, Vec<ClassId>)> = Vec::new();
+        for class in self.instantiated.iter().copied().chain(arrays) {
+            if !self.vm.is_assignable(class, resolved.class) {
+                continue;
+            }
+            let Ok(selected) = self.vm.select_method(class, resolved) else {
+                continue;
+            };
+            match targets.iter_mut().find(|(method, _)| *method == selected) {
+                Some((_, classes)) => classes.push(class),
+                None => targets.push((selected, vec![class])),
+            }
+        }
+        targets
+    }
+
+    fn dispatch(&mut self, resolved: MethodId, params: &[Kind], body: &mut Body) -> Result<()> {
+        let class = body.local(Kind::I32);
+        body.null_check(0);
+        body.code.extend([LOCAL_GET, 0]);
+        body.memarg(I32_LOAD, 0);
+        body.op_u(LOCAL_SET, class);
+        for (method, classes) in self.targets(resolved) {
+            for (index, &receiver) in classes.iter().enumerate() {
+                body.op_u(LOCAL_GET, class);
+                body.i32_const(receiver.0 as i32);
+                body.code.push(I32_EQ);
+                if index > 0 {
+                    body.code.push(I32_OR);
+                }
+            }
+            body.code.extend([IF, EMPTY]);
+            for param in 0..params.len() as u32 {
+                body.op_u(LOCAL_GET, param);
+            }
+            let method = self.function(Function::Method(method));
+            body.op_u(CALL, method);
+            body.code.extend([RETURN_, END]);
+        }
+        body.code.push(UNREACHABLE);
+        Ok(())
+    }
+
+    fn instance_of(&mut self, class: ClassId, body: &mut Body) {
+        let object_class = body.local(Kind::I32);
+        body.code
+            .extend([LOCAL_GET, 0, I32_EQZ, IF, EMPTY, I32_CONST, 0, RETURN_, END]);
+        body.code.extend([LOCAL_GET, 0]);
+        body.memarg(I32_LOAD, 0);
+        body.op_u(LOCAL_SET, object_class);
+        body.i32_const(0);
+        for index in 0..self.vm.classes.len() as u32 {
+            let other = ClassId(index);
+            if self.vm.class(other).kind != ClassKind::Primitive
+                && self.vm.is_assignable(other, class)
+            {
+                body.op_u(LOCAL_GET, object_class);
+                body.i32_const(index as i32);
+                body.code.extend([I32_EQ, I32_OR]);
+            }
+        }
+    }
+
+    /// The address of the static field, its initial value is the `ConstantValue` of the field
+    fn static_address(&mut self, class: ClassId, slot: usize) -> Result<u32> {
+        if let Some(&address) = self.statics.get(&(class, slot)) {
+            return Ok(address);
+        }
+        let address = STATICS + self.data.len() as u32;
+        let field = &self.vm.class(class).static_fields[slot];
+        let value = match field.constant_value {
+            Some(index) => match self.vm.class(class).cp_numeric(index) {
+                Ok(value) => value,
+                Err(_) => return Err(VmError::Unsupported("string constants".to_string())),
+            },
+            None => Value::Long(0),
+        };
+        let bytes = match value {
+            Value::Int(value) => (value as i64).to_le_bytes(),
+            Value::Long(value) => value.to_le_bytes(),
+            Value::Float(value) => (value.to_bits() as u64).to_le_bytes(),
+            Value::Double(value) => value.to_bits().to_le_bytes(),
+            _ => [0; 8],
+        };
+        self.data.extend(bytes);
+        self.statics.insert((class, slot), address);
+        Ok(address)
+    }
+
+    fn method(&mut self, method: MethodId, body: &mut Body) -> Result<()> {
+        let code = self
+            .vm
+            .method(method)
+            .code
+            .clone()
+            .ok_or_else(|| VmError::AbstractMethod(self.vm.method_name(method)))?;
+        if !code.exception_table.is_empty() {
+            return Err(VmError::Unsupported("exception handlers".to_string()));
+        }
+        let nodes = structure::structure(&code)?;
+
+        let (params, _) = self.signature(method);
+        let mut translator = Translator {
+            module: self,
+            method,
+            code,
+            body,
+            variables: HashMap::new(),
+            slots: HashMap::new(),
+            stack: Vec::new(),
+            entries: HashMap::new(),
+            switch_key: None,
+            switch: None,
+        };
+        let mut slot = 0;
+        for (param, kind) in params.into_iter().enumerate() {
+            translator.variables.insert((slot, kind), param as u32);
+            slot += kind.words() as u16;
+        }
+        translator.nodes(&nodes)?;
+        // the code never falls through to the end, but wasm does not know
+        translator.body.code.push(UNREACHABLE);
+        Ok(())
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut types = Vec::new();
+        for (params, result) in &self.types {
+            types.push(0x60);
+            uleb(&mut types, params.len() as u64);
+            types.extend(params.iter().map(|kind| kind.value_type()));
+            uleb(&mut types, result.is_some() as u64);
+            types.extend(result.iter().map(|kind| kind.value_type()));
+        }
+        section(&mut module, 1, self.types.len(), &types);
+
+        let mut imports = Vec::new();
+        for (name, ty) in &self.imports {
+            name_bytes(&mut imports, "natives");
+            name_bytes(&mut imports, name);
+            imports.push(0x00);
+            uleb(&mut imports, *ty as u64);
+        }
+        section(&mut module, 2, self.imports.len(), &imports);
+
+        let mut functions = Vec::new();
+        for (ty, _) in &self.bodies {
+            uleb(&mut functions, *ty as u64);
+        }
+        section(&mut module, 3, self.bodies.len(), &functions);
+
+        let heap = (STATICS + self.data.len() as u32 + 7) & !7;
+        let mut memory = vec![0x00];
+        uleb(&mut memory, (heap as u64 >> 16) + 1);
+        section(&mut module, 5, 1, &memory);
+
+        let mut globals = Vec::new();
+        for (index, &value) in self.globals.iter().enumerate() {
+            globals.extend([Kind::I32.value_type(), 0x01, I32_CONST]);
+            let value = if index as u32 == HEAP {
+                heap as i32
+            } else {
+                value
+            };
+            sleb(&mut globals, value as i64);
+            globals.push(END);
+        }
+        section(&mut module, 6, self.globals.len(), &globals);
+
+        let mut exports = Vec::new();
+        for (name, function) in &self.exports {
+            name_bytes(&mut exports, name);
+            exports.push(0x00);
+            uleb(&mut exports, *function as u64);
+        }
+        name_bytes(&mut exports, "memory");
+        exports.extend([0x02, 0]);
+        section(&mut module, 7, self.exports.len() + 1, &exports);
+
+        let mut code = Vec::new();
+        for (_, body) in &self.bodies {
+            uleb(&mut code, body.len() as u64);
+            code.extend(body);
+        }
+        section(&mut module, 10, self.bodies.len(), &code);
+
+        let mut data = vec![0x00, I32_CONST];
+        sleb(&mut data, STATICS as i64);
+        data.push(END);
+        uleb(&mut data, self.data.len() as u64);
+        data.extend(&self.data);
+        section(&mut module, 11, 1, &data);
+        module
+    }
+}
+
+/// The code of a function and its locals
+struct Body {
+    params: usize,
+    /// The locals after the parameters
+    locals: Vec<Kind>,
+    code: Vec<u8>,
+}
+
+impl Body {
+    fn new(params: usize) -> Self {
+        Body {
+            params,
+            locals: Vec::new(),
+            code: Vec::new(),
+        }
+    }
+
+    fn local(&mut self, kind: Kind) -> u32 {
+        self.locals.push(kind);
+        (self.params + self.locals.len() - 1) as u32
+    }
+
+    /// An instruction with an unsigned operand
+    fn op_u(&mut self, op: u8, operand: u32) {
+        self.code.push(op);
+        uleb(&mut self.code, operand as u64);
+    }
+
+    fn i32_const(&mut self, value: i32) {
+        self.code.push(I32_CONST);
+        sleb(&mut self.code, value as i64);
+    }
+
+    /// A load or store at the offset from the address
+    fn memarg(&mut self, op: u8, offset: u32) {
+        self.code.extend([op, 0]);
+        uleb(&mut self.code, offset as u64);
+    }
+
+    /// Traps if the local is null
+    fn null_check(&mut self, local: u32) {
+        self.op_u(LOCAL_GET, local);
+        self.code.extend([I32_EQZ, IF, EMPTY, UNREACHABLE, END]);
+    }
+
+    /// The function body with the declarations of its locals
+    fn finish(self) -> Vec<u8> {
+        let mut body = Vec::new();
+        uleb(&mut body, self.locals.len() as u64);
+        for kind in &self.locals {
+            body.extend([1, kind.value_type()]);
+        }
+        body.extend(self.code);
+        body.push(END);
+        body
+    }
+}
+
+/// Translates the code of a method
+struct Translator<'a> {
+    module: &'a mut Module,
+    method: MethodId,
+    code: Rc<Code>,
+    body: &'a mut Body,
+    /// The locals of the local variables, by index and kind
+    variables: HashMap<(u16, Kind), u32>,
+    /// The locals of the operand stack, by depth and kind
+    slots: HashMap<(usize, Kind), u32>,
+    /// The kinds of the values on the operand stack
+    stack: Vec<Kind>,
+    /// The operand stack at the start of the basic blocks that were branched to
+    entries: HashMap<usize, Vec<Kind>>,
+    /// The local with the key of switches
+    switch_key: Option<u32>,
+    /// The switch that ends the last basic block
+    switch: Option<Instruction>,
+}
+
+impl Translator<'_> {
+    fn nodes(&mut self, nodes: &[Node]) -> Result<()> {
+        for node in nodes {
+            match node {
+                &Node::Code { start, end } => self.block(start, end)?,
+                Node::Block(nodes) | Node::Loop(nodes) => {
+                    let op = if matches!(node, Node::Block(_)) {
+                        BLOCK
+                    } else {
+                        LOOP
+                    };
+                    self.body.code.extend([op, EMPTY]);
+                    self.nodes(nodes)?;
+                    self.body.code.push(END);
+                }
+                Node::If { then, else_ } => {
+                    self.body.code.extend([IF, EMPTY]);
+                    self.nodes(then)?;
+                    self.body.code.push(ELSE);
+                    self.nodes(else_)?;
+                    self.body.code.push(END);
+                }
+                Node::Switch(cases) => {
+                    let switch = self.switch.take().expect("a switch follows its code");
+                    self.switch(switch, cases)?;
+                }
+                &Node::Br(depth) => self.body.op_u(BR, depth),
+            }
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, start: usize, end: usize) -> Result<()> {
+        self.stack = match start {
+            0 => Vec::new(),
+            _ => (self.entries.get(&start).cloned())
+                .ok_or_else(|| VmError::Verify(format!("No branch to {}", start)))?,
+        };
+        let mut pc = start;
+        let mut last = None;
+        while pc < end {
+            let instruction = self
+                .code
+                .instruction(pc)
+                .expect("the block has instructions");
+            self.instruction(pc, instruction)?;
+            last = Some(instruction);
+            pc = instruction.next as usize;
+        }
+        let last = last.expect("blocks are not empty");
+        if matches!(last.opcode, TABLESWITCH | LOOKUPSWITCH) {
+            self.switch = Some(last);
+        }
+        if !structure::is_terminator(last.opcode) {
+            self.entries.entry(end).or_insert(self.stack.clone());
+        }
+        Ok(())
+    }
+
+    /// Emits the code of a switch and its cases
+    fn switch(&mut self, instruction: Instruction, cases: &[Vec<Node>]) -> Result<()> {
+        let successors = structure::successors(&self.code, instruction);
+        let case = |target: usize| {
+            let case = successors.iter().position(|&successor| successor == target);
+            case.expect("the target is a successor") as u32
+        };
+        let key = self.switch_key.expect("the key was stored");
+        for _ in cases {
+            self.body.code.extend([BLOCK, EMPTY]);
+        }
+        match &self.code.switches[instruction.a as usize] {
+            Switch::Table {
+                low,
+                targets,
+                default,
+            } => {
+                self.body.op_u(LOCAL_GET, key);
+                self.body.i32_const(*low);
+                self.body.code.push(I32_SUB);
+                self.body.code.push(BR_TABLE);
+                uleb(&mut self.body.code, targets.len() as u64);
+                for &target in targets {
+                    uleb(&mut self.body.code, case(target) as u64);
+                }
+                uleb(&mut self.body.code, case(*default) as u64);
+            }
+            Switch::Lookup { pairs, default } => {
+                for &(value, target) in pairs {
+                    self.body.op_u(LOCAL_GET, key);
+                    self.body.i32_const(value);
+                    self.body.code.push(I32_EQ);
+                    self.body.op_u(BR_IF, case(target));
+                }
+                self.body.op_u(BR, case(*default));
+            }
+        }
+        for case in cases {
+            self.body.code.push(END);
+            self.nodes(case)?;
+        }
+        Ok(())
+    }
+
+    fn slot(&mut self, depth: usize, kind: Kind) -> u32 {
+        if let Some(&local) = self.slots.get(&(depth, kind)) {
+            return local;
+        }
+        let local = self.body.local(kind);
+        self.slots.insert((depth, kind), local);
+        local
+    }
+
+    fn variable(&mut self, index: u16, kind: Kind) -> u32 {
+        if let Some(&local) = self.variables.get(&(index, kind)) {
+            return local;
+        }
+        let local = self.body.local(kind);
+        self.variables.insert((index, kind), local);
+        local
+    }
+
+    /// Emits the value at the depth of the operand stack
+    fn get(&mut self, depth: usize) {
+        let local = self.slot(depth, self.stack[depth]);
+        self.body.op_u(LOCAL_GET, local);
+    }
+
+    /// Pops the values from the top of the operand stack and emits them, the deepest first
+    fn pop(&mut self, count: usize) -> Kind {
+        let base = self.stack.len() - count;
+        for depth in base..self.stack.len() {
+            self.get(depth);
+        }
+        let deepest = self.stack[base];
+        self.stack.truncate(base);
+        deepest
+    }
+
+    /// Pushes the value that was emitted
+    fn push(&mut self, kind: Kind) {
+        let local = self.slot(self.stack.len(), kind);
+        self.body.op_u(LOCAL_SET, local);
+        self.stack.push(kind);
+    }
+
+    /// The number of values at the top of the operand stack that take the words
+    fn values(&self, words: usize, above: usize) -> Result<usize> {
+        let mut count = 0;
+        let mut taken = 0;
+        while taken < words {
+            let depth = (self.stack.len() - above)
+                .checked_sub(count + 1)
+                .ok_or(VmError::OperandStackUnderflow)?;
+            taken += self.stack[depth].words();
+            count += 1;
+        }
+        match taken == words {
+            true => Ok(count),
+            false => Err(VmError::Verify("Splits a long or double".to_string())),
+        }
+    }
+
+    /// `dup` and friends: inserts a copy of the values taking `words` below the values taking
+    /// `below` words under them
+    fn duplicate(&mut self, words: usize, below: usize) -> Result<()> {
+        let top = self.values(words, 0)?;
+        let under = self.values(below, top)?;
+        let base = self.stack.len() - top - under;
+        let copied = base + under..self.stack.len();
+        let order = copied.clone().chain(base..base + under).chain(copied);
+        let order = order.collect::<Vec<_>>();
+        for &depth in &order {
+            self.get(depth);
+        }
+        let kinds = order
+            .iter()
+            .map(|&depth| self.stack[depth])
+            .collect::<Vec<_>>();
+        self.reorder(base, &kinds);
+        Ok(())
+    }
+
+    /// Replaces the values from the depth on with the values of the kinds that were emitted
+    fn reorder(&mut self, base: usize, kinds: &[Kind]) {
+        self.stack.truncate(base);
+        for depth in (0..kinds.len()).rev() {
+            let local = self.slot(base + depth, kinds[depth]);
+            self.body.op_u(LOCAL_SET, local);
+        }
+        self.stack.extend(kinds);
+    }
+
+    /// Records the operand stack for a branch target
+    fn branch_to(&mut self, target: usize) {
+        self.entries.entry(target).or_insert(self.stack.clone());
+    }
+
+    fn instruction(&mut self, pc: usize, instruction: Instruction) -> Result<()> {
+        let Instruction {
+            opcode, next, a, b, ..
+        } = instruction;
+        let index = a as u2;
+        let current = self.method.class;
+        match opcode {
+            NOP => {}
+            ACONST_NULL => {
+                self.body.i32_const(0);
+                self.push(Kind::I32);
+            }
+            ICONST_M1..=ICONST_5 => {
+                self.body.i32_const(opcode as i32 - ICONST_0 as i32);
+                self.push(Kind::I32);
+            }
+            LCONST_0 | LCONST_1 => self.constant(Value::Long((opcode - LCONST_0) as i64)),
+            FCONST_0..=FCONST_2 => self.constant(Value::Float((opcode - FCONST_0) as f32)),
+            DCONST_0 | DCONST_1 => self.constant(Value::Double((opcode - DCONST_0) as f64)),
+            BIPUSH | SIPUSH => {
+                self.body.i32_const(a);
+                self.push(Kind::I32);
+            }
+            LDC | LDC_W | LDC2_W => {
+                let value = (self.module.vm.class(current).cp_numeric(index))
+                    .map_err(|_| VmError::Unsupported("strings and class constants".to_string()))?;
+                self.constant(value);
+            }
+            ILOAD..=ALOAD => {
+                let kind = Kind::nth(opcode - ILOAD);
+                let local = self.variable(a as u16, kind);
+                self.body.op_u(LOCAL_GET, local);
+                self.push(kind);
+            }
+            ISTORE..=ASTORE => {
+                let kind = self.pop(1);
+                let local = self.variable(a as u16, kind);
+                self.body.op_u(LOCAL_SET, local);
+            }
+            IALOAD..=SALOAD => {
+                let ty = array_element(opcode - IALOAD);
+                let (size, load, _) = storage(&ty);
+                self.pop(2);
+                self.body.i32_const(size as i32);
+                self.call(Function::Element);
+                self.body.memarg(load, 0);
+                self.push(Kind::of(&ty));
+            }
+            IASTORE..=SASTORE => {
+                let ty = array_element(opcode - IASTORE);
+                let (size, _, store) = storage(&ty);
+                let depth = self.stack.len() - 3;
+                self.get(depth);
+                self.get(depth + 1);
+                self.body.i32_const(size as i32);
+                self.call(Function::Element);
+                self.get(depth + 2);
+                self.body.memarg(store, 0);
+                self.stack.truncate(depth);
+            }
+            POP => {
+                let count = self.values(1, 0)?;
+                self.stack.truncate(self.stack.len() - count);
+            }
+            POP2 => {
+                let count = self.values(2, 0)?;
+                self.stack.truncate(self.stack.len() - count);
+            }
+            DUP => self.duplicate(1, 0)?,
+            DUP_X1 => self.duplicate(1, 1)?,
+            DUP_X2 => self.duplicate(1, 2)?,
+            DUP2 => self.duplicate(2, 0)?,
+            DUP2_X1 => self.duplicate(2, 1)?,
+            DUP2_X2 => self.duplicate(2, 2)?,
+            SWAP => {
+                let base = self.stack.len() - 2;
+                let kinds = [self.stack[base + 1], self.stack[base]];
+                self.get(base + 1);
+                self.get(base);
+                self.reorder(base, &kinds);
+            }
+            IADD..=DMUL => {
+                let ops = [
+                    [0x6a, 0x7c, 0x92, 0xa0],
+                    [0x6b, 0x7d, 0x93, 0xa1],
+                    [0x6c, 0x7e, 0x94, 0xa2],
+                ];
+                let kind = self.pop(2);
+                self.body
+                    .code
+                    .push(kind.op(ops[((opcode - IADD) / 4) as usize]));
+                self.push(kind);
+            }
+            IDIV | LDIV => {
+                let kind = self.pop(2);
+                self.call(Function::Div(kind));
+                self.push(kind);
+            }
+            FDIV | DDIV => {
+                let kind = self.pop(2);
+                self.body.code.push(kind.op([0, 0, 0x95, 0xa3]));
+                self.push(kind);
+            }
+            IREM | LREM => {
+                // the remainder of the minimum value and -1 is 0 in wasm too
+                let kind = self.pop(2);
+                self.body.code.push(kind.op([0x6f, 0x81, 0, 0]));
+                self.push(kind);
+            }
+            INEG | LNEG => {
+                let kind = Kind::nth(opcode - INEG);
+                self.constant_of(kind);
+                self.pop(1);
+                self.body.code.push(kind.op([I32_SUB, 0x7d, 0, 0]));
+                self.push(kind);
+            }
+            FNEG | DNEG => {
+                let kind = self.pop(1);
+                self.body.code.push(kind.op([0, 0, 0x8c, 0x9a]));
+                self.push(kind);
+            }
+            ISHL..=LUSHR => {
+                let ops = [
+                    [I32_SHL, 0x86, 0, 0],
+                    [0x75, 0x87, 0, 0],
+                    [I32_SHR_U, 0x88, 0, 0],
+                ];
+                let kind = self.pop(2);
+                if kind == Kind::I64 {
+                    // i64.extend_i32_u of the shift distance
+                    self.body.code.push(0xad);
+                }
+                self.body
+                    .code
+                    .push(kind.op(ops[((opcode - ISHL) / 2) as usize]));
+                self.push(kind);
+            }
+            IAND..=LXOR => {
+                let ops = [
+                    [I32_AND, 0x83, 0, 0],
+                    [I32_OR, 0x84, 0, 0],
+                    [0x73, 0x85, 0, 0],
+                ];
+                let kind = self.pop(2);
+                self.body
+                    .code
+                    .push(kind.op(ops[((opcode - IAND) / 2) as usize]));
+                self.push(kind);
+            }
+            IINC => {
+                let local = self.variable(a as u16, Kind::I32);
+                self.body.op_u(LOCAL_GET, local);
+                self.body.i32_const(b);
+                self.body.code.push(I32_ADD);
+                self.body.op_u(LOCAL_SET, local);
+            }
+            I2L..=I2S => {
+                let (code, kind): (&[u8], _) = match opcode {
+                    I2L => (&[0xac], Kind::I64),
+                    I2F => (&[0xb2], Kind::F32),
+                    I2D => (&[0xb7], Kind::F64),
+                    L2I => (&[0xa7], Kind::I32),
+                    L2F => (&[0xb4], Kind::F32),
+                    L2D => (&[0xb9], Kind::F64),
+                    F2I => (&[TRUNC_SAT, 0], Kind::I32),
+                    F2L => (&[TRUNC_SAT, 4], Kind::I64),
+                    F2D => (&[0xbb], Kind::F64),
+                    D2I => (&[TRUNC_SAT, 2], Kind::I32),
+                    D2L => (&[TRUNC_SAT, 6], Kind::I64),
+                    D2F => (&[0xb6], Kind::F32),
+                    I2B => (&[0xc0], Kind::I32),
+                    I2C => (&[I32_CONST, 0xff, 0xff, 0x03, I32_AND], Kind::I32),
+                    _ => (&[0xc1], Kind::I32),
+                };
+                self.pop(1);
+                self.body.code.extend(code);
+                self.push(kind);
+            }
+            LCMP..=DCMPG => {
+                let depth = self.stack.len() - 2;
+                // `(a > b) - (a < b)`, where `fcmpl` is -1 and `fcmpg` is 1 for NaN
+                let (greater, less) = match opcode {
+                    LCMP => ([0x55].as_slice(), [0x53].as_slice()),
+                    FCMPL => (&[0x5e][..], &[0x60, I32_EQZ][..]),
+                    FCMPG => (&[0x5f, I32_EQZ][..], &[0x5d][..]),
+                    DCMPL => (&[0x64][..], &[0x66, I32_EQZ][..]),
+                    _ => (&[0x65, I32_EQZ][..], &[0x63][..]),
+                };
+                self.get(depth);
+                self.get(depth + 1);
+                self.body.code.extend(greater);
+                self.pop(2);
+                self.body.code.extend(less);
+                self.body.code.push(I32_SUB);
+                self.push(Kind::I32);
+            }
+            IFEQ..=IFLE => {
+                self.pop(1);
+                self.body.i32_const(0);
+                self.body.code.push(compare(opcode - IFEQ));
+                self.branch_to(a as usize);
+                self.branch_to(next as usize);
+            }
+            IF_ICMPEQ..=IF_ACMPNE => {
+                self.pop(2);
+                self.body.code.push(compare((opcode - IF_ICMPEQ) % 6));
+                self.branch_to(a as usize);
+                self.branch_to(next as usize);
+            }
+            IFNULL | IFNONNULL => {
+                self.pop(1);
+                self.body.i32_const(0);
+                self.body.code.push(compare(opcode - IFNULL));
+                self.branch_to(a as usize);
+                self.branch_to(next as usize);
+            }
+            GOTO | GOTO_W => self.branch_to(a as usize),
+            TABLESWITCH | LOOKUPSWITCH => {
+                self.pop(1);
+                let key = match self.switch_key {
+                    Some(key) => key,
+                    None => self.body.local(Kind::I32),
+                };
+                self.switch_key = Some(key);
+                self.body.op_u(LOCAL_SET, key);
+                for target in structure::successors(&self.code, instruction) {
+                    self.branch_to(target);
+                }
+            }
+            IRETURN..=ARETURN => {
+                self.pop(1);
+                self.body.code.push(RETURN_);
+            }
+            RETURN => self.body.code.push(RETURN_),
+            GETSTATIC | PUTSTATIC => {
+                let (class, slot) = self.static_field(index)?;
+                self.module.initialize(self.body, class, Some(current));
+                let address = self.module.static_address(class, slot)?;
+                let ty = self.module.vm.class(class).static_fields[slot]
+                    .descriptor
+                    .clone();
+                let (_, load, store) = storage(&ty);
+                self.body.i32_const(address as i32);
+                if opcode == GETSTATIC {
+                    self.body.memarg(load, 0);
+                    self.push(Kind::of(&ty));
+                } else {
+                    self.pop(1);
+                    self.body.memarg(store, 0);
+                }
+            }
+            GETFIELD | PUTFIELD => {
+                let (class, slot) = self.module.vm.resolve_instance_field(current, index)?;
+                let ty = self.module.vm.class(class).instance_fields[slot]
+                    .descriptor
+                    .clone();
+                let (_, load, store) = storage(&ty);
+                let depth = self.stack.len() - 1 - (opcode == PUTFIELD) as usize;
+                self.get(depth);
+                self.body.i32_const((HEADER + 8 * slot as u32) as i32);
+                self.call(Function::Field);
+                if opcode == GETFIELD {
+                    self.stack.truncate(depth);
+                    self.body.memarg(load, 0);
+                    self.push(Kind::of(&ty));
+                } else {
+                    self.get(depth + 1);
+                    self.body.memarg(store, 0);
+                    self.stack.truncate(depth);
+                }
+            }
+            INVOKEVIRTUAL..=INVOKEINTERFACE => self.invoke(opcode, index)?,
+            NEW => {
+                let class = self.module.vm.resolve_class_ref(current, index)?;
+                self.module.initialize(self.body, class, Some(current));
+                let fields = self.module.vm.class(class).instance_fields.len() as u32;
+                self.body.i32_const((HEADER + 8 * fields) as i32);
+                self.body.i32_const(class.0 as i32);
+                self.call(Function::Alloc);
+                self.push(Kind::I32);
+            }
+            NEWARRAY | ANEWARRAY => {
+                let (name, size) = match opcode {
+                    NEWARRAY => {
+                        let ty = primitive_array_element(a as u1)?;
+                        (format!("[{}", ty), storage(&ty).0)
+                    }
+                    _ => {
+                        let component = self.module.vm.resolve_class_ref(current, index)?;
+                        (array_class_name(&self.module.vm.class(component).name), 4)
+                    }
+                };
+                let class = self.module.vm.resolve_class(&name)?;
+                self.pop(1);
+                self.body.i32_const(size as i32);
+                self.body.i32_const(class.0 as i32);
+                self.call(Function::NewArray);
+                self.push(Kind::I32);
+            }
+            ARRAYLENGTH => {
+                self.pop(1);
+                self.body.i32_const(4);
+                self.call(Function::Field);
+                self.body.memarg(I32_LOAD, 0);
+                self.push(Kind::I32);
+            }
+            ATHROW => self.body.code.push(UNREACHABLE),
+            CHECKCAST => {
+                let class = self.module.vm.resolve_class_ref(current, index)?;
+                let depth = self.stack.len() - 1;
+                self.get(depth);
+                self.body.code.push(I32_EQZ);
+                self.get(depth);
+                self.call(Function::InstanceOf(class));
+                self.body
+                    .code
+                    .extend([I32_OR, I32_EQZ, IF, EMPTY, UNREACHABLE, END]);
+            }
+            INSTANCEOF => {
+                let class = self.module.vm.resolve_class_ref(current, index)?;
+                self.pop(1);
+                self.call(Function::InstanceOf(class));
+                self.push(Kind::I32);
+            }
+            // there is only one thread
+            MONITORENTER | MONITOREXIT => {
+                self.stack.pop();
+            }
+            _ => {
+                let name = match opcode {
+                    INVALID => "an invalid instruction",
+                    _ => opcode::name(opcode),
+                };
+                return Err(VmError::Unsupported(format!("{} at pc {}", name, pc)));
+            }
+        }
+        Ok(())
+    }
+
+    fn constant(&mut self, value: Value) {
+        let kind = match value {
+            Value::Int(value) => {
+                self.body.i32_const(value);
+                Kind::I32
+            }
+            Value::Long(value) => {
+                self.body.code.push(I64_CONST);
+                sleb(&mut self.body.code, value);
+                Kind::I64
+            }
+            Value::Float(value) => {
+                self.body.code.push(F32_CONST);
+                self.body.code.extend(value.to_le_bytes());
+                Kind::F32
+            }
+            _ => {
+                let value = value.as_double().unwrap_or_default();
+                self.body.code.push(F64_CONST);
+                self.body.code.extend(value.to_le_bytes());
+                Kind::F64
+            }
+        };
+        self.push(kind);
+    }
+
+    /// Emits the zero of the kind
+    fn constant_of(&mut self, kind: Kind) {
+        match kind {
+            Kind::I64 => self.body.code.extend([I64_CONST, 0]),
+            _ => self.body.i32_const(0),
+        }
+    }
+
+    fn call(&mut self, function: Function) {
+        let function = self.module.function(function);
+        self.body.op_u(CALL, function);
+    }
+
+    /// Resolves a `Fieldref` to a static field, without initializing its class
+    fn static_field(&mut self, index: u2) -> Result<(ClassId, usize)> {
+        let vm = &mut self.module.vm;
+        let current = self.method.class;
+        let field = vm.class(current).cp_field_ref(index)?;
+        let (class_name, name) = (field.class.to_string(), field.name.to_string());
+        let descriptor = parse_field_type(field.descriptor)?;
+        let class = vm.resolve_class_from(current, &class_name)?;
+        vm.find_static_field(class, &name, &descriptor)
+            .ok_or_else(|| VmError::NoSuchField(format!("{}.{}", class_name, name)))
+    }
+
+    fn invoke(&mut self, opcode: u1, index: u2) -> Result<()> {
+        let vm = &mut self.module.vm;
+        let current = self.method.class;
+        let (member, interface) = vm.class(current).cp_method_ref(index)?;
+        let (class, name, descriptor) = (
+            member.class.to_string(),
+            member.name.to_string(),
+            member.descriptor.to_string(),
+        );
+        let class = vm.resolve_class_from(current, &class)?;
+        let resolved = vm.resolve_method(class, &name, &descriptor, interface)?;
+        let (params, result) = self.module.signature(resolved);
+        // the parameters include the receiver
+        let args = params.len();
+        let receiver = self.stack.len() - args;
+
+        let function = match opcode {
+            INVOKESTATIC => {
+                self.module
+                    .initialize(self.body, resolved.class, Some(current));
+                Some(Function::Method(resolved))
+            }
+            INVOKESPECIAL => {
+                let selected = self.module.vm.select_special(current, class, resolved)?;
+                Some(Function::Method(selected))
+            }
+            _ => match self.module.targets(resolved).as_slice() {
+                // no receiver can exist, the call throws a `NullPointerException`
+                [] => None,
+                [(selected, _)] => {
+                    let local = self.slot(receiver, Kind::I32);
+                    self.body.null_check(local);
+                    Some(Function::Method(*selected))
+                }
+                _ => Some(Function::Dispatch(resolved)),
+            },
+        };
+        self.pop(args);
+        match function {
+            Some(function) => self.call(function),
+            None => self.body.code.push(UNREACHABLE),
+        }
+        if let Some(result) = result {
+            self.push(result);
+        }
+        Ok(())
+    }
+}
+
+/// The condition of a comparison with `ifeq`, `ifne`, `iflt`, `ifge`, `ifgt` or `ifle` order
+fn compare(condition: u1) -> u8 {
+    [I32_EQ, I32_NE, I32_LT_S, I32_GE_S, I32_GT_S, I32_LE_S][condition as usize]
+}
+
+/// The element type of `iaload` and friends by their offset from `iaload`
+fn array_element(offset: u1) -> FieldType {
+    use FieldType::*;
+    let types = [
+        Int,
+        Long,
+        Float,
+        Double,
+        Object(String::new()),
+        Byte,
+        Char,
+        Short,
+    ];
+    types[offset as usize].clone()
+}
+
+/// The element type of a `newarray`, see JVMS §6.5
+fn primitive_array_element(atype: u1) -> Result<FieldType> {
+    use FieldType::*;
+    Ok(match atype {
+        4 => Boolean,
+        5 => Char,
+        6 => Float,
+        7 => Double,
+        8 => Byte,
+        9 => Short,
+        10 => Int,
+        11 => Long,
+        _ => return Err(VmError::Verify(format!("Invalid array type {}", atype))),
+    })
+}
+
+fn alloc(body: &mut Body) {
+    let object = body.local(Kind::I32) as u8;
+    body.code
+        .extend([GLOBAL_GET, HEAP as u8, LOCAL_SET, object]);
+    body.code.extend([LOCAL_GET, object, LOCAL_GET, 0, I32_ADD]);
+    body.code
+        .extend([I32_CONST, 7, I32_ADD, I32_CONST, 0x78, I32_AND]);
+    body.code.extend([GLOBAL_SET, HEAP as u8]);
+    // grows the memory to the pages the heap needs, or traps
+    body.code.extend([
+        GLOBAL_GET,
+        HEAP as u8,
+        MEMORY_SIZE,
+        0,
+        I32_CONST,
+        16,
+        I32_SHL,
+    ]);
+    body.code.extend([I32_GT_U, IF, EMPTY]);
+    body.code.extend([GLOBAL_GET, HEAP as u8]);
+    body.i32_const(0xffff);
+    body.code
+        .extend([I32_ADD, I32_CONST, 16, I32_SHR_U, MEMORY_SIZE, 0, I32_SUB]);
+    body.code.extend([MEMORY_GROW, 0, I32_CONST, 0x7f, I32_EQ]);
+    body.code.extend([IF, EMPTY, UNREACHABLE, END, END]);
+    body.code
+        .extend([LOCAL_GET, object, LOCAL_GET, 1, I32_STORE, 2, 0]);
+    body.code.extend([LOCAL_GET, object]);
+}
+
+fn new_array(body: &mut Body, alloc: u32) {
+    let array = body.local(Kind::I32) as u8;
+    // negative lengths, and lengths that do not fit in memory, trap
+    body.code.extend([LOCAL_GET, 0]);
+    body.i32_const(0x0fff_ffff);
+    body.code.extend([I32_GT_U, IF, EMPTY, UNREACHABLE, END]);
+    body.code.extend([LOCAL_GET, 0, LOCAL_GET, 1, I32_MUL]);
+    body.i32_const(HEADER as i32);
+    body.code.extend([I32_ADD, LOCAL_GET, 2]);
+    body.op_u(CALL, alloc);
+    body.code
+        .extend([LOCAL_TEE, array, LOCAL_GET, 0, I32_STORE, 2, 4]);
+    body.code.extend([LOCAL_GET, array]);
+}
+
+fn element(body: &mut Body) {
+    body.null_check(0);
+    body.code.extend([LOCAL_GET, 1, LOCAL_GET, 0]);
+    body.memarg(I32_LOAD, 4);
+    body.code.extend([I32_GE_U, IF, EMPTY, UNREACHABLE, END]);
+    body.code.extend([LOCAL_GET, 0]);
+    body.i32_const(HEADER as i32);
+    body.code
+        .extend([I32_ADD, LOCAL_GET, 1, LOCAL_GET, 2, I32_MUL, I32_ADD]);
+}
+
+fn div(body: &mut Body, kind: Kind) {
+    let (minus_one, eq, sub, div) = match kind {
+        Kind::I64 => ([I64_CONST, 0x7f], 0x51, 0x7d, 0x7f),
+        _ => ([I32_CONST, 0x7f], I32_EQ, I32_SUB, 0x6d),
+    };
+    body.code.extend([LOCAL_GET, 1]);
+    body.code.extend(minus_one);
+    body.code.extend([eq, IF, kind.value_type()]);
+    match kind {
+        Kind::I64 => body.code.extend([I64_CONST, 0]),
+        _ => body.i32_const(0),
+    }
+    body.code.extend([LOCAL_GET, 0, sub, ELSE]);
+    body.code.extend([LOCAL_GET, 0, LOCAL_GET, 1, div, END]);
+}
+
+fn uleb(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn sleb(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn name_bytes(bytes: &mut Vec<u8>, name: &str) {
+    uleb(bytes, name.len() as u64);
+    bytes.extend(name.as_bytes());
+}
+
+fn section(module: &mut Vec<u8>, id: u8, count: usize, contents: &[u8]) {
+    let mut section = Vec::new();
+    uleb(&mut section, count as u64);
+    section.extend(contents);
+    module.push(id);
+    uleb(module, section.len() as u64);
+    module.extend(section);
+}
@@ -0,0 +1,376 @@
+//!
+//! The x86-64 code generator of the baseline compiler
+//!
+//! Every instruction is compiled to a fixed template that loads its operands from the slot
+//! array into registers and stores the result back, so the slots always hold the state of the
+//! frame. Compiled code is a System V function `fn(slots: *mut i64, pc: u64) -> u64`: it jumps
+//! to the entry at `pc` and returns `ExitKind::at` of the instruction it exits at. `rdi` holds
+//! the slot array, `rax`, `rcx` and `rdx` are scratch registers
+//!
+
+use crate::jit::{Analysis, BinaryOp, Condition, Conversion, ExitKind, Op, SlotType};
+use crate::model::Value;
+use std::ffi::c_void;
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RSI: u8 = 6;
+const RDI: u8 = 7;
+
+/// Appends machine code, with jumps to instructions that are patched once all are placed
+struct Assembler {
+    bytes: Vec<u8>,
+    /// The offset of the code of every instruction, by pc
+    labels: Vec<Option<usize>>,
+    /// The offsets of `rel32` operands and the pcs they jump to
+    jumps: Vec<(usize, usize)>,
+    /// The offsets of `rel32` operands and the pcs of the deoptimizations they jump to
+    deoptimizations: Vec<(usize, usize)>,
+}
+
+impl Assembler {
+    fn new(code_len: usize) -> Self {
+        Self {
+            bytes: Vec::new(),
+            labels: vec![None; code_len],
+            jumps: Vec::new(),
+            deoptimizations: Vec::new(),
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// A REX prefix for 64-bit operands, when `wide`
+    fn rex_w(&mut self, wide: bool) {
+        if wide {
+            self.emit(&[0x48]);
+        }
+    }
+
+    /// The ModRM byte and displacement of the operand `[rdi + 8 * slot]`
+    fn slot_operand(&mut self, reg: u8, slot: usize) {
+        self.emit(&[0b10 << 6 | reg << 3 | RDI]);
+        self.emit_u32((slot * 8) as u32);
+    }
+
+    /// `op reg, [rdi + 8 * slot]` or the other way around, depending on the opcode
+    fn slot_op(&mut self, wide: bool, opcode: &[u8], reg: u8, slot: usize) {
+        self.rex_w(wide);
+        self.emit(opcode);
+        self.slot_operand(reg, slot);
+    }
+
+    fn load(&mut self, wide: bool, reg: u8, slot: usize) {
+        self.slot_op(wide, &[0x8b], reg, slot);
+    }
+
+    fn store(&mut self, wide: bool, slot: usize, reg: u8) {
+        self.slot_op(wide, &[0x89], reg, slot);
+    }
+
+    /// `op dst, src` on registers, for opcodes in the `r/m, reg` form
+    fn register_op(&mut self, wide: bool, opcode: u8, dst: u8, src: u8) {
+        self.rex_w(wide);
+        self.emit(&[opcode, 0b11 << 6 | src << 3 | dst]);
+    }
+
+    /// Jumps to the instruction at `pc`, with the condition code or unconditionally
+    fn jump(&mut self, condition: Option<u8>, pc: usize) {
+        match condition {
+            Some(cc) => self.emit(&[0x0f, 0x80 | cc]),
+            None => self.emit(&[0xe9]),
+        }
+        self.jumps.push((self.bytes.len(), pc));
+        self.emit_u32(0);
+    }
+
+    /// Jumps to a deoptimization at `pc` if the condition code holds
+    fn deoptimize_if(&mut self, cc: u8, pc: usize) {
+        self.emit(&[0x0f, 0x80 | cc]);
+        self.deoptimizations.push((self.bytes.len(), pc));
+        self.emit_u32(0);
+    }
+
+    /// Returns the exit value
+    fn exit(&mut self, kind: ExitKind, pc: usize) {
+        self.emit(&[0x48, 0xb8]);
+        self.bytes.extend_from_slice(&kind.at(pc).to_le_bytes());
+        self.emit(&[0xc3]);
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        let rel = target as i64 - (at as i64 + 4);
+        self.bytes[at..at + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+    }
+
+    /// Places the deoptimizations and resolves all jumps
+    fn finish(mut self) -> Option<Vec<u8>> {
+        for (at, pc) in std::mem::take(&mut self.deoptimizations) {
+            let target = self.bytes.len();
+            self.exit(ExitKind::Deoptimize, pc);
+            self.patch(at, target);
+        }
+        for (at, pc) in std::mem::take(&mut self.jumps) {
+            let target = (*self.labels.get(pc)?)?;
+            self.patch(at, target);
+        }
+        Some(self.bytes)
+    }
+}
+
+const CC_EQ: u8 = 0x4;
+const CC_NE: u8 = 0x5;
+
+fn condition_code(condition: Condition) -> u8 {
+    match condition {
+        Condition::Eq => CC_EQ,
+        Condition::Ne => CC_NE,
+        Condition::Lt => 0xc,
+        Condition::Ge => 0xd,
+        Condition::Le => 0xe,
+        Condition::Gt => 0xf,
+    }
+}
+
+/// Compiles the analyzed instructions, `None` if executable memory is not available
+pub(crate) fn compile(analysis: &Analysis) -> Option<MachineCode> {
+    MachineCode::new(&assemble(analysis)?)
+}
+
+/// The machine code of the analyzed instructions. It only uses relative jumps and the slots,
+/// so it can run at any address
+pub(crate) fn assemble(analysis: &Analysis) -> Option<Vec<u8>> {
+    let mut asm = Assembler::new(analysis.states.len());
+    for &pc in &analysis.entries {
+        // cmp esi, pc; je entry
+        asm.emit(&[0x81, 0b11 << 6 | 7 << 3 | RSI]);
+        asm.emit_u32(pc as u32);
+        asm.jump(Some(CC_EQ), pc);
+    }
+    asm.emit(&[0x48, 0x89, 0b11 << 6 | RSI << 3 | RAX]);
+    asm.emit(&[0xc3]);
+
+    for &(pc, op) in &analysis.ops {
+        asm.labels[pc] = Some(asm.bytes.len());
+        let depth = analysis.states[pc]
+            .as_ref()
+            .expect("reachable instructions have a state")
+            .stack
+            .len();
+        // only used by instructions that have the operands
+        let top = analysis.stack_slot(depth.saturating_sub(1));
+        let below = analysis.stack_slot(depth.saturating_sub(2));
+        let push = analysis.stack_slot(depth);
+        match op {
+            Op::Const(Value::Long(value)) => {
+                asm.emit(&[0x48, 0xb8]);
+                asm.bytes.extend_from_slice(&value.to_le_bytes());
+                asm.store(true, push, RAX);
+            }
+            Op::Const(value) => {
+                let value = value.as_int().expect("constants are ints or longs");
+                asm.slot_op(false, &[0xc7], 0, push);
+                asm.emit_u32(value as u32);
+            }
+            Op::Load(ty, index) => {
+                let wide = ty == SlotType::Long;
+                asm.load(wide, RAX, analysis.local_slot(index));
+                asm.store(wide, push, RAX);
+            }
+            Op::Store(ty, index) => {
+                let wide = ty == SlotType::Long;
+                asm.load(wide, RAX, top);
+                asm.store(wide, analysis.local_slot(index), RAX);
+            }
+            Op::Increment(index, value) => {
+                // add dword [slot], imm32
+                asm.slot_op(false, &[0x81], 0, analysis.local_slot(index));
+                asm.emit_u32(value as u32);
+            }
+            Op::Binary(ty, op) => binary(&mut asm, ty == SlotType::Long, op, pc, below, top),
+            Op::Negate(ty) => {
+                let wide = ty == SlotType::Long;
+                asm.load(wide, RAX, top);
+                asm.register_op(wide, 0xf7, RAX, 3);
+                asm.store(wide, top, RAX);
+            }
+            Op::Convert(conversion) => {
+                let (wide, opcode): (bool, &[u8]) = match conversion {
+                    Conversion::IntToLong => (true, &[0x63]),
+                    // the low half of a long slot is the int
+                    Conversion::LongToInt => continue,
+                    Conversion::IntToByte => (false, &[0x0f, 0xbe]),
+                    Conversion::IntToChar => (false, &[0x0f, 0xb7]),
+                    Conversion::IntToShort => (false, &[0x0f, 0xbf]),
+                };
+                asm.slot_op(wide, opcode, RAX, top);
+                asm.store(wide, top, RAX);
+            }
+            Op::CompareLongs => {
+                asm.load(true, RAX, below);
+                asm.load(true, RCX, top);
+                asm.register_op(true, 0x39, RAX, RCX);
+                // setg al; setl cl; sub al, cl; movsx eax, al
+                asm.emit(&[
+                    0x0f, 0x9f, 0xc0, 0x0f, 0x9c, 0xc1, 0x28, 0xc8, 0x0f, 0xbe, 0xc0,
+                ]);
+                asm.store(false, below, RAX);
+            }
+            Op::If(condition, target) => {
+                asm.load(false, RAX, top);
+                // cmp eax, 0
+                asm.emit(&[0x83, 0xf8, 0x00]);
+                asm.jump(Some(condition_code(condition)), target);
+            }
+            Op::IfCompare(condition, target) => {
+                asm.load(false, RAX, below);
+                asm.slot_op(false, &[0x3b], RAX, top);
+                asm.jump(Some(condition_code(condition)), target);
+            }
+            Op::Goto(target) if target <= pc => {
+                // sub qword [fuel], 1; jz yield
+                asm.slot_op(true, &[0x83], 5, analysis.fuel_slot());
+                asm.emit(&[0x01, 0x75, 0x0b]);
+                asm.exit(ExitKind::Yield, target);
+                asm.jump(None, target);
+            }
+            Op::Goto(target) => asm.jump(None, target),
+            Op::Pop => {}
+            Op::Dup => {
+                asm.load(false, RAX, top);
+                asm.store(false, push, RAX);
+            }
+            Op::Exit => asm.exit(ExitKind::Interpret, pc),
+        }
+    }
+    asm.finish()
+}
+
+/// `a op b` with `a` in the slot `below` and `b` in the slot `top`, the result replaces `a`
+fn binary(asm: &mut Assembler, wide: bool, op: BinaryOp, pc: usize, below: usize, top: usize) {
+    asm.load(wide, RAX, below);
+    // shift distances are ints, x86 masks them like Java does
+    let shift = matches!(op, BinaryOp::Shl | BinaryOp::Shr | BinaryOp::Ushr);
+    asm.load(wide && !shift, RCX, top);
+    match op {
+        BinaryOp::Add => asm.register_op(wide, 0x01, RAX, RCX),
+        BinaryOp::Sub => asm.register_op(wide, 0x29, RAX, RCX),
+        BinaryOp::And => asm.register_op(wide, 0x21, RAX, RCX),
+        BinaryOp::Or => asm.register_op(wide, 0x09, RAX, RCX),
+        BinaryOp::Xor => asm.register_op(wide, 0x31, RAX, RCX),
+        BinaryOp::Mul => {
+            // imul rax, rcx
+            asm.rex_w(wide);
+            asm.emit(&[0x0f, 0xaf, 0b11 << 6 | RAX << 3 | RCX]);
+        }
+        BinaryOp::Shl => asm.register_op(wide, 0xd3, RAX, 4),
+        BinaryOp::Ushr => asm.register_op(wide, 0xd3, RAX, 5),
+        BinaryOp::Shr => asm.register_op(wide, 0xd3, RAX, 7),
+        BinaryOp::Div | BinaryOp::Rem => {
+            // the interpreter throws the ArithmeticException
+            asm.register_op(wide, 0x85, RCX, RCX);
+            asm.deoptimize_if(CC_EQ, pc);
+            // `idiv` faults on the minimum value divided by -1, where Java wraps around:
+            // cmp rcx, -1; jne divide
+            asm.rex_w(wide);
+            asm.emit(&[0x83, 0b11 << 6 | 7 << 3 | RCX, 0xff]);
+            let rex = wide as u8;
+            asm.emit(&[0x75, 2 + rex + 2]);
+            match op {
+                // neg rax
+                BinaryOp::Div => asm.register_op(wide, 0xf7, RAX, 3),
+                // xor eax, eax
+                _ => asm.register_op(wide, 0x31, RAX, RAX),
+            }
+            // jmp done
+            let divide_len = rex + 1 + rex + 2 + if op == BinaryOp::Rem { rex + 2 } else { 0 };
+            asm.emit(&[0xeb, divide_len]);
+            // cqo; idiv rcx
+            asm.rex_w(wide);
+            asm.emit(&[0x99]);
+            asm.register_op(wide, 0xf7, RCX, 7);
+            if op == BinaryOp::Rem {
+                asm.register_op(wide, 0x89, RAX, RDX);
+            }
+        }
+    }
+    asm.store(wide, below, RAX);
+}
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: i32,
+        flags: i32,
+        fd: i32,
+        offset: i64,
+    ) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+/// Machine code in executable memory, which is unmapped when it is dropped
+pub(crate) struct MachineCode {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl MachineCode {
+    /// Copies the code to new executable memory
+    pub(crate) fn new(code: &[u8]) -> Option<Self> {
+        let len = code.len().max(1);
+        // SAFETY: a new private mapping, which is only written before it becomes executable
+        unsafe {
+            let ptr = mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if ptr as isize == -1 {
+                return None;
+            }
+            let machine_code = Self { ptr, len };
+            std::ptr::copy_nonoverlapping(code.as_ptr(), ptr.cast(), code.len());
+            if mprotect(ptr, len, PROT_READ | PROT_EXEC) != 0 {
+                return None;
+            }
+            Some(machine_code)
+        }
+    }
+
+    /// Runs the code from the entry at `pc`, the slots have to be laid out by the analysis
+    /// it was compiled from
+    pub(crate) fn run(&self, slots: &mut [i64], pc: usize) -> u64 {
+        // SAFETY: the code was generated by `compile`, it only accesses the slots
+        unsafe {
+            let function: extern "sysv64" fn(*mut i64, u64) -> u64 = std::mem::transmute(self.ptr);
+            function(slots.as_mut_ptr(), pc as u64)
+        }
+    }
+}
+
+impl Drop for MachineCode {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is owned by this value
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
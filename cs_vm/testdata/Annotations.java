@@ -0,0 +1,90 @@
+import java.lang.annotation.Annotation;
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Inherited;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+import java.lang.reflect.Method;
+import java.util.Arrays;
+
+// Reads annotations through the proxies of the class library: nested annotations, enum
+// constants, arrays, classes and defaults. Annotations with the class retention are not visible
+
+enum Level {
+    LOW, HIGH
+}
+
+@Retention(RetentionPolicy.RUNTIME)
+@interface Author {
+    String name();
+
+    int year() default 2024;
+}
+
+@Retention(RetentionPolicy.RUNTIME)
+@Target({ ElementType.TYPE, ElementType.METHOD, ElementType.FIELD, ElementType.PARAMETER })
+@Inherited
+@interface Info {
+    Author author();
+
+    Level level() default Level.LOW;
+
+    String[] tags() default {};
+
+    Class<?> type() default Object.class;
+
+    long[] numbers() default { 1, 2 };
+
+    char letter() default 'x';
+
+    double ratio() default 0.5;
+}
+
+@interface Hidden {
+}
+
+@Info(author = @Author(name = "Ann"), level = Level.HIGH, tags = { "a", "b" }, type = String.class)
+@Hidden
+class Annotated {
+    @Info(author = @Author(name = "Bob", year = 1999), numbers = {})
+    int field;
+
+    @Info(author = @Author(name = "Cid"), letter = 'y', ratio = 2)
+    @Deprecated
+    void method(@Info(author = @Author(name = "Dee")) int first, int second) {
+    }
+}
+
+class Derived extends Annotated {
+}
+
+public class Annotations {
+    public static void main(String[] args) throws Exception {
+        Info info = Annotated.class.getAnnotation(Info.class);
+        System.out.println(info.author().name() + " " + info.author().year() + " " + info.level() + " "
+                + Arrays.toString(info.tags()) + " " + info.type().getSimpleName() + " "
+                + Arrays.toString(info.numbers()));
+        System.out.println(Annotated.class.getAnnotation(Hidden.class) + " "
+                + Annotated.class.getAnnotations().length + " " + Derived.class.isAnnotationPresent(Info.class)
+                + " " + Derived.class.getDeclaredAnnotations().length);
+
+        Info field = Annotated.class.getDeclaredField("field").getAnnotation(Info.class);
+        System.out.println(field.author().name() + " " + field.author().year() + " " + field.level() + " "
+                + field.numbers().length + " " + field.letter() + " " + field.ratio());
+
+        Method method = Annotated.class.getDeclaredMethod("method", int.class, int.class);
+        Info methodInfo = method.getAnnotation(Info.class);
+        System.out.println(methodInfo.author().name() + " " + methodInfo.letter() + " " + methodInfo.ratio() + " "
+                + method.isAnnotationPresent(Deprecated.class));
+        Annotation[][] parameters = method.getParameterAnnotations();
+        System.out.println(parameters.length + " " + ((Info) parameters[0][0]).author().name() + " "
+                + parameters[1].length);
+
+        // the proxies implement the methods of `Annotation`
+        Info again = Annotated.class.getAnnotation(Info.class);
+        System.out.println(info.annotationType().getSimpleName() + " " + info.equals(again) + " "
+                + info.equals(field) + " " + (info.hashCode() == again.hashCode()));
+        System.out.println(field);
+        System.out.println(Info.class.getDeclaredMethod("level").getDefaultValue());
+    }
+}
@@ -0,0 +1,89 @@
+// Call sites for the inline caches, see `inline_caches` in test.rs
+public class Caches {
+    static class Counter {
+        int count;
+
+        int next() {
+            return ++count;
+        }
+    }
+
+    static class Shape {
+        int sides() {
+            return 0;
+        }
+    }
+
+    static class Triangle extends Shape {
+        int sides() {
+            return 3;
+        }
+    }
+
+    static class Square extends Shape {
+        int sides() {
+            return 4;
+        }
+    }
+
+    static class Pentagon extends Shape {
+        int sides() {
+            return 5;
+        }
+    }
+
+    static class Hexagon extends Shape {
+        int sides() {
+            return 6;
+        }
+    }
+
+    interface Sized {
+        int size();
+    }
+
+    static class Small implements Sized {
+        public int size() {
+            return 1;
+        }
+    }
+
+    static class Large implements Sized {
+        public int size() {
+            return 100;
+        }
+    }
+
+    // the anonymous subclass is loaded on the first call with `skip`
+    static int count(int n, boolean skip) {
+        Counter counter = skip ? new Counter() {
+            int next() {
+                count += 10;
+                return count;
+            }
+        } : new Counter();
+        int sum = 0;
+        for (int i = 0; i < n; i++) {
+            sum += counter.next();
+        }
+        return sum;
+    }
+
+    static int sides(int kinds) {
+        Shape[] shapes = { new Triangle(), new Square(), new Shape(), new Pentagon(), new Hexagon() };
+        int sum = 0;
+        for (int i = 0; i < 100; i++) {
+            sum += shapes[i % kinds].sides();
+        }
+        return sum;
+    }
+
+    static int sizes() {
+        Sized[] sized = { new Small(), new Large() };
+        int sum = 0;
+        for (int i = 0; i < 10; i++) {
+            sum += sized[i % 2].size();
+        }
+        return sum;
+    }
+}
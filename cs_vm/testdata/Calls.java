@@ -0,0 +1,159 @@
+public class Calls {
+    static final long BIG = 10_000_000_000L;
+    static final int ANSWER = 42;
+    static int initialized = ANSWER + 1;
+
+    static int fib(int n) {
+        if (n < 2) {
+            return n;
+        }
+        return fib(n - 1) + fib(n - 2);
+    }
+
+    static int recurse(int depth) {
+        return recurse(depth + 1);
+    }
+
+    static long slots(int a, long b, double c, float d, Object e, long f) {
+        return a + b + (long) c + (long) d + (e == null ? 1 : 0) + f;
+    }
+
+    static int loop(int n) {
+        int sum = 0;
+        for (int i = 0; i < n; i++) {
+            sum += i;
+        }
+        return sum;
+    }
+
+    static int shapes() {
+        Shape[] shapes = { new Square(3), new Big(2), new Circle() };
+        int result = 0;
+        for (Shape shape : shapes) {
+            result = result * 1000 + shape.twice();
+        }
+        return result;
+    }
+
+    static int kinds() {
+        Base base = new Big(1);
+        return base.kind() * 10 + new Square(1).kind();
+    }
+
+    static int divide(int a, int b) {
+        return a / b;
+    }
+
+    static int switches(int key) {
+        int a = switch (key) {
+            case 0 -> 10;
+            case 1 -> 11;
+            case 2 -> 12;
+            default -> 19;
+        };
+        int b = switch (key) {
+            case -1000 -> 1;
+            case 2 -> 2;
+            case 1000 -> 3;
+            default -> 4;
+        };
+        return a * 10 + b;
+    }
+
+    static double math(long a, int b) {
+        long shifted = (a << b) >>> 3;
+        float f = (float) shifted / 3;
+        int min = Integer.MIN_VALUE;
+        int minusOne = -1;
+        return (byte) (int) f + (char) minusOne + (f > 1.0f ? 0.5 : 0.25) + min / minusOne + min % minusOne;
+    }
+
+    static int arrays() {
+        int[][] grid = new int[3][4];
+        grid[2][3] = 5;
+        long[] longs = new long[2];
+        longs[1] = BIG;
+        Object[] objects = new Square[1];
+        objects[0] = new Big(1);
+        return grid[2][3] + grid.length * grid[0].length + (int) (longs[1] / BIG)
+                + (objects[0] instanceof Big ? 100 : 0);
+    }
+
+    static void storeWrongType() {
+        Object[] objects = new Square[1];
+        objects[0] = new Circle();
+    }
+
+    static Object cast(Object o) {
+        return (Square) o;
+    }
+
+    int value = 7;
+
+    int getValue() {
+        return value;
+    }
+}
+
+interface Shape {
+    int area();
+
+    default int twice() {
+        return area() * 2;
+    }
+}
+
+abstract class Base implements Shape {
+    int scale = 1;
+
+    abstract int side();
+
+    public int area() {
+        return side() * side() * scale;
+    }
+
+    int kind() {
+        return 1;
+    }
+}
+
+class Square extends Base {
+    int side;
+
+    Square(int side) {
+        this.side = side;
+    }
+
+    int side() {
+        return side;
+    }
+
+    int kind() {
+        return 2 + super.kind();
+    }
+}
+
+class Big extends Square {
+    Big(int side) {
+        super(side);
+        scale = 10;
+    }
+
+    public int twice() {
+        return 1 + super.twice();
+    }
+
+    int kind() {
+        return 5;
+    }
+}
+
+class Circle implements Shape {
+    public int area() {
+        return 3;
+    }
+
+    public int twice() {
+        return Shape.super.twice() + 1;
+    }
+}
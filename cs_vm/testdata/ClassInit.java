@@ -0,0 +1,55 @@
+public class ClassInit {
+    static int seen;
+
+    static void sleep(long millis) {
+        try {
+            Thread.sleep(millis);
+        } catch (InterruptedException e) {
+        }
+    }
+
+    static class Slow {
+        static int value;
+
+        static {
+            sleep(10);
+            value = 42;
+        }
+    }
+
+    static class First {
+        static int value;
+
+        static {
+            sleep(10);
+            value = Second.value + 1;
+        }
+    }
+
+    static class Second {
+        static int value = First.value + 1;
+    }
+
+    /** The other thread reads the static field while the initializer of its class sleeps */
+    static int race() throws InterruptedException {
+        Thread reader = new Thread("reader") {
+            public void run() {
+                seen = Slow.value;
+            }
+        };
+        reader.start();
+        int value = Slow.value;
+        reader.join();
+        return value + seen;
+    }
+
+    /** The initializers of the classes need each other and run on different threads */
+    static void deadlock() {
+        new Thread("other") {
+            public void run() {
+                seen = Second.value;
+            }
+        }.start();
+        seen = First.value;
+    }
+}
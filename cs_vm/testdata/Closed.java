@@ -0,0 +1,26 @@
+// Ahead-of-time compilation only dispatches to the classes that are instantiated
+public class Closed {
+    interface Shape {
+        int area();
+    }
+
+    static class Square implements Shape {
+        public int area() {
+            return 4;
+        }
+    }
+
+    static class Never implements Shape {
+        public int area() {
+            return 0;
+        }
+    }
+
+    public static void main(String[] args) throws Exception {
+        Shape shape = new Square();
+        System.out.println(shape instanceof Never ? "never" : "area " + shape.area());
+        if (args.length > 0) {
+            System.out.println(Class.forName(args[0]));
+        }
+    }
+}
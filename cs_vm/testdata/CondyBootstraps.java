@@ -0,0 +1,28 @@
+import java.lang.invoke.MethodHandles;
+
+// The bootstrap methods of the dynamically-computed constants in Condy.class, see GenerateCondy.java
+
+public class CondyBootstraps {
+    static int calls;
+
+    public static int answer(MethodHandles.Lookup lookup, String name, Class<?> type) {
+        calls++;
+        return 42;
+    }
+
+    public static String greeting(MethodHandles.Lookup lookup, String name, Class<?> type, String who,
+            int times) {
+        calls++;
+        return name + ", " + who.repeat(times);
+    }
+
+    public static Object fail(MethodHandles.Lookup lookup, String name, Class<?> type) {
+        calls++;
+        throw new IllegalStateException("no " + name);
+    }
+
+    public static Object same(MethodHandles.Lookup lookup, String name, Class<?> type, Object value) {
+        calls++;
+        return value;
+    }
+}
@@ -0,0 +1,53 @@
+public class Constants {
+    static final String GREETING = "hello";
+
+    static String literal() {
+        return "hello";
+    }
+
+    static int sameLiterals() {
+        int same = 0;
+        if (literal() == "hello") {
+            same += 1;
+        }
+        if (GREETING == Literals.greeting()) {
+            same += 10;
+        }
+        String built = new StringBuilder("hel").append("lo").toString();
+        if (built != "hello") {
+            same += 100;
+        }
+        if (built.intern() == "hello") {
+            same += 1000;
+        }
+        return same;
+    }
+
+    static Class<?> stringClass() {
+        return String.class;
+    }
+
+    static int sameClasses() {
+        int same = 0;
+        if (Constants.class == new Constants().getClass()) {
+            same += 1;
+        }
+        if (int[].class == new int[0].getClass()) {
+            same += 10;
+        }
+        if ((Object) Literals.class != Constants.class) {
+            same += 100;
+        }
+        return same;
+    }
+
+    static long wide() {
+        return 1234567890123L + (long) 0.5e10;
+    }
+}
+
+class Literals {
+    static String greeting() {
+        return "hel" + "lo";
+    }
+}
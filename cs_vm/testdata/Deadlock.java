@@ -0,0 +1,25 @@
+public class Deadlock {
+    static final Object first = new Object();
+    static final Object second = new Object();
+
+    static void lockBoth(Object outer, Object inner) {
+        synchronized (outer) {
+            try {
+                Thread.sleep(10);
+            } catch (InterruptedException e) {
+            }
+            synchronized (inner) {
+                System.out.println("locked both");
+            }
+        }
+    }
+
+    public static void main(String[] args) {
+        new Thread("other") {
+            public void run() {
+                lockBoth(second, first);
+            }
+        }.start();
+        lockBoth(first, second);
+    }
+}
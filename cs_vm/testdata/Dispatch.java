@@ -0,0 +1,105 @@
+public class Dispatch {
+    static int overriding() {
+        Animal animal = new Animal();
+        Animal dog = new Dog();
+        Animal puppy = new Puppy();
+        return animal.sound() * 100 + dog.sound() * 10 + puppy.sound();
+    }
+
+    static int privateMethods() {
+        Animal dog = new Dog();
+        return dog.callSecret() * 10 + ((Dog) dog).secret();
+    }
+
+    static int finalMethods() {
+        Animal puppy = new Puppy();
+        return puppy.id();
+    }
+
+    static int defaults() {
+        Walker dog = new Dog();
+        Walker puppy = new Puppy();
+        Runner runner = new Puppy();
+        return dog.legs() * 100 + puppy.legs() * 10 + runner.legs();
+    }
+
+    static int inheritedDefault(Dog dog) {
+        return dog.legs();
+    }
+
+    static int callName(Named named) {
+        return named.name();
+    }
+
+    static int callSide(Left left) {
+        return left.side();
+    }
+}
+
+class Animal {
+    int sound() {
+        return 1;
+    }
+
+    private int secret() {
+        return 9;
+    }
+
+    int callSecret() {
+        return secret();
+    }
+
+    final int id() {
+        return 4;
+    }
+}
+
+class Dog extends Animal implements Walker {
+    int sound() {
+        return 2;
+    }
+
+    int secret() {
+        return 8;
+    }
+}
+
+class Puppy extends Dog implements Runner {
+    int sound() {
+        return 3 + super.sound();
+    }
+}
+
+interface Walker {
+    default int legs() {
+        return 4;
+    }
+}
+
+interface Runner extends Walker {
+    default int legs() {
+        return Walker.super.legs() + 1;
+    }
+}
+
+interface Named {
+    int name();
+}
+
+class Parent {
+    final int value() {
+        return 1;
+    }
+}
+
+interface Left {
+    default int side() {
+        return 1;
+    }
+}
+
+interface Right {
+    default int side() {
+        return 2;
+    }
+}
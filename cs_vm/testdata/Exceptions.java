@@ -0,0 +1,96 @@
+public class Exceptions {
+    static int caught;
+    static int[] missing;
+
+    static int catchSubclass() {
+        try {
+            throw new ArithmeticException();
+        } catch (IndexOutOfBoundsException e) {
+            return 1;
+        } catch (RuntimeException e) {
+            return 2;
+        }
+    }
+
+    static int finallyRuns() {
+        int result = 0;
+        try {
+            try {
+                result += 1;
+                throw new IllegalStateException();
+            } finally {
+                result += 10;
+            }
+        } catch (IllegalStateException e) {
+            result += 100;
+        }
+        return result;
+    }
+
+    static int vmExceptions(int[] array, Object object) {
+        int result = 0;
+        try {
+            result += missing.length;
+        } catch (NullPointerException e) {
+            result += 1;
+        }
+        try {
+            result += array[5];
+        } catch (ArrayIndexOutOfBoundsException e) {
+            result += 10;
+        }
+        try {
+            result += 1 / result * 0 / 0;
+        } catch (ArithmeticException e) {
+            result += 100;
+        }
+        try {
+            Exceptions cast = (Exceptions) object;
+            result += cast == null ? 0 : 1;
+        } catch (ClassCastException e) {
+            result += 1000;
+        }
+        return result;
+    }
+
+    static int throwDeep(int depth) {
+        if (depth == 0) {
+            throw new IllegalStateException();
+        }
+        return throwDeep(depth - 1) + 1;
+    }
+
+    static int catchFromCallee() {
+        try {
+            return throwDeep(3);
+        } catch (IllegalStateException e) {
+            return -1;
+        }
+    }
+
+    static int rethrow() {
+        try {
+            throwDeep(0);
+            return 0;
+        } catch (RuntimeException e) {
+            caught++;
+            throw e;
+        }
+    }
+
+    static void uncaught() {
+        wrap();
+    }
+
+    static void wrap() {
+        try {
+            throwDeep(1);
+        } catch (IllegalStateException e) {
+            throw new RuntimeException(e);
+        }
+    }
+
+    static Throwable created() {
+        return new Error();
+    }
+}
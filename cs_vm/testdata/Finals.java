@@ -0,0 +1,28 @@
+class Frozen {
+    static final int[] LIMITS = new int[] {1, 2};
+
+    final int value;
+
+    Frozen(int value) {
+        this.value = value;
+    }
+}
+
+public class Finals {
+    public static void main(String[] args) {
+        Frozen frozen = new Frozen(1);
+        System.out.println("value " + frozen.value + " limits " + Frozen.LIMITS.length);
+        try {
+            FrozenWriter.write(frozen);
+            System.out.println("wrote " + frozen.value);
+        } catch (IllegalAccessError e) {
+            System.out.println(e.getMessage());
+        }
+        try {
+            FrozenWriter.writeStatic();
+            System.out.println("wrote " + Frozen.LIMITS.length);
+        } catch (IllegalAccessError e) {
+            System.out.println(e.getMessage());
+        }
+    }
+}
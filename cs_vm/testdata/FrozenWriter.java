@@ -0,0 +1,12 @@
+// This class was compiled against an older version of `Frozen` in Finals.java,
+// where its fields were not final. It can not be compiled against the current version
+
+public class FrozenWriter {
+    public static void write(Frozen frozen) {
+        frozen.value = 2;
+    }
+
+    public static void writeStatic() {
+        Frozen.LIMITS = new int[0];
+    }
+}
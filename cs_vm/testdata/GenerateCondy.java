@@ -0,0 +1,175 @@
+import java.io.ByteArrayOutputStream;
+import java.io.DataOutputStream;
+import java.io.FileOutputStream;
+import java.io.IOException;
+import java.util.ArrayList;
+import java.util.List;
+
+// javac never emits CONSTANT_Dynamic, so this writes Condy.class by hand.
+// Run it with `java GenerateCondy.java` in this directory.
+//
+// Every static method of Condy returns one dynamically-computed constant with `ldc_w`:
+// `answer()I` and `greeting()Ljava/lang/String;` succeed, `fail()Ljava/lang/Object;` throws
+// and `cycle()Ljava/lang/Object;` is a constant that is its own static argument
+
+public class GenerateCondy {
+    private final ByteArrayOutputStream poolBytes = new ByteArrayOutputStream();
+    private final DataOutputStream pool = new DataOutputStream(poolBytes);
+    private int poolCount = 1;
+
+    private int entry(int tag) throws IOException {
+        pool.writeByte(tag);
+        return poolCount++;
+    }
+
+    private int utf8(String value) throws IOException {
+        int index = entry(1);
+        pool.writeUTF(value);
+        return index;
+    }
+
+    private int integer(int value) throws IOException {
+        int index = entry(3);
+        pool.writeInt(value);
+        return index;
+    }
+
+    private int string(String value) throws IOException {
+        int utf8 = utf8(value);
+        int index = entry(8);
+        pool.writeShort(utf8);
+        return index;
+    }
+
+    private int classRef(String name) throws IOException {
+        int utf8 = utf8(name);
+        int index = entry(7);
+        pool.writeShort(utf8);
+        return index;
+    }
+
+    private int nameAndType(String name, String descriptor) throws IOException {
+        int nameIndex = utf8(name);
+        int descriptorIndex = utf8(descriptor);
+        int index = entry(12);
+        pool.writeShort(nameIndex);
+        pool.writeShort(descriptorIndex);
+        return index;
+    }
+
+    private int staticMethodHandle(int classIndex, String name, String descriptor) throws IOException {
+        int nameAndType = nameAndType(name, descriptor);
+        int method = entry(10);
+        pool.writeShort(classIndex);
+        pool.writeShort(nameAndType);
+        int index = entry(15);
+        pool.writeByte(6); // REF_invokeStatic
+        pool.writeShort(method);
+        return index;
+    }
+
+    private int dynamic(int bootstrapMethod, String name, String descriptor) throws IOException {
+        int nameAndType = nameAndType(name, descriptor);
+        int index = entry(17);
+        pool.writeShort(bootstrapMethod);
+        pool.writeShort(nameAndType);
+        return index;
+    }
+
+    public static void main(String[] args) throws IOException {
+        new GenerateCondy().write();
+    }
+
+    private void write() throws IOException {
+        String prefix = "(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/Class;";
+        int thisClass = classRef("Condy");
+        int superClass = classRef("java/lang/Object");
+        int bootstraps = classRef("CondyBootstraps");
+        int code = utf8("Code");
+        int bootstrapMethodsName = utf8("BootstrapMethods");
+
+        // the bootstrap methods are the method handle and the indices of the static arguments
+        List<int[]> bootstrapMethods = new ArrayList<>();
+        bootstrapMethods.add(new int[] {staticMethodHandle(bootstraps, "answer", prefix + ")I")});
+        bootstrapMethods.add(new int[] {
+            staticMethodHandle(bootstraps, "greeting", prefix + "Ljava/lang/String;I)Ljava/lang/String;"),
+            string("condy"),
+            integer(3),
+        });
+        bootstrapMethods.add(new int[] {
+            staticMethodHandle(bootstraps, "fail", prefix + ")Ljava/lang/Object;"),
+        });
+        int same = staticMethodHandle(bootstraps, "same", prefix + "Ljava/lang/Object;)Ljava/lang/Object;");
+
+        int answer = dynamic(0, "answer", "I");
+        int greeting = dynamic(1, "Hello", "Ljava/lang/String;");
+        int fail = dynamic(2, "luck", "Ljava/lang/Object;");
+        // the index of the next entry is known, so the constant can be its own argument
+        int cycle = dynamic(3, "cycle", "Ljava/lang/Object;");
+        bootstrapMethods.add(new int[] {same, cycle});
+
+        String[][] methods = {
+            {"answer", "()I"},
+            {"greeting", "()Ljava/lang/String;"},
+            {"fail", "()Ljava/lang/Object;"},
+            {"cycle", "()Ljava/lang/Object;"},
+        };
+        int[] constants = {answer, greeting, fail, cycle};
+        int[][] methodNames = new int[methods.length][];
+        for (int i = 0; i < methods.length; i++) {
+            methodNames[i] = new int[] {utf8(methods[i][0]), utf8(methods[i][1])};
+        }
+
+        ByteArrayOutputStream bytes = new ByteArrayOutputStream();
+        DataOutputStream out = new DataOutputStream(bytes);
+        out.writeInt(0xCAFEBABE);
+        out.writeShort(0);
+        out.writeShort(55);
+        out.writeShort(poolCount);
+        out.write(poolBytes.toByteArray());
+        out.writeShort(0x0021); // public super
+        out.writeShort(thisClass);
+        out.writeShort(superClass);
+        out.writeShort(0); // interfaces
+        out.writeShort(0); // fields
+
+        out.writeShort(methods.length);
+        for (int i = 0; i < methods.length; i++) {
+            out.writeShort(0x0009); // public static
+            out.writeShort(methodNames[i][0]);
+            out.writeShort(methodNames[i][1]);
+            out.writeShort(1);
+            // ldc_w #constant, then ireturn or areturn
+            out.writeShort(code);
+            out.writeInt(12 + 4);
+            out.writeShort(1); // max stack
+            out.writeShort(0); // max locals
+            out.writeInt(4);
+            out.writeByte(0x13);
+            out.writeShort(constants[i]);
+            out.writeByte(methods[i][1].endsWith("I") ? 0xAC : 0xB0);
+            out.writeShort(0); // exception table
+            out.writeShort(0); // attributes
+        }
+
+        out.writeShort(1);
+        out.writeShort(bootstrapMethodsName);
+        int length = 2;
+        for (int[] method : bootstrapMethods) {
+            length += 4 + 2 * (method.length - 1);
+        }
+        out.writeInt(length);
+        out.writeShort(bootstrapMethods.size());
+        for (int[] method : bootstrapMethods) {
+            out.writeShort(method[0]);
+            out.writeShort(method.length - 1);
+            for (int i = 1; i < method.length; i++) {
+                out.writeShort(method[i]);
+            }
+        }
+
+        try (FileOutputStream file = new FileOutputStream("Condy.class")) {
+            file.write(bytes.toByteArray());
+        }
+    }
+}
@@ -0,0 +1,145 @@
+import java.io.ByteArrayOutputStream;
+import java.io.DataOutputStream;
+import java.io.FileOutputStream;
+import java.io.IOException;
+
+// javac only emits CONSTANT_MethodHandle as static arguments of bootstrap methods,
+// so this writes HandleConstants.class by hand. Run it with `java GenerateHandles.java`
+// in this directory, before compiling Handles.java.
+//
+// HandleConstants extends Described and has a constructor. Every static method is named after
+// a reference kind and returns a method handle constant of that kind with `ldc_w`
+
+public class GenerateHandles {
+    private final ByteArrayOutputStream poolBytes = new ByteArrayOutputStream();
+    private final DataOutputStream pool = new DataOutputStream(poolBytes);
+    private int poolCount = 1;
+
+    private int entry(int tag) throws IOException {
+        pool.writeByte(tag);
+        return poolCount++;
+    }
+
+    private int utf8(String value) throws IOException {
+        int index = entry(1);
+        pool.writeUTF(value);
+        return index;
+    }
+
+    private int classRef(String name) throws IOException {
+        int utf8 = utf8(name);
+        int index = entry(7);
+        pool.writeShort(utf8);
+        return index;
+    }
+
+    private int memberRef(int tag, int classIndex, String name, String descriptor) throws IOException {
+        int nameIndex = utf8(name);
+        int descriptorIndex = utf8(descriptor);
+        int nameAndType = entry(12);
+        pool.writeShort(nameIndex);
+        pool.writeShort(descriptorIndex);
+        int index = entry(tag);
+        pool.writeShort(classIndex);
+        pool.writeShort(nameAndType);
+        return index;
+    }
+
+    private int methodHandle(int kind, int member) throws IOException {
+        int index = entry(15);
+        pool.writeByte(kind);
+        pool.writeShort(member);
+        return index;
+    }
+
+    public static void main(String[] args) throws IOException {
+        new GenerateHandles().write();
+    }
+
+    private void write() throws IOException {
+        int thisClass = classRef("HandleConstants");
+        int described = classRef("Described");
+        int handles = classRef("Handles");
+        int greeter = classRef("Greeter");
+        int code = utf8("Code");
+
+        int value = memberRef(9, handles, "value", "I");
+        int count = memberRef(9, handles, "count", "I");
+        int describe = memberRef(10, described, "describe", "()Ljava/lang/String;");
+        int describedInit = memberRef(10, described, "<init>", "()V");
+        String[] names = {
+            "getField", "getStatic", "putField", "putStatic", "invokeVirtual",
+            "invokeStatic", "invokeSpecial", "newInvokeSpecial", "invokeInterface",
+        };
+        int[] members = {
+            value, count, value, count, describe,
+            memberRef(10, handles, "twice", "(I)I"),
+            describe,
+            memberRef(10, handles, "<init>", "(I)V"),
+            memberRef(11, greeter, "greet", "(Ljava/lang/String;)Ljava/lang/String;"),
+        };
+        int[] constants = new int[names.length];
+        int[] nameIndices = new int[names.length];
+        for (int i = 0; i < names.length; i++) {
+            constants[i] = methodHandle(i + 1, members[i]);
+            nameIndices[i] = utf8(names[i]);
+        }
+        int handleDescriptor = utf8("()Ljava/lang/invoke/MethodHandle;");
+        int init = utf8("<init>");
+        int initDescriptor = utf8("()V");
+
+        ByteArrayOutputStream bytes = new ByteArrayOutputStream();
+        DataOutputStream out = new DataOutputStream(bytes);
+        out.writeInt(0xCAFEBABE);
+        out.writeShort(0);
+        out.writeShort(55);
+        out.writeShort(poolCount);
+        out.write(poolBytes.toByteArray());
+        out.writeShort(0x0021); // public super
+        out.writeShort(thisClass);
+        out.writeShort(described);
+        out.writeShort(0); // interfaces
+        out.writeShort(0); // fields
+
+        out.writeShort(names.length + 1);
+        // aload_0, invokespecial Described.<init>, return
+        out.writeShort(0x0001); // public
+        out.writeShort(init);
+        out.writeShort(initDescriptor);
+        out.writeShort(1);
+        out.writeShort(code);
+        out.writeInt(12 + 5);
+        out.writeShort(1); // max stack
+        out.writeShort(1); // max locals
+        out.writeInt(5);
+        out.writeByte(0x2A);
+        out.writeByte(0xB7);
+        out.writeShort(describedInit);
+        out.writeByte(0xB1);
+        out.writeShort(0); // exception table
+        out.writeShort(0); // attributes
+
+        for (int i = 0; i < names.length; i++) {
+            out.writeShort(0x0009); // public static
+            out.writeShort(nameIndices[i]);
+            out.writeShort(handleDescriptor);
+            out.writeShort(1);
+            // ldc_w #constant, areturn
+            out.writeShort(code);
+            out.writeInt(12 + 4);
+            out.writeShort(1); // max stack
+            out.writeShort(0); // max locals
+            out.writeInt(4);
+            out.writeByte(0x13);
+            out.writeShort(constants[i]);
+            out.writeByte(0xB0);
+            out.writeShort(0); // exception table
+            out.writeShort(0); // attributes
+        }
+        out.writeShort(0); // attributes
+
+        try (FileOutputStream file = new FileOutputStream("HandleConstants.class")) {
+            file.write(bytes.toByteArray());
+        }
+    }
+}
@@ -0,0 +1,93 @@
+import java.lang.invoke.MethodHandle;
+import java.lang.invoke.MethodHandles;
+import java.lang.invoke.MethodType;
+
+// Calls method handles of all nine reference kinds with invokeExact and invoke.
+// HandleConstants.class has the same handles as constants, see GenerateHandles.java
+
+class Described {
+    String describe() {
+        return "described";
+    }
+}
+
+interface Greeter {
+    String greet(String name);
+}
+
+public class Handles extends Described implements Greeter {
+    static int count;
+    int value;
+
+    Handles(int value) {
+        this.value = value;
+    }
+
+    @Override
+    String describe() {
+        return "handles " + value;
+    }
+
+    @Override
+    public String greet(String name) {
+        return "Hello " + name + " " + value;
+    }
+
+    static int twice(int x) {
+        return 2 * x;
+    }
+
+    public static void main(String[] args) throws Throwable {
+        MethodHandles.Lookup lookup = MethodHandles.lookup();
+        MethodType describe = MethodType.methodType(String.class);
+        MethodHandle[] handles = {
+            lookup.findGetter(Handles.class, "value", int.class),
+            lookup.findStaticGetter(Handles.class, "count", int.class),
+            lookup.findSetter(Handles.class, "value", int.class),
+            lookup.findStaticSetter(Handles.class, "count", int.class),
+            lookup.findVirtual(Described.class, "describe", describe),
+            lookup.findStatic(Handles.class, "twice", MethodType.methodType(int.class, int.class)),
+            lookup.findSpecial(Described.class, "describe", describe, Handles.class),
+            lookup.findConstructor(Handles.class, MethodType.methodType(void.class, int.class)),
+            lookup.findVirtual(Greeter.class, "greet", MethodType.methodType(String.class, String.class)),
+        };
+        run(handles);
+
+        run(new MethodHandle[] {
+            HandleConstants.getField(),
+            HandleConstants.getStatic(),
+            HandleConstants.putField(),
+            HandleConstants.putStatic(),
+            HandleConstants.invokeVirtual(),
+            HandleConstants.invokeStatic(),
+            null,
+            HandleConstants.newInvokeSpecial(),
+            HandleConstants.invokeInterface(),
+        });
+        // the constant can only call the method of Described for instances of HandleConstants
+        MethodHandle special = HandleConstants.invokeSpecial();
+        System.out.println(special.type() + " " + (String) special.invokeExact(new HandleConstants()));
+
+        // invokeExact needs the exact type, invoke converts the arguments and the result
+        try {
+            Object wrong = handles[5].invokeExact(4);
+            System.out.println(wrong);
+        } catch (ClassCastException | java.lang.invoke.WrongMethodTypeException e) {
+            System.out.println(e.getClass().getSimpleName());
+        }
+        Object boxed = handles[5].invoke(Integer.valueOf(21));
+        long widened = (long) handles[5].invoke((short) 5);
+        System.out.println(boxed + " " + widened + " " + handles[8].invoke(new Handles(1), (Object) "Bob"));
+    }
+
+    private static void run(MethodHandle[] handles) throws Throwable {
+        Handles handle = (Handles) handles[7].invokeExact(5);
+        handles[2].invokeExact(handle, (int) handles[0].invokeExact(handle) + 1);
+        handles[3].invokeExact((int) handles[1].invokeExact() + 10);
+        String special = handles[6] == null ? "-" : (String) handles[6].invokeExact(handle);
+        System.out.println((int) handles[0].invokeExact(handle) + " " + count + " "
+                + (String) handles[4].invokeExact((Described) handle) + " " + special + " "
+                + (int) handles[5].invokeExact(21) + " "
+                + (String) handles[8].invokeExact((Greeter) handle, "Alice"));
+    }
+}
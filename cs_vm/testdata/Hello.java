@@ -0,0 +1,42 @@
+// Compiled with `javac -encoding UTF-8`, so string concatenation uses `invokedynamic`
+// with `StringConcatFactory`
+
+public class Hello {
+    public static void main(String[] args) {
+        System.out.println("Hello, " + args[0] + "!");
+
+        StringBuilder builder = new StringBuilder();
+        for (int i = 0; i < 5; i++) {
+            builder.append(i).append(',');
+        }
+        System.out.println(builder.reverse());
+
+        Integer small = 127;
+        Integer big = 1000;
+        System.out.println((small == Integer.valueOf(127)) + " " + (big == Integer.valueOf(1000))
+                + " " + big.equals(1000) + " " + (small + big));
+        System.out.println(1.0 / 3 + " " + 1e10 + " " + 100.0f + " " + Math.sqrt(2) + " " + Long.MIN_VALUE);
+        System.out.println(Integer.toHexString(-1) + " " + Integer.parseInt("-123") + " " + "héllo €".length());
+
+        int[] array = { 3, 1, 2 };
+        int[] copy = array.clone();
+        copy[0] = 9;
+        System.out.println(array[0] + " " + copy[0] + " " + new Object().getClass());
+
+        try {
+            Integer.parseInt("x1");
+        } catch (NumberFormatException e) {
+            System.err.println(e);
+        }
+    }
+}
+
+class Natives {
+    static native int add(int a, int b);
+
+    static native void missing();
+
+    static int callAdd() {
+        return add(40, 2) + 1;
+    }
+}
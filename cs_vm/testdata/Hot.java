@@ -0,0 +1,102 @@
+// Loops over the int and long instructions the baseline compiler supports, with their edge cases
+public class Hot {
+    static int sum(int n) {
+        int sum = 0;
+        for (int i = 0; i < n; i++) {
+            sum += i * i - (i >> 1) + (i & 7) - (i | 3) + (i ^ 5);
+        }
+        return sum;
+    }
+
+    static long longs(int n) {
+        long value = 1L;
+        for (int i = 0; i < n; i++) {
+            value = value * 6364136223846793005L + 1442695040888963407L;
+            value ^= value >>> 17;
+            value += value << 3 >> 2;
+            if (value < 0 && (value & 1) == 0) {
+                value = -value;
+            }
+        }
+        return value;
+    }
+
+    static int collatz(long start) {
+        int steps = 0;
+        while (start != 1) {
+            start = (start & 1) == 0 ? start / 2 : 3 * start + 1;
+            steps++;
+        }
+        return steps;
+    }
+
+    static int divisions(int n) {
+        int result = 0;
+        for (int i = -n; i <= n; i++) {
+            result += 1000000 / (i == 0 ? 1 : i) + 1000000 % (i == 0 ? 7 : i);
+            result += Integer.MIN_VALUE / (i == 0 ? -1 : 1) + Integer.MIN_VALUE % -1;
+        }
+        long wide = Long.MIN_VALUE / -1 + Long.MIN_VALUE % -1 + (-7L / 2) + (-7L % 2);
+        return result + (int) wide + (int) (wide >>> 40);
+    }
+
+    static int narrow(int n) {
+        int result = 0;
+        for (int i = 0; i < n; i += 97) {
+            int value = i * 40503;
+            result += (byte) value + (char) value + (short) value + (int) (long) value;
+            result += i << 33 + (i >>> 35) + (i >> -1);
+        }
+        return result;
+    }
+
+    // divides by zero in the loop, the interpreter throws
+    static int zero(int n) {
+        int result = 0;
+        for (int i = n; i >= 0; i--) {
+            result += 100 / i;
+        }
+        return result;
+    }
+
+    static int twice(int value) {
+        return value * 2;
+    }
+
+    // the invocation exits to the interpreter in every iteration
+    static int calls(int n) {
+        int result = 0;
+        for (int i = 0; i < n; i++) {
+            result += twice(i);
+        }
+        return result;
+    }
+
+    static long compare(long n) {
+        long result = 0;
+        for (long i = -n; i < n; i += 3) {
+            result += i > 0 ? 1 : i == 0 ? 100 : -1;
+            result += Long.compare(i, 5) * 7;
+        }
+        return result;
+    }
+
+    public static void main(String[] args) {
+        System.out.println("sum " + sum(30000));
+        System.out.println("longs " + longs(20000));
+        int steps = 0;
+        for (int i = 1; i < 3000; i++) {
+            steps += collatz(i);
+        }
+        System.out.println("collatz " + steps);
+        System.out.println("divisions " + divisions(5000));
+        System.out.println("narrow " + narrow(200000));
+        try {
+            System.out.println("zero " + zero(20000));
+        } catch (ArithmeticException e) {
+            System.out.println("zero " + e.getMessage());
+        }
+        System.out.println("calls " + calls(5000));
+        System.out.println("compare " + compare(30000));
+    }
+}
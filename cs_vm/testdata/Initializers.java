@@ -0,0 +1,40 @@
+public class Initializers {
+    static int attempts;
+
+    static class Failing {
+        static int value = fail();
+
+        static int fail() {
+            attempts++;
+            throw new IllegalStateException();
+        }
+    }
+
+    static class Fatal {
+        static int value = fail();
+
+        static int fail() {
+            throw new StackOverflowError();
+        }
+    }
+
+    static int failing() {
+        int result = 0;
+        try {
+            result += Failing.value;
+        } catch (ExceptionInInitializerError e) {
+            result += e.getCause() instanceof IllegalStateException ? 1 : 2;
+        }
+        // the initializer does not run again
+        try {
+            result += Failing.value;
+        } catch (NoClassDefFoundError e) {
+            result += 10;
+        }
+        return result + attempts * 100;
+    }
+
+    static int fatal() {
+        return Fatal.value;
+    }
+}
@@ -0,0 +1,49 @@
+import java.util.ArrayList;
+import java.util.List;
+import java.util.function.BiFunction;
+import java.util.function.IntBinaryOperator;
+import java.util.function.Supplier;
+import java.util.function.UnaryOperator;
+
+public class Lambdas {
+    private final String name;
+
+    Lambdas(String name) {
+        this.name = name;
+    }
+
+    String greet(String other) {
+        return name + " greets " + other;
+    }
+
+    public static void main(String[] args) {
+        // non-capturing, capturing, bound and unbound method references and constructors
+        IntBinaryOperator add = (a, b) -> a + b;
+        int offset = args.length + 10;
+        IntBinaryOperator addOffset = (a, b) -> a + b + offset;
+        System.out.println(add.applyAsInt(1, 2) + " " + addOffset.applyAsInt(1, 2));
+
+        Lambdas lambdas = new Lambdas("Alice");
+        UnaryOperator<String> bound = lambdas::greet;
+        BiFunction<Lambdas, String, String> unbound = Lambdas::greet;
+        System.out.println(bound.apply("Bob") + ", " + unbound.apply(new Lambdas("Carol"), "Dave"));
+
+        Supplier<List<String>> constructor = ArrayList::new;
+        List<String> list = constructor.get();
+        list.add("b");
+        list.add("a");
+        list.add("c");
+        list.sort(String::compareTo);
+        list.forEach(element -> System.out.print(element + ";"));
+        System.out.println();
+
+        Runnable runnable = () -> System.out.println("run " + lambdas.name);
+        runnable.run();
+        // every evaluation of a non-capturing lambda results in the same object
+        System.out.println(supplier() == supplier());
+    }
+
+    static Supplier<String> supplier() {
+        return () -> "constant";
+    }
+}
@@ -0,0 +1,124 @@
+// Litmus tests of the Java memory model. Every test runs two threads many times and counts
+// the outcomes `r1 r2`, some of which the memory model forbids
+public class Litmus extends Thread {
+    static final int ITERATIONS = 40;
+
+    static final int MP_WRITER = 0;
+    static final int MP_READER = 1;
+    static final int SB_FIRST = 2;
+    static final int SB_SECOND = 3;
+    static final int WIDE_WRITER = 4;
+    static final int WIDE_READER = 5;
+    static final int FINAL_WRITER = 6;
+    static final int FINAL_READER = 7;
+
+    static int data;
+    static volatile boolean ready;
+    static volatile int x;
+    static volatile int y;
+    static volatile long wide;
+    static volatile double wideDouble;
+    static Holder shared;
+    static int r1;
+    static int r2;
+
+    static class Holder {
+        final int value;
+        final long[] values;
+
+        Holder(int value) {
+            this.value = value;
+            this.values = new long[] {value, value};
+        }
+    }
+
+    final int kind;
+
+    Litmus(int kind) {
+        this.kind = kind;
+    }
+
+    public void run() {
+        switch (kind) {
+            // message passing: a reader that sees the flag also sees the data
+            case MP_WRITER:
+                data = 42;
+                ready = true;
+                break;
+            case MP_READER:
+                r1 = ready ? 1 : 0;
+                r2 = data == 42 ? 1 : 0;
+                break;
+            // store buffering: not both threads can miss the store of the other one
+            case SB_FIRST:
+                x = 1;
+                r1 = y;
+                break;
+            case SB_SECOND:
+                y = 1;
+                r2 = x;
+                break;
+            // volatile long and double values are never torn
+            case WIDE_WRITER:
+                for (int i = 0; i < 10; i++) {
+                    wide = i % 2 == 0 ? -1L : 0L;
+                    wideDouble = i % 2 == 0 ? -1.0 : Double.MIN_VALUE;
+                }
+                break;
+            case WIDE_READER:
+                for (int i = 0; i < 10; i++) {
+                    long value = wide;
+                    double doubleValue = wideDouble;
+                    if ((value != 0L && value != -1L)
+                            || (doubleValue != 0.0 && doubleValue != -1.0 && doubleValue != Double.MIN_VALUE)) {
+                        r1 = 1;
+                    }
+                    if (value == -1L) {
+                        r2 = 1;
+                    }
+                }
+                break;
+            // final fields are frozen when the constructor returns: a reader that sees the
+            // object sees the values of its final fields, including the array elements
+            case FINAL_WRITER:
+                shared = new Holder(42);
+                break;
+            case FINAL_READER:
+                Holder holder = shared;
+                r1 = holder != null ? 1 : 0;
+                r2 = holder != null && holder.value == 42 && holder.values[1] == 42 ? 1 : 0;
+                break;
+        }
+    }
+
+    static void run(String name, int first, int second) throws InterruptedException {
+        int[] counts = new int[4];
+        for (int i = 0; i < ITERATIONS; i++) {
+            data = 0;
+            ready = false;
+            x = 0;
+            y = 0;
+            wide = 0L;
+            wideDouble = 0.0;
+            shared = null;
+            r1 = 0;
+            r2 = 0;
+
+            Thread a = new Litmus(first);
+            Thread b = new Litmus(second);
+            a.start();
+            b.start();
+            a.join();
+            b.join();
+            counts[r1 * 2 + r2]++;
+        }
+        System.out.println(name + " 00:" + counts[0] + " 01:" + counts[1] + " 10:" + counts[2] + " 11:" + counts[3]);
+    }
+
+    public static void main(String[] args) throws InterruptedException {
+        run("mp", MP_WRITER, MP_READER);
+        run("sb", SB_FIRST, SB_SECOND);
+        run("wide", WIDE_WRITER, WIDE_READER);
+        run("final", FINAL_WRITER, FINAL_READER);
+    }
+}
@@ -0,0 +1,6 @@
+class Point {
+    int x;
+    long y;
+    Point next;
+    static int count;
+}
@@ -0,0 +1,17 @@
+class Point3 extends Point {
+    double z;
+    byte flag;
+    int x;
+
+    static int getX(Point p) {
+        return p.x;
+    }
+
+    static void setY(Point p, long y) {
+        p.y = y;
+    }
+
+    static int getShadowedX(Point3 p) {
+        return p.x;
+    }
+}
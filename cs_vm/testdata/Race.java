@@ -0,0 +1,25 @@
+public class Race extends Thread {
+    static int count;
+
+    public void run() {
+        for (int i = 0; i < 100; i++) {
+            count++;
+        }
+    }
+
+    public static void main(String[] args) throws InterruptedException {
+        Thread[] threads = new Thread[3];
+        for (int i = 0; i < threads.length; i++) {
+            threads[i] = new Race();
+            threads[i].start();
+        }
+        for (Thread thread : threads) {
+            thread.join();
+        }
+
+        long start = System.currentTimeMillis();
+        Thread.sleep(60000);
+        long slept = System.currentTimeMillis() - start;
+        System.out.println(count + " " + (slept >= 60000));
+    }
+}
@@ -0,0 +1,121 @@
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.reflect.Field;
+import java.lang.reflect.InvocationTargetException;
+import java.lang.reflect.Method;
+import java.lang.reflect.Modifier;
+import java.lang.reflect.Parameter;
+import java.util.Arrays;
+import java.util.Comparator;
+import java.util.List;
+import java.util.Map;
+
+// Reflects on the fields, methods, parameters and annotations of its own classes.
+// Compiled with -parameters for the MethodParameters attributes
+
+@Retention(RetentionPolicy.RUNTIME)
+@interface Marked {
+    String value() default "marked";
+}
+
+class Account {
+    private int balance;
+
+    int deposit(int amount) {
+        return balance += amount;
+    }
+}
+
+class Savings extends Account {
+    @Override
+    int deposit(int amount) {
+        return super.deposit(amount * 2);
+    }
+}
+
+@Marked("class")
+public class Reflection<T extends Comparable<T>> {
+    public static final String NAME = "reflection";
+    static int counter;
+    private List<T> items;
+    protected final Map<String, ? super Integer> counts = null;
+    public transient volatile long stamp;
+
+    @Marked
+    public static int add(int left, int right) {
+        return left + right;
+    }
+
+    private String secret(String prefix) {
+        return prefix + counter;
+    }
+
+    public <E extends Exception> T first(List<? extends T> list) throws E, InterruptedException {
+        return list.get(0);
+    }
+
+    static void fail(String message) {
+        throw new IllegalStateException(message);
+    }
+
+    public static void main(String[] args) throws Exception {
+        Class<?> reflection = Class.forName("Reflection");
+        System.out.println(Modifier.toString(reflection.getModifiers()) + " " + reflection.getName()
+                + " " + Arrays.toString(reflection.getTypeParameters()) + " "
+                + reflection.getTypeParameters()[0].getBounds()[0]);
+
+        Field[] fields = reflection.getDeclaredFields();
+        for (Field field : fields) {
+            System.out.println(Modifier.toString(field.getModifiers()) + " " + field.getGenericType().getTypeName()
+                    + " " + field.getName());
+        }
+
+        Method[] methods = reflection.getDeclaredMethods();
+        Arrays.sort(methods, Comparator.comparing(Method::getName));
+        for (Method method : methods) {
+            StringBuilder names = new StringBuilder();
+            for (Parameter parameter : method.getParameters()) {
+                names.append(" ").append(parameter.getName());
+            }
+            System.out.println(method.toGenericString() + names);
+        }
+
+        Method add = reflection.getMethod("add", int.class, int.class);
+        System.out.println(add.invoke(null, 20, 22) + " "
+                + Marked.class.getDeclaredMethod("value").getDefaultValue());
+
+        Reflection<String> instance = new Reflection<>();
+        Method secret = reflection.getDeclaredMethod("secret", String.class);
+        secret.setAccessible(true);
+        Field counter = reflection.getDeclaredField("counter");
+        counter.setInt(null, 7);
+        System.out.println(secret.invoke(instance, "counter ") + " " + counter.get(null) + " "
+                + reflection.getField("NAME").get(null));
+
+        Field stamp = reflection.getField("stamp");
+        stamp.set(instance, 123L);
+        System.out.println(instance.stamp + " " + stamp.getLong(instance));
+
+        Method deposit = Account.class.getDeclaredMethod("deposit", int.class);
+        Field balance = Account.class.getDeclaredField("balance");
+        balance.setAccessible(true);
+        Savings savings = new Savings();
+        System.out.println(deposit.invoke(savings, 5) + " " + balance.get(savings));
+
+        try {
+            reflection.getDeclaredMethod("fail", String.class).invoke(null, "failed");
+        } catch (InvocationTargetException e) {
+            System.out.println(e.getCause());
+        }
+        try {
+            deposit.invoke("not an account", 1);
+        } catch (IllegalArgumentException e) {
+            System.out.println(e.getMessage());
+        }
+        try {
+            reflection.getField("NAME").set(null, "changed");
+        } catch (IllegalAccessException e) {
+            System.out.println("IllegalAccessException");
+        }
+    }
+}
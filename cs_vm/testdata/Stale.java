@@ -0,0 +1,15 @@
+// These classes were compiled against older versions of the classes in Dispatch.java:
+// `Named` had no methods, `Parent.value` was not final, and `Left` and `Right` had no default methods.
+// They can not be compiled against the current versions
+
+class Nameless implements Named {
+}
+
+class Child extends Parent {
+    int value() {
+        return 2;
+    }
+}
+
+class Both implements Left, Right {
+}
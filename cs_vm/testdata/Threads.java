@@ -0,0 +1,136 @@
+public class Threads {
+    static final int COUNT = 0;
+    static final int PRODUCE = 1;
+    static final int SLEEP = 2;
+    static final int WAIT = 3;
+    static final int FAIL = 4;
+    static final int LAST = 5;
+
+    static int count;
+    static final Object lock = new Object();
+    static final int[] buffer = new int[2];
+    static int size;
+
+    static synchronized void increment() {
+        count = count + 1;
+    }
+
+    static void put(int value) throws InterruptedException {
+        synchronized (buffer) {
+            while (size == buffer.length) {
+                buffer.wait();
+            }
+            buffer[size++] = value;
+            buffer.notifyAll();
+        }
+    }
+
+    static int take() throws InterruptedException {
+        synchronized (buffer) {
+            while (size == 0) {
+                buffer.wait();
+            }
+            int value = buffer[0];
+            buffer[0] = buffer[1];
+            size--;
+            buffer.notifyAll();
+            return value;
+        }
+    }
+
+    public static void main(String[] args) throws InterruptedException {
+        Thread[] counters = new Thread[4];
+        for (int i = 0; i < counters.length; i++) {
+            counters[i] = new Task(COUNT);
+            counters[i].start();
+        }
+        for (Thread counter : counters) {
+            counter.join();
+        }
+        System.out.println("count " + count);
+
+        Thread producer = new Thread(new Task(PRODUCE), "producer");
+        producer.start();
+        int sum = 0;
+        for (int i = 0; i < 10; i++) {
+            sum += take();
+        }
+        producer.join();
+        System.out.println("sum " + sum + " " + producer.getName() + " alive " + producer.isAlive());
+
+        Thread sleeper = new Task(SLEEP);
+        sleeper.start();
+        Thread.sleep(5);
+        sleeper.interrupt();
+        sleeper.join();
+
+        Thread waiter = new Task(WAIT);
+        waiter.start();
+        Thread.sleep(5);
+        waiter.interrupt();
+        waiter.join();
+
+        long start = System.currentTimeMillis();
+        Thread.sleep(20);
+        System.out.println("slept " + (System.currentTimeMillis() - start >= 20));
+
+        try {
+            lock.notify();
+        } catch (IllegalMonitorStateException e) {
+            System.out.println("not owner " + Thread.holdsLock(lock));
+        }
+
+        Thread.currentThread().interrupt();
+        System.out.println("interrupted " + Thread.interrupted() + " " + Thread.interrupted());
+
+        Thread daemon = new Task(WAIT);
+        daemon.setDaemon(true);
+        daemon.start();
+
+        new Thread(new Task(FAIL), "failing").start();
+        new Thread(new Task(LAST), "last").start();
+        System.out.println("main done " + Thread.currentThread().getName());
+    }
+
+    static class Task extends Thread {
+        final int kind;
+
+        Task(int kind) {
+            this.kind = kind;
+        }
+
+        public void run() {
+            try {
+                switch (kind) {
+                    case COUNT:
+                        for (int i = 0; i < 500; i++) {
+                            increment();
+                        }
+                        break;
+                    case PRODUCE:
+                        for (int i = 1; i <= 10; i++) {
+                            put(i);
+                        }
+                        break;
+                    case SLEEP:
+                        Thread.sleep(60000);
+                        System.out.println("woke up");
+                        break;
+                    case WAIT:
+                        synchronized (lock) {
+                            lock.wait();
+                        }
+                        break;
+                    case FAIL:
+                        throw new IllegalStateException("failed");
+                    case LAST:
+                        Thread.sleep(10);
+                        System.out.println("last " + Thread.currentThread().getName());
+                        break;
+                }
+            } catch (InterruptedException e) {
+                System.out.println("interrupted " + kind + " " + isInterrupted() + " " + Thread.holdsLock(lock));
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+// Compiled to WebAssembly, where the natives are imported from the host
+public class Wasm {
+    static native void print(int value);
+
+    static native void print(long value);
+
+    static native void print(double value);
+
+    static final int[] SQUARES = new int[10];
+    static int counter = 100;
+
+    static {
+        for (int i = 0; i < SQUARES.length; i++) {
+            SQUARES[i] = i * i;
+        }
+    }
+
+    interface Shape {
+        double area();
+    }
+
+    static class Square implements Shape {
+        final int side;
+
+        Square(int side) {
+            this.side = side;
+        }
+
+        public double area() {
+            return side * side;
+        }
+    }
+
+    static class Rectangle extends Square {
+        final int height;
+
+        Rectangle(int side, int height) {
+            super(side);
+            this.height = height;
+        }
+
+        public double area() {
+            return side * height;
+        }
+    }
+
+    static class Circle implements Shape {
+        final double radius;
+
+        Circle(double radius) {
+            this.radius = radius;
+        }
+
+        public double area() {
+            return 3.0 * radius * radius;
+        }
+    }
+
+    public static int gcd(int a, int b) {
+        while (b != 0) {
+            int t = a % b;
+            a = b;
+            b = t;
+        }
+        return a;
+    }
+
+    public static long factorial(int n) {
+        return n <= 1 ? 1 : n * factorial(n - 1);
+    }
+
+    public static int primes(int limit) {
+        boolean[] composite = new boolean[limit + 1];
+        int count = 0;
+        for (int i = 2; i <= limit; i++) {
+            if (!composite[i]) {
+                count++;
+                for (int j = i * 2; j <= limit; j += i) {
+                    composite[j] = true;
+                }
+            }
+        }
+        return count;
+    }
+
+    public static int table(int day) {
+        switch (day) {
+            case 0:
+            case 6:
+                return 0;
+            case 1:
+                return 8;
+            case 2:
+            case 3:
+                return 7;
+            case 4:
+                return 6;
+            default:
+                return -1;
+        }
+    }
+
+    public static int lookup(int key) {
+        int result = 1;
+        switch (key) {
+            case -1000:
+                result = 2;
+                break;
+            case 7:
+                result += 3;
+            case 100000:
+                result *= 5;
+                break;
+            default:
+                result = key;
+        }
+        return result;
+    }
+
+    public static double areas(int n) {
+        Shape[] shapes = new Shape[n];
+        for (int i = 0; i < n; i++) {
+            switch (i % 3) {
+                case 0:
+                    shapes[i] = new Square(i);
+                    break;
+                case 1:
+                    shapes[i] = new Rectangle(i, 2);
+                    break;
+                default:
+                    shapes[i] = new Circle(i / 2.0);
+            }
+        }
+        double total = 0;
+        for (Shape shape : shapes) {
+            total += shape.area();
+        }
+        return total;
+    }
+
+    public static int divide(int a, int b) {
+        return a / b + a % b;
+    }
+
+    public static long divide(long a, long b) {
+        return a / b;
+    }
+
+    public static int next() {
+        return counter++;
+    }
+
+    public static int compare(double a, double b) {
+        if (a < b) {
+            return -1;
+        }
+        if (a > b) {
+            return 1;
+        }
+        return a == b ? 0 : 2;
+    }
+
+    public static int narrow(int value) {
+        char[] chars = {(char) value, (char) (value >> 8)};
+        long wide = (long) value << 40 >>> 20;
+        return chars[0] + chars[1] + (byte) value + (short) value + (int) (wide >> 3) + (int) (float) value;
+    }
+
+    public static void report() {
+        print(gcd(1071, 462));
+        print(factorial(20));
+        print(areas(7));
+        print(SQUARES[7]);
+        Object square = new Square(3);
+        print(square instanceof Shape ? 1 : 0);
+        print(square instanceof Circle ? 1 : 0);
+        print(((Square) square).side);
+    }
+}
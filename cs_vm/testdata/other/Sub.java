@@ -0,0 +1,11 @@
+package other;
+
+public class Sub extends pkg.Base {
+    int value() {
+        return 2;
+    }
+
+    public static int callValue(Sub sub) {
+        return sub.value();
+    }
+}
@@ -0,0 +1,12 @@
+package pkg;
+
+// `value` is package private, so `other.Sub.value` does not override it but `Deep.value` does
+public class Base {
+    int value() {
+        return 1;
+    }
+
+    public static int callValue(Base base) {
+        return base.value();
+    }
+}
@@ -0,0 +1,7 @@
+package pkg;
+
+public class Deep extends other.Sub {
+    int value() {
+        return 3;
+    }
+}
@@ -1,9 +1,40 @@
 fn main() {
-    let file = std::env::args().nth(1).unwrap_or_else(|| {
+    match cs_vm::Image::embedded() {
+        Ok(Some(image)) => run_image(&image),
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let mut args = std::env::args().skip(1);
+    let file = args.next().unwrap_or_else(|| {
         eprintln!("No file provided");
         std::process::exit(1);
     });
 
+    if file == "run" {
+        run(args.collect());
+        return;
+    }
+    if file == "aot" {
+        aot(args.collect());
+        return;
+    }
+    if file == "wasm" {
+        wasm(args.collect());
+        return;
+    }
+    if file == "diff" {
+        diff(args.collect());
+        return;
+    }
+    if file == "compat" {
+        compat(args.collect());
+        return;
+    }
+
     let contents = std::fs::read(file).unwrap_or_else(|_| {
         eprintln!("Could not read file");
         std::process::exit(1);
@@ -19,3 +50,253 @@ fn main() {
 
     cs_class_printer::print(&class_file);
 }
+
+/// `run [-cp <dir>] [--jdk <java.home>] [--print-inline-caches] <class> [args...]` runs the main
+/// method of the class, with the class library of the JDK if one is given
+fn run(mut args: Vec<String>) {
+    let mut class_path = ".".to_string();
+    let mut jdk = None;
+    let mut print_inline_caches = false;
+    while args.len() > 1 {
+        match args[0].as_str() {
+            "-cp" | "-classpath" => class_path = args.remove(1),
+            "--jdk" => jdk = Some(args.remove(1)),
+            "--print-inline-caches" => print_inline_caches = true,
+            _ => break,
+        }
+        args.remove(0);
+    }
+    if args.is_empty() {
+        eprintln!("No class provided");
+        std::process::exit(1);
+    }
+    let class = args.remove(0).replace('.', "/");
+
+    let mut vm = cs_vm::Vm::new();
+    vm.add_class_path(class_path);
+    let initialized = match jdk {
+        Some(jdk) => {
+            let modules = std::path::Path::new(&jdk).join("lib/modules");
+            let image = cs_vm::JImage::open(&modules).unwrap_or_else(|err| {
+                eprintln!("Could not read {}: {}", modules.display(), err);
+                std::process::exit(1);
+            });
+            vm.set_boot_image(image);
+            vm.initialize_jdk()
+        }
+        None => Ok(()),
+    };
+    let result = initialized.and_then(|()| vm.run_main(&class, &args));
+    if print_inline_caches {
+        print_caches(&vm);
+    }
+    exit_on_error(result);
+}
+
+/// `aot [-cp <dir>] [-o <executable>] <class>` compiles the program with the class files in the
+/// directory into an executable that runs the main method of the class
+fn aot(mut args: Vec<String>) {
+    let mut class_path = ".".to_string();
+    let mut output = None;
+    while args.len() > 1 {
+        match args[0].as_str() {
+            "-cp" | "-classpath" => class_path = args.remove(1),
+            "-o" => output = Some(args.remove(1)),
+            _ => break,
+        }
+        args.remove(0);
+    }
+    let Some(class) = args.first().map(|class| class.replace('.', "/")) else {
+        eprintln!("No class provided");
+        std::process::exit(1);
+    };
+    let output =
+        output.unwrap_or_else(|| class.rsplit('/').next().unwrap_or(&class).to_lowercase());
+
+    let mut class_files = Vec::new();
+    if let Err(err) = read_class_files(std::path::Path::new(&class_path), &mut class_files) {
+        eprintln!("Could not read {}: {}", class_path, err);
+        std::process::exit(1);
+    }
+    let image = cs_vm::Image::compile(&class_files, &class).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    });
+    let written = std::env::current_exe()
+        .and_then(std::fs::read)
+        .and_then(|runtime| image.write_executable(&runtime, std::path::Path::new(&output)));
+    if let Err(err) = written {
+        eprintln!("Could not write {}: {}", output, err);
+        std::process::exit(1);
+    }
+    println!(
+        "{}: {} classes, {} compiled methods",
+        output,
+        image.classes.len(),
+        image.methods.len()
+    );
+}
+
+/// `wasm [-cp <dir>] [-o <module>] <class>` compiles the public static methods of the class, with
+/// the class files in the directory, into a WebAssembly module that exports them
+fn wasm(mut args: Vec<String>) {
+    let mut class_path = ".".to_string();
+    let mut output = None;
+    while args.len() > 1 {
+        match args[0].as_str() {
+            "-cp" | "-classpath" => class_path = args.remove(1),
+            "-o" => output = Some(args.remove(1)),
+            _ => break,
+        }
+        args.remove(0);
+    }
+    let Some(class) = args.first().map(|class| class.replace('.', "/")) else {
+        eprintln!("No class provided");
+        std::process::exit(1);
+    };
+    let output =
+        output.unwrap_or_else(|| format!("{}.wasm", class.rsplit('/').next().unwrap_or(&class)));
+
+    let mut class_files = Vec::new();
+    if let Err(err) = read_class_files(std::path::Path::new(&class_path), &mut class_files) {
+        eprintln!("Could not read {}: {}", class_path, err);
+        std::process::exit(1);
+    }
+    let module = cs_vm::compile_wasm(&class_files, &class).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    });
+    if let Err(err) = std::fs::write(&output, &module) {
+        eprintln!("Could not write {}: {}", output, err);
+        std::process::exit(1);
+    }
+    println!("{}: {} bytes", output, module.len());
+}
+
+/// `diff [--json] <old.class> <new.class>` prints what changed between the two versions of a class
+fn diff(mut args: Vec<String>) {
+    let json = args.first().is_some_and(|arg| arg == "--json");
+    if json {
+        args.remove(0);
+    }
+    let [old, new] = &args[..] else {
+        eprintln!("Expected the old and the new class file");
+        std::process::exit(1);
+    };
+
+    let parse = |path: &String| {
+        let contents = std::fs::read(path).unwrap_or_else(|err| {
+            eprintln!("Could not read {}: {}", path, err);
+            std::process::exit(1);
+        });
+        cs_parser::parse_class_file(&contents).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        })
+    };
+    let diff = cs_diff::diff(&parse(old), &parse(new));
+    if json {
+        println!("{}", diff.to_json());
+    } else {
+        print!("{}", diff);
+    }
+}
+
+/// `compat <old> <new>` checks that the new version of a library is binary compatible with the old one.
+/// Both are jar files or directories of class files. Exits with 1 if there are incompatible changes
+/// and with 2 if the classes can not be read, to be usable as a release gate
+fn compat(args: Vec<String>) {
+    let [old, new] = &args[..] else {
+        eprintln!("Expected the old and the new jar file");
+        std::process::exit(2);
+    };
+
+    let read = |path: &String| {
+        let mut class_files = Vec::new();
+        let path = std::path::Path::new(path);
+        let read = if path.is_dir() {
+            read_class_files(path, &mut class_files)
+        } else {
+            std::fs::read(path)
+                .and_then(|jar| cs_diff::class_files(&jar))
+                .map(|classes| class_files.extend(classes.into_iter().map(|(_, class)| class)))
+        };
+        if let Err(err) = read {
+            eprintln!("Could not read {}: {}", path.display(), err);
+            std::process::exit(2);
+        }
+        class_files
+            .iter()
+            .map(|class_file| cs_parser::parse_class_file(class_file))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|err| {
+                eprintln!("{}: {}", path.display(), err);
+                std::process::exit(2);
+            })
+    };
+    let incompatibilities = cs_diff::check(&read(old), &read(new));
+    for incompatibility in &incompatibilities {
+        println!("{}", incompatibility);
+    }
+    if incompatibilities.is_empty() {
+        println!("compatible");
+    } else {
+        println!("{} incompatible changes", incompatibilities.len());
+        std::process::exit(1);
+    }
+}
+
+/// Reads the class files in the directory and its subdirectories
+fn read_class_files(dir: &std::path::Path, class_files: &mut Vec<Vec<u8>>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_class_files(&path, class_files)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "class")
+        {
+            class_files.push(std::fs::read(path)?);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the image this executable was compiled with by `aot`, with all arguments
+fn run_image(image: &cs_vm::Image) -> ! {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut vm = cs_vm::Vm::new();
+    exit_on_error(vm.run_image(image, &args));
+    std::process::exit(0);
+}
+
+fn exit_on_error(result: cs_vm::Result<()>) {
+    match result {
+        Ok(()) => {}
+        // the stack trace was already printed
+        Err(cs_vm::VmError::Exception(_)) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints the hit rate of every call site with an inline cache
+fn print_caches(vm: &cs_vm::Vm) {
+    for cache in vm.inline_caches() {
+        let state = match &cache.state {
+            cs_vm::CacheState::Unique(_) => "unique".to_string(),
+            cs_vm::CacheState::Receivers(receivers) => format!("{} receivers", receivers.len()),
+            cs_vm::CacheState::Megamorphic => "megamorphic".to_string(),
+        };
+        eprintln!(
+            "{} pc {}: {:.1}% of {} calls hit, {}",
+            vm.method_name(cache.caller),
+            cache.pc,
+            cache.hit_rate() * 100.0,
+            cache.hits + cache.misses,
+            state
+        );
+    }
+}
@@ -0,0 +1,8 @@
+// Prints its arguments, one per line
+public class Echo {
+    public static void main(String[] args) {
+        for (String arg : args) {
+            System.out.println(arg);
+        }
+    }
+}
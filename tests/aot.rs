@@ -0,0 +1,39 @@
+use std::process::Command;
+
+/// An executable compiled by `aot` passes all its arguments to the program, even the names of
+/// subcommands and class files
+#[test]
+fn executable_arguments() {
+    let dir = std::env::temp_dir().join(format!("coldsquare-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let executable = dir.join("echo");
+    let classes = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/aot");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_coldsquare"))
+        .args(["aot", "-cp", classes, "-o"])
+        .arg(&executable)
+        .arg("Echo")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let class_file = format!("{}/Echo.class", classes);
+    let output = Command::new(&executable)
+        .args(["run", "diff", &class_file])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("run\ndiff\n{}\n", class_file)
+    );
+}